@@ -0,0 +1,176 @@
+//! The throughput of the generator and history path, measured by criterion
+//! and run by `cargo bench`, see the README.
+
+use std::{hint::black_box, sync::Arc, thread};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use jepsen_rs::{
+    generator::{
+        cas_register::CasRegisterGenerator, context::Global, GeneratorBuilder, GeneratorGroup,
+        RawGenerator, RawGeneratorMap,
+    },
+    history::HistoryType,
+    op::{Op, OpOrNemesis, OpTags},
+    runtime,
+    utils::AsyncIter,
+};
+
+fn ops() -> impl RawGenerator<Item = OpOrNemesis> + Send {
+    RawGeneratorMap::new(CasRegisterGenerator::new(8), OpOrNemesis::Op)
+}
+
+/// Collect the ops of `gens` generators of `n` ops each through a
+/// [`GeneratorGroup`].
+fn collect_group(gens: usize, n: usize) -> usize {
+    runtime::block_on(0, async {
+        let global = Arc::new(Global::<_, String>::new(ops()));
+        let gens = (0..gens).map(|_| {
+            GeneratorBuilder::new(global.clone())
+                .seq(tokio_stream::iter(global.take_seq(n)))
+                .build()
+        });
+        GeneratorGroup::new(gens.collect::<Vec<_>>())
+            .collect()
+            .await
+            .len()
+    })
+}
+
+/// Push an invoke and a result of `n` ops from each of `threads` threads,
+/// and collect the history. The ops are txns of a write and a read, or
+/// single writes without the allocation of a txn.
+fn push_history(threads: usize, n: usize, txn: bool) -> usize {
+    let global = Arc::new(Global::<_, String>::new(ops()));
+    thread::scope(|s| {
+        for process in 0..threads as u64 {
+            let global = &global;
+            s.spawn(move || {
+                for i in 0..n as u64 {
+                    let op = match txn {
+                        true => Op::Txn(vec![Op::Write(i % 8, i), Op::Read(i % 8, None)]),
+                        false => Op::Write(i % 8, i),
+                    };
+                    global.push_invoke(process, op.clone(), OpTags::new());
+                    global.push_result(process, HistoryType::Ok, op, None, None, OpTags::new());
+                }
+            });
+        }
+    });
+    global
+        .full_history()
+        .expect("the history is in memory")
+        .0
+        .len()
+}
+
+fn generator_group_collect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generator_group_collect");
+    for (gens, n) in [(1, 10_000), (16, 1_000)] {
+        group.throughput(Throughput::Elements((gens * n) as u64));
+        let id = BenchmarkId::from_parameter(format!("{}x{}", gens, n));
+        group.bench_function(id, |b| b.iter(|| black_box(collect_group(gens, n))));
+    }
+    group.finish();
+}
+
+fn history_push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("history_push");
+    for (threads, n) in [(1, 10_000), (8, 10_000)] {
+        // an invoke and a result of every op
+        group.throughput(Throughput::Elements((2 * threads * n) as u64));
+        let id = BenchmarkId::from_parameter(format!("{}x{}", threads, n));
+        group.bench_function(id, |b| b.iter(|| black_box(push_history(threads, n, true))));
+        let id = BenchmarkId::new("write", format!("{}x{}", threads, n));
+        group.bench_function(id, |b| {
+            b.iter(|| black_box(push_history(threads, n, false)))
+        });
+    }
+    group.finish();
+}
+
+/// Build, clone and drop the micro-ops of `n` txns on each of `threads`
+/// threads, as the invoke and the result of a txn do, on the heap as in
+/// [`Op::Txn`] or inline in a small vec.
+fn txn_micro_ops<T: Clone>(threads: usize, n: usize, txn: impl Fn(u64) -> T + Sync) {
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for i in 0..n as u64 {
+                    let invoke = black_box(txn(i));
+                    black_box(invoke.clone());
+                }
+            });
+        }
+    });
+}
+
+fn txn_alloc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("txn_alloc");
+    for (threads, n) in [(1, 10_000), (8, 10_000)] {
+        group.throughput(Throughput::Elements((threads * n) as u64));
+        let param = format!("{}x{}", threads, n);
+        group.bench_function(BenchmarkId::new("vec", &param), |b| {
+            b.iter(|| {
+                txn_micro_ops(threads, n, |i| {
+                    vec![Op::Write(i % 8, i), Op::Read(i % 8, None)]
+                })
+            })
+        });
+        group.bench_function(BenchmarkId::new("smallvec", &param), |b| {
+            b.iter(|| {
+                txn_micro_ops(threads, n, |i| {
+                    let ops: smallvec::SmallVec<[Op; 2]> =
+                        smallvec::smallvec![Op::Write(i % 8, i), Op::Read(i % 8, None)];
+                    ops
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Generate the ops of elle by one FFI batch, skipped if the JVM or the jars
+/// are not available. The JVM is only started if the benchmark is selected by
+/// the filter of `cargo bench`, e.g. not by `cargo bench -- history`, where
+/// the values of the options are passed as `--baseline=main`.
+#[cfg(feature = "clojure")]
+fn ffi_batch(c: &mut Criterion) {
+    use jepsen_rs::generator::elle_rw::ElleRwGenerator;
+
+    const NAME: &str = "ffi_batch/10000";
+    let filters: Vec<_> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    if !filters.is_empty() && !filters.iter().any(|f| NAME.contains(f.as_str())) {
+        return;
+    }
+    let available =
+        jepsen_rs::executor::JvmExecutor::try_global().is_ok() && ElleRwGenerator::new().is_ok();
+    if !available {
+        println!("{} skipped: the JVM or the jars are not available", NAME);
+        return;
+    }
+    let mut group = c.benchmark_group("ffi_batch");
+    group.throughput(Throughput::Elements(10_000));
+    group.bench_function(BenchmarkId::from_parameter(10_000), |b| {
+        b.iter(|| {
+            // a new generator every round, so its cache is not reused
+            let mut gen = ElleRwGenerator::new().expect("the generator is created once");
+            black_box(gen.gen_n(10_000).len())
+        })
+    });
+    group.finish();
+}
+
+#[cfg(not(feature = "clojure"))]
+fn ffi_batch(_c: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    generator_group_collect,
+    history_push,
+    txn_alloc,
+    ffi_batch
+);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+//! Runs the elle rw-register workload against a real etcd cluster through
+//! its JSON gateway, see [`jepsen_rs::adapter::etcd`].
+//!
+//! ```sh
+//! etcd &
+//! cargo run --example etcd --features etcd -- --seed 1 --duration 10
+//! cargo run --example etcd --features etcd -- --endpoints 127.0.0.1:2379,127.0.0.2:2379
+//! ```
+//!
+//! It runs on tokio, as the cluster is not simulated, so the seed only
+//! decides the generated ops. No nemesis is run, as they need the etcd
+//! processes, see [`EtcdClusterClient::with_supervisor`].
+//!
+//! [`EtcdClusterClient::with_supervisor`]: jepsen_rs::adapter::etcd::EtcdClusterClient::with_supervisor
+
+#[cfg(not(madsim))]
+fn main() -> anyhow::Result<()> {
+    use std::{net::SocketAddr, time::Duration};
+
+    use jepsen_rs::{
+        adapter::etcd::EtcdClusterClient, checker::ConsistencyModel, runner::TestBuilder,
+        workload::RwRegisterWorkload,
+    };
+
+    /// The number of ops of every process.
+    const OPS: usize = 200;
+
+    let mut seed = 0;
+    let mut duration = Duration::from_secs(10);
+    let mut endpoints: Vec<SocketAddr> = vec!["127.0.0.1:2379".parse()?];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--seed" => seed = value()?.parse()?,
+            "--duration" => duration = Duration::from_secs(value()?.parse()?),
+            "--endpoints" => {
+                endpoints = value()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?
+            }
+            _ => anyhow::bail!(
+                "usage: etcd [--seed <u64>] [--duration <secs>] [--endpoints <addr,...>]"
+            ),
+        }
+    }
+
+    let report = TestBuilder::new(
+        move || EtcdClusterClient::new(endpoints),
+        RwRegisterWorkload::new(ConsistencyModel::StrictSerializable),
+    )
+    .name("etcd")
+    .seed(seed)
+    .ops(OPS)
+    .duration(duration)
+    .build()
+    .run()?;
+    println!("{:#?}", report);
+    Ok(())
+}
+
+#[cfg(madsim)]
+fn main() {
+    eprintln!("this example runs against a real etcd, build it without `--cfg madsim`");
+}
@@ -0,0 +1,151 @@
+//! Runs the elle rw-register workload with nemeses against a mock cluster of
+//! madsim nodes.
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg madsim" cargo run --example madsim_cluster -- --seed 1 --duration 10
+//! ```
+//!
+//! Every op is executed on the leader node of the cluster, so killing or
+//! pausing it makes the following ops fail until the nemesis is recovered.
+
+#[cfg(madsim)]
+mod cluster {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use jepsen_rs::{
+        client::{ClusterLifecycle, ElleRwClusterClient},
+        nemesis::NemesisClusterClient,
+    };
+    use madsim::runtime::{Handle, NodeHandle};
+
+    /// Timeout of every request sent to the cluster.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// A mock cluster of madsim nodes sharing one in-memory kv store.
+    pub struct MockCluster {
+        nodes: Vec<NodeHandle>,
+        db: Arc<Mutex<HashMap<u64, u64>>>,
+    }
+
+    impl MockCluster {
+        pub fn new(size: usize) -> Self {
+            let handle = Handle::current();
+            let nodes = (0..size)
+                .map(|i| handle.create_node().name(format!("server-{}", i)).build())
+                .collect();
+            Self {
+                nodes,
+                db: Arc::default(),
+            }
+        }
+
+        /// Execute `f` on the leader node.
+        async fn on_leader<T: Send + 'static>(
+            &self,
+            f: impl FnOnce(&mut HashMap<u64, u64>) -> T + Send + 'static,
+        ) -> Result<T, String> {
+            let leader = self.get_leader_without_term().await as usize;
+            let db = Arc::clone(&self.db);
+            let task = self.nodes[leader].spawn(async move { f(&mut db.lock().unwrap()) });
+            madsim::time::timeout(REQUEST_TIMEOUT, task)
+                .await
+                .map_err(|_| "request timeout".to_string())?
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ElleRwClusterClient for MockCluster {
+        async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+            self.on_leader(move |db| db.get(&key).cloned()).await
+        }
+        async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+            self.on_leader(move |db| {
+                db.insert(key, value);
+            })
+            .await
+        }
+    }
+
+    impl ClusterLifecycle for MockCluster {}
+
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for MockCluster {
+        fn size(&self) -> usize {
+            self.nodes.len()
+        }
+        fn get_node_id(&self, id: u64) -> Option<madsim::task::NodeId> {
+            self.nodes.get(id as usize).map(|n| n.id())
+        }
+        async fn get_leader_without_term(&self) -> u64 {
+            0
+        }
+    }
+}
+
+#[cfg(madsim)]
+fn main() -> anyhow::Result<()> {
+    use std::{collections::HashSet, time::Duration};
+
+    use jepsen_rs::{
+        client::{Client, JepsenClient},
+        generator::{
+            controller::DelayStrategy, elle_rw::ElleRwGenerator, GeneratorBuilder, GeneratorGroup,
+        },
+        nemesis::NemesisType,
+        op::OpOrNemesis,
+    };
+
+    /// The number of ops to generate.
+    const OPS: usize = 200;
+
+    let mut seed = 0;
+    let mut duration = Duration::from_secs(10);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--seed" => seed = value()?.parse()?,
+            "--duration" => duration = Duration::from_secs(value()?.parse()?),
+            _ => anyhow::bail!("usage: madsim_cluster [--seed <u64>] [--duration <secs>]"),
+        }
+    }
+
+    let mut rt = madsim::runtime::Runtime::with_seed_and_config(seed, Default::default());
+    rt.set_allow_system_thread(true);
+    let raw_gen = ElleRwGenerator::new()?;
+    let res = rt.block_on(async move {
+        let client = JepsenClient::new(cluster::MockCluster::new(3), raw_gen);
+        let client: &'static _ = Box::leak(client.into());
+        // spread the ops and nemeses over the duration
+        let ops = GeneratorBuilder::new(client.global.clone())
+            .seq(tokio_stream::iter(client.global.take_seq(OPS)))
+            .delay(DelayStrategy::Fixed(duration / OPS as u32))
+            .build();
+        let nemeses = [
+            NemesisType::Pause(HashSet::from([0])),
+            NemesisType::PartitionHalves,
+            NemesisType::Kill(HashSet::from([0])),
+            NemesisType::PartitionRandomNode,
+        ];
+        let nemeses = GeneratorBuilder::new(client.global.clone())
+            .seq(tokio_stream::iter(nemeses.map(OpOrNemesis::Nemesis)))
+            .delay(DelayStrategy::Fixed(duration / 4))
+            .build();
+        client.run(GeneratorGroup::new([ops, nemeses])).await
+    });
+    println!("{:#?}", res.map_err(|e| anyhow::anyhow!(e))?);
+    Ok(())
+}
+
+#[cfg(not(madsim))]
+fn main() {
+    eprintln!("this example must be run with `RUSTFLAGS=\"--cfg madsim\"`");
+}
@@ -0,0 +1,95 @@
+//! Runs the elle rw-register workload with nemeses against a real etcd
+//! cluster on remote hosts, whose processes are paused, killed and
+//! partitioned over ssh, see [`jepsen_rs::nemesis::ssh`].
+//!
+//! ```sh
+//! cargo run --example ssh --features etcd,ssh -- --seed 1 --duration 60 --hosts 10.0.0.1,10.0.0.2,10.0.0.3
+//! ```
+//!
+//! etcd runs as the `etcd` systemd unit on every host and listens for clients
+//! on port 2379, and the hosts are reached as `--user` (root by default) by
+//! the keys of the ssh agent. It runs on tokio, as the cluster is not
+//! simulated, so the seed only decides the generated ops and nemeses.
+
+#[cfg(not(madsim))]
+fn main() -> anyhow::Result<()> {
+    use std::{collections::HashSet, net::IpAddr, time::Duration};
+
+    use jepsen_rs::{
+        adapter::etcd::EtcdClusterClient,
+        checker::ConsistencyModel,
+        generator::nemesis_mix::NemesisGeneratorBuilder,
+        nemesis::{
+            ssh::{SshCluster, SshHost},
+            NemesisType,
+        },
+        runner::TestBuilder,
+        workload::RwRegisterWorkload,
+    };
+
+    /// The number of ops of every process.
+    const OPS: usize = 200;
+    /// The average interval between the nemeses.
+    const NEMESIS_INTERVAL: Duration = Duration::from_secs(10);
+
+    let mut seed = 0;
+    let mut duration = Duration::from_secs(60);
+    let mut hosts: Vec<IpAddr> = vec![];
+    let mut user = "root".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--seed" => seed = value()?.parse()?,
+            "--duration" => duration = Duration::from_secs(value()?.parse()?),
+            "--hosts" => {
+                hosts = value()?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?
+            }
+            "--user" => user = value()?,
+            _ => anyhow::bail!(
+                "usage: ssh [--seed <u64>] [--duration <secs>] --hosts <ip,...> [--user <name>]"
+            ),
+        }
+    }
+    if hosts.is_empty() {
+        anyhow::bail!("--hosts is required");
+    }
+
+    let supervisor = SshCluster::new(
+        hosts
+            .iter()
+            .map(|ip| SshHost::new(format!("{}@{}", user, ip), *ip, "etcd")),
+    );
+    let endpoints: Vec<_> = hosts.iter().map(|ip| (*ip, 2379).into()).collect();
+    let mix = NemesisGeneratorBuilder::new(NEMESIS_INTERVAL)
+        .fault(2, NemesisType::Pause(HashSet::from([0])))
+        .fault(1, NemesisType::Kill(HashSet::from([0])))
+        .fault(2, NemesisType::PartitionHalves)
+        .build();
+    let nemeses = (duration.as_secs() / NEMESIS_INTERVAL.as_secs()).max(1) as usize;
+
+    let report = TestBuilder::new(
+        move || EtcdClusterClient::new(endpoints).with_supervisor(supervisor),
+        RwRegisterWorkload::new(ConsistencyModel::StrictSerializable),
+    )
+    .name("ssh")
+    .seed(seed)
+    .ops(OPS)
+    .duration(duration)
+    .nemesis_mix(mix, nemeses)
+    .build()
+    .run()?;
+    println!("{:#?}", report);
+    Ok(())
+}
+
+#[cfg(madsim)]
+fn main() {
+    eprintln!("this example runs against a real etcd, build it without `--cfg madsim`");
+}
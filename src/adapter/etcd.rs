@@ -0,0 +1,513 @@
+//! A client of etcd over the JSON gateway of the v3 API (`/v3/kv/range`,
+//! `/v3/kv/put` and `/v3/kv/txn`), which needs no extra dependency.
+//!
+//! Keys are stored as `{prefix}{key}` and values as decimal strings. The
+//! nemeses are executed on the etcd processes by the [`OsClusterClient`]
+//! given by [`EtcdClusterClient::with_supervisor`].
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use log::{trace, warn};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient},
+    nemesis::{os::OsClusterClient, NemesisClusterClient, ServerId},
+    op::Op,
+};
+
+/// The default prefix of keys.
+const DEFAULT_PREFIX: &str = "jepsen/";
+/// The default timeout of a request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A cluster client of etcd.
+pub struct EtcdClusterClient {
+    /// The client URLs of the members, indexed by [`ServerId`].
+    endpoints: Vec<SocketAddr>,
+    prefix: String,
+    timeout: Duration,
+    supervisor: Option<Box<dyn OsClusterClient + Send + Sync>>,
+    /// The endpoint of the next request, requests are spread over members.
+    next: AtomicUsize,
+}
+
+impl EtcdClusterClient {
+    pub fn new(endpoints: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            endpoints: endpoints.into_iter().collect(),
+            prefix: DEFAULT_PREFIX.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            supervisor: None,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Set the prefix of keys, `jepsen/` by default.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the timeout of a request, 5s by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the supervisor of the etcd processes, which executes the nemeses.
+    pub fn with_supervisor(
+        mut self,
+        supervisor: impl OsClusterClient + Send + Sync + 'static,
+    ) -> Self {
+        self.supervisor = Some(Box::new(supervisor));
+        self
+    }
+
+    fn key(&self, key: u64) -> String {
+        base64_encode(format!("{}{}", self.prefix, key).as_bytes())
+    }
+
+    /// Post a request to the given member.
+    async fn post_to(
+        &self,
+        endpoint: SocketAddr,
+        path: &str,
+        body: &Value,
+    ) -> Result<Value, PostError> {
+        let stream = madsim::time::timeout(self.timeout, TcpStream::connect(endpoint))
+            .await
+            .map_err(|_| PostError::Connect(format!("connecting {} timed out", endpoint)))?
+            .map_err(|e| PostError::Connect(format!("failed to connect {}: {}", endpoint, e)))?;
+        madsim::time::timeout(self.timeout, post(stream, endpoint, path, body))
+            .await
+            .map_err(|_| PostError::Sent(format!("request to {} timed out", endpoint)))?
+            .map_err(PostError::Sent)
+    }
+
+    /// Post a request to the members in turn, until one of them is
+    /// connected. Once the request is sent, its error is returned rather than
+    /// sent again to the next member, as it may have been applied, and the op
+    /// is indeterminate; retrying it is up to the
+    /// [`RetryPolicy`](crate::retry::RetryPolicy).
+    async fn request(&self, path: &str, body: Value) -> Result<Value, String> {
+        if self.endpoints.is_empty() {
+            return Err("no etcd endpoint".to_string());
+        }
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut last_err = String::new();
+        for i in 0..self.endpoints.len() {
+            let endpoint = self.endpoints[(first + i) % self.endpoints.len()];
+            match self.post_to(endpoint, path, &body).await {
+                Ok(res) => return Ok(res),
+                Err(PostError::Connect(err)) => {
+                    trace!("failed to connect etcd member {}: {}", endpoint, err);
+                    last_err = err;
+                }
+                Err(PostError::Sent(err)) => return Err(err),
+            }
+        }
+        Err(last_err)
+    }
+}
+
+/// The error of a request to a member.
+#[derive(Debug)]
+enum PostError {
+    /// The member is not connected, so nothing is sent to it.
+    Connect(String),
+    /// The request may have been sent.
+    Sent(String),
+}
+
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostError::Connect(err) | PostError::Sent(err) => f.write_str(err),
+        }
+    }
+}
+
+/// Post a JSON body by HTTP/1.0 on the connection, so that the response is
+/// never chunked.
+async fn post(
+    mut stream: TcpStream,
+    endpoint: SocketAddr,
+    path: &str,
+    body: &Value,
+) -> Result<Value, String> {
+    let body = body.to_string();
+    let request = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        path,
+        endpoint,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| format!("failed to send to {}: {}", endpoint, e))?;
+    let mut response = vec![];
+    stream
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| format!("failed to receive from {}: {}", endpoint, e))?;
+    parse_response(&response)
+}
+
+/// Parse an HTTP response with a JSON body.
+fn parse_response(response: &[u8]) -> Result<Value, String> {
+    let response = String::from_utf8_lossy(response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| "malformed HTTP response".to_string())?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| {
+            format!(
+                "malformed HTTP status line: {}",
+                head.lines().next().unwrap_or("")
+            )
+        })?;
+    if status != 200 {
+        return Err(format!("etcd responds {}: {}", status, body.trim()));
+    }
+    serde_json::from_str(body).map_err(|e| format!("malformed etcd response: {}", e))
+}
+
+/// The value of the first kv in a range response, which is base64-encoded.
+fn range_value(range: &Value) -> Result<Option<u64>, String> {
+    let Some(value) = range["kvs"].get(0).map(|kv| &kv["value"]) else {
+        return Ok(None);
+    };
+    // an empty value is omitted
+    let value = base64_decode(value.as_str().unwrap_or(""))?;
+    let value = String::from_utf8(value).map_err(|e| e.to_string())?;
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("unexpected value `{}`", value))
+}
+
+/// Build the request ops of an etcd txn from the ops of an elle txn. etcd
+/// rejects a txn writing a key twice, and reads of a txn don't observe its own
+/// writes, so the reads after a write of the same key are answered locally
+/// and only the last write of a key is sent, at the position of the first
+/// one. Returns `None` for the ops answered locally.
+fn txn_requests(client: &EtcdClusterClient, ops: &[Op]) -> Result<Vec<Option<Value>>, String> {
+    let mut last_write: HashMap<u64, u64> = HashMap::new();
+    for op in ops {
+        if let Op::Write(key, value) = op {
+            last_write.insert(*key, *value);
+        }
+    }
+    let mut written = HashMap::new();
+    let mut requests = vec![];
+    for op in ops {
+        requests.push(match op {
+            Op::Read(key, _) if written.contains_key(key) => None,
+            Op::Read(key, _) => Some(json!({ "request_range": { "key": client.key(*key) } })),
+            Op::Write(key, value) => {
+                let first = written.insert(*key, *value).is_none();
+                first.then(|| {
+                    let value = base64_encode(last_write[key].to_string().as_bytes());
+                    json!({ "request_put": { "key": client.key(*key), "value": value } })
+                })
+            }
+            Op::Cas(..)
+            | Op::Delete(_)
+            | Op::Incr(..)
+            | Op::Append(..)
+            | Op::ReadList(..)
+            | Op::BatchRead(_)
+            | Op::ScanRange(..) => {
+                return Err("only reads and writes are supported in txns".to_string())
+            }
+            Op::Txn(_) => return Err("txns cannot be nested".to_string()),
+        });
+    }
+    Ok(requests)
+}
+
+/// Fill the reads of the elle txn by the responses of the etcd txn built by
+/// [`txn_requests`].
+fn txn_results(
+    ops: Vec<Op>,
+    requests: &[Option<Value>],
+    responses: &[Value],
+) -> Result<Vec<Op>, String> {
+    let mut responses = responses.iter();
+    let mut written = HashMap::new();
+    let mut out = Vec::with_capacity(ops.len());
+    for (op, request) in ops.into_iter().zip(requests) {
+        let response = match request {
+            Some(_) => Some(
+                responses
+                    .next()
+                    .ok_or_else(|| "missing responses in etcd txn".to_string())?,
+            ),
+            None => None,
+        };
+        out.push(match op {
+            Op::Read(key, _) => match response {
+                Some(response) => Op::Read(key, range_value(&response["response_range"])?),
+                None => Op::Read(key, written.get(&key).copied()),
+            },
+            Op::Write(key, value) => {
+                written.insert(key, value);
+                op
+            }
+            Op::Cas(..)
+            | Op::Delete(_)
+            | Op::Incr(..)
+            | Op::Append(..)
+            | Op::ReadList(..)
+            | Op::BatchRead(_)
+            | Op::ScanRange(..)
+            | Op::Txn(_) => {
+                unreachable!("checked in `txn_requests`")
+            }
+        });
+    }
+    Ok(out)
+}
+
+#[async_trait::async_trait]
+impl ElleRwClusterClient for EtcdClusterClient {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        let res = self
+            .request("/v3/kv/range", json!({ "key": self.key(key) }))
+            .await?;
+        range_value(&res)
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        let value = base64_encode(value.to_string().as_bytes());
+        self.request(
+            "/v3/kv/put",
+            json!({ "key": self.key(key), "value": value }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let requests = txn_requests(self, &ops)?;
+        let success: Vec<_> = requests.iter().flatten().cloned().collect();
+        let res = self
+            .request("/v3/kv/txn", json!({ "success": success }))
+            .await?;
+        let responses = res["responses"].as_array().cloned().unwrap_or_default();
+        txn_results(ops, &requests, &responses)
+    }
+}
+
+impl ClusterLifecycle for EtcdClusterClient {}
+
+#[async_trait::async_trait]
+impl NemesisClusterClient for EtcdClusterClient {
+    fn size(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    /// The member whose id is the leader reported by itself.
+    async fn get_leader_without_term(&self) -> ServerId {
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            match self
+                .post_to(*endpoint, "/v3/maintenance/status", &json!({}))
+                .await
+            {
+                Ok(status) if status["leader"] == status["header"]["member_id"] => {
+                    return i as ServerId;
+                }
+                Ok(_) => {}
+                Err(err) => trace!("failed to get status of {}: {}", endpoint, err),
+            }
+        }
+        warn!("no etcd member is the leader, assume it's server 0");
+        0
+    }
+
+    fn os(&self) -> Option<&(dyn OsClusterClient + Sync)> {
+        self.supervisor.as_deref().map(|s| s as _)
+    }
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The standard base64 with padding, which is used by the JSON gateway for
+/// bytes.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    let s = s.trim_end_matches('=');
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let mut n = 0u32;
+    for (i, c) in s.bytes().enumerate() {
+        let v = BASE64_CHARS
+            .iter()
+            .position(|x| *x == c)
+            .ok_or_else(|| format!("invalid base64 `{}`", s))?;
+        n = n << 6 | v as u32;
+        if i % 4 == 3 {
+            out.extend_from_slice(&n.to_be_bytes()[1..]);
+            n = 0;
+        }
+    }
+    match s.len() % 4 {
+        0 => {}
+        2 => out.push((n >> 4) as u8),
+        3 => out.extend_from_slice(&((n >> 2) as u16).to_be_bytes()),
+        _ => return Err(format!("invalid base64 length of `{}`", s)),
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        for (raw, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("jepsen/42", "amVwc2VuLzQy"),
+        ] {
+            assert_eq!(base64_encode(raw.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), raw.as_bytes());
+        }
+        assert!(base64_decode("Z").is_err());
+        assert!(base64_decode("Z*==").is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let ok = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"count\":\"0\"}";
+        assert_eq!(parse_response(ok).unwrap()["count"], "0");
+        let err = b"HTTP/1.0 400 Bad Request\r\n\r\n{\"error\":\"bad\"}";
+        assert!(parse_response(err).unwrap_err().contains("400"));
+        assert!(parse_response(b"garbage").is_err());
+    }
+
+    /// A member accepting `n` connections, answering every request by
+    /// `response`, or closing it if none. Returns its address and the number
+    /// of the requests it got.
+    #[cfg(not(madsim))]
+    fn member(
+        n: usize,
+        response: Option<&'static str>,
+    ) -> (SocketAddr, std::sync::Arc<AtomicUsize>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(n) {
+                let mut stream = stream.unwrap();
+                let _ = stream.read(&mut [0; 4096]);
+                counter.fetch_add(1, Ordering::SeqCst);
+                if let Some(response) = response {
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            }
+        });
+        (addr, requests)
+    }
+
+    #[cfg(not(madsim))]
+    #[test]
+    fn test_failover() {
+        const OK: &str = "HTTP/1.0 200 OK\r\n\r\n{}";
+        // nothing listens on the port of a dropped listener
+        let down = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (up, _) = member(1, Some(OK));
+        let client = EtcdClusterClient::new([down, up]);
+        let res = crate::runtime::block_on(0, client.put(1, 1));
+        assert_eq!(res, Ok(()));
+
+        // the put may be applied by the member closing the connection, so
+        // it's not sent again to the next one
+        let (closing, _) = member(1, None);
+        let (next, requests) = member(1, Some(OK));
+        let client = EtcdClusterClient::new([closing, next]);
+        let res = crate::runtime::block_on(0, client.put(1, 1));
+        assert!(res.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_etcd_txn() {
+        let client = EtcdClusterClient::new([]);
+        let ops = vec![
+            Op::Read(1, None),
+            Op::Write(1, 5),
+            Op::Read(1, None),
+            Op::Write(1, 6),
+            Op::Read(2, None),
+        ];
+        let requests = txn_requests(&client, &ops).unwrap();
+        let sent: Vec<_> = requests.iter().map(Option::is_some).collect();
+        assert_eq!(sent, [true, true, false, false, true]);
+        assert_eq!(
+            requests[1].as_ref().unwrap()["request_put"]["value"],
+            base64_encode(b"6")
+        );
+        let responses = [
+            json!({ "response_range": { "kvs": [{ "value": base64_encode(b"3") }] } }),
+            json!({ "response_put": {} }),
+            json!({ "response_range": {} }),
+        ];
+        assert_eq!(
+            txn_results(ops, &requests, &responses).unwrap(),
+            vec![
+                Op::Read(1, Some(3)),
+                Op::Write(1, 5),
+                Op::Read(1, Some(5)),
+                Op::Write(1, 6),
+                Op::Read(2, None),
+            ]
+        );
+    }
+}
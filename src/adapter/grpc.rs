@@ -0,0 +1,747 @@
+//! A client of any KV database serving the gRPC service in
+//! `proto/kv.proto` (shipped as [`PROTO`]), with Get, Put and Txn RPCs.
+//!
+//! [`TonicKvService`] calls the service on one endpoint over a tonic channel,
+//! so pointing the adapter at the endpoints is enough:
+//!
+//! ```ignore
+//! let endpoints = ["http://10.0.0.1:50051", "http://10.0.0.2:50051"];
+//! let client = GrpcKvClusterClient::new(
+//!     endpoints.map(|e| TonicKvService::connect_lazy(e).unwrap()),
+//! );
+//! ```
+//!
+//! A request falls through to the next endpoint only on
+//! [`KvError::Unavailable`], which a [`KvService`] returns when the endpoint
+//! surely did not receive it, e.g. the channel failed to connect. Any other
+//! error is returned, as the request may have taken effect.
+//!
+//! Another gRPC stack is used by generating the client stubs of the proto
+//! with it, and implementing [`KvService`] for them by converting the
+//! messages, which have the same fields as the generated ones:
+//!
+//! ```ignore
+//! #[async_trait::async_trait]
+//! impl KvService for pb::kv_client::KvClient<MyChannel> {
+//!     async fn get(&self, req: GetRequest) -> Result<GetResponse, KvError> {
+//!         let res = self.clone().get(pb::GetRequest { key: req.key }).await;
+//!         let res = res.map_err(|e| KvError::Failed(e.to_string()))?.into_inner();
+//!         Ok(GetResponse { value: res.value })
+//!     }
+//!     // ...
+//! }
+//! ```
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use futures_util::future::BoxFuture;
+use log::trace;
+use tonic::{
+    client::Grpc,
+    codec::ProstCodec,
+    codegen::http::uri::PathAndQuery,
+    transport::{Channel, Endpoint},
+};
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient},
+    nemesis::{os::OsClusterClient, NemesisClusterClient, ServerId},
+    op::Op,
+};
+
+/// The proto of the KV service.
+pub const PROTO: &str = include_str!("../../proto/kv.proto");
+
+/// The default timeout of a request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetRequest {
+    pub key: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetResponse {
+    pub value: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PutRequest {
+    pub key: u64,
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PutResponse {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxnOpKind {
+    Read = 0,
+    Write = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxnOp {
+    pub kind: TxnOpKind,
+    pub key: u64,
+    pub value: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxnRequest {
+    pub ops: Vec<TxnOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxnResponse {
+    pub ops: Vec<TxnOp>,
+}
+
+/// The error of an RPC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvError {
+    /// The endpoint did not receive the request, so the next one is tried.
+    Unavailable(String),
+    /// The request failed after it was sent, so its effect is unknown.
+    Failed(String),
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::Unavailable(e) | KvError::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+/// The client stubs of the KV service on one endpoint.
+#[async_trait::async_trait]
+pub trait KvService: Send + Sync {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, KvError>;
+    async fn put(&self, req: PutRequest) -> Result<PutResponse, KvError>;
+    async fn txn(&self, req: TxnRequest) -> Result<TxnResponse, KvError>;
+}
+
+impl TryFrom<&Op> for TxnOp {
+    type Error = String;
+
+    fn try_from(op: &Op) -> Result<Self, Self::Error> {
+        match op {
+            Op::Read(key, _) => Ok(TxnOp {
+                kind: TxnOpKind::Read,
+                key: *key,
+                value: None,
+            }),
+            Op::Write(key, value) => Ok(TxnOp {
+                kind: TxnOpKind::Write,
+                key: *key,
+                value: Some(*value),
+            }),
+            Op::Cas(..)
+            | Op::Delete(_)
+            | Op::Incr(..)
+            | Op::Append(..)
+            | Op::ReadList(..)
+            | Op::BatchRead(_)
+            | Op::ScanRange(..) => Err("only reads and writes are supported in txns".to_string()),
+            Op::Txn(_) => Err("txns cannot be nested".to_string()),
+        }
+    }
+}
+
+/// The messages of `proto/kv.proto`, as generated by prost.
+mod pb {
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct GetRequest {
+        #[prost(uint64, tag = "1")]
+        pub key: u64,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct GetResponse {
+        #[prost(uint64, optional, tag = "1")]
+        pub value: Option<u64>,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct PutRequest {
+        #[prost(uint64, tag = "1")]
+        pub key: u64,
+        #[prost(uint64, tag = "2")]
+        pub value: u64,
+    }
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct PutResponse {}
+
+    #[derive(Clone, Copy, PartialEq, prost::Message)]
+    pub struct TxnOp {
+        #[prost(int32, tag = "1")]
+        pub kind: i32,
+        #[prost(uint64, tag = "2")]
+        pub key: u64,
+        #[prost(uint64, optional, tag = "3")]
+        pub value: Option<u64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TxnRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub ops: Vec<TxnOp>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TxnResponse {
+        #[prost(message, repeated, tag = "1")]
+        pub ops: Vec<TxnOp>,
+    }
+}
+
+impl From<TxnOp> for pb::TxnOp {
+    fn from(op: TxnOp) -> Self {
+        pb::TxnOp {
+            kind: op.kind as i32,
+            key: op.key,
+            value: op.value,
+        }
+    }
+}
+
+impl TryFrom<pb::TxnOp> for TxnOp {
+    type Error = KvError;
+
+    fn try_from(op: pb::TxnOp) -> Result<Self, KvError> {
+        let kind = match op.kind {
+            0 => TxnOpKind::Read,
+            1 => TxnOpKind::Write,
+            kind => return Err(KvError::Failed(format!("invalid txn op kind {}", kind))),
+        };
+        Ok(TxnOp {
+            kind,
+            key: op.key,
+            value: op.value,
+        })
+    }
+}
+
+/// The [`KvService`] of one endpoint over a tonic channel.
+#[derive(Debug, Clone)]
+pub struct TonicKvService {
+    grpc: Grpc<Channel>,
+}
+
+impl TonicKvService {
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            grpc: Grpc::new(channel),
+        }
+    }
+
+    /// The service of the endpoint, e.g. `http://10.0.0.1:50051`, which is
+    /// connected by the first request. It must be called in the tokio
+    /// runtime, as the channel is not simulated by madsim.
+    pub fn connect_lazy(endpoint: impl Into<String>) -> Result<Self, String> {
+        let endpoint = Endpoint::from_shared(endpoint.into())
+            .map_err(|e| format!("invalid grpc endpoint: {}", e))?;
+        Ok(Self::new(endpoint.connect_lazy()))
+    }
+
+    async fn unary<Req, Res>(&self, path: &'static str, req: Req) -> Result<Res, KvError>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Res: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut grpc = self.grpc.clone();
+        grpc.ready()
+            .await
+            .map_err(|e| KvError::Unavailable(format!("grpc channel is not ready: {}", e)))?;
+        let path = PathAndQuery::from_static(path);
+        grpc.unary(tonic::Request::new(req), path, ProstCodec::default())
+            .await
+            .map(tonic::Response::into_inner)
+            .map_err(kv_error)
+    }
+}
+
+/// The error of a status, which is unavailable only if the channel failed to
+/// connect, as the other unavailable statuses, e.g. of a reset connection,
+/// may follow sending the request.
+fn kv_error(status: tonic::Status) -> KvError {
+    let message = format!("{:?}: {}", status.code(), status.message());
+    let mut source = std::error::Error::source(&status);
+    while let Some(err) = source {
+        if err.is::<tonic::ConnectError>() {
+            return KvError::Unavailable(message);
+        }
+        source = err.source();
+    }
+    KvError::Failed(message)
+}
+
+#[async_trait::async_trait]
+impl KvService for TonicKvService {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, KvError> {
+        let req = pb::GetRequest { key: req.key };
+        let res: pb::GetResponse = self.unary("/jepsen.kv.Kv/Get", req).await?;
+        Ok(GetResponse { value: res.value })
+    }
+
+    async fn put(&self, req: PutRequest) -> Result<PutResponse, KvError> {
+        let req = pb::PutRequest {
+            key: req.key,
+            value: req.value,
+        };
+        let _: pb::PutResponse = self.unary("/jepsen.kv.Kv/Put", req).await?;
+        Ok(PutResponse {})
+    }
+
+    async fn txn(&self, req: TxnRequest) -> Result<TxnResponse, KvError> {
+        let req = pb::TxnRequest {
+            ops: req.ops.into_iter().map(pb::TxnOp::from).collect(),
+        };
+        let res: pb::TxnResponse = self.unary("/jepsen.kv.Kv/Txn", req).await?;
+        let ops = res
+            .ops
+            .into_iter()
+            .map(TxnOp::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(TxnResponse { ops })
+    }
+}
+
+/// A cluster client of a gRPC KV service.
+pub struct GrpcKvClusterClient<S> {
+    /// The services of the endpoints, indexed by [`ServerId`].
+    endpoints: Vec<S>,
+    timeout: Duration,
+    supervisor: Option<Box<dyn OsClusterClient + Send + Sync>>,
+    /// The endpoint of the next request, requests are spread over endpoints.
+    next: AtomicUsize,
+}
+
+impl<S: KvService> GrpcKvClusterClient<S> {
+    pub fn new(endpoints: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            endpoints: endpoints.into_iter().collect(),
+            timeout: DEFAULT_TIMEOUT,
+            supervisor: None,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Set the timeout of a request, 5s by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the supervisor of the database processes, which executes the
+    /// nemeses.
+    pub fn with_supervisor(
+        mut self,
+        supervisor: impl OsClusterClient + Send + Sync + 'static,
+    ) -> Self {
+        self.supervisor = Some(Box::new(supervisor));
+        self
+    }
+
+    /// Call the endpoints in turn until one of them receives the request, or
+    /// only the given endpoint. The result of the first endpoint that received
+    /// it is returned, failed or timed out, as the request may have taken
+    /// effect; retrying it is left to the [`crate::retry::RetryPolicy`].
+    async fn call<'a, T, F>(&'a self, node: Option<ServerId>, rpc: F) -> Result<T, String>
+    where
+        F: Fn(&'a S) -> BoxFuture<'a, Result<T, KvError>>,
+    {
+        let ids: Vec<_> = match node {
+            Some(node) if node as usize >= self.endpoints.len() => {
+                return Err(format!("no grpc endpoint {}", node));
+            }
+            Some(node) => vec![node as usize],
+            None => {
+                let first = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..self.endpoints.len())
+                    .map(|i| (first + i) % self.endpoints.len())
+                    .collect()
+            }
+        };
+        let mut last_err = "no grpc endpoint".to_string();
+        for id in ids {
+            match madsim::time::timeout(self.timeout, rpc(&self.endpoints[id])).await {
+                Ok(Ok(res)) => return Ok(res),
+                Ok(Err(KvError::Unavailable(err))) => last_err = err,
+                Ok(Err(KvError::Failed(err))) => return Err(err),
+                Err(_) => return Err(format!("request to endpoint {} timed out", id)),
+            }
+            trace!("grpc request to endpoint {} failed: {}", id, last_err);
+        }
+        Err(last_err)
+    }
+
+    async fn get_at(&self, node: Option<ServerId>, key: u64) -> Result<Option<u64>, String> {
+        let res = self.call(node, |s| s.get(GetRequest { key })).await?;
+        Ok(res.value)
+    }
+
+    async fn put_at(&self, node: Option<ServerId>, key: u64, value: u64) -> Result<(), String> {
+        self.call(node, |s| s.put(PutRequest { key, value }))
+            .await?;
+        Ok(())
+    }
+
+    async fn txn_at(&self, node: Option<ServerId>, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let req = TxnRequest {
+            ops: ops.iter().map(TxnOp::try_from).collect::<Result<_, _>>()?,
+        };
+        let res = self.call(node, |s| s.txn(req.clone())).await?;
+        if res.ops.len() != ops.len() {
+            return Err(format!(
+                "the txn responds {} ops for {} ops",
+                res.ops.len(),
+                ops.len()
+            ));
+        }
+        ops.into_iter()
+            .zip(res.ops)
+            .map(|(op, res)| match op {
+                Op::Read(key, _) if res.key == key && res.kind == TxnOpKind::Read => {
+                    Ok(Op::Read(key, res.value))
+                }
+                Op::Write(key, _) if res.key == key && res.kind == TxnOpKind::Write => Ok(op),
+                op => Err(format!("the txn responds {:?} for {:?}", res, op)),
+            })
+            .collect()
+    }
+}
+
+/// The ops bound to a node are sent only to its endpoint.
+#[async_trait::async_trait]
+impl<S: KvService> ElleRwClusterClient for GrpcKvClusterClient<S> {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        self.get_at(None, key).await
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        self.put_at(None, key, value).await
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        self.txn_at(None, ops).await
+    }
+
+    async fn get_on(&self, node: ServerId, key: u64) -> Result<Option<u64>, String> {
+        self.get_at(Some(node), key).await
+    }
+
+    async fn put_on(&self, node: ServerId, key: u64, value: u64) -> Result<(), String> {
+        self.put_at(Some(node), key, value).await
+    }
+
+    async fn txn_on(&self, node: ServerId, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        self.txn_at(Some(node), ops).await
+    }
+}
+
+impl<S: KvService> ClusterLifecycle for GrpcKvClusterClient<S> {}
+
+#[async_trait::async_trait]
+impl<S: KvService> NemesisClusterClient for GrpcKvClusterClient<S> {
+    fn size(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    /// The service has no notion of leader, so the first endpoint is assumed.
+    async fn get_leader_without_term(&self) -> ServerId {
+        0
+    }
+
+    fn os(&self) -> Option<&(dyn OsClusterClient + Sync)> {
+        self.supervisor.as_deref().map(|s| s as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// A service on a shared map, or an endpoint failing with the error.
+    struct MapService {
+        rows: Arc<Mutex<HashMap<u64, u64>>>,
+        error: Option<KvError>,
+    }
+
+    impl MapService {
+        fn check(&self) -> Result<(), KvError> {
+            self.error.clone().map_or(Ok(()), Err)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl KvService for MapService {
+        async fn get(&self, req: GetRequest) -> Result<GetResponse, KvError> {
+            self.check()?;
+            let value = self.rows.lock().unwrap().get(&req.key).copied();
+            Ok(GetResponse { value })
+        }
+
+        async fn put(&self, req: PutRequest) -> Result<PutResponse, KvError> {
+            self.check()?;
+            self.rows.lock().unwrap().insert(req.key, req.value);
+            Ok(PutResponse {})
+        }
+
+        async fn txn(&self, req: TxnRequest) -> Result<TxnResponse, KvError> {
+            self.check()?;
+            let mut rows = self.rows.lock().unwrap();
+            let ops = req
+                .ops
+                .into_iter()
+                .map(|mut op| {
+                    match op.kind {
+                        TxnOpKind::Read => op.value = rows.get(&op.key).copied(),
+                        TxnOpKind::Write => {
+                            rows.insert(op.key, op.value.unwrap());
+                        }
+                    }
+                    op
+                })
+                .collect();
+            Ok(TxnResponse { ops })
+        }
+    }
+
+    #[madsim::test]
+    async fn test_grpc_kv() {
+        assert!(PROTO.contains("service Kv"));
+        let rows = Arc::new(Mutex::new(HashMap::new()));
+        let down = || Some(KvError::Unavailable("unavailable".to_string()));
+        let client = GrpcKvClusterClient::new([down(), None, down()].map(|error| MapService {
+            rows: rows.clone(),
+            error,
+        }));
+        // every request falls through the down endpoints
+        for i in 0..3 {
+            client.put(i, i * 10).await.unwrap();
+        }
+        assert_eq!(client.get(1).await.unwrap(), Some(10));
+        assert_eq!(client.get(5).await.unwrap(), None);
+        let res = client
+            .txn(vec![Op::Write(5, 50), Op::Read(5, None), Op::Read(2, None)])
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            vec![
+                Op::Write(5, 50),
+                Op::Read(5, Some(50)),
+                Op::Read(2, Some(20))
+            ]
+        );
+        assert!(client.txn(vec![Op::Txn(vec![])]).await.is_err());
+
+        // the ops bound to a down endpoint fail
+        assert_eq!(client.get_on(0, 1).await, Err("unavailable".to_string()));
+        assert_eq!(client.get_on(1, 1).await.unwrap(), Some(10));
+        assert!(client.put_on(3, 1, 1).await.is_err());
+
+        let client = GrpcKvClusterClient::new([MapService {
+            rows: rows.clone(),
+            error: down(),
+        }]);
+        assert_eq!(client.get(1).await, Err("unavailable".to_string()));
+
+        // a request that failed after it was sent is not sent again
+        let client = GrpcKvClusterClient::new(
+            [Some(KvError::Failed("reset".to_string())), None].map(|error| MapService {
+                rows: rows.clone(),
+                error,
+            }),
+        );
+        assert_eq!(client.put(7, 70).await, Err("reset".to_string()));
+        assert_eq!(rows.lock().unwrap().get(&7), None);
+    }
+
+    /// The server of a [`MapService`], as generated by `tonic-build`.
+    #[cfg(not(madsim))]
+    #[derive(Clone)]
+    struct KvServer(Arc<MapService>);
+
+    #[cfg(not(madsim))]
+    impl tonic::server::NamedService for KvServer {
+        const NAME: &'static str = "jepsen.kv.Kv";
+    }
+
+    /// A unary RPC of the [`KvServer`].
+    #[cfg(not(madsim))]
+    struct Unary<F>(F);
+
+    #[cfg(not(madsim))]
+    impl<Req, Res, F, Fut> tonic::codegen::Service<tonic::Request<Req>> for Unary<F>
+    where
+        F: FnMut(Req) -> Fut,
+        Fut: std::future::Future<Output = Result<Res, KvError>> + Send + 'static,
+    {
+        type Response = tonic::Response<Res>;
+        type Error = tonic::Status;
+        type Future = tonic::codegen::BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(
+            &mut self,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: tonic::Request<Req>) -> Self::Future {
+            let res = (self.0)(req.into_inner());
+            Box::pin(async move {
+                res.await
+                    .map(tonic::Response::new)
+                    .map_err(|e| tonic::Status::internal(e.to_string()))
+            })
+        }
+    }
+
+    #[cfg(not(madsim))]
+    impl tonic::codegen::Service<tonic::codegen::http::Request<tonic::body::BoxBody>> for KvServer {
+        type Response = tonic::codegen::http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = tonic::codegen::BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(
+            &mut self,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(
+            &mut self,
+            req: tonic::codegen::http::Request<tonic::body::BoxBody>,
+        ) -> Self::Future {
+            use tonic::server::Grpc;
+
+            let kv = self.0.clone();
+            Box::pin(async move {
+                Ok(match req.uri().path() {
+                    "/jepsen.kv.Kv/Get" => {
+                        let get = move |req: pb::GetRequest| {
+                            let kv = kv.clone();
+                            async move {
+                                let res = kv.get(GetRequest { key: req.key }).await?;
+                                Ok(pb::GetResponse { value: res.value })
+                            }
+                        };
+                        Grpc::new(ProstCodec::default())
+                            .unary(Unary(get), req)
+                            .await
+                    }
+                    "/jepsen.kv.Kv/Put" => {
+                        let put = move |req: pb::PutRequest| {
+                            let kv = kv.clone();
+                            async move {
+                                let req = PutRequest {
+                                    key: req.key,
+                                    value: req.value,
+                                };
+                                kv.put(req).await.map(|_| pb::PutResponse {})
+                            }
+                        };
+                        Grpc::new(ProstCodec::default())
+                            .unary(Unary(put), req)
+                            .await
+                    }
+                    "/jepsen.kv.Kv/Txn" => {
+                        let txn = move |req: pb::TxnRequest| {
+                            let kv = kv.clone();
+                            async move {
+                                let ops = req.ops.into_iter().map(TxnOp::try_from);
+                                let req = TxnRequest {
+                                    ops: ops.collect::<Result<_, _>>()?,
+                                };
+                                let res = kv.txn(req).await?;
+                                Ok(pb::TxnResponse {
+                                    ops: res.ops.into_iter().map(pb::TxnOp::from).collect(),
+                                })
+                            }
+                        };
+                        Grpc::new(ProstCodec::default())
+                            .unary(Unary(txn), req)
+                            .await
+                    }
+                    path => tonic::Status::unimplemented(path).into_http(),
+                })
+            })
+        }
+    }
+
+    #[cfg(not(madsim))]
+    #[test]
+    fn test_tonic_kv() {
+        let rows = Arc::new(Mutex::new(HashMap::new()));
+        let server = KvServer(Arc::new(MapService {
+            rows: rows.clone(),
+            error: None,
+        }));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let up = listener.local_addr().unwrap();
+        // nothing listens on the port of a dropped listener
+        let down = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        // a listener closing the connections, which the request may reach
+        let closing = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closing_addr = closing.local_addr().unwrap();
+        std::thread::spawn(move || closing.incoming().for_each(drop));
+
+        let res = crate::runtime::block_on(0, async move {
+            listener.set_nonblocking(true).unwrap();
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            let incoming =
+                tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+            let serve = tonic::transport::Server::builder()
+                .add_service(server)
+                .serve_with_incoming(incoming);
+            tokio::spawn(serve);
+
+            let service = |addr| TonicKvService::connect_lazy(format!("http://{}", addr)).unwrap();
+            // every request falls through the down endpoint
+            let client = GrpcKvClusterClient::new([down, up].map(service));
+            client.put(1, 10).await?;
+            let txn = client
+                .txn(vec![Op::Write(2, 20), Op::Read(1, None), Op::Read(3, None)])
+                .await?;
+            let get = client.get(2).await?;
+            let unavailable = client.get_on(0, 1).await;
+            // the request may have reached the closing endpoint, so it's not
+            // sent again
+            let client = GrpcKvClusterClient::new([closing_addr, up].map(service));
+            let failed = client.put(4, 40).await;
+            Ok::<_, String>((txn, get, unavailable, failed))
+        });
+        let (txn, get, unavailable, failed) = res.unwrap();
+        assert_eq!(
+            txn,
+            [Op::Write(2, 20), Op::Read(1, Some(10)), Op::Read(3, None)]
+        );
+        assert_eq!(get, Some(20));
+        assert!(unavailable.unwrap_err().starts_with("Unavailable"));
+        assert!(failed.is_err());
+        assert_eq!(rows.lock().unwrap().get(&4), None);
+    }
+}
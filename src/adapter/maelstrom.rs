@@ -0,0 +1,380 @@
+//! A client of node binaries written for
+//! [Maelstrom](https://github.com/jepsen-io/maelstrom), speaking its protocol
+//! of JSON messages over stdin and stdout.
+//!
+//! The nodes are spawned as child processes named `n0`, `n1`..., indexed by
+//! [`ServerId`], and initialized in [`ClusterLifecycle::setup`]. The client
+//! also plays the network of Maelstrom: the messages between nodes are routed
+//! from the stdout of the sender to the stdin of the receiver. The stderr of
+//! the nodes is inherited, as Maelstrom nodes log to it.
+//!
+//! The ops follow the workloads of Maelstrom: reads, writes and cas are the
+//! `read`, `write` and `cas` of `lin-kv`, and txns are the `txn` of
+//! `txn-rw-register`. As the nodes are real processes, the test should run
+//! without `--cfg madsim`.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use log::{debug, trace, warn};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient},
+    convert::elle,
+    nemesis::{NemesisClusterClient, ServerId},
+    op::Op,
+};
+
+/// The default timeout of a request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// The id of the client in the messages.
+const CLIENT_ID: &str = "c0";
+/// The error code of a missing key.
+const KEY_DOES_NOT_EXIST: u64 = 20;
+/// The error code of a cas whose expected value mismatches.
+const PRECONDITION_FAILED: u64 = 22;
+
+/// The requests waiting for replies, by message id.
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+
+/// The receiver of a message.
+#[derive(Debug, PartialEq, Eq)]
+enum Dest {
+    Node(ServerId),
+    /// A reply to the client, with the id of the request.
+    Client(u64),
+    Unknown,
+}
+
+fn node_name(id: ServerId) -> String {
+    format!("n{}", id)
+}
+
+fn dest(msg: &Value) -> Dest {
+    let dest = msg["dest"].as_str().unwrap_or_default();
+    if let Some(id) = dest.strip_prefix('n').and_then(|id| id.parse().ok()) {
+        return Dest::Node(id);
+    }
+    match msg["body"]["in_reply_to"].as_u64() {
+        Some(id) if dest == CLIENT_ID => Dest::Client(id),
+        _ => Dest::Unknown,
+    }
+}
+
+/// An error reply of Maelstrom.
+fn error_code(body: &Value) -> Option<u64> {
+    (body["type"] == "error").then(|| body["code"].as_u64().unwrap_or_default())
+}
+
+/// Check the type of the reply, returns an error for the error replies.
+fn expect_reply(body: Value, type_: &str) -> Result<Value, String> {
+    if body["type"] == type_ {
+        return Ok(body);
+    }
+    match error_code(&body) {
+        Some(code) => Err(format!("error {}: {}", code, body["text"])),
+        None => Err(format!("expected {}, got {}", type_, body)),
+    }
+}
+
+/// Write a message as a line to a node.
+fn send_line(stdin: &Mutex<ChildStdin>, msg: &str) -> Result<(), String> {
+    let mut stdin = stdin.lock().unwrap();
+    writeln!(stdin, "{}", msg)
+        .and_then(|_| stdin.flush())
+        .map_err(|e| format!("failed to write to node: {}", e))
+}
+
+/// Route the messages from the stdout of a node, until it exits.
+fn route(from: ServerId, stdout: impl BufRead, nodes: &[Mutex<ChildStdin>], pending: &Pending) {
+    for line in stdout.lines() {
+        let Ok(line) = line else { break };
+        let msg = match serde_json::from_str::<Value>(&line) {
+            Ok(msg) => msg,
+            Err(err) => {
+                warn!(
+                    "invalid message from {}: {}: {}",
+                    node_name(from),
+                    err,
+                    line
+                );
+                continue;
+            }
+        };
+        trace!("maelstrom message: {}", line);
+        match dest(&msg) {
+            Dest::Node(id) => match nodes.get(id as usize) {
+                Some(stdin) => {
+                    if let Err(err) = send_line(stdin, &line) {
+                        debug!("failed to route to {}: {}", node_name(id), err);
+                    }
+                }
+                None => warn!("message to unknown node: {}", line),
+            },
+            Dest::Client(id) => match pending.lock().unwrap().remove(&id) {
+                Some(tx) => _ = tx.send(msg["body"].clone()),
+                // the request has timed out
+                None => debug!("late reply: {}", line),
+            },
+            Dest::Unknown => warn!("message to unknown receiver: {}", line),
+        }
+    }
+    debug!("stdout of {} closed", node_name(from));
+}
+
+/// A cluster client of Maelstrom nodes.
+pub struct MaelstromClient {
+    /// The stdin of the nodes, indexed by [`ServerId`].
+    nodes: Arc<Vec<Mutex<ChildStdin>>>,
+    children: Mutex<Vec<Child>>,
+    pending: Pending,
+    next_msg_id: AtomicU64,
+    /// The node of the next request, requests are spread over nodes.
+    next_node: AtomicUsize,
+    timeout: Duration,
+}
+
+impl MaelstromClient {
+    /// Spawn `n` nodes by the commands made by `command`, which gets the id
+    /// of the node.
+    pub fn spawn(n: usize, command: impl Fn(ServerId) -> Command) -> Result<Self, String> {
+        let mut children = vec![];
+        let mut stdins = vec![];
+        let mut stdouts = vec![];
+        for id in 0..n as ServerId {
+            let mut child = command(id)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("failed to spawn {}: {}", node_name(id), e))?;
+            stdins.push(Mutex::new(child.stdin.take().expect("stdin is piped")));
+            stdouts.push(child.stdout.take().expect("stdout is piped"));
+            children.push(child);
+        }
+        let nodes = Arc::new(stdins);
+        let pending = Pending::default();
+        for (id, stdout) in stdouts.into_iter().enumerate() {
+            let (nodes, pending) = (nodes.clone(), pending.clone());
+            std::thread::spawn(move || {
+                route(id as ServerId, BufReader::new(stdout), &nodes, &pending)
+            });
+        }
+        Ok(Self {
+            nodes,
+            children: Mutex::new(children),
+            pending,
+            next_msg_id: AtomicU64::new(1),
+            next_node: AtomicUsize::new(0),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Set the timeout of a request, 5s by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn pick(&self, node: Option<ServerId>) -> ServerId {
+        node.unwrap_or_else(|| {
+            (self.next_node.fetch_add(1, Ordering::Relaxed) % self.nodes.len().max(1)) as _
+        })
+    }
+
+    /// Send a request to the node, returns the body of the reply.
+    async fn request(&self, node: ServerId, mut body: Value) -> Result<Value, String> {
+        let stdin = self
+            .nodes
+            .get(node as usize)
+            .ok_or_else(|| format!("no maelstrom node {}", node))?;
+        let msg_id = self.next_msg_id.fetch_add(1, Ordering::Relaxed);
+        body["msg_id"] = msg_id.into();
+        let msg = json!({ "src": CLIENT_ID, "dest": node_name(node), "body": body });
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(msg_id, tx);
+        let res = match send_line(stdin, &msg.to_string()) {
+            Ok(()) => match madsim::time::timeout(self.timeout, rx).await {
+                Ok(Ok(reply)) => Ok(reply),
+                Ok(Err(_)) => Err(format!("{} exited", node_name(node))),
+                Err(_) => Err(format!("request to {} timed out", node_name(node))),
+            },
+            Err(err) => Err(err),
+        };
+        self.pending.lock().unwrap().remove(&msg_id);
+        res
+    }
+
+    async fn read(&self, node: Option<ServerId>, key: u64) -> Result<Option<u64>, String> {
+        let body = json!({ "type": "read", "key": key });
+        let reply = self.request(self.pick(node), body).await?;
+        if error_code(&reply) == Some(KEY_DOES_NOT_EXIST) {
+            return Ok(None);
+        }
+        let reply = expect_reply(reply, "read_ok")?;
+        match reply["value"].as_u64() {
+            Some(value) => Ok(Some(value)),
+            None => Err(format!("unexpected value {}", reply["value"])),
+        }
+    }
+
+    async fn write(&self, node: Option<ServerId>, key: u64, value: u64) -> Result<(), String> {
+        let body = json!({ "type": "write", "key": key, "value": value });
+        let reply = self.request(self.pick(node), body).await?;
+        expect_reply(reply, "write_ok").map(|_| ())
+    }
+
+    async fn compare_and_set(
+        &self,
+        node: Option<ServerId>,
+        key: u64,
+        expect: u64,
+        new: u64,
+    ) -> Result<bool, String> {
+        let body = json!({ "type": "cas", "key": key, "from": expect, "to": new });
+        let reply = self.request(self.pick(node), body).await?;
+        match error_code(&reply) {
+            Some(KEY_DOES_NOT_EXIST | PRECONDITION_FAILED) => Ok(false),
+            _ => expect_reply(reply, "cas_ok").map(|_| true),
+        }
+    }
+
+    async fn transact(&self, node: Option<ServerId>, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let txn = elle::op_to_json(&Op::Txn(ops)).map_err(|e| e.to_string())?;
+        let body = json!({ "type": "txn", "txn": txn });
+        let reply = self.request(self.pick(node), body).await?;
+        let reply = expect_reply(reply, "txn_ok")?;
+        match elle::op_from_json(&reply["txn"]) {
+            Ok(Op::Txn(ops)) => Ok(ops),
+            _ => Err(format!("unexpected txn {}", reply["txn"])),
+        }
+    }
+}
+
+impl Drop for MaelstromClient {
+    fn drop(&mut self) {
+        for child in self.children.get_mut().unwrap() {
+            _ = child.kill();
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ElleRwClusterClient for MaelstromClient {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        self.read(None, key).await
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        self.write(None, key, value).await
+    }
+
+    async fn cas(&self, key: u64, expect: u64, new: u64) -> Result<bool, String> {
+        self.compare_and_set(None, key, expect, new).await
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        self.transact(None, ops).await
+    }
+
+    async fn get_on(&self, node: ServerId, key: u64) -> Result<Option<u64>, String> {
+        self.read(Some(node), key).await
+    }
+
+    async fn put_on(&self, node: ServerId, key: u64, value: u64) -> Result<(), String> {
+        self.write(Some(node), key, value).await
+    }
+
+    async fn cas_on(
+        &self,
+        node: ServerId,
+        key: u64,
+        expect: u64,
+        new: u64,
+    ) -> Result<bool, String> {
+        self.compare_and_set(Some(node), key, expect, new).await
+    }
+
+    async fn txn_on(&self, node: ServerId, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        self.transact(Some(node), ops).await
+    }
+}
+
+/// The nodes are initialized in setup, and killed in teardown.
+#[async_trait::async_trait]
+impl ClusterLifecycle for MaelstromClient {
+    async fn setup(&self) -> Result<(), String> {
+        let node_ids: Vec<_> = (0..self.nodes.len() as ServerId).map(node_name).collect();
+        for id in 0..self.nodes.len() as ServerId {
+            let body = json!({ "type": "init", "node_id": node_name(id), "node_ids": node_ids });
+            expect_reply(self.request(id, body).await?, "init_ok")?;
+        }
+        Ok(())
+    }
+
+    async fn teardown(&self) -> Result<(), String> {
+        for child in self.children.lock().unwrap().iter_mut() {
+            child
+                .kill()
+                .map_err(|e| format!("failed to kill node: {}", e))?;
+            _ = child.wait();
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl NemesisClusterClient for MaelstromClient {
+    fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    /// Maelstrom has no notion of leader, so the first node is assumed.
+    async fn get_leader_without_term(&self) -> ServerId {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maelstrom_dest() {
+        let msg = json!({ "src": "n1", "dest": "n2", "body": { "type": "gossip" } });
+        assert_eq!(dest(&msg), Dest::Node(2));
+        let msg = json!({ "src": "n1", "dest": "c0", "body": { "in_reply_to": 7 } });
+        assert_eq!(dest(&msg), Dest::Client(7));
+        let msg = json!({ "src": "n1", "dest": "c0", "body": {} });
+        assert_eq!(dest(&msg), Dest::Unknown);
+        let msg = json!({ "src": "n1", "dest": "seq-kv", "body": { "in_reply_to": 7 } });
+        assert_eq!(dest(&msg), Dest::Unknown);
+    }
+
+    #[test]
+    fn test_maelstrom_reply() {
+        let ok = json!({ "type": "read_ok", "value": 3 });
+        assert_eq!(expect_reply(ok.clone(), "read_ok"), Ok(ok.clone()));
+        assert!(expect_reply(ok, "write_ok").is_err());
+        let err = json!({ "type": "error", "code": 22, "text": "mismatch" });
+        assert_eq!(error_code(&err), Some(PRECONDITION_FAILED));
+        assert_eq!(
+            expect_reply(err, "cas_ok"),
+            Err("error 22: \"mismatch\"".to_string())
+        );
+    }
+}
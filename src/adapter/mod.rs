@@ -0,0 +1,20 @@
+//! Adapters that implement [`ElleRwClusterClient`] and
+//! [`NemesisClusterClient`] for common databases, so that jepsen-rs can be
+//! pointed at a real cluster without writing a client. Each adapter is behind
+//! a feature of its name.
+//!
+//! [`ElleRwClusterClient`]: crate::client::ElleRwClusterClient
+//! [`NemesisClusterClient`]: crate::nemesis::NemesisClusterClient
+
+#[cfg(feature = "etcd")]
+pub mod etcd;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "maelstrom")]
+pub mod maelstrom;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "sql")]
+pub mod sql;
+#[cfg(feature = "tikv")]
+pub mod tikv;
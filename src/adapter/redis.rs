@@ -0,0 +1,373 @@
+//! A client of redis over RESP, which needs no extra dependency. Reads and
+//! writes are `GET` and `SET`, and txns are `MULTI` ... `EXEC`.
+//!
+//! Keys are stored as `{prefix}{key}` and values as decimal strings. All the
+//! ops are sent to the primary set by [`RedisClusterClient::with_primary`],
+//! as replicas are read-only. The nemeses are executed on the redis processes
+//! by the [`OsClusterClient`] given by [`RedisClusterClient::with_supervisor`].
+
+use std::{net::SocketAddr, time::Duration};
+
+use log::{trace, warn};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient},
+    nemesis::{os::OsClusterClient, NemesisClusterClient, ServerId},
+    op::Op,
+};
+
+/// The default prefix of keys.
+const DEFAULT_PREFIX: &str = "jepsen:";
+/// The default timeout of a request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A reply of RESP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Reply {
+    Status(String),
+    Error(String),
+    Int(i64),
+    /// `None` for the null bulk string.
+    Bulk(Option<Vec<u8>>),
+    /// `None` for the null array, e.g. the `EXEC` of an aborted txn.
+    Array(Option<Vec<Reply>>),
+}
+
+/// A cluster client of redis.
+pub struct RedisClusterClient {
+    /// The addresses of the nodes, indexed by [`ServerId`].
+    nodes: Vec<SocketAddr>,
+    primary: ServerId,
+    prefix: String,
+    timeout: Duration,
+    supervisor: Option<Box<dyn OsClusterClient + Send + Sync>>,
+}
+
+impl RedisClusterClient {
+    pub fn new(nodes: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            nodes: nodes.into_iter().collect(),
+            primary: 0,
+            prefix: DEFAULT_PREFIX.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            supervisor: None,
+        }
+    }
+
+    /// Set the node that receives the ops, the first one by default.
+    pub fn with_primary(mut self, primary: ServerId) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Set the prefix of keys, `jepsen:` by default.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the timeout of a request, 5s by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the supervisor of the redis processes, which executes the nemeses.
+    pub fn with_supervisor(
+        mut self,
+        supervisor: impl OsClusterClient + Send + Sync + 'static,
+    ) -> Self {
+        self.supervisor = Some(Box::new(supervisor));
+        self
+    }
+
+    fn key(&self, key: u64) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Send the commands to the node in one connection, returns a reply for
+    /// every command.
+    async fn send(&self, node: ServerId, commands: &[Vec<String>]) -> Result<Vec<Reply>, String> {
+        let addr = *self
+            .nodes
+            .get(node as usize)
+            .ok_or_else(|| format!("no redis node {}", node))?;
+        let res = madsim::time::timeout(self.timeout, send(addr, commands)).await;
+        res.map_err(|_| format!("request to {} timed out", addr))?
+    }
+}
+
+async fn send(addr: SocketAddr, commands: &[Vec<String>]) -> Result<Vec<Reply>, String> {
+    let mut request = vec![];
+    for command in commands {
+        encode_command(command, &mut request);
+    }
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .map_err(|e| format!("failed to connect {}: {}", addr, e))?;
+    stream
+        .write_all(&request)
+        .await
+        .map_err(|e| format!("failed to send to {}: {}", addr, e))?;
+    let mut buf = vec![];
+    let mut replies = vec![];
+    let mut chunk = [0; 4096];
+    while replies.len() < commands.len() {
+        while let Some((reply, len)) = parse_reply(&buf)? {
+            buf.drain(..len);
+            replies.push(reply);
+        }
+        if replies.len() == commands.len() {
+            break;
+        }
+        let n = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|e| format!("failed to receive from {}: {}", addr, e))?;
+        if n == 0 {
+            return Err(format!("connection to {} closed", addr));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    trace!("redis {} replies: {:?}", addr, replies);
+    Ok(replies)
+}
+
+/// Encode a command as an array of bulk strings.
+fn encode_command(command: &[String], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("*{}\r\n", command.len()).as_bytes());
+    for arg in command {
+        out.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+    }
+}
+
+/// Parse a reply from the head of `buf`. Returns `None` if the reply is not
+/// complete yet, or the reply and its length.
+fn parse_reply(buf: &[u8]) -> Result<Option<(Reply, usize)>, String> {
+    let Some(end) = buf.windows(2).position(|w| w == b"\r\n") else {
+        return Ok(None);
+    };
+    let Some((kind, line)) = buf[..end].split_first() else {
+        return Err("empty RESP line".to_string());
+    };
+    let line = String::from_utf8_lossy(line).into_owned();
+    let int = |line: &str| {
+        line.parse::<i64>()
+            .map_err(|_| format!("invalid RESP integer `{}`", line))
+    };
+    let mut len = end + 2;
+    let reply = match kind {
+        b'+' => Reply::Status(line),
+        b'-' => Reply::Error(line),
+        b':' => Reply::Int(int(&line)?),
+        b'$' => match int(&line)? {
+            -1 => Reply::Bulk(None),
+            n => {
+                let n = n as usize;
+                if buf.len() < len + n + 2 {
+                    return Ok(None);
+                }
+                let data = buf[len..len + n].to_vec();
+                len += n + 2;
+                Reply::Bulk(Some(data))
+            }
+        },
+        b'*' => match int(&line)? {
+            -1 => Reply::Array(None),
+            n => {
+                let mut items = vec![];
+                for _ in 0..n {
+                    let Some((item, item_len)) = parse_reply(&buf[len..])? else {
+                        return Ok(None);
+                    };
+                    items.push(item);
+                    len += item_len;
+                }
+                Reply::Array(Some(items))
+            }
+        },
+        _ => return Err(format!("unknown RESP type `{}`", *kind as char)),
+    };
+    Ok(Some((reply, len)))
+}
+
+/// The value of a `GET` reply.
+fn get_value(reply: Reply) -> Result<Option<u64>, String> {
+    match reply {
+        Reply::Bulk(None) => Ok(None),
+        Reply::Bulk(Some(value)) => {
+            let value = String::from_utf8_lossy(&value);
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("unexpected value `{}`", value))
+        }
+        Reply::Error(err) => Err(err),
+        reply => Err(format!("unexpected reply of GET: {:?}", reply)),
+    }
+}
+
+fn check_ok(reply: Reply) -> Result<(), String> {
+    match reply {
+        Reply::Error(err) => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait::async_trait]
+impl ElleRwClusterClient for RedisClusterClient {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        let command = vec!["GET".to_string(), self.key(key)];
+        let mut replies = self.send(self.primary, &[command]).await?;
+        get_value(replies.remove(0))
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        let command = vec!["SET".to_string(), self.key(key), value.to_string()];
+        let mut replies = self.send(self.primary, &[command]).await?;
+        check_ok(replies.remove(0))
+    }
+
+    async fn incr(&self, key: u64, delta: i64) -> Result<(), String> {
+        let command = vec!["INCRBY".to_string(), self.key(key), delta.to_string()];
+        let mut replies = self.send(self.primary, &[command]).await?;
+        check_ok(replies.remove(0))
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let mut commands = vec![vec!["MULTI".to_string()]];
+        for op in &ops {
+            commands.push(match op {
+                Op::Read(key, _) => vec!["GET".to_string(), self.key(*key)],
+                Op::Write(key, value) => {
+                    vec!["SET".to_string(), self.key(*key), value.to_string()]
+                }
+                Op::Incr(key, delta) => {
+                    vec!["INCRBY".to_string(), self.key(*key), delta.to_string()]
+                }
+                Op::Cas(..)
+                | Op::Delete(_)
+                | Op::Append(..)
+                | Op::ReadList(..)
+                | Op::BatchRead(_)
+                | Op::ScanRange(..) => {
+                    return Err("only reads and writes are supported in txns".to_string())
+                }
+                Op::Txn(_) => return Err("txns cannot be nested".to_string()),
+            });
+        }
+        commands.push(vec!["EXEC".to_string()]);
+        let replies = self.send(self.primary, &commands).await?;
+        // the queued commands reply `QUEUED` or an error
+        for reply in &replies[..replies.len() - 1] {
+            if let Reply::Error(err) = reply {
+                return Err(err.clone());
+            }
+        }
+        let results = match replies.into_iter().last() {
+            Some(Reply::Array(Some(results))) if results.len() == ops.len() => results,
+            Some(Reply::Array(None)) => return Err("txn aborted".to_string()),
+            reply => return Err(format!("unexpected reply of EXEC: {:?}", reply)),
+        };
+        ops.into_iter()
+            .zip(results)
+            .map(|(op, reply)| match op {
+                Op::Read(key, _) => Ok(Op::Read(key, get_value(reply)?)),
+                op => check_ok(reply).map(|_| op),
+            })
+            .collect()
+    }
+}
+
+impl ClusterLifecycle for RedisClusterClient {}
+
+#[async_trait::async_trait]
+impl NemesisClusterClient for RedisClusterClient {
+    fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    /// The first node whose `ROLE` is `master`.
+    async fn get_leader_without_term(&self) -> ServerId {
+        for node in 0..self.nodes.len() as ServerId {
+            match self.send(node, &[vec!["ROLE".to_string()]]).await {
+                Ok(replies) => match &replies[0] {
+                    Reply::Array(Some(role)) if role.first() == Some(&bulk("master")) => {
+                        return node;
+                    }
+                    _ => {}
+                },
+                Err(err) => trace!("failed to get role of node {}: {}", node, err),
+            }
+        }
+        warn!(
+            "no redis node is the master, assume it's the primary {}",
+            self.primary
+        );
+        self.primary
+    }
+
+    fn os(&self) -> Option<&(dyn OsClusterClient + Sync)> {
+        self.supervisor.as_deref().map(|s| s as _)
+    }
+}
+
+fn bulk(s: &str) -> Reply {
+    Reply::Bulk(Some(s.as_bytes().to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_command() {
+        let mut out = vec![];
+        encode_command(
+            &["SET".to_string(), "k".to_string(), "10".to_string()],
+            &mut out,
+        );
+        assert_eq!(out, b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$2\r\n10\r\n");
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let buf = b"+OK\r\n$2\r\n12\r\n*3\r\n$-1\r\n:1\r\n-ERR bad\r\n*-1\r\n";
+        let mut pos = 0;
+        let mut replies = vec![];
+        while let Some((reply, len)) = parse_reply(&buf[pos..]).unwrap() {
+            replies.push(reply);
+            pos += len;
+        }
+        assert_eq!(pos, buf.len());
+        assert_eq!(
+            replies,
+            vec![
+                Reply::Status("OK".to_string()),
+                bulk("12"),
+                Reply::Array(Some(vec![
+                    Reply::Bulk(None),
+                    Reply::Int(1),
+                    Reply::Error("ERR bad".to_string())
+                ])),
+                Reply::Array(None),
+            ]
+        );
+        // incomplete replies
+        for buf in [&b"$2\r\n1"[..], b"*2\r\n:1\r\n", b"+OK"] {
+            assert_eq!(parse_reply(buf).unwrap(), None);
+        }
+        assert!(parse_reply(b"?\r\n").is_err());
+        assert_eq!(get_value(bulk("12")).unwrap(), Some(12));
+        assert!(get_value(Reply::Int(1)).is_err());
+    }
+}
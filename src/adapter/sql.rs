@@ -0,0 +1,436 @@
+//! A client of SQL databases (Postgres and MySQL), which maps the registers of
+//! elle onto the rows of a table, and runs every txn in a SQL transaction of a
+//! configurable isolation level.
+//!
+//! The adapter generates SQL, which is run by a [`SqlConnector`]. The
+//! [`SqlxConnector`] runs it on a `sqlx` pool of the database at a URL, other
+//! drivers are used by implementing the trait.
+//!
+//! ```ignore
+//! let url = "postgres://postgres@127.0.0.1/jepsen";
+//! let connector = SqlxConnector::connect(url).await?;
+//! let client = SqlClusterClient::new(connector, SqlDialect::from_url(url)?);
+//! ```
+//!
+//! The `sqlx` pool runs on tokio, so the connector does not work under
+//! madsim, where the database cannot be simulated anyway.
+
+use log::trace;
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient},
+    nemesis::{NemesisClusterClient, ServerId},
+    op::Op,
+};
+
+/// Runs SQL statements over a driver.
+#[async_trait::async_trait]
+pub trait SqlConnector: Send + Sync {
+    /// Run the statements in order on one connection, and return the first
+    /// column of the first row of every statement, or `None` if it returns no
+    /// row. If a statement fails, return the error and drop the connection,
+    /// so the open transaction is rolled back.
+    async fn run(&self, statements: &[String]) -> Result<Vec<Option<u64>>, String>;
+}
+
+/// The SQL dialect, which decides the syntax of transactions and upserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDialect {
+    Postgres,
+    MySql,
+}
+
+impl SqlDialect {
+    /// The dialect of a database URL, by its scheme.
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        match url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("postgres" | "postgresql") => Ok(SqlDialect::Postgres),
+            Some("mysql" | "mariadb") => Ok(SqlDialect::MySql),
+            _ => Err(format!("no SQL dialect of the URL {}", url)),
+        }
+    }
+}
+
+/// The isolation level of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// The table that stores the registers, one row per key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqlSchema {
+    pub table: String,
+    pub key_column: String,
+    pub value_column: String,
+}
+
+impl Default for SqlSchema {
+    fn default() -> Self {
+        Self {
+            table: "jepsen_registers".to_string(),
+            key_column: "id".to_string(),
+            value_column: "val".to_string(),
+        }
+    }
+}
+
+impl SqlSchema {
+    /// Check the names are plain identifiers, as they are put into the
+    /// statements as is.
+    fn validate(&self) -> Result<(), String> {
+        for name in [&self.table, &self.key_column, &self.value_column] {
+            let valid = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(format!("`{}` is not a plain SQL identifier", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Picks the isolation level of a txn.
+type IsolationFn = Box<dyn Fn(&[Op]) -> IsolationLevel + Send + Sync>;
+
+/// A cluster client of a SQL database.
+pub struct SqlClusterClient<C> {
+    connector: C,
+    dialect: SqlDialect,
+    schema: SqlSchema,
+    isolation: IsolationFn,
+}
+
+impl<C: SqlConnector> SqlClusterClient<C> {
+    /// A client of the default schema, whose txns are serializable.
+    pub fn new(connector: C, dialect: SqlDialect) -> Self {
+        Self {
+            connector,
+            dialect,
+            schema: SqlSchema::default(),
+            isolation: Box::new(|_| IsolationLevel::Serializable),
+        }
+    }
+
+    /// Set the schema of the table. Returns an error if the names are not
+    /// plain identifiers.
+    pub fn with_schema(mut self, schema: SqlSchema) -> Result<Self, String> {
+        schema.validate()?;
+        self.schema = schema;
+        Ok(self)
+    }
+
+    /// Run every txn in the given isolation level.
+    pub fn with_isolation(self, level: IsolationLevel) -> Self {
+        self.with_isolation_fn(move |_| level)
+    }
+
+    /// Decide the isolation level of every txn by its ops, e.g. to mix
+    /// serializable and read committed txns in a test.
+    pub fn with_isolation_fn(
+        mut self,
+        f: impl Fn(&[Op]) -> IsolationLevel + Send + Sync + 'static,
+    ) -> Self {
+        self.isolation = Box::new(f);
+        self
+    }
+
+    /// The statement creating the table if it does not exist.
+    pub fn create_table_sql(&self) -> String {
+        let SqlSchema {
+            table,
+            key_column,
+            value_column,
+        } = &self.schema;
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ({} BIGINT PRIMARY KEY, {} BIGINT NOT NULL)",
+            table, key_column, value_column
+        )
+    }
+
+    /// The statements of a txn, wrapped by the statements starting and
+    /// committing the transaction.
+    fn statements(&self, ops: &[Op]) -> Result<Vec<String>, String> {
+        let SqlSchema {
+            table,
+            key_column: k,
+            value_column: v,
+        } = &self.schema;
+        let level = (self.isolation)(ops).as_sql();
+        let mut out = match self.dialect {
+            SqlDialect::Postgres => vec![
+                "BEGIN".to_string(),
+                format!("SET TRANSACTION ISOLATION LEVEL {}", level),
+            ],
+            SqlDialect::MySql => vec![
+                format!("SET TRANSACTION ISOLATION LEVEL {}", level),
+                "START TRANSACTION".to_string(),
+            ],
+        };
+        for op in ops {
+            out.push(match (op, self.dialect) {
+                (Op::Read(key, _), _) => {
+                    format!("SELECT {} FROM {} WHERE {} = {}", v, table, k, key)
+                }
+                (Op::Write(key, value), SqlDialect::Postgres) => format!(
+                    "INSERT INTO {t} ({k}, {v}) VALUES ({}, {}) ON CONFLICT ({k}) DO UPDATE SET {v} = EXCLUDED.{v}",
+                    key, value, t = table, k = k, v = v
+                ),
+                (Op::Write(key, value), SqlDialect::MySql) => format!(
+                    "INSERT INTO {t} ({k}, {v}) VALUES ({}, {}) ON DUPLICATE KEY UPDATE {v} = VALUES({v})",
+                    key, value, t = table, k = k, v = v
+                ),
+                (
+                    Op::Cas(..)
+                    | Op::Delete(_)
+                    | Op::Incr(..)
+                    | Op::Append(..)
+                    | Op::ReadList(..)
+                    | Op::BatchRead(_)
+                    | Op::ScanRange(..),
+                    _,
+                ) => {
+                    return Err("only reads and writes are supported in txns".to_string());
+                }
+                (Op::Txn(_), _) => return Err("txns cannot be nested".to_string()),
+            });
+        }
+        out.push("COMMIT".to_string());
+        Ok(out)
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: SqlConnector> ElleRwClusterClient for SqlClusterClient<C> {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        match self.txn(vec![Op::Read(key, None)]).await?.pop() {
+            Some(Op::Read(_, value)) => Ok(value),
+            _ => unreachable!("the txn returns the read"),
+        }
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        self.txn(vec![Op::Write(key, value)]).await.map(|_| ())
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let statements = self.statements(&ops)?;
+        trace!("run sql: {:?}", statements);
+        let rows = self.connector.run(&statements).await?;
+        if rows.len() != statements.len() {
+            return Err(format!(
+                "the connector returns {} results for {} statements",
+                rows.len(),
+                statements.len()
+            ));
+        }
+        // skip the statements starting the transaction
+        let rows = &rows[2..];
+        Ok(ops
+            .into_iter()
+            .zip(rows)
+            .map(|(op, row)| match op {
+                Op::Read(key, _) => Op::Read(key, *row),
+                op => op,
+            })
+            .collect())
+    }
+}
+
+/// A [`SqlConnector`] of a `sqlx` pool of Postgres or MySQL.
+pub struct SqlxConnector {
+    pool: sqlx::AnyPool,
+}
+
+impl SqlxConnector {
+    /// Connect a pool to the database at the URL. A connection is rolled back
+    /// when released, so a txn that failed or timed out leaves no open
+    /// transaction in the pool.
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        sqlx::any::install_default_drivers();
+        let pool = sqlx::any::AnyPoolOptions::new()
+            .after_release(|conn, _| {
+                Box::pin(async move {
+                    sqlx::query("ROLLBACK").execute(conn).await?;
+                    Ok(true)
+                })
+            })
+            .connect(url)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait::async_trait]
+impl SqlConnector for SqlxConnector {
+    async fn run(&self, statements: &[String]) -> Result<Vec<Option<u64>>, String> {
+        use sqlx::Row;
+
+        let mut conn = self.pool.acquire().await.map_err(|e| e.to_string())?;
+        let mut out = Vec::with_capacity(statements.len());
+        for s in statements {
+            let row = sqlx::query(s)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| e.to_string())?;
+            out.push(
+                row.map(|row| row.try_get::<i64, _>(0).map(|v| v as u64))
+                    .transpose()
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        Ok(out)
+    }
+}
+
+/// The table is created in setup.
+#[async_trait::async_trait]
+impl<C: SqlConnector> ClusterLifecycle for SqlClusterClient<C> {
+    async fn setup(&self) -> Result<(), String> {
+        self.connector.run(&[self.create_table_sql()]).await?;
+        Ok(())
+    }
+}
+
+/// A SQL database is nemesized as a single server.
+#[async_trait::async_trait]
+impl<C: SqlConnector> NemesisClusterClient for SqlClusterClient<C> {
+    fn size(&self) -> usize {
+        1
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    async fn get_leader_without_term(&self) -> ServerId {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use super::*;
+
+    /// Runs the statements on a map, understanding only the generated ones.
+    #[derive(Default)]
+    struct MapConnector {
+        rows: Mutex<HashMap<u64, u64>>,
+        log: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl SqlConnector for MapConnector {
+        async fn run(&self, statements: &[String]) -> Result<Vec<Option<u64>>, String> {
+            self.log.lock().unwrap().extend(statements.iter().cloned());
+            let mut rows = self.rows.lock().unwrap();
+            Ok(statements
+                .iter()
+                .map(|s| {
+                    let nums: Vec<u64> = s
+                        .split(|c: char| !c.is_ascii_digit())
+                        .filter_map(|x| x.parse().ok())
+                        .collect();
+                    if s.starts_with("SELECT") {
+                        rows.get(&nums[0]).copied()
+                    } else {
+                        if s.starts_with("INSERT") {
+                            rows.insert(nums[0], nums[1]);
+                        }
+                        None
+                    }
+                })
+                .collect())
+        }
+    }
+
+    #[madsim::test]
+    async fn test_sql_txn() {
+        let client = SqlClusterClient::new(MapConnector::default(), SqlDialect::Postgres)
+            .with_isolation_fn(|ops| {
+                if ops.len() > 1 {
+                    IsolationLevel::Serializable
+                } else {
+                    IsolationLevel::ReadCommitted
+                }
+            });
+        client.setup().await.unwrap();
+        client.put(1, 10).await.unwrap();
+        assert_eq!(client.get(1).await.unwrap(), Some(10));
+        assert_eq!(client.get(2).await.unwrap(), None);
+        let res = client
+            .txn(vec![Op::Write(2, 20), Op::Read(2, None), Op::Read(1, None)])
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            vec![
+                Op::Write(2, 20),
+                Op::Read(2, Some(20)),
+                Op::Read(1, Some(10))
+            ]
+        );
+        let log = client.connector.log.lock().unwrap();
+        assert!(log[0].starts_with("CREATE TABLE"));
+        assert_eq!(log[2], "SET TRANSACTION ISOLATION LEVEL READ COMMITTED");
+        assert_eq!(
+            log[3],
+            "INSERT INTO jepsen_registers (id, val) VALUES (1, 10) ON CONFLICT (id) DO UPDATE SET val = EXCLUDED.val"
+        );
+        assert!(log.contains(&"SET TRANSACTION ISOLATION LEVEL SERIALIZABLE".to_string()));
+    }
+
+    #[test]
+    fn test_sql_schema() {
+        let client = SqlClusterClient::new(MapConnector::default(), SqlDialect::MySql);
+        let schema = SqlSchema {
+            table: "kv; DROP TABLE kv".to_string(),
+            ..Default::default()
+        };
+        assert!(client.with_schema(schema).is_err());
+        let client = SqlClusterClient::new(MapConnector::default(), SqlDialect::MySql)
+            .with_schema(SqlSchema {
+                table: "kv".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            client.create_table_sql(),
+            "CREATE TABLE IF NOT EXISTS kv (id BIGINT PRIMARY KEY, val BIGINT NOT NULL)"
+        );
+        let statements = client.statements(&[Op::Write(1, 2)]).unwrap();
+        assert_eq!(statements[1], "START TRANSACTION");
+        assert!(statements[2].ends_with("ON DUPLICATE KEY UPDATE val = VALUES(val)"));
+    }
+
+    #[test]
+    fn test_sql_dialect_from_url() {
+        assert_eq!(
+            SqlDialect::from_url("postgres://u@localhost/db"),
+            Ok(SqlDialect::Postgres)
+        );
+        assert_eq!(
+            SqlDialect::from_url("mysql://u@localhost:3306/db"),
+            Ok(SqlDialect::MySql)
+        );
+        assert!(SqlDialect::from_url("sqlite::memory:").is_err());
+    }
+}
@@ -0,0 +1,656 @@
+//! A client of TiKV, which runs the single-key ops and multi-key reads on the
+//! raw API and the txns on the transactional API.
+//!
+//! [`TikvClient`] connects it by the TiKV client to the cluster of the PD
+//! endpoints, and other clients can be plugged in by [`TikvConnector`].
+//!
+//! ```ignore
+//! let connector = TikvClient::connect(vec!["127.0.0.1:2379".to_string()]).await?;
+//! let client = TikvClusterClient::new(connector, 3).with_supervisor(servers);
+//! ```
+//!
+//! Keys are stored as `{prefix}{key}` with the key zero-padded to 20 digits,
+//! so that the order of keys is kept by the raw scan. Values are decimal
+//! strings. The nemeses are executed on the tikv-server processes, e.g. by
+//! [`TikvServers`].
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    process::Command,
+};
+
+use log::trace;
+use tikv_client::{
+    CheckLevel, KvPair, RawClient, Transaction, TransactionClient, TransactionOptions,
+};
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient, CAS_MISMATCH},
+    nemesis::{os::OsClusterClient, NemesisClusterClient, ServerId},
+    op::Op,
+};
+
+/// The default prefix of keys.
+const DEFAULT_PREFIX: &str = "jepsen/";
+
+/// The raw and transactional APIs of TiKV, implemented by [`TikvClient`].
+#[async_trait::async_trait]
+pub trait TikvConnector: Send + Sync {
+    async fn raw_get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>, String>;
+    async fn raw_put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), String>;
+    async fn raw_delete(&self, key: Vec<u8>) -> Result<(), String>;
+    /// Returns the pairs of the existing keys, in any order.
+    async fn raw_batch_get(&self, keys: Vec<Vec<u8>>) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String>;
+    /// Returns the pairs in `[start, end)`, ordered by key.
+    async fn raw_scan(
+        &self,
+        start: Vec<u8>,
+        end: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String>;
+    /// Set the key to `new` if its value is `previous`, returns whether it's
+    /// swapped.
+    async fn raw_compare_and_swap(
+        &self,
+        key: Vec<u8>,
+        previous: Option<Vec<u8>>,
+        new: Vec<u8>,
+    ) -> Result<bool, String>;
+    /// Begin a transaction.
+    async fn begin(&self) -> Result<Box<dyn TikvTransaction>, String>;
+}
+
+/// A transaction of TiKV.
+#[async_trait::async_trait]
+pub trait TikvTransaction: Send {
+    async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, String>;
+    async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), String>;
+    async fn delete(&mut self, key: Vec<u8>) -> Result<(), String>;
+    async fn commit(&mut self) -> Result<(), String>;
+    async fn rollback(&mut self) -> Result<(), String>;
+}
+
+/// The max number of pairs of a raw scan, which is paginated beyond it.
+const SCAN_LIMIT: u32 = 10240;
+
+/// The [`TikvConnector`] over the raw and transactional clients of the TiKV
+/// client.
+pub struct TikvClient {
+    raw: RawClient,
+    txn: TransactionClient,
+}
+
+impl TikvClient {
+    /// Connect to the cluster of the PD endpoints. The raw client is atomic,
+    /// as TiKV requires it for compare-and-swap.
+    pub async fn connect(pd_endpoints: Vec<String>) -> Result<Self, String> {
+        let raw = RawClient::new(pd_endpoints.clone())
+            .await
+            .map_err(|e| e.to_string())?
+            .with_atomic_for_cas();
+        let txn = TransactionClient::new(pd_endpoints)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Self { raw, txn })
+    }
+}
+
+fn into_pair(pair: KvPair) -> (Vec<u8>, Vec<u8>) {
+    let (key, value) = pair.into();
+    (key.into(), value)
+}
+
+/// The first key after `key`.
+fn next_key(mut key: Vec<u8>) -> Vec<u8> {
+    key.push(0);
+    key
+}
+
+#[async_trait::async_trait]
+impl TikvConnector for TikvClient {
+    async fn raw_get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+        self.raw.get(key).await.map_err(|e| e.to_string())
+    }
+
+    async fn raw_put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), String> {
+        self.raw.put(key, value).await.map_err(|e| e.to_string())
+    }
+
+    async fn raw_delete(&self, key: Vec<u8>) -> Result<(), String> {
+        self.raw.delete(key).await.map_err(|e| e.to_string())
+    }
+
+    async fn raw_batch_get(&self, keys: Vec<Vec<u8>>) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
+        let pairs = self.raw.batch_get(keys).await.map_err(|e| e.to_string())?;
+        Ok(pairs.into_iter().map(into_pair).collect())
+    }
+
+    async fn raw_scan(
+        &self,
+        mut start: Vec<u8>,
+        end: Vec<u8>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
+        let mut pairs = vec![];
+        loop {
+            let page = self
+                .raw
+                .scan(start..end.clone(), SCAN_LIMIT)
+                .await
+                .map_err(|e| e.to_string())?;
+            let full = page.len() == SCAN_LIMIT as usize;
+            pairs.extend(page.into_iter().map(into_pair));
+            match pairs.last() {
+                Some((last, _)) if full => start = next_key(last.clone()),
+                _ => return Ok(pairs),
+            }
+        }
+    }
+
+    async fn raw_compare_and_swap(
+        &self,
+        key: Vec<u8>,
+        previous: Option<Vec<u8>>,
+        new: Vec<u8>,
+    ) -> Result<bool, String> {
+        let (_, swapped) = self
+            .raw
+            .compare_and_swap(key, previous, new)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(swapped)
+    }
+
+    /// Begin an optimistic transaction, which only warns if dropped unfinished,
+    /// e.g. after a failed commit.
+    async fn begin(&self) -> Result<Box<dyn TikvTransaction>, String> {
+        let options = TransactionOptions::new_optimistic().drop_check(CheckLevel::Warn);
+        let txn = self
+            .txn
+            .begin_with_options(options)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(Box::new(txn))
+    }
+}
+
+#[async_trait::async_trait]
+impl TikvTransaction for Transaction {
+    async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+        Transaction::get(self, key).await.map_err(|e| e.to_string())
+    }
+
+    async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), String> {
+        Transaction::put(self, key, value)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn delete(&mut self, key: Vec<u8>) -> Result<(), String> {
+        Transaction::delete(self, key)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn commit(&mut self) -> Result<(), String> {
+        Transaction::commit(self)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn rollback(&mut self) -> Result<(), String> {
+        Transaction::rollback(self).await.map_err(|e| e.to_string())
+    }
+}
+
+/// A cluster client of TiKV.
+pub struct TikvClusterClient<C> {
+    connector: C,
+    /// The number of tikv-servers.
+    size: usize,
+    prefix: String,
+    supervisor: Option<Box<dyn OsClusterClient + Send + Sync>>,
+}
+
+impl<C: TikvConnector> TikvClusterClient<C> {
+    /// A client of the cluster of `size` tikv-servers.
+    pub fn new(connector: C, size: usize) -> Self {
+        Self {
+            connector,
+            size,
+            prefix: DEFAULT_PREFIX.to_string(),
+            supervisor: None,
+        }
+    }
+
+    /// Set the prefix of keys, `jepsen/` by default.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the supervisor of the tikv-server processes, which executes the
+    /// nemeses.
+    pub fn with_supervisor(
+        mut self,
+        supervisor: impl OsClusterClient + Send + Sync + 'static,
+    ) -> Self {
+        self.supervisor = Some(Box::new(supervisor));
+        self
+    }
+
+    fn key(&self, key: u64) -> Vec<u8> {
+        format!("{}{:020}", self.prefix, key).into_bytes()
+    }
+
+    fn parse_key(&self, key: &[u8]) -> Result<u64, String> {
+        std::str::from_utf8(key)
+            .ok()
+            .and_then(|key| key.strip_prefix(&self.prefix))
+            .and_then(|key| key.parse().ok())
+            .ok_or_else(|| format!("invalid key {:?}", String::from_utf8_lossy(key)))
+    }
+
+    /// Run the ops in a transaction, which is rolled back if any of them
+    /// fails.
+    async fn run_txn(
+        &self,
+        txn: &mut dyn TikvTransaction,
+        ops: Vec<Op>,
+    ) -> Result<Vec<Op>, String> {
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            let op = match op {
+                Op::Read(key, _) => {
+                    let value = txn.get(self.key(key)).await?;
+                    Op::Read(key, value.map(|v| parse_value(&v)).transpose()?)
+                }
+                Op::Write(key, value) => {
+                    txn.put(self.key(key), encode_value(value)).await?;
+                    op
+                }
+                Op::Cas(key, expect, new) => {
+                    let value = txn.get(self.key(key)).await?;
+                    if value.map(|v| parse_value(&v)).transpose()? != Some(expect) {
+                        return Err(CAS_MISMATCH.to_string());
+                    }
+                    txn.put(self.key(key), encode_value(new)).await?;
+                    op
+                }
+                Op::Delete(key) => {
+                    txn.delete(self.key(key)).await?;
+                    op
+                }
+                Op::Incr(key, delta) => {
+                    let value = txn.get(self.key(key)).await?;
+                    let value = value.map(|v| parse_value(&v)).transpose()?.unwrap_or(0);
+                    txn.put(
+                        self.key(key),
+                        encode_value(value.wrapping_add_signed(delta)),
+                    )
+                    .await?;
+                    op
+                }
+                Op::BatchRead(_) | Op::ScanRange(..) => {
+                    return Err("multi-key reads cannot be in txns".to_string())
+                }
+                Op::Append(..) | Op::ReadList(..) => {
+                    return Err("lists are not supported in txns".to_string())
+                }
+                Op::Txn(_) => return Err("txns cannot be nested".to_string()),
+            };
+            results.push(op);
+        }
+        Ok(results)
+    }
+}
+
+fn encode_value(value: u64) -> Vec<u8> {
+    value.to_string().into_bytes()
+}
+
+fn parse_value(value: &[u8]) -> Result<u64, String> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("invalid value {:?}", String::from_utf8_lossy(value)))
+}
+
+#[async_trait::async_trait]
+impl<C: TikvConnector> ElleRwClusterClient for TikvClusterClient<C> {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        let value = self.connector.raw_get(self.key(key)).await?;
+        value.map(|v| parse_value(&v)).transpose()
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        self.connector
+            .raw_put(self.key(key), encode_value(value))
+            .await
+    }
+
+    async fn cas(&self, key: u64, expect: u64, new: u64) -> Result<bool, String> {
+        self.connector
+            .raw_compare_and_swap(self.key(key), Some(encode_value(expect)), encode_value(new))
+            .await
+    }
+
+    async fn delete(&self, key: u64) -> Result<(), String> {
+        self.connector.raw_delete(self.key(key)).await
+    }
+
+    async fn batch_get(&self, keys: Vec<u64>) -> Result<Vec<Option<u64>>, String> {
+        let pairs = self
+            .connector
+            .raw_batch_get(keys.iter().map(|k| self.key(*k)).collect())
+            .await?;
+        let mut values = vec![None; keys.len()];
+        for (key, value) in pairs {
+            let key = self.parse_key(&key)?;
+            let value = parse_value(&value)?;
+            for (_, v) in keys.iter().zip(&mut values).filter(|(k, _)| **k == key) {
+                *v = Some(value);
+            }
+        }
+        Ok(values)
+    }
+
+    async fn scan(&self, start: u64, end: u64) -> Result<Vec<(u64, u64)>, String> {
+        let pairs = self
+            .connector
+            .raw_scan(self.key(start), self.key(end))
+            .await?;
+        pairs
+            .iter()
+            .map(|(key, value)| Ok((self.parse_key(key)?, parse_value(value)?)))
+            .collect()
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let mut txn = self.connector.begin().await?;
+        match self.run_txn(txn.as_mut(), ops).await {
+            Ok(results) => {
+                txn.commit().await?;
+                Ok(results)
+            }
+            Err(err) => {
+                if let Err(e) = txn.rollback().await {
+                    trace!("failed to roll back the tikv txn: {}", e);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<C: TikvConnector> ClusterLifecycle for TikvClusterClient<C> {}
+
+#[async_trait::async_trait]
+impl<C: TikvConnector> NemesisClusterClient for TikvClusterClient<C> {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    /// Every region has a leader of its own, so the first server is assumed.
+    async fn get_leader_without_term(&self) -> ServerId {
+        0
+    }
+
+    fn os(&self) -> Option<&(dyn OsClusterClient + Sync)> {
+        self.supervisor.as_deref().map(|s| s as _)
+    }
+}
+
+/// The local tikv-server processes of a cluster, which are found by their data
+/// directories and restarted by the same arguments.
+#[derive(Debug, Clone)]
+pub struct TikvServers {
+    binary: PathBuf,
+    pd_endpoints: Vec<String>,
+    /// The address and data directory of the servers, indexed by
+    /// [`ServerId`].
+    servers: Vec<(SocketAddr, PathBuf)>,
+}
+
+impl TikvServers {
+    pub fn new(pd_endpoints: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            binary: PathBuf::from("tikv-server"),
+            pd_endpoints: pd_endpoints.into_iter().map(Into::into).collect(),
+            servers: vec![],
+        }
+    }
+
+    /// Set the path of the tikv-server binary, found in `PATH` by default.
+    pub fn with_binary(mut self, binary: impl Into<PathBuf>) -> Self {
+        self.binary = binary.into();
+        self
+    }
+
+    /// Add a server listening on `addr` with its data in `data_dir`.
+    pub fn with_server(mut self, addr: SocketAddr, data_dir: impl Into<PathBuf>) -> Self {
+        self.servers.push((addr, data_dir.into()));
+        self
+    }
+
+    fn server(&self, server: ServerId) -> &(SocketAddr, PathBuf) {
+        &self.servers[server as usize]
+    }
+}
+
+impl OsClusterClient for TikvServers {
+    fn pid(&self, server: ServerId) -> Result<u32, String> {
+        let (_, dir) = self.server(server);
+        let output = Command::new("pgrep")
+            .arg("-f")
+            .arg(format!("--data-dir {}", dir.display()))
+            .output()
+            .map_err(|e| format!("failed to run pgrep: {}", e))?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .and_then(|pid| pid.trim().parse().ok())
+            .ok_or_else(|| format!("tikv-server {} is not running", server))
+    }
+
+    fn restart_command(&self, server: ServerId) -> Command {
+        let (addr, dir) = self.server(server);
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("--pd")
+            .arg(self.pd_endpoints.join(","))
+            .arg("--addr")
+            .arg(addr.to_string())
+            .arg("--data-dir")
+            .arg(dir);
+        command
+    }
+
+    fn address(&self, server: ServerId) -> IpAddr {
+        self.server(server).0.ip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    type Rows = Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>;
+
+    #[derive(Default)]
+    struct MapConnector(Rows);
+
+    /// Buffers the writes until commit.
+    struct MapTxn {
+        rows: Rows,
+        writes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    }
+
+    #[async_trait::async_trait]
+    impl TikvConnector for MapConnector {
+        async fn raw_get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+            Ok(self.0.lock().unwrap().get(&key).cloned())
+        }
+        async fn raw_put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), String> {
+            self.0.lock().unwrap().insert(key, value);
+            Ok(())
+        }
+        async fn raw_delete(&self, key: Vec<u8>) -> Result<(), String> {
+            self.0.lock().unwrap().remove(&key);
+            Ok(())
+        }
+        async fn raw_batch_get(
+            &self,
+            keys: Vec<Vec<u8>>,
+        ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
+            let rows = self.0.lock().unwrap();
+            Ok(keys
+                .into_iter()
+                .rev()
+                .filter_map(|k| rows.get(&k).map(|v| (k, v.clone())))
+                .collect())
+        }
+        async fn raw_scan(
+            &self,
+            start: Vec<u8>,
+            end: Vec<u8>,
+        ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, String> {
+            let rows = self.0.lock().unwrap();
+            Ok(rows
+                .range(start..end)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+        }
+        async fn raw_compare_and_swap(
+            &self,
+            key: Vec<u8>,
+            previous: Option<Vec<u8>>,
+            new: Vec<u8>,
+        ) -> Result<bool, String> {
+            let mut rows = self.0.lock().unwrap();
+            if rows.get(&key) != previous.as_ref() {
+                return Ok(false);
+            }
+            rows.insert(key, new);
+            Ok(true)
+        }
+        async fn begin(&self) -> Result<Box<dyn TikvTransaction>, String> {
+            Ok(Box::new(MapTxn {
+                rows: self.0.clone(),
+                writes: vec![],
+            }))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TikvTransaction for MapTxn {
+        async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+            match self.writes.iter().rev().find(|(k, _)| *k == key) {
+                Some((_, value)) => Ok(value.clone()),
+                None => Ok(self.rows.lock().unwrap().get(&key).cloned()),
+            }
+        }
+        async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), String> {
+            self.writes.push((key, Some(value)));
+            Ok(())
+        }
+        async fn delete(&mut self, key: Vec<u8>) -> Result<(), String> {
+            self.writes.push((key, None));
+            Ok(())
+        }
+        async fn commit(&mut self) -> Result<(), String> {
+            let mut rows = self.rows.lock().unwrap();
+            for (key, value) in self.writes.drain(..) {
+                match value {
+                    Some(value) => rows.insert(key, value),
+                    None => rows.remove(&key),
+                };
+            }
+            Ok(())
+        }
+        async fn rollback(&mut self) -> Result<(), String> {
+            self.writes.clear();
+            Ok(())
+        }
+    }
+
+    #[madsim::test]
+    async fn test_tikv_ops() {
+        let client = TikvClusterClient::new(MapConnector::default(), 3);
+        assert_eq!(client.key(12), b"jepsen/00000000000000000012");
+        assert!(client.parse_key(b"other/1").is_err());
+
+        client.put(1, 10).await.unwrap();
+        client.put(9, 90).await.unwrap();
+        client.put(10, 100).await.unwrap();
+        assert_eq!(client.get(1).await.unwrap(), Some(10));
+        assert!(client.cas(1, 10, 11).await.unwrap());
+        assert!(!client.cas(1, 10, 12).await.unwrap());
+        assert_eq!(
+            client.batch_get(vec![10, 2, 1]).await.unwrap(),
+            vec![Some(100), None, Some(11)]
+        );
+        // 10 is after 9 as the keys are padded
+        assert_eq!(client.scan(2, 11).await.unwrap(), vec![(9, 90), (10, 100)]);
+        client.delete(9).await.unwrap();
+        assert_eq!(client.get(9).await.unwrap(), None);
+
+        let res = client
+            .txn(vec![
+                Op::Write(2, 20),
+                Op::Read(2, None),
+                Op::Cas(1, 11, 12),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(res[1], Op::Read(2, Some(20)));
+        assert_eq!(client.get(1).await.unwrap(), Some(12));
+        // a failed txn is rolled back
+        let res = client.txn(vec![Op::Write(3, 30), Op::Cas(1, 11, 13)]).await;
+        assert_eq!(res, Err(CAS_MISMATCH.to_string()));
+        assert_eq!(client.get(3).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_next_key() {
+        // the page after `jepsen/1` starts right after it, before `jepsen/10`
+        let next = next_key(b"jepsen/1".to_vec());
+        assert_eq!(next, b"jepsen/1\x00");
+        assert!(b"jepsen/1".as_slice() < next.as_slice());
+        assert!(next.as_slice() < b"jepsen/10".as_slice());
+    }
+
+    #[test]
+    fn test_tikv_servers() {
+        let servers = TikvServers::new(["127.0.0.1:2379"])
+            .with_server("127.0.0.2:20160".parse().unwrap(), "/tmp/tikv0");
+        let command = servers.restart_command(0);
+        assert_eq!(command.get_program(), "tikv-server");
+        let args: Vec<_> = command
+            .get_args()
+            .map(|x| x.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "--pd",
+                "127.0.0.1:2379",
+                "--addr",
+                "127.0.0.2:20160",
+                "--data-dir",
+                "/tmp/tikv0"
+            ]
+        );
+        assert_eq!(servers.address(0).to_string(), "127.0.0.2");
+    }
+}
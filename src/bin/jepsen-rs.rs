@@ -0,0 +1,5 @@
+//! The `jepsen-rs` binary, see [`jepsen_rs::cli`].
+
+fn main() -> std::process::ExitCode {
+    jepsen_rs::cli::Cli::default().main()
+}
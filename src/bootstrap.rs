@@ -0,0 +1,347 @@
+//! The jars of clojure, jepsen and elle on the classpath of the JVM, for a
+//! machine where the build script did not deploy them.
+//!
+//! The jars listed in `assets/jars.txt` are looked up in the cache directory
+//! and the local maven repository, in the maven layout or flat. A jar with a
+//! `.sha1` file next to it, as maven saves it, is verified against it. With
+//! the `bootstrap` feature, the missing jars are downloaded into the cache
+//! directory by `curl` together with their `.sha1`, and the build script
+//! skips deploying the jars, so the default JVM config is bootstrapped:
+//!
+//! ```ignore
+//! use jepsen_rs::{bootstrap::Bootstrap, init_jvm_with};
+//!
+//! init_jvm_with(Bootstrap::default().cache_dir("/tmp/jars").jvm_config()?)?;
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use default_struct_builder::DefaultBuilder;
+use log::debug;
+
+use crate::JvmConfig;
+
+/// The jars of jepsen, elle and their dependencies, as `group:id:version`.
+pub const JARS: &str = include_str!("../assets/jars.txt");
+
+/// The maven repositories the jars are downloaded from, in order.
+pub const REPOS: [&str; 2] = ["https://repo1.maven.org/maven2", "https://repo.clojars.org"];
+
+/// A maven artifact packaged as a jar.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Artifact {
+    pub group: String,
+    pub id: String,
+    pub version: String,
+}
+
+impl Artifact {
+    /// Parse the `group:id:version` coordinates.
+    pub fn parse(coords: &str) -> Result<Self> {
+        match coords.trim().split(':').collect::<Vec<_>>()[..] {
+            [group, id, version] if [group, id, version].iter().all(|s| !s.is_empty()) => {
+                Ok(Self {
+                    group: group.to_string(),
+                    id: id.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            _ => bail!("invalid artifact coordinates `{}`", coords),
+        }
+    }
+
+    /// The file name of the jar, e.g. `elle-0.2.1.jar`.
+    pub fn file_name(&self) -> String {
+        format!("{}-{}.jar", self.id, self.version)
+    }
+
+    /// The path of the jar in a maven repository, e.g.
+    /// `org/clojure/clojure/1.11.3/clojure-1.11.3.jar`.
+    pub fn repo_path(&self) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.group.replace('.', "/"),
+            self.id,
+            self.version,
+            self.file_name()
+        )
+    }
+}
+
+/// The artifacts listed in [`JARS`].
+pub fn artifacts() -> Vec<Artifact> {
+    JARS.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| Artifact::parse(line).expect("the jars listed are valid"))
+        .collect()
+}
+
+/// Locates, verifies and downloads the jars, see the [module](self) doc.
+#[derive(Debug, Clone, Default, DefaultBuilder)]
+pub struct Bootstrap {
+    /// The directory caching the downloaded jars, `$JEPSEN_RS_CACHE_DIR`, or
+    /// `jepsen-rs/jars` in the cache directory of the user by default.
+    #[builder(into)]
+    cache_dir: Option<PathBuf>,
+    /// Extra directories to look the jars up in before the cache directory.
+    search_dirs: Vec<PathBuf>,
+    /// Do not look the jars up in the local maven repository `~/.m2`.
+    skip_m2: bool,
+}
+
+impl Bootstrap {
+    /// The cache directory of the jars, see [`Bootstrap::cache_dir`].
+    pub fn resolved_cache_dir(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.cache_dir {
+            return Some(dir.clone());
+        }
+        if let Some(dir) = std::env::var_os("JEPSEN_RS_CACHE_DIR") {
+            return Some(dir.into());
+        }
+        let cache = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(cache.join("jepsen-rs").join("jars"))
+    }
+
+    /// The directories looked up, in order.
+    fn dirs(&self) -> Vec<PathBuf> {
+        let m2 = std::env::var_os("HOME")
+            .filter(|_| !self.skip_m2)
+            .map(|home| Path::new(&home).join(".m2").join("repository"));
+        let mut dirs = self.search_dirs.clone();
+        dirs.extend(self.resolved_cache_dir());
+        dirs.extend(m2);
+        dirs
+    }
+
+    /// Look the jar of the artifact up, in the maven layout or flat.
+    pub fn locate(&self, artifact: &Artifact) -> Option<PathBuf> {
+        self.dirs()
+            .into_iter()
+            .flat_map(|dir| {
+                [
+                    dir.join(artifact.repo_path()),
+                    dir.join(artifact.file_name()),
+                ]
+            })
+            .find(|path| path.is_file())
+    }
+
+    /// The verified jars of all the [`artifacts`]. The missing ones are
+    /// downloaded with the `bootstrap` feature, or reported in the error.
+    pub fn jars(&self) -> Result<Vec<PathBuf>> {
+        let mut jars = vec![];
+        let mut missing = vec![];
+        for artifact in artifacts() {
+            match self.locate(&artifact) {
+                Some(jar) => {
+                    verify(&jar)?;
+                    jars.push(jar);
+                }
+                None => missing.push(artifact),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(jars);
+        }
+        #[cfg(feature = "bootstrap")]
+        {
+            for artifact in missing {
+                jars.push(self.download(&artifact)?);
+            }
+            Ok(jars)
+        }
+        #[cfg(not(feature = "bootstrap"))]
+        bail!(
+            "{} jars are missing, e.g. {}, enable the `bootstrap` feature to download them",
+            missing.len(),
+            missing[0].file_name()
+        )
+    }
+
+    /// The default [`JvmConfig`] with the [`jars`](Self::jars) on the
+    /// classpath.
+    pub fn jvm_config(&self) -> Result<JvmConfig> {
+        Ok(JvmConfig::default().classpath(self.jars()?))
+    }
+
+    /// Download the jar and its `.sha1` into the cache directory, and verify
+    /// it.
+    #[cfg(feature = "bootstrap")]
+    pub fn download(&self, artifact: &Artifact) -> Result<PathBuf> {
+        let dir = self
+            .resolved_cache_dir()
+            .ok_or_else(|| anyhow!("no cache directory to download the jars into"))?;
+        let jar = dir.join(artifact.repo_path());
+        fs::create_dir_all(jar.parent().expect("the jar is in a directory"))?;
+        let sha1 = sha1_path(&jar);
+        let mut errors = vec![];
+        for repo in REPOS {
+            let url = format!("{}/{}", repo, artifact.repo_path());
+            match curl(&url, &jar).and_then(|_| curl(&format!("{}.sha1", url), &sha1)) {
+                Ok(()) => {
+                    debug!("downloaded {}", url);
+                    verify(&jar).inspect_err(|_| {
+                        let _ = fs::remove_file(&jar);
+                        let _ = fs::remove_file(&sha1);
+                    })?;
+                    return Ok(jar);
+                }
+                Err(err) => errors.push(format!("{}: {}", url, err)),
+            }
+        }
+        bail!(
+            "failed to download {}: {}",
+            artifact.file_name(),
+            errors.join("; ")
+        )
+    }
+}
+
+/// Download the url to the file.
+#[cfg(feature = "bootstrap")]
+fn curl(url: &str, to: &Path) -> Result<()> {
+    let output = std::process::Command::new("curl")
+        .args(["-fsSL", "--retry", "2", "-o"])
+        .arg(to)
+        .arg(url)
+        .output()
+        .context("failed to run curl")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// The path of the `.sha1` file of the jar.
+fn sha1_path(jar: &Path) -> PathBuf {
+    let mut name = jar.as_os_str().to_owned();
+    name.push(".sha1");
+    name.into()
+}
+
+/// Verify the jar against the `.sha1` file next to it, if any.
+pub fn verify(jar: &Path) -> Result<()> {
+    let Ok(expected) = fs::read_to_string(sha1_path(jar)) else {
+        debug!("no sha1 of {}, not verified", jar.display());
+        return Ok(());
+    };
+    // the file may hold the name of the jar after the hash
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let bytes = fs::read(jar).with_context(|| format!("failed to read {}", jar.display()))?;
+    let actual = sha1_hex(&bytes);
+    if actual != expected {
+        return Err(anyhow!(
+            "the sha1 of {} is {}, expect {}",
+            jar.display(),
+            actual,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// The SHA-1 of the bytes in lowercase hex, as maven publishes it.
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((bytes.len() as u64).wrapping_mul(8)).to_be_bytes());
+    for chunk in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    h.iter().map(|x| format!("{:08x}", x)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            sha1_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn test_artifacts() -> Result<()> {
+        let clojure = Artifact::parse("org.clojure:clojure:1.11.3")?;
+        assert_eq!(clojure.file_name(), "clojure-1.11.3.jar");
+        assert_eq!(
+            clojure.repo_path(),
+            "org/clojure/clojure/1.11.3/clojure-1.11.3.jar"
+        );
+        assert!(Artifact::parse("elle:elle").is_err());
+        assert!(artifacts().contains(&clojure));
+        Ok(())
+    }
+
+    #[test]
+    fn test_locate_and_verify() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-jars-{}", std::process::id()));
+        let elle = Artifact::parse("elle:elle:0.2.1")?;
+        let bootstrap = Bootstrap::default().cache_dir(&dir).skip_m2(true);
+        assert_eq!(bootstrap.locate(&elle), None);
+
+        let jar = dir.join(elle.repo_path());
+        fs::create_dir_all(jar.parent().unwrap())?;
+        fs::write(&jar, b"abc")?;
+        assert_eq!(bootstrap.locate(&elle), Some(jar.clone()));
+        verify(&jar)?;
+        fs::write(
+            sha1_path(&jar),
+            "a9993e364706816aba3e25717850c26c9cd0d89d  elle-0.2.1.jar",
+        )?;
+        verify(&jar)?;
+        fs::write(&jar, b"abd")?;
+        assert!(verify(&jar).is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
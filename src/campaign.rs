@@ -0,0 +1,392 @@
+//! A campaign runs the same test across many seeds, like a fuzzer: every seed
+//! runs in a madsim runtime of its own, into a subdirectory of the output
+//! directory, and the validity and the anomalies of the runs are aggregated.
+//!
+//! ```ignore
+//! let report = Campaign::new(|_seed| {
+//!     Ok(TestBuilder::new(|| MockCluster::new(3), RwRegisterWorkload::default()))
+//! })
+//! .seeds(0..100)
+//! .threads(4)
+//! .stop_on_failure(true)
+//! .out_dir("./store")
+//! .run();
+//! println!("{}", report);
+//! assert!(report.is_valid(), "failing seeds: {:?}", report.failing_seeds());
+//! ```
+//!
+//! The runs of a seed are written to `<out-dir>/seed-<seed>/`, and the summary
+//! of the campaign to `<out-dir>/campaign.json`.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    checker::ValidType,
+    runner::{TestBuilder, TestCluster, TestReport},
+    utils::panic_message,
+    workload::Workload,
+};
+
+/// The file in the output directory to save the summary of a campaign.
+pub const CAMPAIGN_FILE: &str = "campaign.json";
+
+/// Runs the test of a scenario across seeds, see the [module](self) doc.
+pub struct Campaign<F> {
+    scenario: F,
+    seeds: Vec<u64>,
+    threads: usize,
+    stop_on_failure: bool,
+    out_dir: Option<PathBuf>,
+}
+
+impl<EC, W, F> Campaign<F>
+where
+    EC: TestCluster,
+    W: Workload,
+    F: Fn(u64) -> Result<TestBuilder<EC, W>> + Sync,
+{
+    /// A campaign of the test built by `scenario` for every seed, with the
+    /// seeds `0..10` run one by one by default. The seed and the output
+    /// directory of the builder are set by the campaign.
+    pub fn new(scenario: F) -> Self {
+        Self {
+            scenario,
+            seeds: (0..10).collect(),
+            threads: 1,
+            stop_on_failure: false,
+            out_dir: None,
+        }
+    }
+
+    /// The seeds to run, in order.
+    pub fn seeds(mut self, seeds: impl IntoIterator<Item = u64>) -> Self {
+        self.seeds = seeds.into_iter().collect();
+        self
+    }
+
+    /// The number of threads running the seeds concurrently.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Start no more seeds after a run fails, i.e. is invalid or errors. The
+    /// seeds already started still finish.
+    pub fn stop_on_failure(mut self, stop: bool) -> Self {
+        self.stop_on_failure = stop;
+        self
+    }
+
+    /// Save the runs of every seed in a jepsen store at
+    /// `<dir>/seed-<seed>/`, and the summary at `<dir>/campaign.json`.
+    pub fn out_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(dir.into());
+        self
+    }
+
+    /// Run the seeds, and aggregate their outcomes.
+    pub fn run(self) -> CampaignReport {
+        let next = AtomicUsize::new(0);
+        let stopped = AtomicBool::new(false);
+        let runs = Mutex::new(vec![]);
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(self.seeds.len()) {
+                scope.spawn(|| {
+                    while !stopped.load(Ordering::SeqCst) {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(seed) = self.seeds.get(index) else {
+                            break;
+                        };
+                        let run = self.run_seed(*seed);
+                        if self.stop_on_failure && !run.is_valid() {
+                            info!("seed {} failed, stop the campaign", seed);
+                            stopped.store(true, Ordering::SeqCst);
+                        }
+                        runs.lock().unwrap().push(run);
+                    }
+                });
+            }
+        });
+        let mut runs = runs.into_inner().unwrap();
+        runs.sort_by_key(|run| run.seed);
+        let skipped = self
+            .seeds
+            .iter()
+            .filter(|seed| runs.iter().all(|run| run.seed != **seed))
+            .copied()
+            .collect();
+        let report = CampaignReport { runs, skipped };
+        if let Some(dir) = &self.out_dir {
+            let path = dir.join(CAMPAIGN_FILE);
+            let res = std::fs::create_dir_all(dir)
+                .and_then(|_| std::fs::write(&path, serde_json::to_vec_pretty(&report.summary())?));
+            if let Err(err) = res {
+                warn!("failed to save the campaign to {:?}: {}", path, err);
+            }
+        }
+        report
+    }
+
+    /// Run the test of the seed, a panic of the run is an error of the seed.
+    fn run_seed(&self, seed: u64) -> SeedRun {
+        info!("campaign: run seed {}", seed);
+        let run = || {
+            let mut builder = (self.scenario)(seed)?.seed(seed);
+            if let Some(dir) = &self.out_dir {
+                builder = builder.out_dir(dir.join(format!("seed-{}", seed)));
+            }
+            builder.build().run()
+        };
+        let report = match panic::catch_unwind(AssertUnwindSafe(run)) {
+            Ok(report) => report.map_err(|err| err.to_string()),
+            Err(panic) => Err(format!("panicked: {}", panic_message(&*panic))),
+        };
+        SeedRun { seed, report }
+    }
+}
+
+/// The outcome of the test of a seed.
+#[derive(Debug)]
+pub struct SeedRun {
+    pub seed: u64,
+    /// The report of the run, or the error (or panic) failing it.
+    pub report: Result<TestReport, String>,
+}
+
+impl SeedRun {
+    /// Whether the run finishes and its history is checked valid.
+    pub fn is_valid(&self) -> bool {
+        self.report.as_ref().is_ok_and(TestReport::is_valid)
+    }
+}
+
+/// The outcomes of a [`Campaign`].
+#[derive(Debug)]
+pub struct CampaignReport {
+    /// The runs of the seeds, sorted by seed.
+    pub runs: Vec<SeedRun>,
+    /// The seeds not run, as the campaign stops on failure.
+    pub skipped: Vec<u64>,
+}
+
+/// The summary of a [`CampaignReport`], saved as `campaign.json`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CampaignSummary {
+    pub valid: usize,
+    pub invalid: usize,
+    pub unknown: usize,
+    pub errors: usize,
+    pub skipped: usize,
+    /// The number of seeds finding every anomaly type.
+    pub anomalies: BTreeMap<String, usize>,
+    pub failing_seeds: Vec<u64>,
+    pub seeds: Vec<SeedSummary>,
+}
+
+/// The outcome of a seed in a [`CampaignSummary`].
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SeedSummary {
+    pub seed: u64,
+    /// `true`, `false` or `unknown`, or `error`.
+    pub valid: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub anomaly_types: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CampaignReport {
+    /// Whether every seed runs and is valid.
+    pub fn is_valid(&self) -> bool {
+        self.skipped.is_empty() && self.runs.iter().all(SeedRun::is_valid)
+    }
+
+    /// The seeds whose runs are not valid or fail.
+    pub fn failing_seeds(&self) -> Vec<u64> {
+        self.runs
+            .iter()
+            .filter(|run| !run.is_valid())
+            .map(|run| run.seed)
+            .collect()
+    }
+
+    /// Aggregate the outcomes of the seeds.
+    pub fn summary(&self) -> CampaignSummary {
+        let mut summary = CampaignSummary {
+            valid: 0,
+            invalid: 0,
+            unknown: 0,
+            errors: 0,
+            skipped: self.skipped.len(),
+            anomalies: BTreeMap::new(),
+            failing_seeds: self.failing_seeds(),
+            seeds: vec![],
+        };
+        for run in &self.runs {
+            let seed = match &run.report {
+                Ok(report) => {
+                    let (count, valid) = match report.result.valid() {
+                        ValidType::True => (&mut summary.valid, "true"),
+                        ValidType::False => (&mut summary.invalid, "false"),
+                        ValidType::Unknown => (&mut summary.unknown, "unknown"),
+                    };
+                    *count += 1;
+                    let anomaly_types = report.result.anomaly_types().to_vec();
+                    for anomaly in &anomaly_types {
+                        *summary.anomalies.entry(anomaly.clone()).or_default() += 1;
+                    }
+                    SeedSummary {
+                        seed: run.seed,
+                        valid,
+                        anomaly_types,
+                        out_dir: report.out_dir.clone(),
+                        error: None,
+                    }
+                }
+                Err(err) => {
+                    summary.errors += 1;
+                    SeedSummary {
+                        seed: run.seed,
+                        valid: "error",
+                        anomaly_types: vec![],
+                        out_dir: None,
+                        error: Some(err.clone()),
+                    }
+                }
+            };
+            summary.seeds.push(seed);
+        }
+        summary
+    }
+}
+
+impl fmt::Display for CampaignReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = self.summary();
+        writeln!(
+            f,
+            "{} seeds: {} valid, {} invalid, {} unknown, {} errors, {} skipped",
+            self.runs.len() + self.skipped.len(),
+            summary.valid,
+            summary.invalid,
+            summary.unknown,
+            summary.errors,
+            summary.skipped
+        )?;
+        for (anomaly, seeds) in &summary.anomalies {
+            writeln!(f, "  {:<16}{} seeds", anomaly, seeds)?;
+        }
+        for seed in summary.seeds.iter().filter(|s| s.valid != "true") {
+            write!(f, "  seed {}: {}", seed.seed, seed.valid)?;
+            if let Some(err) = &seed.error {
+                write!(f, " ({})", err)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, madsim))]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{Check, CheckOption, SerializableCheckResult},
+        history::SerializableHistoryList,
+        mock::MockCluster,
+        utils::testing::{WriteGenerator, VALID},
+    };
+
+    /// Finds a G1c anomaly in the histories of the odd seeds.
+    struct SeedChecker(u64);
+
+    impl Check for SeedChecker {
+        fn check<F: serde::Serialize, ERR: serde::Serialize>(
+            &self,
+            _history: &SerializableHistoryList<F, ERR>,
+            _option: CheckOption,
+        ) -> Result<SerializableCheckResult> {
+            let result = match self.0 % 2 {
+                0 => VALID,
+                _ => {
+                    r#"{"valid?":false,"anomaly-types":["G1c"],"anomalies":{},"not":[],"also-not":[]}"#
+                }
+            };
+            Ok(serde_json::from_str(result)?)
+        }
+    }
+
+    struct SeedWorkload(u64);
+
+    impl Workload for SeedWorkload {
+        type Gen = WriteGenerator;
+        type Checker = SeedChecker;
+
+        fn generator(&self) -> Result<Self::Gen> {
+            Ok(WriteGenerator(0))
+        }
+
+        fn checker(&self) -> Result<Self::Checker> {
+            Ok(SeedChecker(self.0))
+        }
+    }
+
+    fn scenario(seed: u64) -> Result<TestBuilder<MockCluster, SeedWorkload>> {
+        if seed == 4 {
+            panic!("no seed 4");
+        }
+        Ok(TestBuilder::new(|| MockCluster::new(3), SeedWorkload(seed)).ops(5))
+    }
+
+    #[test]
+    fn test_campaign() {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-campaign-{}", std::process::id()));
+        let report = Campaign::new(scenario)
+            .seeds(0..6)
+            .threads(3)
+            .out_dir(&dir)
+            .run();
+        assert!(!report.is_valid());
+        assert_eq!(report.failing_seeds(), vec![1, 3, 4, 5]);
+        let summary = report.summary();
+        assert_eq!((summary.valid, summary.invalid, summary.errors), (2, 3, 1));
+        assert_eq!(summary.anomalies["G1c"], 3);
+        assert_eq!(
+            summary.seeds[4].error.as_deref(),
+            Some("panicked: no seed 4")
+        );
+        assert!(summary.seeds[0]
+            .out_dir
+            .as_ref()
+            .unwrap()
+            .starts_with(dir.join("seed-0")));
+        assert!(dir.join(CAMPAIGN_FILE).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // seed 1 fails first, as the seeds run one by one
+        let report = Campaign::new(scenario)
+            .seeds(0..6)
+            .stop_on_failure(true)
+            .run();
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.skipped, vec![2, 3, 4, 5]);
+    }
+}
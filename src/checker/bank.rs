@@ -0,0 +1,147 @@
+//! The checker of the bank workload, see
+//! [`BankGenerator`](crate::generator::bank::BankGenerator).
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::json;
+
+use super::{Check, CheckOption, SerializableCheckResult, ValidType};
+use crate::{
+    history::{HistoryType, HistoryValue, SerializableHistoryList},
+    op::Op,
+};
+
+/// Checks that every completed read of all accounts sums up to the total,
+/// reporting the others as `wrong-total` anomalies. A balance is the `u64`
+/// of the wrapping increments, summed as `i64`, and a missing account is 0.
+#[derive(Debug, Clone, Default)]
+pub struct BankChecker {
+    total: i64,
+}
+
+impl BankChecker {
+    /// The total of the balances, 0 by default.
+    pub fn total(mut self, total: i64) -> Self {
+        self.total = total;
+        self
+    }
+}
+
+/// The sum of the balances read by the op, if it's a read of the accounts,
+/// i.e. a batch read, or a txn of reads as expanded by
+/// [`Op::expand_reads`].
+fn read_total(op: &Op) -> Option<i64> {
+    let sum = |values: &mut dyn Iterator<Item = Option<u64>>| {
+        values.fold(0i64, |sum, v| sum.wrapping_add(v.unwrap_or(0) as i64))
+    };
+    match op {
+        Op::BatchRead(reads) => Some(sum(&mut reads.iter().map(|(_, v)| *v))),
+        Op::Txn(ops) if ops.iter().all(|op| matches!(op, Op::Read(..))) => {
+            Some(sum(&mut ops.iter().filter_map(|op| match op {
+                Op::Read(_, v) => Some(*v),
+                _ => None,
+            })))
+        }
+        _ => None,
+    }
+}
+
+impl Check for BankChecker {
+    fn check<F: Serialize, ERR: Serialize>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+        _option: CheckOption,
+    ) -> Result<SerializableCheckResult> {
+        let wrong: Vec<_> = history
+            .0
+            .iter()
+            .filter(|item| item.type_ == HistoryType::Ok)
+            .filter_map(|item| match &item.value {
+                HistoryValue::Op(op) => read_total(op)
+                    .filter(|total| *total != self.total)
+                    .map(|total| json!({ "index": item.index, "total": total })),
+                _ => None,
+            })
+            .collect();
+        let valid = match wrong.is_empty() {
+            true => ValidType::True,
+            false => ValidType::False,
+        };
+        let anomaly_types = match wrong.is_empty() {
+            true => vec![],
+            false => vec!["wrong-total".to_string()],
+        };
+        Ok(SerializableCheckResult {
+            valid,
+            anomaly_types,
+            anomalies: json!({ "wrong-total": wrong }),
+            not: vec![],
+            also_not: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history::{HistoryProcess, SerializableHistory},
+        txn,
+    };
+
+    fn item(index: u64, type_: HistoryType, op: Op) -> SerializableHistory<String, String> {
+        SerializableHistory {
+            index,
+            type_,
+            f: "txn".to_string(),
+            value: HistoryValue::Op(op),
+            time: index,
+            process: HistoryProcess::Gen(0),
+            error: None,
+            meta: None,
+            tags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_bank_checker() -> Result<()> {
+        let history = SerializableHistoryList(vec![
+            item(0, HistoryType::Ok, txn![Op::Incr(0, -3), Op::Incr(1, 3)]),
+            item(
+                1,
+                HistoryType::Ok,
+                Op::BatchRead(vec![(0, Some(-3i64 as u64)), (1, Some(3))]),
+            ),
+            // a lost half of a transfer
+            item(
+                2,
+                HistoryType::Ok,
+                Op::BatchRead(vec![(0, Some(-3i64 as u64)), (1, None)]),
+            ),
+            item(
+                3,
+                HistoryType::Invoke,
+                Op::BatchRead(vec![(0, None), (1, None)]),
+            ),
+            item(
+                4,
+                HistoryType::Ok,
+                txn![Op::Read(0, None), Op::Read(1, Some(2))],
+            ),
+        ]);
+        let res = BankChecker::default().check(&history, CheckOption::default())?;
+        assert!(matches!(res.valid(), ValidType::False));
+        assert_eq!(res.anomaly_types(), ["wrong-total"]);
+        assert_eq!(
+            res.anomalies,
+            json!({ "wrong-total": [{ "index": 2, "total": -3 }, { "index": 4, "total": 2 }] })
+        );
+
+        let res = BankChecker::default().total(2).check(
+            &SerializableHistoryList(history.0[4..].to_vec()),
+            CheckOption::default(),
+        )?;
+        assert!(matches!(res.valid(), ValidType::True));
+        Ok(())
+    }
+}
@@ -0,0 +1,496 @@
+//! Checkers made of independent sub-checks, whose results are aggregated into
+//! one:
+//!
+//! - [`CompositeChecker`] checks the whole history by several checkers, e.g.
+//!   elle and a checker of its own;
+//! - [`PerKeyChecker`] checks the history of every key on its own by one
+//!   checker, see [`SerializableHistoryList::split_by_key`].
+//!
+//! ```ignore
+//! let checker = CompositeChecker::new()
+//!     .with("elle", ElleRwChecker::default())
+//!     .with("registers", PerKeyChecker::new(RegisterChecker));
+//! ```
+//!
+//! The sub-checks run concurrently on the global rayon pool, or on a pool of
+//! their own if the number of threads is set, except those of the checkers
+//! [requiring the JVM](Check::requires_jvm),
+//! which run one after another on the calling thread, as they are serialized
+//! on the [`JvmExecutor`](crate::executor::JvmExecutor) thread anyway.
+//!
+//! The result is valid if every sub-check is, invalid if any is not, and
+//! unknown otherwise. The anomaly types are the union of the sub-checks, and
+//! the anomalies are by the name of the sub-check, e.g. the key, for the
+//! sub-checks that are not valid.
+
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use self::sealed::{CheckList, ErasedCheck};
+use super::{Check, CheckOption, SerializableCheckResult, ValidType};
+use crate::history::{SerializableHistory, SerializableHistoryList};
+
+mod sealed {
+    use super::*;
+
+    /// A [`Check`] of the histories of `F` and `ERR`, object safe so the
+    /// checkers of different types run as the sub-checks of one.
+    pub trait ErasedCheck<F, ERR>: Send + Sync {
+        fn check_erased(
+            &self,
+            history: &SerializableHistoryList<F, ERR>,
+            option: CheckOption,
+        ) -> Result<SerializableCheckResult>;
+        fn on_jvm(&self) -> bool;
+    }
+
+    impl<C, F, ERR> ErasedCheck<F, ERR> for C
+    where
+        C: Check + Send + Sync,
+        F: Serialize + Sync,
+        ERR: Serialize + Sync,
+    {
+        fn check_erased(
+            &self,
+            history: &SerializableHistoryList<F, ERR>,
+            option: CheckOption,
+        ) -> Result<SerializableCheckResult> {
+            self.check(history, option)
+        }
+
+        fn on_jvm(&self) -> bool {
+            self.requires_jvm()
+        }
+    }
+
+    /// The named checkers of a [`CompositeChecker`], nested by
+    /// [`CompositeChecker::with`].
+    pub trait CheckList: Send + Sync {
+        /// Push the checkers in the order they are added.
+        fn push_to<'a, F: Serialize + Sync + 'a, ERR: Serialize + Sync + 'a>(
+            &'a self,
+            checkers: &mut Vec<(&'a str, &'a dyn ErasedCheck<F, ERR>)>,
+        );
+        fn requires_jvm(&self) -> bool;
+    }
+
+    impl CheckList for () {
+        fn push_to<'a, F: Serialize + Sync + 'a, ERR: Serialize + Sync + 'a>(
+            &'a self,
+            _checkers: &mut Vec<(&'a str, &'a dyn ErasedCheck<F, ERR>)>,
+        ) {
+        }
+
+        fn requires_jvm(&self) -> bool {
+            false
+        }
+    }
+
+    impl<L: CheckList, C: Check + Send + Sync> CheckList for (L, (String, C)) {
+        fn push_to<'a, F: Serialize + Sync + 'a, ERR: Serialize + Sync + 'a>(
+            &'a self,
+            checkers: &mut Vec<(&'a str, &'a dyn ErasedCheck<F, ERR>)>,
+        ) {
+            let (list, (name, checker)) = self;
+            list.push_to(checkers);
+            checkers.push((name, checker));
+        }
+
+        fn requires_jvm(&self) -> bool {
+            let (list, (_, checker)) = self;
+            list.requires_jvm() || checker.requires_jvm()
+        }
+    }
+}
+
+/// The history with the functions and the errors borrowed, so it's split by
+/// key without copying them.
+fn borrowed<F, ERR>(
+    history: &SerializableHistoryList<F, ERR>,
+) -> SerializableHistoryList<&F, &ERR> {
+    let items = history
+        .0
+        .iter()
+        .map(|item| SerializableHistory {
+            index: item.index,
+            type_: item.type_.clone(),
+            f: &item.f,
+            value: item.value.clone(),
+            time: item.time,
+            process: item.process,
+            error: item.error.as_ref(),
+            meta: item.meta,
+            tags: item.tags.clone(),
+        })
+        .collect();
+    SerializableHistoryList(items)
+}
+
+struct SubCheck<'a, F, ERR> {
+    name: String,
+    checker: &'a dyn ErasedCheck<F, ERR>,
+    history: &'a SerializableHistoryList<F, ERR>,
+}
+
+/// Run the sub-checks, the native ones on the rayon pool of `threads` threads,
+/// or the global one, and the ones requiring the JVM on the current thread,
+/// and aggregate their results in order.
+fn run<F: Sync, ERR: Sync>(
+    checks: &[SubCheck<'_, F, ERR>],
+    option: &CheckOption,
+    threads: Option<usize>,
+) -> Result<SerializableCheckResult> {
+    let results: Vec<OnceLock<Result<SerializableCheckResult>>> =
+        checks.iter().map(|_| OnceLock::new()).collect();
+    let run = |i: usize| {
+        let check = &checks[i];
+        let _ = results[i].set(check.checker.check_erased(check.history, option.clone()));
+    };
+    let (jvm, native): (Vec<_>, Vec<_>) =
+        (0..checks.len()).partition(|&i| checks[i].checker.on_jvm());
+    match threads {
+        Some(n) => rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()?
+            .in_place_scope(|s| run_in(s, &native, &jvm, &run)),
+        None => rayon::in_place_scope(|s| run_in(s, &native, &jvm, &run)),
+    }
+    aggregate(checks.iter().zip(results).map(|(check, res)| {
+        (
+            check.name.as_str(),
+            res.into_inner().expect("every sub-check runs"),
+        )
+    }))
+}
+
+/// Spawn the native sub-checks on the pool of the scope, and run the ones
+/// requiring the JVM meanwhile.
+fn run_in<'s>(
+    s: &rayon::Scope<'s>,
+    native: &'s [usize],
+    jvm: &[usize],
+    run: &'s (impl Fn(usize) + Sync),
+) {
+    s.spawn(move |_| native.par_iter().for_each(|&i| run(i)));
+    jvm.iter().for_each(|&i| run(i));
+}
+
+fn aggregate<'a>(
+    results: impl IntoIterator<Item = (&'a str, Result<SerializableCheckResult>)>,
+) -> Result<SerializableCheckResult> {
+    let mut valid = ValidType::True;
+    let mut anomalies = Map::new();
+    let (mut anomaly_types, mut not, mut also_not) = (vec![], vec![], vec![]);
+    for (name, res) in results {
+        let res = res.with_context(|| format!("sub-check `{}` failed", name))?;
+        valid = match (valid, &res.valid) {
+            (ValidType::False, _) | (_, ValidType::False) => ValidType::False,
+            (ValidType::Unknown, _) | (_, ValidType::Unknown) => ValidType::Unknown,
+            (ValidType::True, ValidType::True) => ValidType::True,
+        };
+        if !matches!(res.valid, ValidType::True) {
+            anomalies.insert(name.to_string(), res.anomalies);
+        }
+        anomaly_types.extend(res.anomaly_types);
+        not.extend(res.not);
+        also_not.extend(res.also_not);
+    }
+    for names in [&mut anomaly_types, &mut not, &mut also_not] {
+        names.sort();
+        names.dedup();
+    }
+    Ok(SerializableCheckResult {
+        valid,
+        anomaly_types,
+        anomalies: Value::Object(anomalies),
+        not,
+        also_not,
+    })
+}
+
+/// Checks the whole history by several named checkers, see the
+/// [module](self) doc. The checkers are nested in `L` by
+/// [`CompositeChecker::with`], so every one checks the history of the caller
+/// by its own type.
+pub struct CompositeChecker<L = ()> {
+    checkers: L,
+    threads: Option<usize>,
+}
+
+impl Default for CompositeChecker {
+    fn default() -> Self {
+        Self {
+            checkers: (),
+            threads: None,
+        }
+    }
+}
+
+impl CompositeChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<L> CompositeChecker<L> {
+    /// Add a checker, its anomalies are under the name.
+    pub fn with<C: Check + Send + Sync>(
+        self,
+        name: impl Into<String>,
+        checker: C,
+    ) -> CompositeChecker<(L, (String, C))> {
+        CompositeChecker {
+            checkers: (self.checkers, (name.into(), checker)),
+            threads: self.threads,
+        }
+    }
+
+    /// The number of the threads of the native sub-checks, on a pool of their
+    /// own instead of the global rayon pool.
+    pub fn threads(mut self, n: usize) -> Self {
+        self.threads = Some(n.max(1));
+        self
+    }
+}
+
+impl<L: CheckList> Check for CompositeChecker<L> {
+    fn check<F: Serialize + Sync, ERR: Serialize + Sync>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+        option: CheckOption,
+    ) -> Result<SerializableCheckResult> {
+        let mut checkers = vec![];
+        self.checkers.push_to(&mut checkers);
+        let checks: Vec<_> = checkers
+            .into_iter()
+            .map(|(name, checker)| SubCheck {
+                name: name.to_string(),
+                checker,
+                history,
+            })
+            .collect();
+        run(&checks, &option, self.threads)
+    }
+
+    fn requires_jvm(&self) -> bool {
+        self.checkers.requires_jvm()
+    }
+}
+
+/// Checks the history of every key on its own, see the [module](self) doc.
+pub struct PerKeyChecker<C> {
+    checker: C,
+    threads: Option<usize>,
+}
+
+impl<C> PerKeyChecker<C> {
+    pub fn new(checker: C) -> Self {
+        Self {
+            checker,
+            threads: None,
+        }
+    }
+
+    /// The number of the threads of the sub-checks, on a pool of their own
+    /// instead of the global rayon pool, unless the checker requires the JVM.
+    pub fn threads(mut self, n: usize) -> Self {
+        self.threads = Some(n.max(1));
+        self
+    }
+}
+
+impl<C: Check + Send + Sync> Check for PerKeyChecker<C> {
+    fn check<F: Serialize + Sync, ERR: Serialize + Sync>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+        option: CheckOption,
+    ) -> Result<SerializableCheckResult> {
+        let split = borrowed(history).split_by_key();
+        let checks: Vec<_> = split
+            .iter()
+            .map(|(key, history)| SubCheck {
+                name: key.to_string(),
+                checker: &self.checker,
+                history,
+            })
+            .collect();
+        run(&checks, &option, self.threads)
+    }
+
+    fn requires_jvm(&self) -> bool {
+        self.checker.requires_jvm()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread::{self, ThreadId},
+    };
+
+    use super::*;
+
+    /// Finds a `G0` in the histories accessing the key 2, and records the
+    /// threads it runs on.
+    #[derive(Clone, Default)]
+    struct KeyChecker {
+        jvm: bool,
+        threads: Arc<Mutex<HashSet<ThreadId>>>,
+    }
+
+    impl Check for KeyChecker {
+        fn check<F: Serialize, ERR: Serialize>(
+            &self,
+            history: &SerializableHistoryList<F, ERR>,
+            _option: CheckOption,
+        ) -> Result<SerializableCheckResult> {
+            self.threads.lock().unwrap().insert(thread::current().id());
+            let result = match history.0.iter().any(|item| item.value.keys().contains(&2)) {
+                false => {
+                    r#"{"valid?":true,"anomaly-types":[],"anomalies":{},"not":[],"also-not":[]}"#
+                }
+                true => {
+                    r#"{"valid?":false,"anomaly-types":["G0"],"anomalies":{"G0":[]},"not":["read-committed"],"also-not":[]}"#
+                }
+            };
+            Ok(serde_json::from_str(result)?)
+        }
+
+        fn requires_jvm(&self) -> bool {
+            self.jvm
+        }
+    }
+
+    fn history() -> SerializableHistoryList {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, 1]], "time": 1, "process": 0, "error": null },
+          { "index": 1, "type": "invoke", "f": "txn", "value": [["w", 2, 1]], "time": 2, "process": 1, "error": null },
+          { "index": 2, "type": "ok", "f": "txn", "value": [["w", 1, 1]], "time": 3, "process": 0, "error": null },
+          { "index": 3, "type": "ok", "f": "txn", "value": [["w", 2, 1]], "time": 4, "process": 1, "error": null },
+          { "index": 4, "type": "invoke", "f": "txn", "value": [["w", 3, 1]], "time": 5, "process": 0, "error": null },
+          { "index": 5, "type": "fail", "f": "txn", "value": [["w", 3, 1]], "time": 6, "process": 0, "error": ["x"] }
+        ]"#;
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_per_key_checker() -> Result<()> {
+        let checker = KeyChecker::default();
+        let res = PerKeyChecker::new(checker.clone())
+            .threads(2)
+            .check(&history(), CheckOption::default())?;
+        assert!(matches!(res.valid(), ValidType::False));
+        assert_eq!(res.anomaly_types(), ["G0"]);
+        assert_eq!(res.anomalies, serde_json::json!({ "2": { "G0": [] } }));
+        assert_eq!(res.not, ["read-committed"]);
+        assert!(!checker
+            .threads
+            .lock()
+            .unwrap()
+            .contains(&thread::current().id()));
+
+        // the keys share the threads of the pool
+        let items: Vec<_> = (0..1000)
+            .map(|k| format!(r#"{{ "index": {k}, "type": "ok", "f": "txn", "value": [["w", {k}, 1]], "time": {k}, "process": 0, "error": null }}"#))
+            .collect();
+        let many: SerializableHistoryList =
+            serde_json::from_str(&format!("[{}]", items.join(",")))?;
+        let checker = KeyChecker::default();
+        PerKeyChecker::new(checker.clone())
+            .threads(2)
+            .check(&many, CheckOption::default())?;
+        assert!(checker.threads.lock().unwrap().len() <= 2);
+
+        // the checks requiring the JVM run on the calling thread
+        let jvm = KeyChecker {
+            jvm: true,
+            ..Default::default()
+        };
+        let res = PerKeyChecker::new(jvm.clone())
+            .check(&history().filter_by_key(1), CheckOption::default())?;
+        assert!(matches!(res.valid(), ValidType::True));
+        assert_eq!(
+            *jvm.threads.lock().unwrap(),
+            HashSet::from([thread::current().id()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_checker() -> Result<()> {
+        let history = history().filter_by_key(1);
+        let checker = CompositeChecker::new()
+            .with("native", KeyChecker::default())
+            .with(
+                "jvm",
+                KeyChecker {
+                    jvm: true,
+                    ..Default::default()
+                },
+            );
+        assert!(checker.requires_jvm());
+        assert!(matches!(
+            checker.check(&history, CheckOption::default())?.valid(),
+            ValidType::True
+        ));
+
+        let res = checker
+            .with("per-key", PerKeyChecker::new(KeyChecker::default()))
+            .check(&self::history(), CheckOption::default())?;
+        assert!(matches!(res.valid(), ValidType::False));
+        assert_eq!(
+            res.anomalies,
+            serde_json::json!({
+                "native": { "G0": [] },
+                "jvm": { "G0": [] },
+                "per-key": { "2": { "G0": [] } },
+            })
+        );
+        Ok(())
+    }
+
+    /// A function counting its serializations.
+    struct CountedF;
+
+    static SERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+    impl Serialize for CountedF {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            SERIALIZED.fetch_add(1, Ordering::Relaxed);
+            serializer.serialize_unit()
+        }
+    }
+
+    #[test]
+    fn test_history_shared() -> Result<()> {
+        let items = history().0.into_iter().map(|item| SerializableHistory {
+            f: CountedF,
+            index: item.index,
+            type_: item.type_,
+            value: item.value,
+            time: item.time,
+            process: item.process,
+            error: item.error,
+            meta: item.meta,
+            tags: item.tags,
+        });
+        let history = SerializableHistoryList(items.collect());
+        let res = CompositeChecker::new()
+            .with("all", KeyChecker::default())
+            .with("per-key", PerKeyChecker::new(KeyChecker::default()))
+            .check(&history, CheckOption::default())?;
+        assert!(matches!(res.valid(), ValidType::False));
+        // the history is checked as is, not serialized for the sub-checks
+        assert_eq!(SERIALIZED.load(Ordering::Relaxed), 0);
+        Ok(())
+    }
+}
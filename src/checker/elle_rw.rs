@@ -4,43 +4,112 @@ use serde::Serialize;
 
 use super::{CheckOption, SerializableCheckResult};
 use crate::{
-    history::SerializableHistoryList,
+    cljinvoke,
+    convert::edn::to_edn,
+    executor::{JvmExecutor, JvmHandle, Remote},
+    history::{untagged, SerializableHistoryList},
     nsinvoke,
-    utils::{historify, FromSerde, ToDe},
-    with_jvm, CljNs, CLOJURE,
+    utils::{clj_from_edn, historify, FfiError, ToDe},
+    CljNs, CLOJURE,
 };
 
+/// The number of the history items sent to the JVM at a time by default, see
+/// [`ElleRwChecker::chunk`].
+pub const HISTORY_CHUNK: usize = 10_000;
+
 pub struct ElleRwChecker {
     /// The namespace of the generator, default is `elle.rw-register`
     ns: CljNs,
+    /// The number of the history items sent to the JVM at a time.
+    chunk: usize,
 }
 
 impl Default for ElleRwChecker {
     fn default() -> Self {
-        with_jvm(|_| Self {
-            ns: CLOJURE
-                .require("elle.rw-register")
+        Self {
+            ns: JvmExecutor::global()
+                .call_blocking(|_| CLOJURE.require("elle.rw-register"))
                 .expect("elle.rw-register ns should be available"),
-        })
+            chunk: HISTORY_CHUNK,
+        }
+    }
+}
+
+impl ElleRwChecker {
+    /// The checker of `elle.list-append`, for the histories of
+    /// [`ElleRwGenerator::list_append`](crate::generator::elle_rw::ElleRwGenerator::list_append).
+    pub fn list_append() -> Self {
+        Self {
+            ns: JvmExecutor::global()
+                .call_blocking(|_| CLOJURE.require("elle.list-append"))
+                .expect("elle.list-append ns should be available"),
+            chunk: HISTORY_CHUNK,
+        }
+    }
+
+    /// Send the history to the JVM `n` items at a time, appended to the
+    /// clojure vector of the history chunk by chunk, so the EDN of only one
+    /// chunk is held besides the history, rather than of the whole history.
+    pub fn chunk(mut self, n: usize) -> Self {
+        self.chunk = n.max(1);
+        self
+    }
+
+    /// Build the clojure vector of the history on the JVM thread, chunk by
+    /// chunk.
+    fn send_history<F: Serialize, ERR: Serialize>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+    ) -> anyhow::Result<Remote<Option<Instance>>> {
+        let vector =
+            JvmHandle::global().try_remote(|_| Ok::<_, FfiError>(Some(clj_from_edn("[]")?)))?;
+        for chunk in history.0.chunks(self.chunk) {
+            // serialized here, the JVM thread only reads the EDN
+            let edn = to_edn(&untagged(chunk))?;
+            vector.with(move |_, vector| -> Result<_, FfiError> {
+                let prev = vector.take().expect("the history vector is built");
+                *vector = Some(cljinvoke!("into", prev, clj_from_edn(&edn)?)?);
+                Ok(())
+            })?;
+        }
+        Ok(vector)
     }
 }
 
 impl super::Check for ElleRwChecker {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "elle",
+            skip_all,
+            fields(items = history.0.len(), model = ?option.consistency_models)
+        )
+    )]
     fn check<F: Serialize, ERR: Serialize>(
         &self,
         history: &SerializableHistoryList<F, ERR>,
         option: CheckOption,
     ) -> anyhow::Result<SerializableCheckResult> {
-        with_jvm(|_| {
-            let h = historify(Instance::from_ser(history)?)?;
-            trace!("historify done");
-            info!("check with option: {:?}", serde_json::to_string(&option));
-            let op_clj = Instance::from_ser(option)?;
-            let res = nsinvoke!(self.ns, "check", op_clj, h)?;
+        info!("check with option: {:?}", serde_json::to_string(&option));
+        let vector = self.send_history(history)?;
+        trace!("history sent in chunks of {}", self.chunk);
+        let (option, ns) = (to_edn(&option)?, self.ns.clone());
+        vector.with(move |_, vector| {
+            // the errors of clojure are `FfiError`s, to be downcasted by callers
+            let res = || -> Result<_, FfiError> {
+                let h = historify(vector.take().expect("the history vector is built"))?;
+                trace!("historify done");
+                let op_clj = clj_from_edn(&option)?;
+                Ok(nsinvoke!(ns, "check", op_clj, h)?)
+            }()?;
             trace!("check done");
-            res.to_de::<SerializableCheckResult>()
+            res.to_de_edn::<SerializableCheckResult>()
         })
     }
+
+    fn requires_jvm(&self) -> bool {
+        true
+    }
 }
 
 #[cfg(test)]
@@ -69,7 +138,33 @@ mod tests {
                 .analyzer("wr-graph"),
         )?;
         println!("{:#?}", res);
+        // the same history sent an item at a time
+        let chunked = ElleRwChecker::default().chunk(1).check(
+            &history,
+            CheckOption::default()
+                .consistency_models(ConsistencyModel::Serializable)
+                .analyzer("wr-graph"),
+        )?;
+        assert_eq!(chunked.anomaly_types(), res.anomaly_types());
         // assert!(res.valid);
         Ok(())
     }
+
+    #[test]
+    fn test_list_append_checker() -> anyhow::Result<()> {
+        log_init();
+        let history_str = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["append", 2, 1]], "time": 1, "process": 0, "error": null },
+          { "index": 1, "type": "ok", "f": "txn", "value": [["append", 2, 1]], "time": 2, "process": 0, "error": null },
+          { "index": 2, "type": "invoke", "f": "txn", "value": [["r", 2, null]], "time": 3, "process": 1, "error": null },
+          { "index": 3, "type": "ok", "f": "txn", "value": [["r", 2, [1]]], "time": 4, "process": 1, "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(history_str)?;
+        let res = ElleRwChecker::list_append().check(
+            &history,
+            CheckOption::default().consistency_models(ConsistencyModel::Serializable),
+        )?;
+        assert!(matches!(res.valid(), crate::checker::ValidType::True));
+        Ok(())
+    }
 }
@@ -1,4 +1,8 @@
+pub mod bank;
+pub mod composite;
+#[cfg(feature = "clojure")]
 pub mod elle_rw;
+pub mod resolver;
 use std::path::PathBuf;
 
 use anyhow::Result;
@@ -33,11 +37,19 @@ pub struct CheckOption {
     #[serde(default = "default_out_dir")]
     directory: PathBuf,
     #[builder(into)]
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::convert::edn::keywords"
+    )]
     anomalies: Option<Vec<String>>,
     #[builder(into)]
     #[serde(skip_serializing_if = "Option::is_none")]
     analyzer: Option<String>,
+    /// The timeout of searching cycles in the dependency graph, in
+    /// milliseconds.
+    #[builder(into)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cycle_search_timeout: Option<u64>,
 }
 
 impl Default for CheckOption {
@@ -47,10 +59,30 @@ impl Default for CheckOption {
             directory: default_out_dir(),
             anomalies: None,
             analyzer: None,
+            cycle_search_timeout: None,
         }
     }
 }
 
+impl CheckOption {
+    /// The output directory.
+    pub(crate) fn out_dir(&self) -> &std::path::Path {
+        &self.directory
+    }
+}
+
+impl SerializableCheckResult {
+    /// The `:valid?` of the result.
+    pub fn valid(&self) -> &ValidType {
+        &self.valid
+    }
+
+    /// The types of the anomalies found, e.g. `G1c`.
+    pub fn anomaly_types(&self) -> &[String] {
+        &self.anomaly_types
+    }
+}
+
 /// `:valid?` value in `check` result
 #[derive(Debug, Clone)]
 pub enum ValidType {
@@ -93,7 +125,7 @@ impl Serialize for ValidType {
 }
 
 /// canonical-model-names in src/elle/consistency_model.clj
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum ConsistencyModel {
     ConsistentView,
@@ -119,12 +151,19 @@ pub enum ConsistencyModel {
 
 /// Checker trait
 pub trait Check {
-    /// The check function, returns a map like `{:valid? true}`
-    fn check<F: Serialize, ERR: Serialize>(
+    /// The check function, returns a map like `{:valid? true}`. The history
+    /// is `Sync` so the sub-checks of [`composite`] checkers share it.
+    fn check<F: Serialize + Sync, ERR: Serialize + Sync>(
         &self,
         history: &SerializableHistoryList<F, ERR>,
         option: CheckOption,
     ) -> Result<SerializableCheckResult>;
+
+    /// Whether the check runs on the JVM, so it's not run concurrently with
+    /// the other checks on the JVM, see [`composite`]. False by default.
+    fn requires_jvm(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
@@ -142,10 +181,11 @@ mod tests {
     fn test_check_option_serialization() {
         let option = CheckOption::default()
             .analyzer("wr-graph")
-            .consistency_models(ConsistencyModel::CursorStability);
+            .consistency_models(ConsistencyModel::CursorStability)
+            .cycle_search_timeout(1000u64);
         let json = serde_json::to_string(&option).unwrap();
         assert_eq!(
-            r#"{"consistency-models":"cursor-stability","directory":"./out","analyzer":"wr-graph"}"#,
+            r#"{"consistency-models":"cursor-stability","directory":"./out","analyzer":"wr-graph","cycle-search-timeout":1000}"#,
             json
         );
     }
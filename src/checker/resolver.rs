@@ -0,0 +1,118 @@
+//! Resolvers of [`CheckOption`], which are evaluated just before checking, so
+//! that the check options can be changed at run time (e.g. by the CI policy)
+//! without recompiling the test.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+
+use super::{CheckOption, ConsistencyModel};
+
+/// Resolve the final [`CheckOption`] from the one set in the test.
+pub trait CheckOptionResolver: Send + Sync {
+    fn resolve(&self, option: CheckOption) -> Result<CheckOption>;
+}
+
+impl<F: Fn(CheckOption) -> Result<CheckOption> + Send + Sync> CheckOptionResolver for F {
+    fn resolve(&self, option: CheckOption) -> Result<CheckOption> {
+        self(option)
+    }
+}
+
+/// Overrides the check options by environment variables. With the default
+/// prefix `JEPSEN_CHECK_`, the variables are:
+///
+/// - `JEPSEN_CHECK_CONSISTENCY_MODELS`: a kebab-case model name, e.g.
+///   `snapshot-isolation`
+/// - `JEPSEN_CHECK_ANOMALIES`: comma separated anomaly names, e.g. `G0,G1c`
+/// - `JEPSEN_CHECK_ANALYZER`: the analyzer name, e.g. `wr-graph`
+/// - `JEPSEN_CHECK_DIRECTORY`: the output directory
+/// - `JEPSEN_CHECK_CYCLE_SEARCH_TIMEOUT`: the cycle search timeout in
+///   milliseconds
+///
+/// An unset variable keeps the option unchanged.
+#[derive(Debug, Clone)]
+pub struct EnvResolver {
+    prefix: String,
+}
+
+impl Default for EnvResolver {
+    fn default() -> Self {
+        Self::new("JEPSEN_CHECK_")
+    }
+}
+
+impl EnvResolver {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(format!("{}{}", self.prefix, name)).ok()
+    }
+}
+
+impl CheckOptionResolver for EnvResolver {
+    fn resolve(&self, mut option: CheckOption) -> Result<CheckOption> {
+        if let Some(model) = self.var("CONSISTENCY_MODELS") {
+            let model: ConsistencyModel = serde_json::from_value(model.clone().into())
+                .map_err(|_| anyhow!("unknown consistency model `{}`", model))?;
+            option.consistency_models = Some(model);
+        }
+        if let Some(anomalies) = self.var("ANOMALIES") {
+            option.anomalies = Some(anomalies.split(',').map(|s| s.trim().to_string()).collect());
+        }
+        if let Some(analyzer) = self.var("ANALYZER") {
+            option.analyzer = Some(analyzer);
+        }
+        if let Some(directory) = self.var("DIRECTORY") {
+            option.directory = PathBuf::from(directory);
+        }
+        if let Some(timeout) = self.var("CYCLE_SEARCH_TIMEOUT") {
+            option.cycle_search_timeout = Some(
+                timeout
+                    .parse()
+                    .with_context(|| format!("invalid cycle search timeout `{}`", timeout))?,
+            );
+        }
+        Ok(option)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_resolver() -> Result<()> {
+        let prefix = "JEPSEN_RS_TEST_RESOLVER_";
+        std::env::set_var(
+            format!("{}CONSISTENCY_MODELS", prefix),
+            "snapshot-isolation",
+        );
+        std::env::set_var(format!("{}ANOMALIES", prefix), "G0, G1c");
+        std::env::set_var(format!("{}CYCLE_SEARCH_TIMEOUT", prefix), "500");
+        let option =
+            EnvResolver::new(prefix).resolve(CheckOption::default().analyzer("wr-graph"))?;
+        assert_eq!(
+            serde_json::to_string(&option)?,
+            r#"{"consistency-models":"snapshot-isolation","directory":"./out","anomalies":["G0","G1c"],"analyzer":"wr-graph","cycle-search-timeout":500}"#
+        );
+
+        std::env::set_var(format!("{}CONSISTENCY_MODELS", prefix), "no-such-model");
+        assert!(EnvResolver::new(prefix)
+            .resolve(CheckOption::default())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_closure_resolver() -> Result<()> {
+        let resolver = |option: CheckOption| Ok(option.analyzer("realtime"));
+        let option = resolver.resolve(CheckOption::default())?;
+        assert_eq!(option.analyzer.as_deref(), Some("realtime"));
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! The checkpoints of a run, so a harness process interrupted in the middle
+//! of a long run can be restarted by [`Test::resume`] to go on with the run,
+//! appending to the same history in the same directory rather than starting
+//! over:
+//!
+//! ```ignore
+//! let test = || TestBuilder::new(cluster, workload).seed(7).out_dir("store")
+//!     .checkpoint(Duration::from_secs(10));
+//! // in the restarted process
+//! let report = test().build().resume("store/test/20241015T120000.000Z")?;
+//! ```
+//!
+//! A checkpoint is taken between the items of the generators, and the run
+//! resumes from the last one: the history after it is dropped, and the items
+//! of the generators taken after it are taken again. So the generators must be
+//! deterministic by the seed, and the ops of the interval before the
+//! interruption may run twice on the cluster, which the interval bounds.
+//!
+//! [`Test::resume`]: crate::runner::Test::resume
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::nemesis::NemesisRecord;
+
+/// The file in the output directory of the last checkpoint.
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// The state of a run to resume it from.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Checkpoint {
+    /// The seed of the runtime of the run.
+    pub seed: u64,
+    /// The number of the items taken from every generator, by its id.
+    pub positions: BTreeMap<u64, usize>,
+    /// The nemeses active on the cluster, to heal them after resuming.
+    pub active_nemeses: Vec<NemesisRecord>,
+    /// The number of the history items, which are kept on resuming.
+    pub history_offset: u64,
+    /// The time since the start of the run, in nanoseconds.
+    pub time: u64,
+    /// The next process number, the processes after resuming are new.
+    pub next_process: u64,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint in the directory of a run.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(CHECKPOINT_FILE);
+        let json = std::fs::read(&path).with_context(|| format!("no checkpoint at {:?}", path))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// Save the checkpoint in the directory of a run, replacing the last one
+    /// atomically.
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let path = dir.as_ref().join(CHECKPOINT_FILE);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let checkpoint = Checkpoint {
+            seed: 7,
+            positions: BTreeMap::from([(0, 3), (1, 2)]),
+            active_nemeses: vec![NemesisRecord::Kill(HashSet::from([1]))],
+            history_offset: 11,
+            time: 1_000_000,
+            next_process: 2,
+        };
+        checkpoint.save(&dir)?;
+        assert_eq!(Checkpoint::load(&dir)?, checkpoint);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+//! The `jepsen-rs` command line, enabled by the `cli` feature, to operate the
+//! harness without writing Rust:
+//!
+//! ```text
+//! jepsen-rs run <config.toml> [--seed <seed>] [--out <dir>]
+//! jepsen-rs check <history> [--model <model>]... [--out <dir>]
+//! jepsen-rs report <dir>
+//! jepsen-rs preflight <config.toml> [--out <dir>]
+//! ```
+//!
+//! - `run` runs the test of a [`TestConfig`] against the cluster of its
+//!   `adapter`, and checks the history;
+//! - `check` checks a `history.jsonl` or `history.edn`, or the history in a
+//!   run directory of a store, once for every consistency model;
+//! - `report` renders the stats and the fault timeline of a run directory of
+//!   a [`Store`](crate::store::Store);
+//! - `preflight` checks the environment and the cluster of the test of a
+//!   [`TestConfig`] without running it, see [`crate::preflight`].
+//!
+//! The exit code is 0 if the histories are valid, or the preflight checks
+//! pass, 1 if not and 2 on errors.
+//! The binary knows the `mock` adapter, and `etcd` and `redis` with their
+//! features. Other adapters are plugged in by a binary of their own:
+//!
+//! ```ignore
+//! fn main() -> std::process::ExitCode {
+//!     Cli::default()
+//!         .adapter("mydb", |config| Ok(MyCluster::connect(&config.nodes)?))
+//!         .main()
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+
+use crate::{
+    checker::{elle_rw::ElleRwChecker, Check, CheckOption, ConsistencyModel, ValidType},
+    config::TestConfig,
+    history::{HistoryProcess, HistoryType, SerializableHistoryList},
+    mock::MockCluster,
+    op::OpOrNemesisFuncType,
+    perf::{latency_stats, node_stats},
+    preflight::PreflightReport,
+    runner::{TestCluster, TestReport},
+    utils::serialized_name,
+};
+
+const USAGE: &str = "\
+usage:
+  jepsen-rs run <config.toml> [--seed <seed>] [--out <dir>]
+  jepsen-rs check <history> [--model <model>]... [--out <dir>]
+  jepsen-rs report <dir>
+  jepsen-rs preflight <config.toml> [--out <dir>]";
+
+/// A parsed command line, see the [module](self) doc.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run {
+        config: PathBuf,
+        seed: Option<u64>,
+        out_dir: Option<PathBuf>,
+    },
+    Check {
+        history: PathBuf,
+        models: Vec<ConsistencyModel>,
+        out_dir: Option<PathBuf>,
+    },
+    Report {
+        dir: PathBuf,
+    },
+    Preflight {
+        config: PathBuf,
+        out_dir: Option<PathBuf>,
+    },
+    Help,
+}
+
+impl Command {
+    /// Parse the arguments, without the name of the binary.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter();
+        let Some(sub) = args.next() else {
+            return Ok(Self::Help);
+        };
+        let mut path = None;
+        let (mut seed, mut out_dir, mut models) = (None, None, vec![]);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value of {}", arg))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Self::Help),
+                "--seed" if sub == "run" => seed = Some(value()?.parse()?),
+                "--out" if sub != "report" => out_dir = Some(PathBuf::from(value()?)),
+                "--model" if sub == "check" => {
+                    let model = value()?;
+                    models.push(
+                        serde_json::from_value(Value::String(model.clone()))
+                            .map_err(|_| anyhow!("unknown consistency model `{}`", model))?,
+                    );
+                }
+                _ if arg.starts_with('-') => bail!("unknown option `{}` of `{}`", arg, sub),
+                _ if path.is_none() => path = Some(PathBuf::from(arg)),
+                _ => bail!("unexpected argument `{}`", arg),
+            }
+        }
+        let path = || {
+            path.clone()
+                .ok_or_else(|| anyhow!("missing the path of `{}`", sub))
+        };
+        Ok(match sub.as_str() {
+            "run" => Self::Run {
+                config: path()?,
+                seed,
+                out_dir,
+            },
+            "check" => Self::Check {
+                history: path()?,
+                models,
+                out_dir,
+            },
+            "report" => Self::Report { dir: path()? },
+            "preflight" => Self::Preflight {
+                config: path()?,
+                out_dir,
+            },
+            "help" | "-h" | "--help" => Self::Help,
+            _ => bail!("unknown command `{}`", sub),
+        })
+    }
+}
+
+/// The outcome of an adapter, by whether it's asked to only check.
+enum Outcome {
+    Run(Box<TestReport>),
+    Preflight(PreflightReport),
+}
+
+type Adapter = Box<dyn Fn(TestConfig, bool) -> Result<Outcome>>;
+
+/// The command line with its adapters, see the [module](self) doc.
+pub struct Cli {
+    adapters: BTreeMap<String, Adapter>,
+}
+
+impl Default for Cli {
+    /// The command line with the builtin adapters: `mock`, a [`MockCluster`]
+    /// of as many servers as nodes (3 if none), and `etcd` and `redis` with
+    /// their features, whose nodes are socket addresses.
+    fn default() -> Self {
+        let cli = Self::new().adapter("mock", |config| {
+            Ok(MockCluster::new(match config.nodes.len() {
+                0 => 3,
+                n => n,
+            }))
+        });
+        #[cfg(feature = "etcd")]
+        let cli = cli.adapter("etcd", |config| {
+            Ok(crate::adapter::etcd::EtcdClusterClient::new(addrs(
+                &config.nodes,
+            )?))
+        });
+        #[cfg(feature = "redis")]
+        let cli = cli.adapter("redis", |config| {
+            Ok(crate::adapter::redis::RedisClusterClient::new(addrs(
+                &config.nodes,
+            )?))
+        });
+        cli
+    }
+}
+
+#[cfg(any(feature = "etcd", feature = "redis"))]
+fn addrs(nodes: &[String]) -> Result<Vec<std::net::SocketAddr>> {
+    nodes
+        .iter()
+        .map(|node| {
+            node.parse()
+                .map_err(|err| anyhow!("invalid node `{}`: {}", node, err))
+        })
+        .collect()
+}
+
+impl Cli {
+    /// The command line without any adapter.
+    pub fn new() -> Self {
+        Self {
+            adapters: BTreeMap::new(),
+        }
+    }
+
+    /// Add the adapter of the name, which creates the cluster of a config.
+    /// The cluster is created before the runtime of the test, so it should
+    /// not create madsim nodes.
+    pub fn adapter<EC: TestCluster>(
+        mut self,
+        name: impl Into<String>,
+        cluster: impl Fn(&TestConfig) -> Result<EC> + 'static,
+    ) -> Self {
+        let run = move |config: TestConfig, preflight: bool| {
+            let cluster = cluster(&config)?;
+            let test = config.builder(move || cluster)?.build();
+            if preflight {
+                return Ok(Outcome::Preflight(test.preflight()));
+            }
+            Ok(Outcome::Run(Box::new(test.run()?)))
+        };
+        self.adapters.insert(name.into(), Box::new(run));
+        self
+    }
+
+    /// Parse the arguments of the process, execute the command and print its
+    /// outcome.
+    pub fn main(&self) -> ExitCode {
+        let res = Command::parse(std::env::args().skip(1)).and_then(|cmd| self.execute(cmd));
+        match res {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(1),
+            Err(err) => {
+                eprintln!("error: {:#}", err);
+                ExitCode::from(2)
+            }
+        }
+    }
+
+    /// Run the test of the config by its adapter, or only check it.
+    fn adapt(&self, config: TestConfig, preflight: bool) -> Result<Outcome> {
+        let name = config
+            .adapter
+            .clone()
+            .ok_or_else(|| anyhow!("the config sets no adapter"))?;
+        let adapter = self.adapters.get(&name).ok_or_else(|| {
+            anyhow!(
+                "unknown adapter `{}`, available: {}",
+                name,
+                self.adapters.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        adapter(config, preflight)
+    }
+
+    /// Execute the command, returns whether the histories are valid.
+    pub fn execute(&self, command: Command) -> Result<bool> {
+        match command {
+            Command::Run {
+                config,
+                seed,
+                out_dir,
+            } => {
+                let mut config = TestConfig::load(config)?;
+                config.seed = seed.unwrap_or(config.seed);
+                config.out_dir = out_dir.or(config.out_dir);
+                let Outcome::Run(report) = self.adapt(config, false)? else {
+                    unreachable!("the test is run");
+                };
+                println!(
+                    "{} (seed {}) finished in {:?}: valid? {}",
+                    report.name,
+                    report.seed,
+                    report.elapsed,
+                    valid_str(report.result.valid())
+                );
+                if !report.result.anomaly_types().is_empty() {
+                    println!("anomalies: {}", report.result.anomaly_types().join(", "));
+                }
+                if let Some(dir) = &report.out_dir {
+                    println!("saved in {}", dir.display());
+                }
+                Ok(report.is_valid())
+            }
+            Command::Check {
+                history,
+                models,
+                out_dir,
+            } => check(&history, models, out_dir),
+            Command::Report { dir } => {
+                print!("{}", report(&dir)?);
+                Ok(true)
+            }
+            Command::Preflight { config, out_dir } => {
+                let mut config = TestConfig::load(config)?;
+                config.out_dir = out_dir.or(config.out_dir);
+                let Outcome::Preflight(report) = self.adapt(config, true)? else {
+                    unreachable!("the test is only checked");
+                };
+                print!("{}", report);
+                Ok(report.is_ok())
+            }
+            Command::Help => {
+                println!("{}", USAGE);
+                println!("adapters: {}", {
+                    let names: Vec<_> = self.adapters.keys().cloned().collect();
+                    names.join(", ")
+                });
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn valid_str(valid: &ValidType) -> &'static str {
+    match valid {
+        ValidType::True => "true",
+        ValidType::False => "false",
+        ValidType::Unknown => "unknown",
+    }
+}
+
+/// The history file of the path: the path itself, or the `history.jsonl` or
+/// `history.edn` of a run directory.
+fn history_file(path: &Path) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    ["history.jsonl", "history.edn"]
+        .into_iter()
+        .map(|name| path.join(name))
+        .find(|file| file.exists())
+        .ok_or_else(|| anyhow!("no history in {}", path.display()))
+}
+
+/// Check the history by elle for every model, or once by the default option
+/// if there is none.
+fn check(path: &Path, models: Vec<ConsistencyModel>, out_dir: Option<PathBuf>) -> Result<bool> {
+    let file = history_file(path)?;
+    let mut options: Vec<_> = models
+        .into_iter()
+        .map(|model| CheckOption::default().consistency_models(model))
+        .collect();
+    if options.is_empty() {
+        options.push(CheckOption::default());
+    }
+    let checker = ElleRwChecker::default();
+    let mut all_valid = true;
+    for mut option in options {
+        if let Some(dir) = &out_dir {
+            option = option.directory(dir.clone());
+        }
+        let name = serde_json::to_value(&option)?
+            .get("consistency-models")
+            .map_or("default".to_string(), |m| {
+                m.as_str().unwrap_or_default().to_string()
+            });
+        let result = if file.extension().is_some_and(|ext| ext == "edn") {
+            checker.check(&SerializableHistoryList::from_jepsen_store(&file)?, option)?
+        } else {
+            let history: SerializableHistoryList<OpOrNemesisFuncType, Value> =
+                SerializableHistoryList::from_jsonl(&file)?;
+            checker.check(&history, option)?
+        };
+        println!("{}: valid? {}", name, valid_str(result.valid()));
+        if !result.anomaly_types().is_empty() {
+            println!("  anomalies: {}", result.anomaly_types().join(", "));
+        }
+        all_valid &= matches!(result.valid(), ValidType::True);
+    }
+    Ok(all_valid)
+}
+
+fn ms(nanos: f64) -> f64 {
+    nanos / 1e6
+}
+
+/// Render the stats and the fault timeline of the `history.jsonl` in the run
+/// directory, and the validity in its `results.json` if any.
+pub fn report(dir: &Path) -> Result<String> {
+    let history: SerializableHistoryList<OpOrNemesisFuncType, Value> =
+        SerializableHistoryList::from_jsonl(dir.join("history.jsonl"))?;
+    let mut out = String::new();
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut nemesis = 0;
+    for item in history.0.iter() {
+        if item.process == HistoryProcess::Nemesis {
+            nemesis += 1;
+            continue;
+        }
+        let type_ = match item.type_ {
+            HistoryType::Invoke => "invoke",
+            HistoryType::Ok => "ok",
+            HistoryType::Fail => "fail",
+            HistoryType::Info => "info",
+        };
+        *counts.entry(type_).or_default() += 1;
+    }
+    writeln!(
+        out,
+        "history: {} items, {} of the nemesis",
+        history.0.len(),
+        nemesis
+    )?;
+    for (type_, count) in counts {
+        writeln!(out, "  {:<8}{:>8}", type_, count)?;
+    }
+
+    writeln!(out, "latency (ms):")?;
+    writeln!(
+        out,
+        "  {:<12}{:>8}{:>10}{:>10}{:>10}{:>10}{:>10}",
+        "f", "count", "mean", "p50", "p90", "p99", "max"
+    )?;
+    for (f, stats) in latency_stats(&history) {
+        writeln!(
+            out,
+            "  {:<12}{:>8}{:>10.3}{:>10.3}{:>10.3}{:>10.3}{:>10.3}",
+            serialized_name(f),
+            stats.count,
+            ms(stats.mean),
+            ms(stats.p50 as f64),
+            ms(stats.p90 as f64),
+            ms(stats.p99 as f64),
+            ms(stats.max as f64),
+        )?;
+    }
+
+    let nodes = node_stats(&history);
+    if !nodes.is_empty() {
+        writeln!(out, "nodes:")?;
+        for (node, stats) in nodes {
+            writeln!(
+                out,
+                "  {:<4} ok {} fail {} info {} retries {}",
+                node, stats.ok, stats.fail, stats.info, stats.retries
+            )?;
+        }
+    }
+
+    let windows = history.fault_windows();
+    writeln!(out, "faults:")?;
+    for window in windows {
+        let end = match window.end {
+            Some(end) => format!("{:.3}s", end as f64 / 1e9),
+            None => "active".to_string(),
+        };
+        writeln!(
+            out,
+            "  {:>10.3}s - {:>10}  {:<16}{}",
+            window.start as f64 / 1e9,
+            end,
+            serialized_name(window.f),
+            window.nemesis
+        )?;
+    }
+
+    let results = dir.join("results.json");
+    if results.exists() {
+        let result: crate::checker::SerializableCheckResult =
+            serde_json::from_slice(&std::fs::read(results)?)?;
+        writeln!(out, "valid? {}", valid_str(result.valid()))?;
+        if !result.anomaly_types().is_empty() {
+            writeln!(out, "anomalies: {}", result.anomaly_types().join(", "))?;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn test_parse_command() -> Result<()> {
+        assert_eq!(Command::parse(args(""))?, Command::Help);
+        assert_eq!(
+            Command::parse(args("run a.toml --seed 3"))?,
+            Command::Run {
+                config: "a.toml".into(),
+                seed: Some(3),
+                out_dir: None,
+            }
+        );
+        let Command::Check { models, .. } = Command::parse(args(
+            "check store/latest --model serializable --model read-committed",
+        ))?
+        else {
+            panic!("not a check command");
+        };
+        assert_eq!(models.len(), 2);
+        assert!(Command::parse(args("check h.edn --model nope")).is_err());
+        assert_eq!(
+            Command::parse(args("preflight a.toml --out /tmp/store"))?,
+            Command::Preflight {
+                config: "a.toml".into(),
+                out_dir: Some("/tmp/store".into()),
+            }
+        );
+        assert!(Command::parse(args("preflight")).is_err());
+        assert!(Command::parse(args("report")).is_err());
+        assert!(Command::parse(args("report dir --seed 1")).is_err());
+        assert!(Command::parse(args("fly")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_report() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-cli-report-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("history.jsonl"),
+            concat!(
+                r#"{"index":0,"type":"invoke","f":"txn","value":[["w",1,1]],"time":0,"process":0,"error":null}"#,
+                "\n",
+                r#"{"index":1,"type":"info","f":"kill","value":{"nemesis":"Kill({1})","servers":[1]},"time":1000,"process":"nemesis","error":null}"#,
+                "\n",
+                r#"{"index":2,"type":"ok","f":"txn","value":[["w",1,1]],"time":2000000,"process":0,"error":null}"#,
+                "\n",
+            ),
+        )?;
+        let report = report(&dir)?;
+        assert!(report.contains("history: 3 items, 1 of the nemesis"));
+        assert!(report.contains("  txn                1     2.000"));
+        assert!(report.contains("active  kill            Kill({1})"));
+        assert!(!report.contains("valid?"));
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -1,56 +1,891 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write as _,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
 
 use anyhow::Result;
-use log::{debug, info, trace};
+use futures_util::{
+    future::{self, BoxFuture, Either},
+    FutureExt as _,
+};
+use log::{debug, error, info, trace, warn};
+use tokio::sync::watch;
 
+#[cfg(feature = "clojure")]
+use crate::checker::elle_rw::ElleRwChecker;
 use crate::{
-    checker::{elle_rw::ElleRwChecker, Check, CheckOption, SerializableCheckResult},
-    generator::{Generator, GeneratorBuilder, GeneratorGroup, Global, RawGenerator},
-    history::HistoryType,
-    op::Op,
-    utils::AsyncIter,
+    checker::{resolver::CheckOptionResolver, Check, CheckOption, SerializableCheckResult},
+    checkpoint::Checkpoint,
+    generator::{
+        nemesis_mix::NemesisMix, Generator, GeneratorBuilder, GeneratorGroup, Global, RawGenerator,
+        RawGeneratorMap,
+    },
+    history::{
+        HistoryProcess, HistorySpill, HistoryType, HistoryWriter, NemesisValue, OpError, OpMeta,
+        SerializableHistoryList,
+    },
+    interceptor::OpInterceptor,
+    nemesis::{
+        plan::NemesisPlanReport,
+        policy::NemesisPolicy,
+        register::{NemesisRegister, NemesisRegisterStrategy},
+        schedule::{NemesisSchedule, ScheduledNemesis},
+        NemesisClusterClient, NemesisRecord, NemesisType, NodeHealth, SerializableNemesisType,
+        ServerId,
+    },
+    op::{Op, OpOrNemesis, OpOrNemesisFuncType, OpTags},
+    preflight::{self, PreflightReport, PreflightStatus},
+    progress::ProgressReporter,
+    replay::Replay,
+    retry::RetryPolicy,
+    runner::TestCluster,
+    runtime::{self, TimeScale},
+    store::Store,
+    utils::{panic_message, AsyncIter},
+    watchdog::{StallReport, Watchdog, STALL_FILE},
+    workload::{Workload, WorkloadOptions},
+    Error,
 };
 
+/// The file in the output directory to save the activation intervals of
+/// nemeses.
+pub const FAULT_INTERVALS_FILE: &str = "nemesis-intervals.json";
+
+/// An async callback on a nemesis record, see
+/// [`JepsenClient::on_nemesis_start`] and [`JepsenClient::on_nemesis_heal`].
+pub type NemesisHook = Box<dyn Fn(NemesisRecord) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Maps a process (the id of a generator) and the size of the cluster to the
+/// node the process is bound to, see [`JepsenClient::with_node_for_process`].
+pub type NodeForProcess = Box<dyn Fn(u64, usize) -> ServerId + Send + Sync>;
+
+/// The error of a cas op whose expected value mismatches, which is recorded
+/// as `:fail`.
+pub const CAS_MISMATCH: &str = "cas mismatch";
+
+/// The error of an op cancelled by [`RunHandle::abort`], which is recorded as
+/// `:info`.
+pub const RUN_ABORTED: &str = "cancelled as the run is stopped";
+
+/// The prefix of the error of an op whose call panicked in the harness, which
+/// is recorded as `:info` followed by the panic message.
+pub const HARNESS_PANIC: &str = "harness panicked";
+
+/// How far a run is asked to stop before its generators are exhausted, see
+/// [`RunHandle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stop {
+    None,
+    /// Stop generating, and let the in-flight ops complete.
+    Finish,
+    /// Stop generating, and cancel the in-flight ops.
+    Abort,
+}
+
+/// Wait until the run is asked to stop at least as far as `stop`.
+async fn stopped(stop: &watch::Sender<Stop>, at_least: Stop) {
+    // the sender outlives the receiver, so it never fails
+    let _ = stop.subscribe().wait_for(|s| *s >= at_least).await;
+}
+
+/// Ask the run to stop generating, unless it's aborted.
+fn finish_early(stop: &watch::Sender<Stop>) {
+    stop.send_if_modified(|s| {
+        let modified = *s < Stop::Finish;
+        *s = (*s).max(Stop::Finish);
+        modified
+    });
+}
+
+/// The interval of the simulated time to pace it, see [`TimeScale`].
+const PACE_TICK: Duration = Duration::from_millis(10);
+
+/// The handle of a run started by [`JepsenClient::run_detached`], to stop it
+/// early, e.g. on Ctrl-C or by an external supervisor. A stopped run heals
+/// all active nemeses and checks the partial history, so it still ends with
+/// a check result.
+pub struct RunHandle {
+    stop: Arc<watch::Sender<Stop>>,
+    task: madsim::task::JoinHandle<crate::Result<SerializableCheckResult>>,
+}
+
+impl RunHandle {
+    /// Stop generating and wait for the in-flight ops to complete.
+    pub fn finish_early(&self) {
+        finish_early(&self.stop);
+    }
+
+    /// Stop generating and cancel the in-flight ops, which are recorded as
+    /// `:info`. The nemeses being executed are not cancelled.
+    pub fn abort(&self) {
+        self.stop.send_replace(Stop::Abort);
+    }
+
+    /// Whether the run, including its check, is finished.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Wait for the run to finish, and get its check result.
+    pub async fn join(self) -> crate::Result<SerializableCheckResult> {
+        self.task
+            .await
+            .map_err(|err| Error::Other(anyhow::anyhow!("the run panicked: {}", err)))?
+    }
+}
+
+/// Heals all the active nemeses of the client when dropped armed, i.e. when
+/// the run panics or is cancelled before healing them.
+struct HealGuard<EC: TestCluster>(Option<&'static JepsenClient<EC>>);
+
+impl<EC: TestCluster> HealGuard<EC> {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl<EC: TestCluster> Drop for HealGuard<EC> {
+    fn drop(&mut self) {
+        let Some(client) = self.0.take() else {
+            return;
+        };
+        // a simulation is torn down with its nodes, but a real cluster is left
+        // broken
+        if runtime::is_simulated() {
+            return;
+        }
+        warn!("the run ended before healing the nemeses, heal them now");
+        // the runtime of the run may be gone or stuck in the panic, so heal in
+        // a runtime of its own
+        let res = std::thread::spawn(move || runtime::block_on(0, client.heal_all())).join();
+        if !matches!(res, Ok(Ok(()))) {
+            warn!("failed to heal the nemeses");
+        }
+    }
+}
+
 /// The interface of a cluster client, needs to be implemented by the external
 /// user.
 #[async_trait::async_trait]
-pub trait ElleRwClusterClient {
+pub trait ElleRwClusterClient: Sync {
     async fn get(&self, key: u64) -> std::result::Result<Option<u64>, String>;
     async fn put(&self, key: u64, value: u64) -> std::result::Result<(), String>;
+    /// Set the key to `new` if its value is `expect`, returns whether it's
+    /// set. Not supported by default.
+    async fn cas(&self, key: u64, expect: u64, new: u64) -> std::result::Result<bool, String> {
+        let _ = (key, expect, new);
+        Err("cas is not supported by the cluster client".to_string())
+    }
+    /// Delete the key, so later reads of it return `None`. Not supported by
+    /// default.
+    async fn delete(&self, key: u64) -> std::result::Result<(), String> {
+        let _ = key;
+        Err("delete is not supported by the cluster client".to_string())
+    }
+    /// Add the delta to the counter of the key, which is 0 if it does not
+    /// exist. Not supported by default.
+    async fn incr(&self, key: u64, delta: i64) -> std::result::Result<(), String> {
+        let _ = (key, delta);
+        Err("incr is not supported by the cluster client".to_string())
+    }
+    /// Append the value to the list of the key, which is empty if it does not
+    /// exist. Not supported by default.
+    async fn append(&self, key: u64, value: u64) -> std::result::Result<(), String> {
+        let _ = (key, value);
+        Err("append is not supported by the cluster client".to_string())
+    }
+    /// Read the list of the key, empty if it does not exist. Not supported by
+    /// default.
+    async fn get_list(&self, key: u64) -> std::result::Result<Vec<u64>, String> {
+        let _ = key;
+        Err("list reads are not supported by the cluster client".to_string())
+    }
+    /// Read the keys at once, returns their values in order. The default
+    /// implementation reads them concurrently by [`ElleRwClusterClient::get`],
+    /// which is not atomic.
+    async fn batch_get(&self, keys: Vec<u64>) -> std::result::Result<Vec<Option<u64>>, String> {
+        futures_util::future::join_all(keys.into_iter().map(|key| self.get(key)))
+            .await
+            .into_iter()
+            .collect()
+    }
+    /// Read the existing keys in `[start, end)` with their values, ordered by
+    /// key. Not supported by default.
+    async fn scan(&self, start: u64, end: u64) -> std::result::Result<Vec<(u64, u64)>, String> {
+        let _ = (start, end);
+        Err("scan is not supported by the cluster client".to_string())
+    }
+    /// Execute the reads and writes of a txn, returns them with the read
+    /// values filled. The default implementation executes them concurrently
+    /// by [`ElleRwClusterClient::get`] and [`ElleRwClusterClient::put`], which
+    /// is not atomic; override it if the cluster supports transactions.
+    async fn txn(&self, ops: Vec<Op>) -> std::result::Result<Vec<Op>, String> {
+        futures_util::future::join_all(ops.into_iter().map(|op| async move {
+            match op {
+                Op::Read(key, _) => Ok(Op::Read(key, self.get(key).await?)),
+                Op::Write(key, value) => self.put(key, value).await.map(|_| op),
+                Op::Cas(key, expect, new) => match self.cas(key, expect, new).await? {
+                    true => Ok(op),
+                    false => Err(CAS_MISMATCH.to_string()),
+                },
+                Op::Delete(key) => self.delete(key).await.map(|_| op),
+                Op::Incr(key, delta) => self.incr(key, delta).await.map(|_| op),
+                Op::Append(key, value) => self.append(key, value).await.map(|_| op),
+                Op::ReadList(key, _) => Ok(Op::ReadList(key, Some(self.get_list(key).await?))),
+                Op::BatchRead(_) | Op::ScanRange(..) => {
+                    Err("multi-key reads cannot be in txns".to_string())
+                }
+                Op::Txn(_) => Err("txns cannot be nested".to_string()),
+            }
+        }))
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// [`ElleRwClusterClient::get`] on the node the process is bound to, like
+    /// a client of jepsen connecting to one node. The default implementation
+    /// ignores the node; override it to make session anomalies (e.g.
+    /// non-monotonic reads across nodes) observable.
+    async fn get_on(&self, _node: ServerId, key: u64) -> std::result::Result<Option<u64>, String> {
+        self.get(key).await
+    }
+    /// [`ElleRwClusterClient::put`] on the node the process is bound to.
+    async fn put_on(
+        &self,
+        _node: ServerId,
+        key: u64,
+        value: u64,
+    ) -> std::result::Result<(), String> {
+        self.put(key, value).await
+    }
+    /// [`ElleRwClusterClient::cas`] on the node the process is bound to.
+    async fn cas_on(
+        &self,
+        _node: ServerId,
+        key: u64,
+        expect: u64,
+        new: u64,
+    ) -> std::result::Result<bool, String> {
+        self.cas(key, expect, new).await
+    }
+    /// [`ElleRwClusterClient::delete`] on the node the process is bound to.
+    async fn delete_on(&self, _node: ServerId, key: u64) -> std::result::Result<(), String> {
+        self.delete(key).await
+    }
+    /// [`ElleRwClusterClient::incr`] on the node the process is bound to.
+    async fn incr_on(
+        &self,
+        _node: ServerId,
+        key: u64,
+        delta: i64,
+    ) -> std::result::Result<(), String> {
+        self.incr(key, delta).await
+    }
+    /// [`ElleRwClusterClient::append`] on the node the process is bound to.
+    async fn append_on(
+        &self,
+        _node: ServerId,
+        key: u64,
+        value: u64,
+    ) -> std::result::Result<(), String> {
+        self.append(key, value).await
+    }
+    /// [`ElleRwClusterClient::get_list`] on the node the process is bound to.
+    async fn get_list_on(
+        &self,
+        _node: ServerId,
+        key: u64,
+    ) -> std::result::Result<Vec<u64>, String> {
+        self.get_list(key).await
+    }
+    /// [`ElleRwClusterClient::batch_get`] on the node the process is bound to.
+    async fn batch_get_on(
+        &self,
+        _node: ServerId,
+        keys: Vec<u64>,
+    ) -> std::result::Result<Vec<Option<u64>>, String> {
+        self.batch_get(keys).await
+    }
+    /// [`ElleRwClusterClient::scan`] on the node the process is bound to.
+    async fn scan_on(
+        &self,
+        _node: ServerId,
+        start: u64,
+        end: u64,
+    ) -> std::result::Result<Vec<(u64, u64)>, String> {
+        self.scan(start, end).await
+    }
+    /// [`ElleRwClusterClient::txn`] on the node the process is bound to.
+    async fn txn_on(&self, _node: ServerId, ops: Vec<Op>) -> std::result::Result<Vec<Op>, String> {
+        self.txn(ops).await
+    }
+}
+
+/// Pair the keys of a batch read with the values read.
+fn batch_read_result(keys: Vec<u64>, values: Vec<Option<u64>>) -> std::result::Result<Op, String> {
+    if keys.len() != values.len() {
+        return Err(format!(
+            "the batch read returns {} values for {} keys",
+            values.len(),
+            keys.len()
+        ));
+    }
+    Ok(Op::BatchRead(keys.into_iter().zip(values).collect()))
+}
+
+/// The setup and teardown of the cluster around a test, e.g. creating the
+/// schema, wiping the data or collecting the logs. Both do nothing by
+/// default.
+#[async_trait::async_trait]
+pub trait ClusterLifecycle: Sync {
+    /// Called by [`Client::run`] before the first op is generated. The test
+    /// is not run if it fails.
+    async fn setup(&self) -> std::result::Result<(), String> {
+        Ok(())
+    }
+    /// Called by [`Client::run`] after the history is checked, whether the
+    /// check passes or not. A failure is only logged.
+    async fn teardown(&self) -> std::result::Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Check the history by elle, the checker of [`Client::run`].
+#[cfg(feature = "clojure")]
+fn check_by_elle(
+    history: &SerializableHistoryList<OpOrNemesisFuncType, OpError>,
+    option: CheckOption,
+) -> Result<SerializableCheckResult> {
+    ElleRwChecker::default().check(history, option)
+}
+
+/// Without the `clojure` feature there is no elle to check the history by, use
+/// [`JepsenClient::run_workload`] with a native checker instead.
+#[cfg(not(feature = "clojure"))]
+fn check_by_elle(
+    _history: &SerializableHistoryList<OpOrNemesisFuncType, OpError>,
+    _option: CheckOption,
+) -> Result<SerializableCheckResult> {
+    anyhow::bail!("checking by elle requires the `clojure` feature")
 }
 
 /// The interface of a jepsen client.
 #[async_trait::async_trait]
 pub trait Client {
+    /// The error type of the ops in the history.
     type ERR: Send + 'static;
     /// client received an op, send it to cluster and deal the result. The
-    /// history (both invoke and result) will be recorded in this function.
-    async fn handle_op(&'static self, id: u64, op: Op);
+    /// history (both invoke and result) will be recorded in this function,
+    /// with the tags of the op.
+    async fn handle_op(&'static self, id: u64, op: Op, tags: OpTags);
+    /// client received a nemesis, execute it on the cluster. The history of
+    /// the nemesis (and the recoveries it causes) will be recorded in this
+    /// function. Fails if a lock of the client is poisoned.
+    async fn handle_nemesis(&'static self, nemesis: NemesisType) -> crate::Result<()>;
     async fn run(
         &'static self,
-        gen: GeneratorGroup<'_, Op, Self::ERR>,
-    ) -> Result<SerializableCheckResult, Self::ERR>;
-    fn new_generator(&self, n: usize) -> Generator<'static, Op, Self::ERR>;
+        gen: GeneratorGroup<'_, OpOrNemesis, Self::ERR>,
+    ) -> crate::Result<SerializableCheckResult>;
+    fn new_generator(&self, n: usize) -> Generator<'static, OpOrNemesis, Self::ERR>;
 }
 
 /// A client that leads the jepsen test, execute between the generator and the
 /// cluster.
-pub struct JepsenClient<EC: ElleRwClusterClient + Send + Sync + 'static> {
+pub struct JepsenClient<
+    EC: ElleRwClusterClient + NemesisClusterClient + ClusterLifecycle + Send + Sync + 'static,
+> {
     cluster_client: EC,
-    pub global: Arc<Global<'static, Op, <Self as Client>::ERR>>,
+    pub global: Arc<Global<'static, OpOrNemesis, <Self as Client>::ERR>>,
+    /// The register of executed nemeses, which decides when to recover them.
+    nemesis_register: Mutex<NemesisRegister>,
+    /// The exclusion rules checked before executing every nemesis.
+    nemesis_policy: Option<NemesisPolicy>,
+    /// The background tasks recovering the records of a
+    /// [`NemesisRegisterStrategy::TimeBased`] register.
+    recovery_tasks: Mutex<Vec<madsim::task::JoinHandle<()>>>,
+    /// The records of the active scheduled fault windows, by window id.
+    scheduled_records: Mutex<HashMap<u64, NemesisRecord>>,
+    /// The check option set in the test.
+    check_option: CheckOption,
+    /// The resolver of the final check option, evaluated just before checking.
+    check_option_resolver: Option<Box<dyn CheckOptionResolver>>,
+    /// The hooks called after a nemesis is executed.
+    start_hooks: Vec<NemesisHook>,
+    /// The hooks called after a nemesis is recovered.
+    heal_hooks: Vec<NemesisHook>,
+    /// The policy of retrying the failed ops, ops are attempted once if unset.
+    retry_policy: Option<RetryPolicy>,
+    /// The node every process is bound to.
+    node_for_process: NodeForProcess,
+    /// The timeout of handling an op, including its retries.
+    op_timeout: Option<Duration>,
+    /// The interval of probing the health of the servers, not probed if unset.
+    health_probe: Option<Duration>,
+    /// The interceptors of the ops, in the order they are added.
+    interceptors: Vec<Box<dyn OpInterceptor>>,
+    /// The interval and the reporter of the progress, not reported if unset.
+    progress: Option<(Duration, ProgressReporter)>,
+    /// The run directory in a jepsen store to save the output, not saved if
+    /// unset.
+    store: Option<Store>,
+    /// The stop requested by the [`RunHandle`] of the run.
+    stop: Arc<watch::Sender<Stop>>,
+    /// How fast the simulated time runs.
+    time_scale: TimeScale,
+    /// The wall-clock time after which the run is finished early.
+    deadline: Option<Duration>,
+    /// The number of ops in flight to the cluster.
+    in_flight: AtomicUsize,
+    /// The watchdog of the stuck runs.
+    watchdog: Option<Watchdog>,
+    /// The interval of the checkpoints of the run, not taken if unset.
+    checkpoint: Option<Duration>,
+    /// The number of the items taken from every generator, by its id.
+    positions: Mutex<BTreeMap<u64, usize>>,
+    /// The active nemeses restored from a checkpoint, put in the register
+    /// when the run starts.
+    restored: Mutex<Vec<NemesisRecord>>,
+    /// The timeout of the read of the cluster after setting it up, not read
+    /// if unset.
+    ping: Option<Duration>,
 }
 
-impl<EC: ElleRwClusterClient + Send + Sync + 'static> JepsenClient<EC> {
+impl<EC: ElleRwClusterClient + NemesisClusterClient + ClusterLifecycle + Send + Sync + 'static>
+    JepsenClient<EC>
+{
     pub fn new(cluster: EC, raw_gen: impl RawGenerator<Item = Op> + Send + 'static) -> Self {
         Self {
             cluster_client: cluster,
-            global: Arc::new(Global::new(raw_gen)),
+            global: Arc::new(Global::new(RawGeneratorMap::new(raw_gen, OpOrNemesis::Op))),
+            nemesis_register: Mutex::new(NemesisRegister::default()),
+            nemesis_policy: None,
+            recovery_tasks: Mutex::default(),
+            scheduled_records: Mutex::default(),
+            check_option: CheckOption::default(),
+            check_option_resolver: None,
+            start_hooks: vec![],
+            heal_hooks: vec![],
+            retry_policy: None,
+            node_for_process: Box::new(|process, size| process % size.max(1) as u64),
+            op_timeout: None,
+            health_probe: None,
+            interceptors: vec![],
+            progress: None,
+            store: None,
+            stop: Arc::new(watch::Sender::new(Stop::None)),
+            time_scale: TimeScale::default(),
+            deadline: None,
+            in_flight: AtomicUsize::new(0),
+            watchdog: None,
+            checkpoint: None,
+            positions: Mutex::default(),
+            restored: Mutex::default(),
+            ping: None,
         }
     }
 
-    /// Recursively handle an op, return the result.
-    #[allow(clippy::await_holding_lock)]
-    #[async_recursion::async_recursion]
+    /// Save the test metadata, the history and the check result to the run
+    /// directory in a jepsen store, which also becomes the output directory
+    /// of the check.
+    pub fn with_store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Take a checkpoint of the run every `interval` in the store, with the
+    /// history written to it as it goes, see [`crate::checkpoint`].
+    pub fn with_checkpoint(mut self, interval: Duration) -> Self {
+        self.checkpoint = Some(interval);
+        self
+    }
+
+    /// Read the cluster after setting it up, and fail the run with
+    /// [`Error::Preflight`] if it does not reply in the timeout, see
+    /// [`preflight::ping`].
+    pub fn with_ping(mut self, timeout: Duration) -> Self {
+        self.ping = Some(timeout);
+        self
+    }
+
+    /// Resume the run of the checkpoint in the directory of the store: the
+    /// history up to the checkpoint is kept and written on, and the
+    /// generators skip the items taken before it. See [`crate::checkpoint`].
+    ///
+    /// It must be called in the runtime of the run, before the global
+    /// context is shared, e.g. by the generators.
+    pub fn with_resume(mut self, checkpoint: Checkpoint) -> crate::Result<Self> {
+        let dir = match &self.store {
+            Some(store) => store.dir().to_path_buf(),
+            None => return Err(Error::Other(anyhow::anyhow!("resuming requires the store"))),
+        };
+        let mut history: SerializableHistoryList<OpOrNemesisFuncType, OpError> =
+            SerializableHistoryList::from_jsonl(dir.join("history.jsonl"))
+                .map_err(Error::History)?;
+        let offset = checkpoint.history_offset;
+        if (history.0.len() as u64) < offset {
+            return Err(Error::History(anyhow::anyhow!(
+                "the history has {} items, fewer than the {} of the checkpoint",
+                history.0.len(),
+                offset
+            )));
+        }
+        history.0.truncate(offset as usize);
+        let mut writer = HistoryWriter::create(&dir).map_err(Error::History)?;
+        for item in &history.0 {
+            writer.write(item).map_err(Error::History)?;
+        }
+        writer.sync().map_err(Error::History)?;
+
+        let global = Arc::get_mut(&mut self.global).ok_or_else(|| {
+            Error::Other(anyhow::anyhow!(
+                "the global context is shared before resuming"
+            ))
+        })?;
+        // the time goes on from the checkpoint
+        let elapsed = Duration::from_nanos(checkpoint.time);
+        #[cfg(madsim)]
+        madsim::time::advance(elapsed);
+        global.start_time = madsim::time::Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or(global.start_time);
+        for item in history
+            .0
+            .iter()
+            .filter(|item| item.process != HistoryProcess::Nemesis)
+        {
+            global.progress.count(&item.type_);
+        }
+        global.pushed = offset.into();
+        global.restore_processes(checkpoint.next_process);
+        *global
+            .history_writer
+            .get_mut()
+            .map_err(Error::poisoned("history writer"))? = Some(writer);
+        global.history.restore(history);
+        info!(
+            "resume the run from {} history items, {:?} items of the generators",
+            offset, checkpoint.positions
+        );
+        self.positions = Mutex::new(checkpoint.positions);
+        self.restored = Mutex::new(checkpoint.active_nemeses);
+        Ok(self)
+    }
+
+    /// Take a checkpoint of the run in the store, see [`crate::checkpoint`].
+    /// A checkpoint failing to be saved is only logged.
+    fn save_checkpoint(&self) -> crate::Result<()> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        // the history of the checkpoint is on the disk before it
+        let history_offset = self.global.merge_history(true).unwrap_or_default();
+        let mut writer = self
+            .global
+            .history_writer
+            .lock()
+            .map_err(Error::poisoned("history writer"))?;
+        if let Some(writer) = writer.as_mut() {
+            if let Err(err) = writer.sync() {
+                warn!("failed to sync the history: {}", err);
+                return Ok(());
+            }
+        }
+        drop(writer);
+        let mut active_nemeses = self
+            .nemesis_register
+            .lock()
+            .map_err(Error::poisoned("nemesis register"))?
+            .records();
+        active_nemeses.extend(
+            self.scheduled_records
+                .lock()
+                .map_err(Error::poisoned("scheduled nemeses"))?
+                .values()
+                .cloned(),
+        );
+        let checkpoint = Checkpoint {
+            seed: runtime::seed(),
+            positions: self
+                .positions
+                .lock()
+                .map_err(Error::poisoned("generator positions"))?
+                .clone(),
+            active_nemeses,
+            history_offset,
+            time: self.global.start_time.elapsed().as_nanos() as u64,
+            next_process: self.global.next_process(),
+        };
+        match checkpoint.save(store.dir()) {
+            Ok(()) => debug!("checkpoint at {} history items", checkpoint.history_offset),
+            Err(err) => warn!("failed to save the checkpoint: {}", err),
+        }
+        Ok(())
+    }
+
+    /// Set the check option.
+    pub fn with_check_option(mut self, option: CheckOption) -> Self {
+        self.check_option = option;
+        self
+    }
+
+    /// Set a resolver that decides the final check option at run time, e.g.
+    /// [`EnvResolver`](crate::checker::resolver::EnvResolver).
+    pub fn with_check_option_resolver(
+        mut self,
+        resolver: impl CheckOptionResolver + 'static,
+    ) -> Self {
+        self.check_option_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// Set the strategy of the nemesis register.
+    pub fn with_nemesis_register_strategy(mut self, strategy: NemesisRegisterStrategy) -> Self {
+        self.nemesis_register = Mutex::new(NemesisRegister::new(strategy));
+        self
+    }
+
+    /// Set the policy that rejects or defers the nemeses which would take too
+    /// many servers down.
+    pub fn with_nemesis_policy(mut self, policy: NemesisPolicy) -> Self {
+        self.nemesis_policy = Some(policy);
+        self
+    }
+
+    /// Set the policy of retrying the ops which fail transiently.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Set the timeout of handling an op, including its retries. The call to
+    /// the cluster client is cancelled on expiry, and the op is recorded as
+    /// `:info`, so a wedged node cannot stall the whole run.
+    pub fn with_op_timeout(mut self, timeout: Duration) -> Self {
+        self.op_timeout = Some(timeout);
+        self
+    }
+
+    /// Probe the health of every server by [`NemesisClusterClient::health`]
+    /// every `interval` during the test. The servers are assumed up at first,
+    /// and every observed transition is recorded as a `:node-up` or
+    /// `:node-down` nemesis history, so the history shows the actual
+    /// availability besides the injected faults.
+    pub fn with_health_probe(mut self, interval: Duration) -> Self {
+        self.health_probe = Some(interval);
+        self
+    }
+
+    /// Report the progress of the run every `interval`, and once more when
+    /// all the ops are completed, see [`crate::progress`].
+    pub fn with_progress(mut self, interval: Duration, reporter: ProgressReporter) -> Self {
+        self.progress = Some((interval, reporter));
+        self
+    }
+
+    /// Set how fast the simulated time runs, see [`TimeScale`].
+    pub fn with_time_scale(mut self, scale: TimeScale) -> Self {
+        self.time_scale = scale;
+        self
+    }
+
+    /// Finish the run early like [`RunHandle::finish_early`] once it has run
+    /// for the duration of the wall clock, whatever the time scale is.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Report, or abort, the run once it's stuck, see [`crate::watchdog`].
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Add an interceptor of the ops, e.g. to inject client-side latency or to
+    /// log the ops. See [`crate::interceptor`] for the order of interceptors.
+    pub fn with_interceptor(mut self, interceptor: impl OpInterceptor + 'static) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    /// Stream the history to the disk by the writer while running, see
+    /// [`HistoryWriter`].
+    pub fn with_history_writer(self, writer: HistoryWriter) -> crate::Result<Self> {
+        *self
+            .global
+            .history_writer
+            .lock()
+            .map_err(Error::poisoned("history writer"))? = Some(writer);
+        Ok(self)
+    }
+
+    /// Bound the history in memory by the spill, see [`HistorySpill`].
+    pub fn with_history_spill(self, spill: HistorySpill) -> crate::Result<Self> {
+        *self
+            .global
+            .history_spill
+            .lock()
+            .map_err(Error::poisoned("history spill"))? = Some(spill);
+        Ok(self)
+    }
+
+    /// Set the node every process is bound to, processes are bound to the
+    /// nodes in round-robin by default. The mapping is called with the process
+    /// and the size of the cluster.
+    pub fn with_node_for_process(
+        mut self,
+        f: impl Fn(u64, usize) -> ServerId + Send + Sync + 'static,
+    ) -> Self {
+        self.node_for_process = Box::new(f);
+        self
+    }
+
+    /// The node the process is bound to.
+    pub fn node_for_process(&self, process: u64) -> ServerId {
+        (self.node_for_process)(process, self.cluster_client.size())
+    }
+
+    /// Bind the history built so far to `user/history` in the nREPL server,
+    /// see [`crate::nrepl`].
+    #[cfg(feature = "nrepl")]
+    pub fn publish_history(&self, server: &crate::nrepl::NreplServer) -> Result<()> {
+        server.def_history(&self.global.full_history()?)
+    }
+
+    /// Register a callback called with the record after every nemesis is
+    /// executed successfully, e.g. to snapshot db metrics or mark the logs of
+    /// the cluster.
+    pub fn on_nemesis_start<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(NemesisRecord) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.start_hooks.push(Box::new(move |r| Box::pin(hook(r))));
+        self
+    }
+
+    /// Register a callback called with the record after every nemesis is
+    /// recovered, whether the recovery succeeds or not.
+    pub fn on_nemesis_heal<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn(NemesisRecord) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.heal_hooks.push(Box::new(move |r| Box::pin(hook(r))));
+        self
+    }
+
+    /// Make a new generator which yields the given nemeses.
+    pub fn new_nemeses(
+        &self,
+        nemeses: impl IntoIterator<Item = NemesisType>,
+    ) -> Generator<'static, OpOrNemesis, <Self as Client>::ERR> {
+        let seq: Vec<_> = nemeses.into_iter().map(OpOrNemesis::Nemesis).collect();
+        debug!(
+            "Jepsen client make new generator with {} nemeses",
+            seq.len()
+        );
+        GeneratorBuilder::new(self.global.clone())
+            .seq(tokio_stream::iter(seq))
+            .build()
+    }
+
+    /// Make a new generator which yields `n` nemeses sampled from the mix,
+    /// with the delays of the mix.
+    pub fn new_nemesis_mix(
+        &self,
+        mix: &mut NemesisMix,
+        n: usize,
+    ) -> Generator<'static, OpOrNemesis, <Self as Client>::ERR> {
+        let seq: Vec<_> = mix.gen_n(n).into_iter().map(OpOrNemesis::Nemesis).collect();
+        debug!(
+            "Jepsen client make new generator with {} sampled nemeses",
+            n
+        );
+        GeneratorBuilder::new(self.global.clone())
+            .seq(tokio_stream::iter(seq))
+            .delay(mix.delay())
+            .build()
+    }
+
+    /// Make a new generator which yields `cycles` fault windows of the
+    /// schedule.
+    pub fn new_schedule(
+        &self,
+        schedule: &NemesisSchedule,
+        cycles: usize,
+    ) -> Generator<'static, OpOrNemesis, <Self as Client>::ERR> {
+        let (seq, delay): (Vec<_>, Vec<_>) = schedule
+            .cycle(cycles)
+            .into_iter()
+            .map(|(item, delay)| (OpOrNemesis::Scheduled(item), delay))
+            .unzip();
+        debug!(
+            "Jepsen client make new generator with {} fault windows",
+            cycles
+        );
+        GeneratorBuilder::new(self.global.clone())
+            .seq(tokio_stream::iter(seq))
+            .delay_stream(tokio_stream::iter(delay))
+            .build()
+    }
+
+    /// Make a new generator which yields the ops and the fault windows of the
+    /// replay, with the same relative timing as recorded.
+    pub fn new_replay(
+        &self,
+        replay: &Replay,
+    ) -> Generator<'static, OpOrNemesis, <Self as Client>::ERR> {
+        let (seq, delay): (Vec<_>, Vec<_>) = replay.with_delays().into_iter().unzip();
+        debug!(
+            "Jepsen client make new generator replaying {} items",
+            seq.len()
+        );
+        GeneratorBuilder::new(self.global.clone())
+            .seq(tokio_stream::iter(seq))
+            .delay_stream(tokio_stream::iter(delay))
+            .build()
+    }
+
+    /// Statically validate the nemeses the generator group would emit against
+    /// the cluster and the nemesis policy, without executing them. Returns the
+    /// rebuilt group to run and the report.
+    pub async fn validate_nemesis_plan<'a>(
+        &self,
+        gen: GeneratorGroup<'a, OpOrNemesis, <Self as Client>::ERR>,
+    ) -> (
+        GeneratorGroup<'a, OpOrNemesis, <Self as Client>::ERR>,
+        NemesisPlanReport,
+    ) {
+        let (items, gen) = gen.peek_all().await;
+        let nemeses = items.into_iter().filter_map(|item| match item {
+            OpOrNemesis::Nemesis(nemesis) => Some(nemesis),
+            OpOrNemesis::Scheduled(ScheduledNemesis::Start { fault, .. }) => Some(fault),
+            _ => None,
+        });
+        let report = NemesisPlanReport::validate(
+            nemeses,
+            &self.cluster_client,
+            self.nemesis_policy.as_ref(),
+        )
+        .await;
+        for issue in &report.issues {
+            warn!(
+                "nemesis #{} {:?} is {:?}: {}",
+                issue.index, issue.nemesis, issue.kind, issue.reason
+            );
+        }
+        (gen, report)
+    }
+
+    /// Handle an op, return the result.
     pub async fn handle_op_inner(&self, op: Op) -> std::result::Result<Op, String> {
         match op {
             Op::Read(key, _) => {
@@ -61,21 +896,654 @@ impl<EC: ElleRwClusterClient + Send + Sync + 'static> JepsenClient<EC> {
                 self.cluster_client.put(key, value).await?;
                 Ok(Op::Write(key, value))
             }
-            Op::Txn(ops) => Ok(Op::Txn(
-                futures_util::future::join_all(ops.into_iter().map(|op| self.handle_op_inner(op)))
-                    .await
-                    .into_iter()
-                    .collect::<Result<_, _>>()?,
+            Op::Cas(key, expect, new) => match self.cluster_client.cas(key, expect, new).await? {
+                true => Ok(op),
+                false => Err(CAS_MISMATCH.to_string()),
+            },
+            Op::Delete(key) => {
+                self.cluster_client.delete(key).await?;
+                Ok(op)
+            }
+            Op::Incr(key, delta) => {
+                self.cluster_client.incr(key, delta).await?;
+                Ok(op)
+            }
+            Op::Append(key, value) => {
+                self.cluster_client.append(key, value).await?;
+                Ok(op)
+            }
+            Op::ReadList(key, _) => {
+                let list = self.cluster_client.get_list(key).await?;
+                Ok(Op::ReadList(key, Some(list)))
+            }
+            Op::BatchRead(reads) => {
+                let keys: Vec<_> = reads.iter().map(|(k, _)| *k).collect();
+                let values = self.cluster_client.batch_get(keys.clone()).await?;
+                batch_read_result(keys, values)
+            }
+            Op::ScanRange(start, end, _) => {
+                let results = self.cluster_client.scan(start, end).await?;
+                Ok(Op::ScanRange(start, end, Some(results)))
+            }
+            Op::Txn(ops) => Ok(Op::Txn(self.cluster_client.txn(ops).await?)),
+        }
+    }
+
+    /// Handle an op on the given node, return the result.
+    pub async fn handle_op_on(&self, node: ServerId, op: Op) -> std::result::Result<Op, String> {
+        let client = &self.cluster_client;
+        match op {
+            Op::Read(key, _) => Ok(Op::Read(key, client.get_on(node, key).await?)),
+            Op::Write(key, value) => {
+                client.put_on(node, key, value).await?;
+                Ok(Op::Write(key, value))
+            }
+            Op::Cas(key, expect, new) => match client.cas_on(node, key, expect, new).await? {
+                true => Ok(op),
+                false => Err(CAS_MISMATCH.to_string()),
+            },
+            Op::Delete(key) => {
+                client.delete_on(node, key).await?;
+                Ok(op)
+            }
+            Op::Incr(key, delta) => {
+                client.incr_on(node, key, delta).await?;
+                Ok(op)
+            }
+            Op::Append(key, value) => {
+                client.append_on(node, key, value).await?;
+                Ok(op)
+            }
+            Op::ReadList(key, _) => Ok(Op::ReadList(
+                key,
+                Some(client.get_list_on(node, key).await?),
             )),
+            Op::BatchRead(reads) => {
+                let keys: Vec<_> = reads.iter().map(|(k, _)| *k).collect();
+                let values = client.batch_get_on(node, keys.clone()).await?;
+                batch_read_result(keys, values)
+            }
+            Op::ScanRange(start, end, _) => {
+                let results = client.scan_on(node, start, end).await?;
+                Ok(Op::ScanRange(start, end, Some(results)))
+            }
+            Op::Txn(ops) => Ok(Op::Txn(client.txn_on(node, ops).await?)),
+        }
+    }
+
+    /// Execute a nemesis, and record it in the history. Returns the record to
+    /// recover it.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "nemesis", skip(self), fields(nemesis = ?nemesis))
+    )]
+    async fn execute_nemesis(&self, nemesis: &NemesisType) -> crate::Result<Option<NemesisRecord>> {
+        let admitted = match &self.nemesis_policy {
+            Some(policy) => policy
+                .admit(nemesis, || {
+                    self.global
+                        .active_records()
+                        .into_iter()
+                        .map(|(r, _)| r)
+                        .collect()
+                })
+                .await
+                .map_err(|err| format!("rejected by nemesis policy: {}", err)),
+            None => Ok(()),
+        };
+        let res = match admitted {
+            Ok(()) => nemesis.execute(&self.cluster_client).await,
+            Err(err) => Err(err),
+        };
+        let (record, err) = match res {
+            Ok(record) => (record, None),
+            Err(err) => {
+                warn!("failed to execute nemesis {:?}: {}", nemesis, err);
+                (None, Some(err))
+            }
+        };
+        let value = NemesisValue::new(format!("{:?}", nemesis));
+        let value = match &record {
+            Some(record) => value.with_record(record),
+            None => value.with_servers(nemesis.named_servers()),
+        };
+        self.global.push_nemesis(
+            SerializableNemesisType::from(nemesis),
+            value,
+            err.map(OpError::from),
+        );
+        if let Some(record) = &record {
+            self.global
+                .nemeses
+                .lock()
+                .map_err(Error::poisoned("nemeses"))?
+                .activate(nemesis, record.clone(), self.global.start_time);
+            for hook in &self.start_hooks {
+                hook(record.clone()).await;
+            }
+        }
+        Ok(record)
+    }
+
+    /// Classify the error of an op sent to the node, which is
+    /// [`OpError::NodeDown`] if the node is killed or paused by an active
+    /// nemesis.
+    fn op_error(&self, node: ServerId, err: String) -> OpError {
+        // only a classification, so a poisoned lock is read as is
+        let nemeses = self.global.nemeses.lock();
+        let down = nemeses.unwrap_or_else(PoisonError::into_inner).snapshot().iter().any(
+            |(record, _)| {
+                matches!(record, NemesisRecord::Kill(servers) | NemesisRecord::Pause(servers) if servers.contains(&node))
+            },
+        );
+        match down {
+            true => OpError::NodeDown(node),
+            false => OpError::from(err),
+        }
+    }
+
+    /// Save the activation intervals of nemeses to the output directory.
+    fn save_fault_intervals(&self, dir: &std::path::Path) {
+        let path = dir.join(FAULT_INTERVALS_FILE);
+        let res = (|| -> crate::Result<()> {
+            std::fs::create_dir_all(dir)?;
+            let nemeses = self
+                .global
+                .nemeses
+                .lock()
+                .map_err(Error::poisoned("nemeses"))?;
+            let json = serde_json::to_vec_pretty(nemeses.intervals())?;
+            Ok(std::fs::write(&path, json)?)
+        })();
+        if let Err(err) = res {
+            warn!("failed to save fault intervals to {:?}: {}", path, err);
+        }
+    }
+
+    /// Start or heal a scheduled fault window.
+    async fn handle_scheduled(&self, item: ScheduledNemesis) -> crate::Result<()> {
+        trace!(
+            "Jepsen client receive and handles a scheduled nemesis: {:?}",
+            item
+        );
+        match item {
+            ScheduledNemesis::Start { id, fault } => {
+                if let Some(record) = self.execute_nemesis(&fault).await? {
+                    self.scheduled_records
+                        .lock()
+                        .map_err(Error::poisoned("scheduled nemeses"))?
+                        .insert(id, record);
+                }
+            }
+            ScheduledNemesis::Heal { id } => {
+                let record = self
+                    .scheduled_records
+                    .lock()
+                    .map_err(Error::poisoned("scheduled nemeses"))?
+                    .remove(&id);
+                // nothing to heal if the fault failed or cannot be recovered
+                if let Some(record) = record {
+                    self.recover_nemesis(record).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Probe the health of the servers every `interval` forever, and record
+    /// the transitions in the history.
+    async fn probe_health(&self, interval: Duration) {
+        let mut last = vec![];
+        loop {
+            madsim::time::sleep(interval).await;
+            let size = self.cluster_client.size();
+            // servers may be added by membership changes
+            last.resize(size, NodeHealth::Up);
+            let healths = futures_util::future::join_all(
+                (0..size as ServerId).map(|server| self.cluster_client.health(server)),
+            )
+            .await;
+            for (server, health) in healths.into_iter().enumerate() {
+                if health == NodeHealth::Unknown || health == last[server] {
+                    continue;
+                }
+                debug!("server {} turns {:?}", server, health);
+                last[server] = health;
+                let f = match health {
+                    NodeHealth::Up => SerializableNemesisType::NodeUp,
+                    _ => SerializableNemesisType::NodeDown,
+                };
+                let value =
+                    NemesisValue::new(format!("{:?}", health)).with_servers([server as ServerId]);
+                self.global.push_nemesis(f, value, None);
+            }
+        }
+    }
+
+    /// Pace the simulated time by the time scale forever, i.e. block the
+    /// simulation while it runs ahead of the wall clock.
+    async fn pace(&self, factor: f64) {
+        // the simulated and the wall-clock time paced from
+        let mut base = None;
+        loop {
+            madsim::time::sleep(PACE_TICK).await;
+            if self.time_scale == TimeScale::Hybrid && self.in_flight.load(Ordering::Relaxed) == 0 {
+                base = None;
+                continue;
+            }
+            let (sim, wall) =
+                *base.get_or_insert_with(|| (madsim::time::Instant::now(), runtime::wall_clock()));
+            let target = sim.elapsed().div_f64(factor.max(f64::MIN_POSITIVE));
+            let ahead = target.saturating_sub(runtime::wall_clock().duration_since(wall));
+            if !ahead.is_zero() {
+                std::thread::sleep(ahead);
+            }
+        }
+    }
+
+    /// Finish the run early after the deadline of the wall clock, on a system
+    /// thread which exits once the returned sender is dropped.
+    fn start_deadline(&self, deadline: Duration) -> std::sync::mpsc::Sender<()> {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let stop = self.stop.clone();
+        std::thread::spawn(move || {
+            if rx.recv_timeout(deadline) == Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+                info!(
+                    "the deadline {:?} is reached, finish the run early",
+                    deadline
+                );
+                finish_early(&stop);
+            }
+        });
+        tx
+    }
+
+    /// Watch the history on a system thread, which exits once the returned
+    /// sender is dropped, and report every stall of it, see [`Watchdog`].
+    fn start_watchdog(&'static self, watchdog: &'static Watchdog) -> std::sync::mpsc::Sender<()> {
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let mut pushed = self.global.pushed();
+            let mut since = std::time::Instant::now();
+            let mut reported = false;
+            while rx.recv_timeout(watchdog.poll_interval())
+                == Err(std::sync::mpsc::RecvTimeoutError::Timeout)
+            {
+                if self.global.pushed() != pushed {
+                    pushed = self.global.pushed();
+                    since = std::time::Instant::now();
+                    reported = false;
+                    continue;
+                }
+                // every stall is reported once
+                if reported || since.elapsed() < watchdog.timeout {
+                    continue;
+                }
+                reported = true;
+                let report = StallReport::capture(
+                    &self.global,
+                    since.elapsed(),
+                    self.in_flight.load(Ordering::Relaxed),
+                );
+                error!("the run is stuck, {}", report);
+                if let Some(store) = &self.store {
+                    let path = store.dir().join(STALL_FILE);
+                    let res = std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path)
+                        .and_then(|mut file| writeln!(file, "{}\n", report));
+                    if let Err(err) = res {
+                        warn!("failed to save the stall to {:?}: {}", path, err);
+                    }
+                }
+                if let Some(on_stall) = &watchdog.on_stall {
+                    on_stall(&report);
+                }
+                if watchdog.abort {
+                    warn!("abort the stuck run");
+                    self.stop.send_replace(Stop::Abort);
+                }
+            }
+        });
+        tx
+    }
+
+    /// Report the progress every `interval` forever.
+    async fn report_progress(&self, interval: Duration, reporter: &ProgressReporter) {
+        loop {
+            madsim::time::sleep(interval).await;
+            reporter.report(&self.global.progress(), false);
+        }
+    }
+
+    /// Run the test like [`Client::run`], and check the history by `check`
+    /// with the option.
+    // There will be only one thread to run start_test, so the `join_handles` lock
+    // will be held only by one thread, which could be safely held across await
+    // point.
+    #[allow(clippy::await_holding_lock)]
+    pub(crate) async fn run_checked(
+        &'static self,
+        mut gen: GeneratorGroup<'_, OpOrNemesis, OpError>,
+        mut option: CheckOption,
+        check: impl FnOnce(
+            &SerializableHistoryList<OpOrNemesisFuncType, OpError>,
+            CheckOption,
+        ) -> Result<SerializableCheckResult>,
+    ) -> crate::Result<SerializableCheckResult> {
+        self.cluster_client
+            .setup()
+            .await
+            .map_err(|err| Error::Client(format!("failed to set up the cluster: {}", err)))?;
+        if let Some(timeout) = self.ping {
+            let ping = preflight::ping(&self.cluster_client, timeout).await;
+            if ping.status == PreflightStatus::Fail {
+                if let Err(err) = self.cluster_client.teardown().await {
+                    warn!("failed to tear down the cluster: {}", err);
+                }
+                return Err(Error::Preflight(PreflightReport { checks: vec![ping] }));
+            }
+        }
+        let probe = self
+            .health_probe
+            .map(|interval| madsim::task::spawn(self.probe_health(interval)));
+        let progress = self.progress.as_ref().map(|(interval, reporter)| {
+            madsim::task::spawn(self.report_progress(*interval, reporter))
+        });
+        let pace = match self.time_scale {
+            _ if !runtime::is_simulated() => None,
+            TimeScale::Accelerated => None,
+            TimeScale::Paced(factor) => Some(factor),
+            TimeScale::Hybrid => Some(1.0),
+        }
+        .map(|factor| madsim::task::spawn(self.pace(factor)));
+        let deadline = self.deadline.map(|deadline| self.start_deadline(deadline));
+        if let (Some(_), Some(store)) = (self.checkpoint, &self.store) {
+            let mut writer = self
+                .global
+                .history_writer
+                .lock()
+                .map_err(Error::poisoned("history writer"))?;
+            if writer.is_none() {
+                *writer = Some(HistoryWriter::create(store.dir()).map_err(Error::History)?);
+            }
+        }
+        gen.skip_items(
+            &*self
+                .positions
+                .lock()
+                .map_err(Error::poisoned("generator positions"))?,
+        );
+        let restored = std::mem::take(
+            &mut *self
+                .restored
+                .lock()
+                .map_err(Error::poisoned("restored nemeses"))?,
+        );
+        for record in restored {
+            let to_recover = self
+                .nemesis_register
+                .lock()
+                .map_err(Error::poisoned("nemesis register"))?
+                .put(record);
+            for record in to_recover {
+                self.recover_nemesis(record).await?;
+            }
+        }
+        let mut checkpointed = madsim::time::Instant::now();
+        let watchdog = self
+            .watchdog
+            .as_ref()
+            .map(|watchdog| self.start_watchdog(watchdog));
+        let heal_guard = HealGuard(Some(self));
+        loop {
+            // the stop is polled first, so no more items are taken once stopped
+            let next = {
+                let stop = std::pin::pin!(stopped(&self.stop, Stop::Finish));
+                let next = std::pin::pin!(gen.next_with_id());
+                match future::select(stop, next).await {
+                    Either::Right((next, _)) => next,
+                    Either::Left(_) => None,
+                }
+            };
+            let Some((item, id)) = next else {
+                break;
+            };
+            *self
+                .positions
+                .lock()
+                .map_err(Error::poisoned("generator positions"))?
+                .entry(id)
+                .or_default() += 1;
+            let handle = async {
+                match item {
+                    OpOrNemesis::Op(op) => self.handle_op(id, op, OpTags::new()).await,
+                    OpOrNemesis::Tagged(op, tags) => self.handle_op(id, op, tags).await,
+                    OpOrNemesis::Nemesis(nemesis) => return self.handle_nemesis(nemesis).await,
+                    OpOrNemesis::Scheduled(item) => return self.handle_scheduled(item).await,
+                }
+                Ok(())
+            };
+            // a panic only takes down the generator task, whose op in flight
+            // is recorded as `:info` by `handle_op`
+            match AssertUnwindSafe(handle).catch_unwind().await {
+                Ok(res) => res?,
+                Err(panic) => {
+                    self.global.record_panic(id, panic_message(&*panic));
+                    gen.free_generator(id);
+                }
+            }
+            // taken between the items, so nothing of the loop is in flight
+            if let Some(interval) = self.checkpoint {
+                if checkpointed.elapsed() >= interval {
+                    self.save_checkpoint()?;
+                    checkpointed = madsim::time::Instant::now();
+                }
+            }
+        }
+        // wait for the background recoveries, so the history is complete. They
+        // return at once if the run is stopped, leaving their records to heal
+        let tasks = std::mem::take(
+            &mut *self
+                .recovery_tasks
+                .lock()
+                .map_err(Error::poisoned("recovery tasks"))?,
+        );
+        for task in tasks {
+            if let Err(err) = task.await {
+                warn!("nemesis recovery task failed: {}", err);
+            }
+        }
+        // the check and the teardown see a healed cluster
+        self.heal_all().await?;
+        heal_guard.disarm();
+        drop(deadline);
+        drop(watchdog);
+        if let Some(pace) = pace {
+            pace.abort();
         }
+        if let Some(probe) = probe {
+            probe.abort();
+        }
+        if let Some(progress) = progress {
+            progress.abort();
+            if let Some((_, reporter)) = &self.progress {
+                reporter.report(&self.global.progress(), true);
+            }
+        }
+        info!("all receiver threads exited, check result...");
+        self.global.merge_history(true);
+        if let Some(writer) = self
+            .global
+            .history_writer
+            .lock()
+            .map_err(Error::poisoned("history writer"))?
+            .as_mut()
+        {
+            if let Err(err) = writer.sync() {
+                warn!("failed to sync the history: {}", err);
+            }
+        }
+
+        // let his = serde_json::to_string(&self.global.history.lock().unwrap().
+        // deref()).unwrap(); std::fs::write("test.json", his);
+
+        if let Some(resolver) = &self.check_option_resolver {
+            option = resolver.resolve(option).map_err(Error::Checker)?;
+        }
+        if let Some(store) = &self.store {
+            option = option.directory(store.dir().to_path_buf());
+            if let Err(err) = store.save_test(self.cluster_client.size(), &option) {
+                warn!("failed to save the test to the store: {}", err);
+            }
+        }
+        self.save_fault_intervals(option.out_dir());
+        // the history is lent instead of cloned, only the multi-key reads
+        // are expanded in a copy
+        let check_result = self.global.with_full_history(|history| {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("check", items = history.0.len()).entered();
+            if let Some(store) = &self.store {
+                store.save_history(history).map_err(Error::History)?;
+            }
+            let result = match history.has_multi_key_reads() {
+                true => history
+                    .clone()
+                    .expand_reads()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|history| check(&history, option)),
+                false => check(history, option),
+            }
+            .map_err(Error::Checker)?;
+            if let Some(store) = &self.store {
+                store.save_results(&result)?;
+            }
+            Ok(result)
+        });
+        let check_result = check_result.and_then(|res| res);
+        if let Err(err) = self.cluster_client.teardown().await {
+            warn!("failed to tear down the cluster: {}", err);
+        }
+        check_result
+    }
+
+    /// Run the test like [`Client::run`] in a new task, which can be stopped
+    /// by the returned handle.
+    pub fn run_detached(
+        &'static self,
+        gen: GeneratorGroup<'static, OpOrNemesis, OpError>,
+    ) -> RunHandle {
+        self.run_checked_detached(gen, self.check_option.clone(), check_by_elle)
+    }
+
+    /// Run the test like [`JepsenClient::run_checked`] in a new task, see
+    /// [`JepsenClient::run_detached`].
+    pub(crate) fn run_checked_detached(
+        &'static self,
+        gen: GeneratorGroup<'static, OpOrNemesis, OpError>,
+        option: CheckOption,
+        check: impl FnOnce(
+                &SerializableHistoryList<OpOrNemesisFuncType, OpError>,
+                CheckOption,
+            ) -> Result<SerializableCheckResult>
+            + Send
+            + 'static,
+    ) -> RunHandle {
+        RunHandle {
+            stop: self.stop.clone(),
+            task: madsim::task::spawn(self.run_checked(gen, option, check)),
+        }
+    }
+
+    /// Recover all the active nemeses, i.e. the records in the register and
+    /// of the scheduled fault windows. It's done at the end of every run, and
+    /// when a run on a real cluster panics or is cancelled.
+    pub async fn heal_all(&self) -> crate::Result<()> {
+        let records: Vec<_> = {
+            let mut register = self
+                .nemesis_register
+                .lock()
+                .map_err(Error::poisoned("nemesis register"))?;
+            let mut scheduled = self
+                .scheduled_records
+                .lock()
+                .map_err(Error::poisoned("scheduled nemeses"))?;
+            let mut records = register.take_all();
+            records.extend(std::mem::take(&mut *scheduled).into_values());
+            records
+        };
+        if !records.is_empty() {
+            info!("heal {} active nemeses", records.len());
+        }
+        // every record is recovered even if the others fail
+        let mut res = Ok(());
+        for record in records {
+            res = res.and(self.recover_nemesis(record).await);
+        }
+        res
+    }
+
+    /// Run the workload, i.e. generate its ops by `opts.generators`
+    /// generators instead of the raw generator of the client, and check the
+    /// history by its checker. The check option of the client is ignored.
+    pub async fn run_workload<W: Workload>(
+        &'static self,
+        workload: &W,
+        opts: WorkloadOptions,
+    ) -> crate::Result<SerializableCheckResult> {
+        let raw_gen = workload
+            .generator()
+            .map_err(|err| Error::Generator(format!("{:#}", err)))?;
+        *self
+            .global
+            .gen
+            .lock()
+            .map_err(Error::poisoned("generator"))? =
+            Some(Box::new(RawGeneratorMap::new(raw_gen, OpOrNemesis::Op)));
+        let gens = (0..opts.generators).map(|_| self.new_generator(opts.ops));
+        let gen = GeneratorGroup::new(gens).with_strategy(opts.strategy.clone());
+        let option = opts.check_option.unwrap_or_else(|| workload.check_option());
+        self.run_checked(gen, option, |history, option| {
+            workload.checker()?.check(history, option)
+        })
+        .await
+    }
+
+    /// Recover a nemesis record, and record it in the history.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "recover", skip(self), fields(record = ?record))
+    )]
+    async fn recover_nemesis(&self, record: NemesisRecord) -> crate::Result<()> {
+        let res = record.recover(&self.cluster_client).await;
+        self.global
+            .nemeses
+            .lock()
+            .map_err(Error::poisoned("nemeses"))?
+            .deactivate(&record, self.global.start_time);
+        if let Err(err) = &res {
+            warn!("failed to recover nemesis {:?}: {}", record, err);
+        }
+        self.global.push_nemesis(
+            SerializableNemesisType::from(&record),
+            NemesisValue::from(&record),
+            res.err().map(OpError::from),
+        );
+        for hook in &self.heal_hooks {
+            hook(record.clone()).await;
+        }
+        Ok(())
     }
 }
 
 #[async_trait::async_trait]
-impl<EC: ElleRwClusterClient + Send + Sync + 'static> Client for JepsenClient<EC> {
-    type ERR = String;
+impl<EC: ElleRwClusterClient + NemesisClusterClient + ClusterLifecycle + Send + Sync + 'static>
+    Client for JepsenClient<EC>
+{
+    type ERR = OpError;
 
-    fn new_generator(&self, n: usize) -> Generator<'static, Op, Self::ERR> {
+    fn new_generator(&self, n: usize) -> Generator<'static, OpOrNemesis, Self::ERR> {
         debug!("Jepsen client make new generator with {} ops", n);
         let global = self.global.clone();
         let seq = global.take_seq(n);
@@ -84,58 +1552,488 @@ impl<EC: ElleRwClusterClient + Send + Sync + 'static> Client for JepsenClient<EC
             .build()
     }
 
-    async fn handle_op(&'static self, id: u64, op: Op) {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "op",
+            level = "debug",
+            skip_all,
+            fields(generator = id, f = ?crate::op::OpFunctionType::from(&op), process, index)
+        )
+    )]
+    async fn handle_op(&'static self, id: u64, op: Op, tags: OpTags) {
         trace!(
             "Jepsen client thread {} receive and handles an op: {:?}",
             id,
             op
         );
-        self.global
-            .history
-            .lock()
-            .unwrap()
-            .push_invoke(&self.global, id, op.clone());
-        let res = self.handle_op_inner(op.clone()).await;
+        let process = self.global.process(id);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("process", process);
+        let mut op = op;
+        for interceptor in &self.interceptors {
+            op = interceptor.before(process, op).await;
+        }
+        if let Err(err) = op.validate() {
+            warn!(
+                "drop the invalid op {:?} of process {}: {}",
+                op, process, err
+            );
+            return;
+        }
+        let _index = self.global.push_invoke(process, op.clone(), tags.clone());
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("index", _index);
+        let node = self.node_for_process(id);
+        let attempts = AtomicUsize::new(0);
+        let attempt = || {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            self.handle_op_on(node, op.clone())
+        };
+        let start = madsim::time::Instant::now();
+        let call = async {
+            match &self.retry_policy {
+                Some(policy) => policy.execute(&op, attempt).await,
+                None => attempt().await.map_err(|err| (HistoryType::Fail, err)),
+            }
+        };
+        let call = async {
+            match self.op_timeout {
+                // the call is cancelled by dropping it on expiry
+                Some(timeout) => madsim::time::timeout(timeout, call)
+                    .await
+                    .unwrap_or_else(|_| {
+                        warn!("op {:?} of process {} timed out", op, process);
+                        Err((HistoryType::Info, format!("timed out after {:?}", timeout)))
+                    }),
+                None => call.await,
+            }
+        };
+        let abort = stopped(&self.stop, Stop::Abort);
+        let call = async {
+            match future::select(std::pin::pin!(call), std::pin::pin!(abort)).await {
+                Either::Left((res, _)) => res,
+                // the call is cancelled like on a timeout
+                Either::Right(_) => {
+                    warn!("op {:?} of process {} aborted", op, process);
+                    Err((HistoryType::Info, RUN_ABORTED.to_string()))
+                }
+            }
+        };
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        // the op may have happened before the panic, so it's recorded as
+        // `:info` before the panic goes on to the generator task
+        let (mut res, panic) = match AssertUnwindSafe(call).catch_unwind().await {
+            Ok(res) => (res, None),
+            Err(panic) => {
+                let err = format!("{}: {}", HARNESS_PANIC, panic_message(&*panic));
+                (Err((HistoryType::Info, err)), Some(panic))
+            }
+        };
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        let meta = OpMeta {
+            node,
+            retries: attempts.load(Ordering::Relaxed).saturating_sub(1),
+            latency: start.elapsed().as_nanos() as u64,
+        };
+        for interceptor in self.interceptors.iter().rev() {
+            res = interceptor.after(process, &op, res).await;
+        }
         match res {
             Ok(op) => {
-                self.global.history.lock().unwrap().push_result(
-                    &self.global,
-                    id,
-                    HistoryType::Ok,
-                    op,
-                    None,
-                );
+                self.global
+                    .push_result(process, HistoryType::Ok, op, None, Some(meta), tags);
             }
-            Err(err) => {
-                self.global.history.lock().unwrap().push_result(
-                    &self.global,
-                    id,
-                    HistoryType::Fail,
-                    op,
-                    Some(err),
-                );
+            Err((type_, err)) => {
+                // the op may still happen, so the process cannot go on
+                if type_ == HistoryType::Info {
+                    self.global.crash_process(id);
+                }
+                let err = match panic {
+                    Some(_) => OpError::Custom(err),
+                    None => self.op_error(node, err),
+                };
+                self.global
+                    .push_result(process, type_, op, Some(err), Some(meta), tags);
             }
         }
+        if let Some(panic) = panic {
+            std::panic::resume_unwind(panic);
+        }
+    }
+
+    async fn handle_nemesis(&'static self, nemesis: NemesisType) -> crate::Result<()> {
+        trace!("Jepsen client receive and handles a nemesis: {:?}", nemesis);
+        let Some(record) = self.execute_nemesis(&nemesis).await? else {
+            return Ok(());
+        };
+        let (to_recover, lifetime) = {
+            let mut register = self
+                .nemesis_register
+                .lock()
+                .map_err(Error::poisoned("nemesis register"))?;
+            let lifetime = match register.strategy() {
+                NemesisRegisterStrategy::TimeBased(lifetime) => Some(*lifetime),
+                _ => None,
+            };
+            (register.put(record), lifetime)
+        };
+        for record in to_recover {
+            self.recover_nemesis(record).await?;
+        }
+        if let Some(lifetime) = lifetime {
+            // recover the record in background when it expires
+            let task = madsim::task::spawn(async move {
+                let sleep = std::pin::pin!(madsim::time::sleep(lifetime));
+                let stop = std::pin::pin!(stopped(&self.stop, Stop::Finish));
+                // the record is healed with all the others if the run stops
+                if let Either::Right(_) = future::select(sleep, stop).await {
+                    return;
+                }
+                // the record is healed with all the others if the lock is
+                // poisoned, which fails the run
+                let expired = match self.nemesis_register.lock() {
+                    Ok(mut register) => register.take_expired(),
+                    Err(_) => {
+                        warn!("the lock of the nemesis register is poisoned");
+                        return;
+                    }
+                };
+                for record in expired {
+                    if let Err(err) = self.recover_nemesis(record).await {
+                        warn!("failed to recover an expired nemesis: {}", err);
+                        return;
+                    }
+                }
+            });
+            self.recovery_tasks
+                .lock()
+                .map_err(Error::poisoned("recovery tasks"))?
+                .push(task);
+        }
+        Ok(())
     }
 
-    // There will be only one thread to run start_test, so the `join_handles` lock
-    // will be held only by one thread, which could be safely held across await
-    // point.
-    #[allow(clippy::await_holding_lock)]
     async fn run(
         &'static self,
-        mut gen: GeneratorGroup<'_, Op, Self::ERR>,
-    ) -> Result<SerializableCheckResult, Self::ERR> {
-        while let Some((op, id)) = gen.next_with_id().await {
-            self.handle_op(id, op).await;
+        gen: GeneratorGroup<'_, OpOrNemesis, Self::ERR>,
+    ) -> crate::Result<SerializableCheckResult> {
+        self.run_checked(gen, self.check_option.clone(), check_by_elle)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generator::controller::DelayStrategy,
+        mock::{FaultSwitch, MockCluster},
+        nemesis::DiskStressMode,
+        utils::testing::{WriteGenerator, VALID},
+    };
+
+    /// A cluster whose ops never complete.
+    #[cfg(madsim)]
+    struct StuckCluster;
+
+    #[cfg(madsim)]
+    #[async_trait::async_trait]
+    impl ElleRwClusterClient for StuckCluster {
+        async fn get(&self, _key: u64) -> std::result::Result<Option<u64>, String> {
+            std::future::pending().await
         }
-        info!("all receiver threads exited, check result...");
+        async fn put(&self, _key: u64, _value: u64) -> std::result::Result<(), String> {
+            std::future::pending().await
+        }
+    }
 
-        // let his = serde_json::to_string(&self.global.history.lock().unwrap().
-        // deref()).unwrap(); std::fs::write("test.json", his);
+    #[cfg(madsim)]
+    impl ClusterLifecycle for StuckCluster {}
 
-        let check_result = ElleRwChecker::default()
-            .check(&self.global.history.lock().unwrap(), CheckOption::default());
-        check_result.map_err(|err| err.to_string())
+    #[cfg(madsim)]
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for StuckCluster {
+        fn size(&self) -> usize {
+            3
+        }
+        fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+            None
+        }
+        async fn get_leader_without_term(&self) -> ServerId {
+            0
+        }
+    }
+
+    /// A cluster which panics on writing the value 2.
+    #[cfg(madsim)]
+    struct PanicCluster;
+
+    #[cfg(madsim)]
+    #[async_trait::async_trait]
+    impl ElleRwClusterClient for PanicCluster {
+        async fn get(&self, _key: u64) -> std::result::Result<Option<u64>, String> {
+            Ok(None)
+        }
+        async fn put(&self, _key: u64, value: u64) -> std::result::Result<(), String> {
+            if value == 2 {
+                panic!("put {} panicked", value);
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(madsim)]
+    impl ClusterLifecycle for PanicCluster {}
+
+    #[cfg(madsim)]
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for PanicCluster {
+        fn size(&self) -> usize {
+            3
+        }
+        fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+            None
+        }
+        async fn get_leader_without_term(&self) -> ServerId {
+            0
+        }
+    }
+
+    fn mock_client() -> (&'static JepsenClient<MockCluster>, FaultSwitch) {
+        let cluster = MockCluster::new(3);
+        let faults = cluster.fault_switch();
+        let client = JepsenClient::new(cluster, WriteGenerator(0));
+        (Box::leak(client.into()), faults)
+    }
+
+    /// A disk stress, and then `ops` ops every `delay`.
+    fn stress(
+        client: &'static JepsenClient<MockCluster>,
+        ops: usize,
+        delay: Duration,
+    ) -> GeneratorGroup<'static, OpOrNemesis, OpError> {
+        let stress = NemesisType::DiskStress {
+            server: 0,
+            mode: DiskStressMode::Enospc,
+        };
+        let ops = GeneratorBuilder::new(client.global.clone())
+            .seq(tokio_stream::iter(client.global.take_seq(ops)))
+            .delay(DelayStrategy::Fixed(delay))
+            .build();
+        GeneratorGroup::new([client.new_nemeses([stress]), ops])
+    }
+
+    fn valid(
+        _history: &SerializableHistoryList<OpOrNemesisFuncType, OpError>,
+        _option: CheckOption,
+    ) -> Result<SerializableCheckResult> {
+        Ok(serde_json::from_str(VALID)?)
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_finish_early() {
+        let (client, faults) = mock_client();
+        let gen = stress(client, 100, Duration::from_secs(1));
+        let handle = client.run_checked_detached(gen, CheckOption::default(), valid);
+        madsim::time::sleep(Duration::from_millis(10_500)).await;
+        assert!(faults.is_active());
+        handle.finish_early();
+        handle.finish_early();
+        assert!(handle.join().await.is_ok());
+
+        assert!(!faults.is_active());
+        assert!(client.global.active_records().is_empty());
+        let history = client.global.full_history().unwrap();
+        let pairs = history.pairs();
+        assert!((5..20).contains(&pairs.len()));
+        assert!(pairs
+            .iter()
+            .all(|p| p.completion.is_some_and(|c| c.type_ == HistoryType::Ok)));
+        let healed = history.0.last().unwrap();
+        assert_eq!(healed.process, HistoryProcess::Nemesis);
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_heal_all() {
+        let (client, faults) = mock_client();
+        let gen = stress(client, 3, Duration::from_secs(1));
+        let handle = client.run_checked_detached(gen, CheckOption::default(), valid);
+        assert!(handle.join().await.is_ok());
+        // the nemesis left in the register is healed on completion
+        assert!(!faults.is_active());
+    }
+
+    #[madsim::test]
+    async fn test_poisoned() {
+        let (client, _) = mock_client();
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let _register = client.nemesis_register.lock().unwrap();
+            panic!("poison");
+        }));
+        let err = client.heal_all().await.unwrap_err();
+        assert!(
+            matches!(err, Error::Poisoned("nemesis register")),
+            "{}",
+            err
+        );
+    }
+
+    #[madsim::test]
+    async fn test_poisoned_recovery() {
+        let (client, faults) = mock_client();
+        let stress = NemesisType::DiskStress {
+            server: 0,
+            mode: DiskStressMode::Enospc,
+        };
+        client.handle_nemesis(stress).await.unwrap();
+        assert!(faults.is_active());
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let _nemeses = client.global.nemeses.lock().unwrap();
+            panic!("poison");
+        }));
+        let err = client.heal_all().await.unwrap_err();
+        assert!(matches!(err, Error::Poisoned("nemeses")), "{}", err);
+        // the fault is recovered before the lock fails
+        assert!(!faults.is_active());
+    }
+
+    #[cfg(not(madsim))]
+    #[test]
+    fn test_heal_guard() {
+        let (client, faults) = mock_client();
+        let gen = stress(client, 100, Duration::from_millis(10));
+        let run = client.run_checked(gen, CheckOption::default(), valid);
+        let res = runtime::block_on(0, async {
+            madsim::time::timeout(Duration::from_millis(200), run).await
+        });
+        assert!(res.is_err());
+        // healed by the guard when the run is cancelled
+        assert!(!faults.is_active());
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_abort() {
+        let client: &'static _ =
+            Box::leak(JepsenClient::new(StuckCluster, WriteGenerator(0)).into());
+        let gen = GeneratorGroup::new([client.new_generator(10)]);
+        let handle = client.run_checked_detached(gen, CheckOption::default(), valid);
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        assert!(!handle.is_finished());
+        handle.abort();
+        assert!(handle.join().await.is_ok());
+
+        let history = client.global.full_history().unwrap();
+        let [invoke, aborted] = &history.0[..] else {
+            panic!("unexpected history {:?}", history.0);
+        };
+        assert_eq!(invoke.type_, HistoryType::Invoke);
+        assert_eq!(aborted.type_, HistoryType::Info);
+        assert_eq!(
+            aborted.error,
+            Some(OpError::Custom(RUN_ABORTED.to_string()))
+        );
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_panic_isolation() {
+        use tokio_stream::StreamExt as _;
+
+        let client: &'static _ =
+            Box::leak(JepsenClient::new(PanicCluster, WriteGenerator(0)).into());
+        // writes 1, 2, 3, and panics on 2
+        let panicking = client.new_generator(3);
+        let panicking_id = panicking.id.get();
+        // writes 4, 5, 6
+        let healthy = client.new_generator(3);
+        let broken = GeneratorBuilder::new(client.global.clone())
+            .seq(
+                tokio_stream::iter(client.global.take_seq(1))
+                    .map(|_| -> OpOrNemesis { panic!("broken generator") }),
+            )
+            .build();
+        let broken_id = broken.id.get();
+        let gen = GeneratorGroup::new([panicking, healthy, broken]);
+        let handle = client.run_checked_detached(gen, CheckOption::default(), valid);
+        assert!(handle.join().await.is_ok());
+
+        let mut panics = client.global.panics();
+        panics.sort_by_key(|panic| panic.generator);
+        let panics: Vec<_> = panics
+            .iter()
+            .map(|panic| (panic.generator, panic.message.as_str()))
+            .collect();
+        let mut expected = vec![
+            (panicking_id, "put 2 panicked"),
+            (broken_id, "broken generator"),
+        ];
+        expected.sort();
+        assert_eq!(panics, expected);
+
+        // the rest of the run completes, without the write of 3
+        let history = client.global.full_history().unwrap();
+        let count = |type_| history.0.iter().filter(|item| item.type_ == type_).count();
+        assert_eq!(count(HistoryType::Invoke), 5);
+        assert_eq!(count(HistoryType::Ok), 4);
+        let info: Vec<_> = history
+            .0
+            .iter()
+            .filter(|item| item.type_ == HistoryType::Info)
+            .collect();
+        assert_eq!(info.len(), 1);
+        assert_eq!(
+            info[0].error,
+            Some(OpError::Custom(format!(
+                "{}: put 2 panicked",
+                HARNESS_PANIC
+            )))
+        );
+        assert!(client.global.id_set.lock().unwrap().is_empty());
+    }
+
+    #[cfg(madsim)]
+    #[test]
+    fn test_watchdog() {
+        let stalls = Arc::new(Mutex::new(vec![]));
+        let watchdog = Watchdog::new(Duration::from_millis(50))
+            .abort(true)
+            .on_stall({
+                let stalls = stalls.clone();
+                move |report| stalls.lock().unwrap().push(report.clone())
+            });
+        let client: &'static _ = Box::leak(
+            JepsenClient::new(StuckCluster, WriteGenerator(0))
+                .with_watchdog(watchdog)
+                .into(),
+        );
+        // the system threads are allowed in the runtime
+        runtime::block_on(0, async {
+            let gen = GeneratorGroup::new([client.new_generator(10)]);
+            let handle = client.run_checked_detached(gen, CheckOption::default(), valid);
+            madsim::time::sleep(Duration::from_secs(1)).await;
+            // the run is stuck in the wall clock
+            std::thread::sleep(Duration::from_millis(200));
+            assert!(handle.join().await.is_ok());
+        });
+
+        let stalls = stalls.lock().unwrap();
+        let [stall] = &stalls[..] else {
+            panic!("unexpected stalls {:?}", stalls);
+        };
+        assert!(stall.stalled_for >= Duration::from_millis(50));
+        assert_eq!(stall.pushed, 1);
+        assert_eq!(stall.in_flight, 1);
+        assert_eq!(stall.pending.as_ref().map(Vec::len), Some(1));
+        assert!(stall.to_string().contains("pending ops: \n    process 0"));
+        // aborted by the watchdog
+        let history = client.global.full_history().unwrap();
+        assert_eq!(
+            history.0.last().unwrap().error,
+            Some(OpError::Custom(RUN_ABORTED.to_string()))
+        );
     }
 }
@@ -0,0 +1,433 @@
+//! The JVM running jepsen and elle, and the clojure namespaces and functions
+//! on it, enabled by the `clojure` feature.
+
+use std::{
+    borrow::Borrow,
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::executor::JvmExecutor;
+use crate::utils::FfiError;
+use default_struct_builder::DefaultBuilder;
+use j4rs::{ClasspathEntry, Instance, InvocationArg, JavaOpt, Jvm, JvmBuilder};
+use log::warn;
+
+thread_local! {
+    static JVM: RefCell<Option<Jvm>> = const { RefCell::new(None) };
+    /// The vars looked up on the thread, by the namespace and the name.
+    static VARS: RefCell<HashMap<(String, String), Instance>> = RefCell::default();
+}
+
+/// The namespaces required in the process.
+static REQUIRED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// The forms warming the namespaces up by their first invocations, which
+/// load and initialize the classes they use.
+const WARMUPS: [(&str, &str); 3] = [
+    (
+        "elle.rw-register",
+        "(do (require 'jepsen.history)
+             (doall (take 1 (elle.rw-register/gen)))
+             (elle.rw-register/check {} (jepsen.history/history [])))",
+    ),
+    ("jepsen.history", "(jepsen.history/history [])"),
+    (
+        "clojure.data.json",
+        "(clojure.data.json/write-str (clojure.data.json/read-str \"{}\"))",
+    ),
+];
+
+/// The config of the JVM of the process, set by the first [`init_jvm_with`].
+pub(crate) static JVM_CONFIG: OnceLock<JvmConfig> = OnceLock::new();
+
+/// The options of the JVM running jepsen and elle. There is one JVM in a
+/// process, so the config takes effect only if it's set by [`init_jvm_with`]
+/// before the JVM is used.
+#[derive(Debug, Clone, Default, PartialEq, Eq, DefaultBuilder)]
+pub struct JvmConfig {
+    /// The maximum heap size passed as `-Xmx`, e.g. `8g` to check large
+    /// histories.
+    #[builder(into)]
+    max_heap: Option<String>,
+    /// Extra classpath entries, e.g. the jars of another version of elle.
+    classpath: Vec<PathBuf>,
+    /// The directory of the `jassets` holding the jars of clojure, jepsen and
+    /// elle provisioned by the build script, next to the executable by
+    /// default.
+    #[builder(into)]
+    jar_dir: Option<PathBuf>,
+    /// Extra JVM flags, e.g. `-XX:+UseG1GC`.
+    flags: Vec<String>,
+}
+
+impl JvmConfig {
+    fn java_opts(&self) -> Vec<String> {
+        let heap = self.max_heap.iter().map(|heap| format!("-Xmx{}", heap));
+        heap.chain(self.flags.iter().cloned()).collect()
+    }
+
+    /// The `jassets` directory j4rs loads the jars from, in the jar dir, or
+    /// the first one next to the executable or its ancestors like j4rs.
+    pub(crate) fn jassets(&self) -> Option<PathBuf> {
+        if let Some(dir) = &self.jar_dir {
+            return Some(dir.join("jassets"));
+        }
+        let exe = std::env::current_exe().ok()?.canonicalize().ok()?;
+        exe.ancestors()
+            .skip(1)
+            .map(|dir| dir.join("jassets"))
+            .find(|dir| dir.is_dir())
+    }
+
+    /// The jars on the classpath, besides the ones in the `jassets`.
+    pub(crate) fn classpath_jars(&self) -> &[PathBuf] {
+        &self.classpath
+    }
+
+    /// Create the JVM, or attach to it if it's created, and attach the
+    /// current thread.
+    fn build(&self) -> j4rs::errors::Result<Jvm> {
+        let opts = self.java_opts();
+        let opts: Vec<_> = opts.iter().map(|opt| JavaOpt::new(opt)).collect();
+        let classpath: Vec<_> = self
+            .classpath
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .collect();
+        let classpath: Vec<_> = classpath.iter().map(|cp| ClasspathEntry::new(cp)).collect();
+        let _jvm = match &self.jar_dir {
+            Some(dir) => JvmBuilder::new()
+                .java_opts(opts)
+                .classpath_entries(classpath)
+                .with_base_path(&dir.to_string_lossy())
+                .build()?,
+            None => JvmBuilder::new()
+                .java_opts(opts)
+                .classpath_entries(classpath)
+                .build()?,
+        };
+        Jvm::attach_thread()
+    }
+}
+
+/// Initialize the JVM for the current thread with the config, which is used
+/// for the JVM of the process if none is set before. A different config set
+/// later is ignored with a warning.
+pub fn init_jvm_with(config: JvmConfig) -> j4rs::errors::Result<()> {
+    let config = match JVM_CONFIG.set(config) {
+        Ok(()) => JVM_CONFIG.get().unwrap(),
+        Err(config) => {
+            let set = JVM_CONFIG.get().unwrap();
+            if *set != config {
+                log::warn!(
+                    "the JVM config is already set to {:?}, {:?} is ignored",
+                    set,
+                    config
+                );
+            }
+            set
+        }
+    };
+    JVM.with(|cell| {
+        if cell.borrow().is_none() {
+            let jvm = config.build()?;
+            *cell.borrow_mut() = Some(jvm);
+        }
+        Ok(())
+    })
+}
+
+/// The config set by [`init_jvm_with`], or the default one. With the
+/// `bootstrap` feature, the default one has the jars located or downloaded by
+/// [`Bootstrap`](crate::bootstrap::Bootstrap) on the classpath.
+pub(crate) fn jvm_config() -> JvmConfig {
+    if let Some(config) = JVM_CONFIG.get() {
+        return config.clone();
+    }
+    #[cfg(feature = "bootstrap")]
+    match crate::bootstrap::Bootstrap::default().jvm_config() {
+        Ok(config) => return config,
+        Err(err) => warn!("failed to bootstrap the jars of the JVM: {:#}", err),
+    }
+    JvmConfig::default()
+}
+
+/// Initialize the JVM for the current thread, with the default config if none
+/// is set by [`init_jvm_with`].
+pub fn try_init_jvm() -> j4rs::errors::Result<()> {
+    init_jvm_with(jvm_config())
+}
+
+/// Like [`try_init_jvm`], but panics on failure.
+pub fn init_jvm() {
+    try_init_jvm().expect("Failed to initialize JVM")
+}
+
+/// Whether the current thread is attached to the JVM, checked by a trivial
+/// call.
+fn is_attached(jvm: &Jvm) -> bool {
+    jvm.static_class("java.lang.Object").is_ok()
+}
+
+/// Check the JVM of the thread, and attach the thread again if it's detached,
+/// e.g. by another [`Jvm`] dropped on it.
+fn ensure_attached(jvm: &mut Option<Jvm>) -> Result<(), FfiError> {
+    if jvm.as_ref().is_some_and(is_attached) {
+        return Ok(());
+    }
+    warn!(
+        "the thread {:?} is detached from the JVM, attaching it again",
+        std::thread::current().name()
+    );
+    // attach before the old one is dropped, which would detach the thread
+    let attached = Jvm::attach_thread().map_err(FfiError::thread_not_attached)?;
+    if let Err(err) = attached.static_class("java.lang.Object") {
+        return Err(FfiError::thread_not_attached(err));
+    }
+    *jvm = Some(attached);
+    Ok(())
+}
+
+/// Run the function with the JVM of the current thread, initialized and
+/// checked to be attached, see [`jvm_healthcheck`]. The nested calls reuse
+/// the check of the outermost one.
+pub fn try_with_jvm<F, R>(f: F) -> Result<R, FfiError>
+where
+    F: FnOnce(&Jvm) -> R,
+{
+    try_init_jvm()?;
+    JVM.with(|cell| {
+        if let Ok(mut jvm) = cell.try_borrow_mut() {
+            ensure_attached(&mut jvm)?;
+        }
+        let jvm = cell.borrow();
+        Ok(f(jvm.as_ref().expect("the JVM is initialized")))
+    })
+}
+
+/// Like [`try_with_jvm`], but panics with the [`FfiError`].
+pub fn with_jvm<F, R>(f: F) -> R
+where
+    F: FnOnce(&Jvm) -> R,
+{
+    try_with_jvm(f).unwrap_or_else(|err| panic!("the JVM is unavailable: {}", err))
+}
+
+/// Check that the current thread can call the JVM, attaching it again if it
+/// was detached. It's cheap enough to call before a batch of invocations,
+/// e.g. after a madsim task resumes on another thread.
+pub fn jvm_healthcheck() -> Result<(), FfiError> {
+    try_with_jvm(|_| ())
+}
+
+pub fn read_edn(arg: &str) -> j4rs::errors::Result<Instance> {
+    with_jvm(|_| cljinvoke!("load-string", arg))
+}
+
+pub(crate) fn invoke_clojure_java_api(
+    method_name: &str,
+    inv_args: &[impl Borrow<InvocationArg>],
+) -> j4rs::errors::Result<Instance> {
+    with_jvm(|jvm| {
+        jvm.invoke(
+            &with_jvm(|jvm| jvm.static_class("clojure.java.api.Clojure"))?,
+            method_name,
+            inv_args,
+        )
+    })
+}
+
+pub struct IFn {
+    inner: Instance,
+}
+
+impl IFn {
+    pub fn new(inner: Instance) -> Self {
+        Self { inner }
+    }
+
+    pub fn invoke0(&self) -> Result<Instance, FfiError> {
+        self.invoke(&[] as &[InvocationArg])
+    }
+
+    pub fn invoke1(&self, arg: impl Into<InvocationArg>) -> Result<Instance, FfiError> {
+        self.invoke(&[arg.into()])
+    }
+
+    /// Invoke the function, the Java exception thrown is parsed into the
+    /// [`FfiError`].
+    pub fn invoke(&self, args: &[impl Borrow<InvocationArg>]) -> Result<Instance, FfiError> {
+        try_with_jvm(|jvm| jvm.invoke(&self.inner, "invoke", args))?.map_err(FfiError::from)
+    }
+
+    pub fn get_cls(&self, name: &str) -> j4rs::errors::Result<Instance> {
+        with_jvm(|jvm| jvm.field(&self.inner, name))
+    }
+
+    pub fn into_inner(self) -> Instance {
+        self.inner
+    }
+}
+
+/// Clojure Namespace. A namespace should be created by `CljCore::require`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CljNs {
+    ns: String,
+}
+
+impl CljNs {
+    pub fn var(&self, name: &str) -> j4rs::errors::Result<IFn> {
+        Self::var_inner(&self.ns, name)
+    }
+
+    /// Look the var up, or clone it from the vars looked up on the thread.
+    fn var_inner(ns: &str, name: &str) -> j4rs::errors::Result<IFn> {
+        let key = (ns.to_string(), name.to_string());
+        let cached = VARS.with(|vars| {
+            let vars = vars.borrow();
+            let var = vars.get(&key)?;
+            Some(with_jvm(|jvm| jvm.clone_instance(var)))
+        });
+        let inner = match cached {
+            Some(var) => var?,
+            None => {
+                let var = cljinvoke_java_api!("var", ns, name)?;
+                let inner = with_jvm(|jvm| jvm.clone_instance(&var))?;
+                VARS.with(|vars| vars.borrow_mut().insert(key, var));
+                inner
+            }
+        };
+        Ok(IFn { inner })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CljCore {
+    ns: &'static str,
+}
+
+pub static CLOJURE: CljCore = CljCore { ns: "clojure.core" };
+
+impl CljCore {
+    /// Require the namespace, once in the process.
+    pub fn require(&self, ns: &str) -> j4rs::errors::Result<CljNs> {
+        init_jvm();
+        let mut required = REQUIRED.lock().unwrap();
+        if !required.contains(ns) {
+            CljNs::var_inner(self.ns, "require")?.invoke1(cljinvoke_java_api!("read", ns)?)?;
+            required.insert(ns.to_string());
+        }
+        Ok(CljNs { ns: ns.to_string() })
+    }
+
+    pub fn var(&self, name: &str) -> j4rs::errors::Result<IFn> {
+        CljNs::var_inner(self.ns, name)
+    }
+}
+
+/// Require the namespaces on the [`JvmExecutor`] and warm the known ones up,
+/// e.g. `elle.rw-register` by generating and checking once, so the first
+/// generation or check of a run does not stall for seconds. Call it at
+/// startup; a failed warmup is only logged.
+pub fn preload_namespaces(namespaces: &[&str]) -> Result<(), FfiError> {
+    let namespaces: Vec<_> = namespaces.iter().map(|ns| ns.to_string()).collect();
+    JvmExecutor::global().call_blocking(move |_| {
+        for ns in &namespaces {
+            CLOJURE.require(ns)?;
+            let Some((_, form)) = WARMUPS.iter().find(|(name, _)| name == ns) else {
+                continue;
+            };
+            if let Err(err) = cljinvoke!("load-string", *form) {
+                warn!("failed to warm {} up: {}", ns, err);
+            }
+        }
+        Ok(())
+    })
+}
+
+impl Default for CljCore {
+    fn default() -> Self {
+        CLOJURE.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::{pre_serialize, print, print_clj};
+
+    #[test]
+    fn test_jvm_config() {
+        let config = JvmConfig::default()
+            .max_heap("8g")
+            .flags(vec!["-XX:+UseG1GC".to_string()]);
+        assert_eq!(config.java_opts(), ["-Xmx8g", "-XX:+UseG1GC"]);
+        assert!(JvmConfig::default().java_opts().is_empty());
+    }
+
+    #[test]
+    fn test_jvm_healthcheck() -> Result<(), FfiError> {
+        jvm_healthcheck()?;
+        // another Jvm built and dropped on the thread
+        drop(JvmBuilder::new().build()?);
+        jvm_healthcheck()?;
+        assert!(with_jvm(is_attached));
+        Ok(())
+    }
+
+    #[test]
+    fn test_preload_namespaces() -> Result<(), Box<dyn std::error::Error>> {
+        preload_namespaces(&["elle.rw-register", "jepsen.history"])?;
+        let required = REQUIRED.lock().unwrap().clone();
+        assert!(required.contains("elle.rw-register") && required.contains("jepsen.history"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_elle_check() -> Result<(), Box<dyn std::error::Error>> {
+        init_jvm();
+        let r = CLOJURE.require("elle.rw-register")?;
+        let h = CLOJURE.require("jepsen.history")?;
+        let history = read_edn(include_str!("../assets/ex_history.edn"))?;
+        let history = nsinvoke!(h, "history", history)?;
+        let res = nsinvoke!(r, "check", history)?;
+        print_clj(res);
+        Ok(())
+    }
+
+    #[test]
+    fn test_elle_gen() -> Result<(), Box<dyn std::error::Error>> {
+        init_jvm();
+        let r = CLOJURE.require("elle.rw-register")?;
+        let gen = nsinvoke!(r, "gen")?;
+        let take = cljinvoke!("take", 5, gen)?;
+        let value = pre_serialize(take)?;
+        print_clj(value);
+        Ok(())
+    }
+
+    #[test]
+    fn elle_gen_analysis() -> Result<(), Box<dyn std::error::Error>> {
+        init_jvm();
+        let r = CLOJURE.require("elle.rw-register")?;
+        let h = CLOJURE.require("jepsen.history")?;
+        let gen = r.var("gen")?.invoke0()?;
+        let history = cljinvoke!("take", 10, gen)?;
+        let res = nsinvoke!(r, "check", nsinvoke!(h, "history", history)?)?;
+        print(res);
+        Ok(())
+    }
+
+    /// We can define a function in namespace, and call it later.
+    #[test]
+    fn test_defn_in_ns() -> Result<(), Box<dyn std::error::Error>> {
+        init_jvm();
+        let _x = cljeval!((defn test [] (str "hello" "world")))?;
+        let y = cljeval!((test))?;
+        print_clj(y);
+        Ok(())
+    }
+}
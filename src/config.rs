@@ -0,0 +1,543 @@
+//! Tests described in config files, enabled by the `config` feature, so the
+//! test matrices can live in version-controlled files instead of Rust code:
+//!
+//! ```toml
+//! name = "register"
+//! concurrency = 5
+//! ops = 200
+//! duration = 10.0
+//! seed = 1
+//! out-dir = "store"
+//! progress = 5.0
+//!
+//! [workload]
+//! type = "rw-register"
+//! conflict-rate = 0.1
+//!
+//! [check]
+//! consistency-models = "strict-serializable"
+//!
+//! [[nemesis]]
+//! fault = { type = "partition-halves" }
+//! duration = 2.0
+//! quiet-period = 1.0
+//! cycles = 3
+//!
+//! [nemesis-mix]
+//! interval = 5.0
+//! count = 4
+//! faults = [
+//!     { weight = 3, fault = { type = "kill-leader" } },
+//!     { weight = 1, fault = { type = "pause", servers = [0, 1] } },
+//! ]
+//! ```
+//!
+//! The durations are in seconds. A config is TOML, or JSON or YAML if the
+//! file ends with `.json`, or `.yaml` or `.yml`. Load it by [`Test::from_config`], or by [`TestConfig::load`]
+//! and [`TestConfig::builder`] to adjust the test before building it.
+
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use madsim::time::Duration;
+use serde::Deserialize;
+
+use crate::{
+    checker::{elle_rw::ElleRwChecker, CheckOption},
+    generator::{
+        conflict::{ConflictOption, ConflictPairGenerator},
+        elle_rw::ElleRwGenerator,
+        nemesis_mix::{NemesisGeneratorBuilder, NemesisMix},
+    },
+    nemesis::{schedule::NemesisSchedule, DiskStressMode, NemesisType, ServerId},
+    progress::ProgressReporter,
+    runner::{Test, TestBuilder, TestCluster},
+    workload::Workload,
+};
+
+/// A whole test, see the [module](self) doc.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TestConfig {
+    #[serde(default = "default_name")]
+    pub name: String,
+    /// The name of the adapter creating the cluster, only used by the `cli`.
+    pub adapter: Option<String>,
+    /// The addresses of the servers, interpreted by the adapter.
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    #[serde(default)]
+    pub workload: WorkloadConfig,
+    /// The check option of elle, in its kebab-case keys.
+    #[serde(default)]
+    pub check: CheckOption,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// The ops of every process.
+    #[serde(default = "default_ops")]
+    pub ops: usize,
+    /// The duration to spread the ops over.
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub seed: u64,
+    pub out_dir: Option<std::path::PathBuf>,
+    /// The fault window schedules, run concurrently.
+    #[serde(default)]
+    pub nemesis: Vec<ScheduleConfig>,
+    pub nemesis_mix: Option<NemesisMixConfig>,
+    /// The interval to report the progress to stderr, not reported if unset.
+    pub progress: Option<f64>,
+    /// The hash of the loaded file, see [`TestConfig::hash`].
+    #[serde(skip)]
+    hash: Option<String>,
+}
+
+/// The 64-bit FNV-1a hash of the bytes in hex, which is stable across
+/// builds unlike the hasher of std.
+fn fnv1a(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+fn default_name() -> String {
+    "test".to_string()
+}
+
+fn default_concurrency() -> usize {
+    3
+}
+
+fn default_ops() -> usize {
+    100
+}
+
+fn default_shared_writes() -> usize {
+    1
+}
+
+/// The workload of a [`TestConfig`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum WorkloadConfig {
+    /// The txns of `elle.rw-register`, some of which are turned into
+    /// conflicting pairs at `conflict-rate`, see [`ConflictPairGenerator`].
+    #[serde(rename_all = "kebab-case")]
+    RwRegister {
+        #[serde(default)]
+        conflict_rate: f64,
+        #[serde(default = "default_shared_writes")]
+        shared_writes: usize,
+    },
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self::RwRegister {
+            conflict_rate: 0.0,
+            shared_writes: default_shared_writes(),
+        }
+    }
+}
+
+/// A [`NemesisSchedule`] run for `cycles` fault windows.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub fault: FaultConfig,
+    pub duration: f64,
+    #[serde(default)]
+    pub quiet_period: f64,
+    #[serde(default = "default_cycles")]
+    pub cycles: usize,
+}
+
+fn default_cycles() -> usize {
+    1
+}
+
+/// A [`NemesisMix`] of `count` nemeses.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NemesisMixConfig {
+    /// The average interval between two nemeses.
+    pub interval: f64,
+    pub count: usize,
+    pub faults: Vec<WeightedFault>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeightedFault {
+    pub weight: u32,
+    pub fault: FaultConfig,
+}
+
+/// A [`NemesisType`] in a config, tagged by `type` in kebab-case, with the
+/// durations in seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum FaultConfig {
+    Kill {
+        servers: HashSet<ServerId>,
+    },
+    Pause {
+        servers: HashSet<ServerId>,
+    },
+    Wipe {
+        server: ServerId,
+    },
+    KillLeader {
+        #[serde(default)]
+        chase: usize,
+    },
+    PauseLeader {
+        #[serde(default)]
+        chase: usize,
+    },
+    PartitionHalves,
+    PartitionMajoritiesRing,
+    PartitionRandomNode,
+    Partition {
+        groups: Vec<HashSet<ServerId>>,
+    },
+    BitflipWal {
+        server: ServerId,
+        bits: usize,
+    },
+    BitflipSnap {
+        server: ServerId,
+        bits: usize,
+    },
+    TruncateWal {
+        server: ServerId,
+        bytes: u64,
+    },
+    /// `slow-fsync` requires the `delay` of every fsync.
+    DiskStress {
+        server: ServerId,
+        mode: DiskStressConfig,
+        delay: Option<f64>,
+    },
+    RemoveNode {
+        server: ServerId,
+    },
+    AddNode {
+        server: ServerId,
+    },
+    Lag {
+        server: ServerId,
+        factor: u32,
+    },
+    ClockSkew {
+        servers: HashSet<ServerId>,
+        offset: i64,
+    },
+    ClockStrobe {
+        servers: HashSet<ServerId>,
+        delta: i64,
+        period: f64,
+        duration: f64,
+    },
+    ClockDrift {
+        servers: HashSet<ServerId>,
+        ppm: i64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiskStressConfig {
+    FillDisk,
+    Enospc,
+    SlowFsync,
+}
+
+fn secs(secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("invalid duration {}", secs))
+}
+
+impl TryFrom<FaultConfig> for NemesisType {
+    type Error = anyhow::Error;
+
+    fn try_from(fault: FaultConfig) -> Result<Self> {
+        Ok(match fault {
+            FaultConfig::Kill { servers } => Self::Kill(servers),
+            FaultConfig::Pause { servers } => Self::Pause(servers),
+            FaultConfig::Wipe { server } => Self::Wipe(server),
+            FaultConfig::KillLeader { chase } => Self::KillLeader { chase },
+            FaultConfig::PauseLeader { chase } => Self::PauseLeader { chase },
+            FaultConfig::PartitionHalves => Self::PartitionHalves,
+            FaultConfig::PartitionMajoritiesRing => Self::PartitionMajoritiesRing,
+            FaultConfig::PartitionRandomNode => Self::PartitionRandomNode,
+            FaultConfig::Partition { groups } => Self::PartitionNamed { groups },
+            FaultConfig::BitflipWal { server, bits } => Self::BitflipWal { server, bits },
+            FaultConfig::BitflipSnap { server, bits } => Self::BitflipSnap { server, bits },
+            FaultConfig::TruncateWal { server, bytes } => Self::TruncateWal { server, bytes },
+            FaultConfig::DiskStress {
+                server,
+                mode,
+                delay,
+            } => Self::DiskStress {
+                server,
+                mode: match (mode, delay) {
+                    (DiskStressConfig::FillDisk, None) => DiskStressMode::FillDisk,
+                    (DiskStressConfig::Enospc, None) => DiskStressMode::Enospc,
+                    (DiskStressConfig::SlowFsync, Some(delay)) => {
+                        DiskStressMode::SlowFsync(secs(delay)?)
+                    }
+                    (DiskStressConfig::SlowFsync, None) => bail!("slow-fsync requires a delay"),
+                    (mode, Some(_)) => bail!("{:?} takes no delay", mode),
+                },
+            },
+            FaultConfig::RemoveNode { server } => Self::RemoveNode(server),
+            FaultConfig::AddNode { server } => Self::AddNode(server),
+            FaultConfig::Lag { server, factor } => Self::Lag { server, factor },
+            FaultConfig::ClockSkew { servers, offset } => Self::ClockSkew { servers, offset },
+            FaultConfig::ClockStrobe {
+                servers,
+                delta,
+                period,
+                duration,
+            } => Self::ClockStrobe {
+                servers,
+                delta,
+                period: secs(period)?,
+                duration: secs(duration)?,
+            },
+            FaultConfig::ClockDrift { servers, ppm } => Self::ClockDrift { servers, ppm },
+        })
+    }
+}
+
+/// The [`Workload`] of a [`WorkloadConfig`], checked by elle with the check
+/// option of the config.
+pub struct ConfigWorkload {
+    workload: WorkloadConfig,
+    check: CheckOption,
+}
+
+impl Workload for ConfigWorkload {
+    type Gen = ConflictPairGenerator<ElleRwGenerator>;
+    type Checker = ElleRwChecker;
+
+    fn generator(&self) -> Result<Self::Gen> {
+        let WorkloadConfig::RwRegister {
+            conflict_rate,
+            shared_writes,
+        } = self.workload;
+        let option = ConflictOption::default()
+            .rate(conflict_rate)
+            .shared_writes(shared_writes);
+        Ok(ConflictPairGenerator::new(ElleRwGenerator::new()?, option))
+    }
+
+    fn checker(&self) -> Result<Self::Checker> {
+        Ok(ElleRwChecker::default())
+    }
+
+    fn check_option(&self) -> CheckOption {
+        self.check.clone()
+    }
+
+    fn requires_jvm(&self) -> bool {
+        true
+    }
+}
+
+impl TestConfig {
+    /// Load the config from a TOML file, or a JSON or YAML file by the
+    /// extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("failed to read {}: {}", path.display(), err))?;
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&text).map_err(anyhow::Error::from),
+            Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(anyhow::Error::from),
+            _ => toml::from_str(&text).map_err(anyhow::Error::from),
+        };
+        let mut config: Self =
+            config.map_err(|err| anyhow!("invalid config {}: {}", path.display(), err))?;
+        config.hash = Some(fnv1a(text.as_bytes()));
+        Ok(config)
+    }
+
+    /// The hash of the content of the config file, recorded in the
+    /// [`TestReport`](crate::runner::TestReport) to tell which config a run is
+    /// of. `None` if the config is not loaded from a file.
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    /// The nemesis mix of the config, if any.
+    fn mix(&self) -> Result<Option<(NemesisMix, usize)>> {
+        let Some(config) = &self.nemesis_mix else {
+            return Ok(None);
+        };
+        if config.faults.iter().all(|f| f.weight == 0) {
+            bail!("the total weight of the nemesis mix must be positive");
+        }
+        let mut builder = NemesisGeneratorBuilder::new(secs(config.interval)?);
+        for fault in &config.faults {
+            builder = builder.fault(fault.weight, fault.fault.clone().try_into()?);
+        }
+        Ok(Some((builder.build(), config.count)))
+    }
+
+    /// The builder of the test of the config on the cluster created by
+    /// `cluster`, see [`TestBuilder::new`].
+    pub fn builder<EC: TestCluster>(
+        self,
+        cluster: impl FnOnce() -> EC + 'static,
+    ) -> Result<TestBuilder<EC, ConfigWorkload>> {
+        let mix = self.mix()?;
+        let workload = ConfigWorkload {
+            workload: self.workload,
+            check: self.check,
+        };
+        let mut builder = TestBuilder::new(cluster, workload)
+            .name(self.name)
+            .concurrency(self.concurrency)
+            .ops(self.ops)
+            .seed(self.seed);
+        if let Some(duration) = self.duration {
+            builder = builder.duration(secs(duration)?);
+        }
+        if let Some(dir) = self.out_dir {
+            builder = builder.out_dir(dir);
+        }
+        for schedule in self.nemesis {
+            let fault = schedule.fault.try_into()?;
+            let schedule_ = NemesisSchedule::new(
+                fault,
+                secs(schedule.duration)?,
+                secs(schedule.quiet_period)?,
+            );
+            builder = builder.nemesis(schedule_, schedule.cycles);
+        }
+        if let Some((mix, n)) = mix {
+            builder = builder.nemesis_mix(mix, n);
+        }
+        if let Some(interval) = self.progress {
+            builder = builder.progress(secs(interval)?, ProgressReporter::stderr());
+        }
+        if let Some(hash) = self.hash {
+            builder = builder.config_hash(hash);
+        }
+        Ok(builder)
+    }
+}
+
+impl<EC: TestCluster> Test<EC, ConfigWorkload> {
+    /// Load the test from a config file, see the [module](crate::config) doc,
+    /// to run on the cluster created by `cluster`.
+    pub fn from_config(
+        path: impl AsRef<Path>,
+        cluster: impl FnOnce() -> EC + 'static,
+    ) -> Result<Self> {
+        Ok(TestConfig::load(path)?.builder(cluster)?.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+name = "register"
+ops = 20
+duration = 2.5
+
+[workload]
+type = "rw-register"
+conflict-rate = 0.5
+
+[check]
+consistency-models = "read-committed"
+
+[[nemesis]]
+fault = { type = "disk-stress", server = 1, mode = "slow-fsync", delay = 0.1 }
+duration = 1.0
+cycles = 2
+
+[nemesis-mix]
+interval = 5.0
+count = 3
+faults = [{ weight = 1, fault = { type = "kill", servers = [0, 2] } }]
+"#;
+
+    #[test]
+    fn test_parse_config() -> Result<()> {
+        let config: TestConfig = toml::from_str(CONFIG)?;
+        assert_eq!((config.name.as_str(), config.ops), ("register", 20));
+        assert_eq!(config.concurrency, 3);
+        assert!(matches!(
+            config.workload,
+            WorkloadConfig::RwRegister { conflict_rate, shared_writes: 1 } if conflict_rate == 0.5
+        ));
+        assert_eq!(
+            serde_json::to_value(&config.check)?["consistency-models"],
+            "read-committed"
+        );
+        let fault: NemesisType = config.nemesis[0].fault.clone().try_into()?;
+        assert_eq!(
+            fault,
+            NemesisType::DiskStress {
+                server: 1,
+                mode: DiskStressMode::SlowFsync(Duration::from_millis(100)),
+            }
+        );
+        assert_eq!(config.nemesis[0].cycles, 2);
+        assert!(config.mix()?.is_some());
+
+        // typos are rejected
+        assert!(toml::from_str::<TestConfig>("concurency = 3").is_err());
+        let fault: FaultConfig = toml::from_str(
+            r#"type = "disk-stress"
+server = 0
+mode = "slow-fsync""#,
+        )?;
+        assert!(NemesisType::try_from(fault).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_json_config() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("jepsen-rs-config-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"seed": 3, "nemesis": [{"fault": {"type": "partition-halves"}, "duration": 1}]}"#,
+        )?;
+        let config = TestConfig::load(&path)?;
+        assert_eq!(config.seed, 3);
+        let fault: NemesisType = config.nemesis[0].fault.clone().try_into()?;
+        assert_eq!(fault, NemesisType::PartitionHalves);
+        assert_eq!(config.hash(), Some(fnv1a(&std::fs::read(&path)?).as_str()));
+        assert_eq!(fnv1a(b"a"), "af63dc4c8601ec8c");
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_yaml_config() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("jepsen-rs-config-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            "seed: 4\nworkload:\n  type: rw-register\n  conflict-rate: 0.5\nnemesis:\n  - fault: { type: partition-halves }\n    duration: 1\n",
+        )?;
+        let config = TestConfig::load(&path)?;
+        std::fs::remove_file(path)?;
+        assert_eq!(config.seed, 4);
+        assert!(matches!(
+            config.workload,
+            WorkloadConfig::RwRegister { conflict_rate, .. } if conflict_rate == 0.5
+        ));
+        let fault: NemesisType = config.nemesis[0].fault.clone().try_into()?;
+        assert_eq!(fault, NemesisType::PartitionHalves);
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+//! The EDN reader and deserializer.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeOwned, EnumAccess, IntoDeserializer, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::{Map, Number, Value};
+
+use super::EdnError;
+
+/// A form read from EDN. Vectors, lists and sets are all sequences, and the
+/// tags of tagged elements are dropped.
+#[derive(Debug, Clone, PartialEq)]
+enum Edn {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    /// A keyword without the `:`, or a symbol.
+    Keyword(String),
+    Seq(Vec<Edn>),
+    Map(Vec<(Edn, Edn)>),
+}
+
+impl Edn {
+    /// Convert to JSON, with keywords as strings and map keys which are not
+    /// strings printed as JSON.
+    fn into_json(self) -> Value {
+        match self {
+            Edn::Nil => Value::Null,
+            Edn::Bool(b) => Value::Bool(b),
+            Edn::Int(n) => n.into(),
+            Edn::UInt(n) => n.into(),
+            Edn::Float(n) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+            Edn::String(s) | Edn::Keyword(s) => Value::String(s),
+            Edn::Seq(items) => Value::Array(items.into_iter().map(Edn::into_json).collect()),
+            Edn::Map(entries) => {
+                let mut map = Map::new();
+                for (k, v) in entries {
+                    let k = match k.into_json() {
+                        Value::String(k) => k,
+                        k => k.to_string(),
+                    };
+                    map.insert(k, v.into_json());
+                }
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+/// Read the EDN forms of the text as JSON values, the way the clojure side
+/// converts them to JSON: keywords and symbols become strings without the
+/// `:`, lists and sets become arrays, map keys which are not strings are
+/// printed as JSON, and tagged elements like `#jepsen.history.Op{..}` become
+/// the tagged value.
+pub fn parse_edn(text: &str) -> Result<Vec<Value>> {
+    Ok(read_forms(text)?.into_iter().map(Edn::into_json).collect())
+}
+
+/// Deserialize a value from the only EDN form of the text.
+pub fn from_edn<T: DeserializeOwned>(text: &str) -> Result<T> {
+    match <[Edn; 1]>::try_from(read_forms(text)?) {
+        Ok([form]) => Ok(T::deserialize(form)?),
+        Err(forms) => bail!("expect one EDN form, found {}", forms.len()),
+    }
+}
+
+fn read_forms(text: &str) -> Result<Vec<Edn>> {
+    let mut reader = Reader {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let mut forms = vec![];
+    while let Some(form) = reader.next_form()? {
+        forms.push(form);
+    }
+    Ok(forms)
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn is_delimiter(c: char) -> bool {
+        c.is_whitespace() || matches!(c, ',' | '(' | ')' | '[' | ']' | '{' | '}' | '"' | ';')
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ';' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                c if c.is_whitespace() || c == ',' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    /// Read the next form, or `None` at the end of the text.
+    fn next_form(&mut self) -> Result<Option<Edn>> {
+        self.skip_whitespace();
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+        let start = self.pos;
+        self.pos += 1;
+        let form = match c {
+            '[' => Edn::Seq(self.read_seq(']')?),
+            '(' => Edn::Seq(self.read_seq(')')?),
+            '{' => self.read_map()?,
+            '"' => Edn::String(self.read_string()?),
+            '\\' => Edn::String(self.read_char()),
+            '#' => match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    Edn::Seq(self.read_seq('}')?)
+                }
+                Some('_') => {
+                    self.pos += 1;
+                    self.expect_form()?;
+                    return self.next_form();
+                }
+                // a tagged element, e.g. `#inst "..."`
+                _ => {
+                    self.read_token();
+                    self.expect_form()?
+                }
+            },
+            ')' | ']' | '}' => bail!("unexpected `{}` at {}", c, start),
+            _ => {
+                self.pos = start;
+                let token = self.read_token();
+                Self::parse_token(&token)
+            }
+        };
+        Ok(Some(form))
+    }
+
+    fn expect_form(&mut self) -> Result<Edn> {
+        self.next_form()?
+            .ok_or_else(|| anyhow!("unexpected end at {}", self.pos))
+    }
+
+    /// Read the forms until the closing char.
+    fn read_seq(&mut self, close: char) -> Result<Vec<Edn>> {
+        let mut items = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                Some(_) => items.push(self.expect_form()?),
+                None => bail!("unclosed `{}` at the end", close),
+            }
+        }
+    }
+
+    fn read_map(&mut self) -> Result<Edn> {
+        let items = self.read_seq('}')?;
+        if items.len() % 2 != 0 {
+            bail!("odd number of forms in the map before {}", self.pos);
+        }
+        let mut entries = Vec::with_capacity(items.len() / 2);
+        let mut items = items.into_iter();
+        while let (Some(k), Some(v)) = (items.next(), items.next()) {
+            entries.push((k, v));
+        }
+        Ok(Edn::Map(entries))
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let mut out = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| anyhow!("unclosed string at the end"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| anyhow!("unclosed string at the end"))?;
+                    self.pos += 1;
+                    match escaped {
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        'r' => out.push('\r'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => {
+                            let hex: String = self.chars[self.pos..].iter().take(4).collect();
+                            self.pos += hex.len();
+                            let code = u32::from_str_radix(&hex, 16)?;
+                            out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        }
+                        c => out.push(c),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Read a char literal after the `\`, e.g. `\a` or `\newline`.
+    fn read_char(&mut self) -> String {
+        let mut token = self.peek().map(String::from).unwrap_or_default();
+        self.pos += token.len().min(1);
+        token.push_str(&self.read_token());
+        match token.as_str() {
+            "newline" => "\n".to_string(),
+            "space" => " ".to_string(),
+            "tab" => "\t".to_string(),
+            "return" => "\r".to_string(),
+            _ => token,
+        }
+    }
+
+    fn read_token(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !Self::is_delimiter(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Parse a number, `nil`, a boolean, a keyword or a symbol.
+    fn parse_token(token: &str) -> Edn {
+        match token {
+            "nil" => return Edn::Nil,
+            "true" => return Edn::Bool(true),
+            "false" => return Edn::Bool(false),
+            "##NaN" => return Edn::Float(f64::NAN),
+            "##Inf" => return Edn::Float(f64::INFINITY),
+            "##-Inf" => return Edn::Float(f64::NEG_INFINITY),
+            _ => {}
+        }
+        if let Some(keyword) = token.strip_prefix(':') {
+            return Edn::Keyword(keyword.to_string());
+        }
+        let number = token.trim_end_matches(['N', 'M']);
+        if let Ok(n) = number.parse::<i64>() {
+            return Edn::Int(n);
+        }
+        if let Ok(n) = number.parse::<u64>() {
+            return Edn::UInt(n);
+        }
+        if let Some((num, den)) = number.split_once('/') {
+            if let (Ok(num), Ok(den)) = (num.parse::<f64>(), den.parse::<f64>()) {
+                return Edn::Float(num / den);
+            }
+        }
+        match number.parse::<f64>() {
+            Ok(n) if number.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') => {
+                Edn::Float(n)
+            }
+            _ => Edn::Keyword(token.to_string()),
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, EdnError> for Edn {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Edn {
+    type Error = EdnError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EdnError> {
+        match self {
+            Edn::Nil => visitor.visit_unit(),
+            Edn::Bool(b) => visitor.visit_bool(b),
+            Edn::Int(n) => visitor.visit_i64(n),
+            Edn::UInt(n) => visitor.visit_u64(n),
+            Edn::Float(n) => visitor.visit_f64(n),
+            Edn::String(s) | Edn::Keyword(s) => visitor.visit_string(s),
+            Edn::Seq(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Edn::Map(entries) => {
+                let mut map = MapDeserializer::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EdnError> {
+        match self {
+            Edn::Nil => visitor.visit_none(),
+            form => visitor.visit_some(form),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, EdnError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// A unit variant is a keyword or a string, and other variants are maps
+    /// from the variant to the value.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, EdnError> {
+        match self {
+            Edn::String(s) | Edn::Keyword(s) => visitor.visit_enum(s.into_deserializer()),
+            Edn::Map(entries) if entries.len() == 1 => {
+                let (variant, value) = entries.into_iter().next().unwrap();
+                visitor.visit_enum(Variant { variant, value })
+            }
+            form => Err(de::Error::custom(format!(
+                "expect a keyword or a map of one entry for an enum, found {:?}",
+                form
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// A variant with a value, see [`Edn::deserialize_enum`].
+struct Variant {
+    variant: Edn,
+    value: Edn,
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = EdnError;
+    type Variant = Edn;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Edn), EdnError> {
+        Ok((seed.deserialize(self.variant)?, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Edn {
+    type Error = EdnError;
+
+    fn unit_variant(self) -> Result<(), EdnError> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, EdnError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, EdnError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, EdnError> {
+        self.deserialize_any(visitor)
+    }
+}
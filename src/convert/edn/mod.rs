@@ -0,0 +1,167 @@
+//! A native EDN (de)serializer for the rust types, so the histories and
+//! options reach the clojure side with true keywords, maps and vectors instead
+//! of the strings of a JSON intermediate.
+//!
+//! - struct fields, map keys that are valid keyword names, unit variants and
+//!   [`Keyword`]s are serialized as keywords, e.g. `{:type :invoke}`;
+//! - sequences are vectors, `None` and unit are `nil`, and other enum
+//!   variants are maps from the variant keyword to the value, like their
+//!   externally tagged JSON;
+//! - when deserializing, keywords and symbols are read as their names, lists
+//!   and sets as sequences, and tagged elements like `#jepsen.history.Op{..}`
+//!   as the tagged value.
+//!
+//! A history item is serialized in the shape of the `history.edn` of jepsen,
+//! e.g. `{:index 0, :type :invoke, :f :txn, :value [[:w 1 2]], :time 5,
+//! :process 0, :error nil}`.
+
+mod de;
+mod ser;
+
+use std::fmt;
+
+use anyhow::Result;
+pub use de::{from_edn, parse_edn};
+pub use ser::to_edn;
+use serde::Serialize;
+
+/// The newtype name marking a [`Keyword`] to the serializer.
+const KEYWORD: &str = "$jepsen_rs::edn::Keyword";
+
+/// A string serialized as a keyword in EDN, and as a plain string by other
+/// serializers, e.g. the `:w` of a micro-op.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keyword<'a>(pub &'a str);
+
+impl Serialize for Keyword<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(KEYWORD, self.0)
+    }
+}
+
+/// Serialize the strings as [`Keyword`]s, for `serialize_with`.
+pub(crate) fn keywords<S: serde::Serializer>(
+    value: &Option<Vec<String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(items) => serializer.collect_seq(items.iter().map(|s| Keyword(s))),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// The error of EDN (de)serialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdnError(String);
+
+impl fmt::Display for EdnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for EdnError {}
+
+impl serde::ser::Error for EdnError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl serde::de::Error for EdnError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{
+        checker::{CheckOption, ConsistencyModel},
+        history::SerializableHistoryList,
+    };
+
+    #[test]
+    fn test_history_to_edn() -> Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "fail", "f": "txn", "value": [["w", 2, 1], ["r", 1, null]], "time": 3, "process": 1, "error": ["aborted \"x\""] },
+          { "index": 1, "type": "info", "f": "partition", "value": { "nemesis": "PartitionHalves", "links": [[0, 1]] }, "time": 4, "process": "nemesis", "error": null },
+          { "index": 2, "type": "ok", "f": "txn", "value": [["w", 2, 1]], "time": 5, "process": 0, "error": null, "meta": { "node": 1, "retries": 0, "latency": 2 } }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let edn: Vec<_> = history.iter().map(to_edn).collect::<Result<_>>()?;
+        assert_eq!(
+            edn,
+            [
+                r#"{:index 0, :type :fail, :f :txn, :value [[:w 2 1] [:r 1 nil]], :time 3, :process 1, :error [:custom "aborted \"x\""]}"#,
+                r#"{:index 1, :type :info, :f :partition, :value {:nemesis "PartitionHalves", :links [[0 1]]}, :time 4, :process :nemesis, :error nil}"#,
+                r#"{:index 2, :type :ok, :f :txn, :value [[:w 2 1]], :time 5, :process 0, :error nil, :meta {:node 1, :retries 0, :latency 2}}"#,
+            ]
+        );
+        // the history is read back from its EDN
+        let read: SerializableHistoryList = from_edn(&to_edn(&history)?)?;
+        assert_eq!(read, history);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edn_round_trip() -> Result<()> {
+        let option = CheckOption::default()
+            .consistency_models(ConsistencyModel::StrictSerializable)
+            .anomalies(vec!["G1c".to_string()]);
+        assert_eq!(
+            to_edn(&option)?,
+            r#"{:consistency-models :strict-serializable, :directory "./out", :anomalies [:G1c]}"#
+        );
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum E {
+            Unit,
+            Newtype(f64),
+            Tuple(u8, Option<String>),
+            Struct { a: Vec<char> },
+        }
+        let values = vec![
+            E::Unit,
+            E::Newtype(1.0),
+            E::Tuple(1, None),
+            E::Struct { a: vec!['x'] },
+        ];
+        let edn = to_edn(&values)?;
+        assert_eq!(
+            edn,
+            r#"[:Unit {:Newtype 1.0} {:Tuple [1 nil]} {:Struct {:a ["x"]}}]"#
+        );
+        assert_eq!(from_edn::<Vec<E>>(&edn)?, values);
+
+        let map: std::collections::BTreeMap<_, _> = [("a b", 1), ("c", 2)].into();
+        assert_eq!(to_edn(&map)?, r#"{"a b" 1, :c 2}"#);
+        assert!(from_edn::<u64>("1 2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_edn() -> Result<()> {
+        let edn = r#"
+          ; a comment
+          {:type :invoke, :f :add, :value 1/2, :time 3N, :process 0, :index 0}
+          #jepsen.history.Op{:type :info, :f :start-partition, :value [:isolated {"n1" #{"n2"}}], :process :nemesis, :index 1}
+          [nil true -1.5 \a \newline "a\"bé" #_ :dropped sym (1 2) {1 2}]
+        "#;
+        let forms = parse_edn(edn)?;
+        assert_eq!(
+            forms,
+            [
+                serde_json::json!({"type": "invoke", "f": "add", "value": 0.5, "time": 3, "process": 0, "index": 0}),
+                serde_json::json!({"type": "info", "f": "start-partition", "value": ["isolated", {"n1": ["n2"]}], "process": "nemesis", "index": 1}),
+                serde_json::json!([null, true, -1.5, "a", "\n", "a\"b\u{e9}", "sym", [1, 2], {"1": 2}]),
+            ]
+        );
+        assert!(parse_edn("[1 2").is_err());
+        assert!(parse_edn("{:a}").is_err());
+        Ok(())
+    }
+}
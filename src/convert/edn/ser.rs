@@ -0,0 +1,373 @@
+//! The EDN serializer.
+
+use anyhow::Result;
+use serde::{ser, Serialize};
+use serde_json::Value;
+
+use super::{EdnError, KEYWORD};
+
+/// Serialize a value to EDN, see the [module](super) doc.
+pub fn to_edn<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let mut serializer = Serializer { out: String::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+/// Whether the string is read back as the same keyword after a `:`.
+fn is_keyword_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with(|c: char| c.is_ascii_digit())
+        && s.chars()
+            .all(|c| c.is_alphanumeric() || "*+!-_?<>=.".contains(c))
+}
+
+/// Serialize the value as a keyword if it's a string of a keyword name, or as
+/// is.
+fn to_keyword<T: Serialize + ?Sized>(value: &T) -> Result<String, EdnError> {
+    let edn = to_edn(value).map_err(|err| EdnError(err.to_string()))?;
+    match serde_json::from_str::<String>(&edn) {
+        Ok(name) if is_keyword_name(&name) => Ok(format!(":{}", name)),
+        _ => Ok(edn),
+    }
+}
+
+struct Serializer {
+    out: String,
+}
+
+impl Serializer {
+    fn open(&mut self, open: &str, close: &'static str) -> Compound<'_> {
+        self.out.push_str(open);
+        Compound {
+            ser: self,
+            first: true,
+            close,
+        }
+    }
+
+    /// Open the map of a variant, e.g. `{:variant `.
+    fn open_variant(&mut self, variant: &str, open: &str, close: &'static str) -> Compound<'_> {
+        self.out.push_str("{:");
+        self.out.push_str(variant);
+        self.out.push(' ');
+        self.open(open, close)
+    }
+}
+
+/// A vector or map being serialized.
+struct Compound<'a> {
+    ser: &'a mut Serializer,
+    first: bool,
+    close: &'static str,
+}
+
+impl Compound<'_> {
+    fn separate(&mut self, separator: &str) {
+        if !self.first {
+            self.ser.out.push_str(separator);
+        }
+        self.first = false;
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EdnError> {
+        self.separate(" ");
+        value.serialize(&mut *self.ser)
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), EdnError> {
+        self.separate(", ");
+        self.ser.out.push(':');
+        self.ser.out.push_str(key);
+        self.ser.out.push(' ');
+        value.serialize(&mut *self.ser)
+    }
+
+    fn finish(self) -> Result<(), EdnError> {
+        self.ser.out.push_str(self.close);
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = EdnError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), EdnError> {
+        self.out.push_str(if v { "true" } else { "false" });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), EdnError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), EdnError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), EdnError> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), EdnError> {
+        self.out.push_str(&v.to_string());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), EdnError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), EdnError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), EdnError> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), EdnError> {
+        self.out.push_str(&v.to_string());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), EdnError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), EdnError> {
+        let edn = match v {
+            v if v.is_nan() => "##NaN".to_string(),
+            f64::INFINITY => "##Inf".to_string(),
+            f64::NEG_INFINITY => "##-Inf".to_string(),
+            // printed with a fraction, so it's read back as a float
+            v => Value::from(v).to_string(),
+        };
+        self.out.push_str(&edn);
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), EdnError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    /// The escapes of JSON strings are valid in EDN.
+    fn serialize_str(self, v: &str) -> Result<(), EdnError> {
+        self.out.push_str(&Value::from(v).to_string());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), EdnError> {
+        ser::Serializer::collect_seq(self, v)
+    }
+
+    fn serialize_none(self) -> Result<(), EdnError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), EdnError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), EdnError> {
+        self.out.push_str("nil");
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), EdnError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), EdnError> {
+        self.out.push(':');
+        self.out.push_str(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), EdnError> {
+        if name == KEYWORD {
+            self.out.push_str(&to_keyword(value)?);
+            return Ok(());
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), EdnError> {
+        let compound = self.open_variant(variant, "", "}");
+        value.serialize(&mut *compound.ser)?;
+        compound.finish()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open("[", "]"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open("[", "]"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open("[", "]"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open_variant(variant, "[", "]}"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open("{", "}"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open("{", "}"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, EdnError> {
+        Ok(self.open_variant(variant, "{", "}}"))
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EdnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EdnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EdnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EdnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    /// String keys of keyword names are keywords.
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), EdnError> {
+        self.separate(", ");
+        self.ser.out.push_str(&to_keyword(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EdnError> {
+        self.ser.out.push(' ');
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), EdnError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = EdnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), EdnError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), EdnError> {
+        self.finish()
+    }
+}
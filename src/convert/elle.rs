@@ -0,0 +1,744 @@
+//! Explicit conversions between [`Op`] and the micro-op vectors of elle, both
+//! in EDN (`[[:r 1 nil] [:w 2 3]]`) and in JSON (`[["r",1,null],["w",2,3]]`).
+//!
+//! The shapes follow `elle.rw-register` and `elle.list-append`:
+//!
+//! - a micro-op is `[f key value]`, where `f` is one of `:r`, `:w` and
+//!   `:append`, or `:cas` with `[expect new]` and `:delete` with `nil` for
+//!   compare-and-set registers, or `:incr` with a signed delta for counters;
+//! - a read that has not completed yet has a `nil` value, a completed read of
+//!   a list-append key has a list value;
+//! - a txn is a vector of micro-ops, and txns cannot be nested.
+//!
+//! The multi-key reads of [`Op`] are not micro-ops of elle, and cannot be in
+//! txns: a batch read is `[:batch-read [k1 k2] [v1 v2]]`, and a range scan is
+//! `[:scan [start end] [[k1 v1] [k2 v2]]]`, both with `nil` values before they
+//! complete. [`Op::expand_reads`] converts them to txns of reads for elle.
+//!
+//! A completed list read is an [`Op::ReadList`], and a read not completed is
+//! an [`Op::Read`], as their micro-ops are the same. [`MicroOp`] keeps the
+//! micro-ops as they are.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use super::edn::Keyword;
+use crate::op::Op;
+
+/// A micro-op of elle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MicroOp {
+    /// `[:r key value]`, the value is `nil` before the read completes.
+    Read(u64, Option<ReadValue>),
+    /// `[:w key value]`
+    Write(u64, u64),
+    /// `[:append key value]`
+    Append(u64, u64),
+    /// `[:cas key [expect new]]`
+    Cas(u64, u64, u64),
+    /// `[:delete key nil]`
+    Delete(u64),
+    /// `[:incr key delta]`
+    Incr(u64, i64),
+}
+
+/// The value of a completed read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadValue {
+    /// The value of a register.
+    Register(u64),
+    /// The elements of a list.
+    List(Vec<u64>),
+}
+
+/// The syntax tree shared by EDN and JSON. Keywords are strings in JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Form {
+    Vector(Vec<Form>),
+    Keyword(String),
+    Int(u64),
+    /// A negative integer, only the delta of an incr.
+    Neg(i64),
+    Nil,
+}
+
+impl MicroOp {
+    /// The key of the micro-op.
+    pub fn key(&self) -> u64 {
+        match self {
+            MicroOp::Read(key, _)
+            | MicroOp::Write(key, _)
+            | MicroOp::Append(key, _)
+            | MicroOp::Cas(key, ..)
+            | MicroOp::Delete(key)
+            | MicroOp::Incr(key, _) => *key,
+        }
+    }
+
+    /// Map the key and the values of the micro-op, including the elements of
+    /// a read list.
+    pub fn map(
+        &self,
+        key: &mut impl FnMut(u64) -> u64,
+        value: &mut impl FnMut(u64) -> u64,
+    ) -> MicroOp {
+        match self {
+            MicroOp::Read(k, v) => MicroOp::Read(
+                key(*k),
+                v.as_ref().map(|v| match v {
+                    ReadValue::Register(v) => ReadValue::Register(value(*v)),
+                    ReadValue::List(list) => {
+                        ReadValue::List(list.iter().map(|v| value(*v)).collect())
+                    }
+                }),
+            ),
+            MicroOp::Write(k, v) => MicroOp::Write(key(*k), value(*v)),
+            MicroOp::Append(k, v) => MicroOp::Append(key(*k), value(*v)),
+            MicroOp::Cas(k, expect, new) => MicroOp::Cas(key(*k), value(*expect), value(*new)),
+            MicroOp::Delete(k) => MicroOp::Delete(key(*k)),
+            MicroOp::Incr(k, delta) => MicroOp::Incr(key(*k), *delta),
+        }
+    }
+
+    fn to_form(&self) -> Form {
+        let (f, key, value) = match self {
+            MicroOp::Read(key, value) => {
+                let value = match value {
+                    None => Form::Nil,
+                    Some(ReadValue::Register(v)) => Form::Int(*v),
+                    Some(ReadValue::List(list)) => {
+                        Form::Vector(list.iter().map(|v| Form::Int(*v)).collect())
+                    }
+                };
+                ("r", key, value)
+            }
+            MicroOp::Write(key, value) => ("w", key, Form::Int(*value)),
+            MicroOp::Append(key, value) => ("append", key, Form::Int(*value)),
+            MicroOp::Cas(key, expect, new) => (
+                "cas",
+                key,
+                Form::Vector(vec![Form::Int(*expect), Form::Int(*new)]),
+            ),
+            MicroOp::Delete(key) => ("delete", key, Form::Nil),
+            MicroOp::Incr(key, delta) => ("incr", key, Form::signed(*delta)),
+        };
+        Form::Vector(vec![Form::Keyword(f.to_string()), Form::Int(*key), value])
+    }
+
+    fn from_form(form: &Form) -> Result<Self> {
+        let Form::Vector(items) = form else {
+            bail!("a micro-op should be a vector, got {:?}", form);
+        };
+        let [Form::Keyword(f), key, value] = items.as_slice() else {
+            bail!("a micro-op should be `[f key value]`, got {:?}", form);
+        };
+        let Form::Int(key) = key else {
+            bail!("the key of a micro-op should be an integer, got {:?}", key);
+        };
+        let int = |value: &Form| match value {
+            Form::Int(v) => Ok(*v),
+            _ => Err(anyhow!("the value of `{}` should be an integer", f)),
+        };
+        Ok(match f.as_str() {
+            "r" => {
+                let value = match value {
+                    Form::Nil => None,
+                    Form::Int(v) => Some(ReadValue::Register(*v)),
+                    Form::Vector(list) => Some(ReadValue::List(
+                        list.iter().map(int).collect::<Result<_>>()?,
+                    )),
+                    Form::Keyword(_) | Form::Neg(_) => {
+                        bail!("the value of `r` should be an integer, a list or `nil`")
+                    }
+                };
+                MicroOp::Read(*key, value)
+            }
+            "w" => MicroOp::Write(*key, int(value)?),
+            "append" => MicroOp::Append(*key, int(value)?),
+            "cas" => match value {
+                Form::Vector(pair) => match pair.as_slice() {
+                    [expect, new] => MicroOp::Cas(*key, int(expect)?, int(new)?),
+                    _ => bail!("the value of `cas` should be `[expect new]`"),
+                },
+                _ => bail!("the value of `cas` should be `[expect new]`"),
+            },
+            "delete" => match value {
+                Form::Nil => MicroOp::Delete(*key),
+                _ => bail!("the value of `delete` should be `nil`"),
+            },
+            "incr" => match value {
+                Form::Int(delta) => MicroOp::Incr(
+                    *key,
+                    i64::try_from(*delta).map_err(|_| anyhow!("the delta of `incr` overflows"))?,
+                ),
+                Form::Neg(delta) => MicroOp::Incr(*key, *delta),
+                _ => bail!("the value of `incr` should be an integer"),
+            },
+            _ => bail!("unknown micro-op function `{}`", f),
+        })
+    }
+}
+
+impl TryFrom<&Op> for MicroOp {
+    type Error = anyhow::Error;
+    fn try_from(op: &Op) -> Result<Self> {
+        match op {
+            Op::Read(key, value) => Ok(MicroOp::Read(*key, value.map(ReadValue::Register))),
+            Op::Write(key, value) => Ok(MicroOp::Write(*key, *value)),
+            Op::Cas(key, expect, new) => Ok(MicroOp::Cas(*key, *expect, *new)),
+            Op::Delete(key) => Ok(MicroOp::Delete(*key)),
+            Op::Incr(key, delta) => Ok(MicroOp::Incr(*key, *delta)),
+            Op::Append(key, value) => Ok(MicroOp::Append(*key, *value)),
+            Op::ReadList(key, list) => Ok(MicroOp::Read(*key, list.clone().map(ReadValue::List))),
+            Op::BatchRead(_) | Op::ScanRange(..) => bail!("a multi-key read is not a micro-op"),
+            Op::Txn(_) => bail!("a txn is not a micro-op"),
+        }
+    }
+}
+
+/// A read not completed is an [`Op::Read`], see [`Op::ReadList`].
+impl From<MicroOp> for Op {
+    fn from(mop: MicroOp) -> Self {
+        match mop {
+            MicroOp::Read(key, None) => Op::Read(key, None),
+            MicroOp::Read(key, Some(ReadValue::Register(value))) => Op::Read(key, Some(value)),
+            MicroOp::Read(key, Some(ReadValue::List(list))) => Op::ReadList(key, Some(list)),
+            MicroOp::Write(key, value) => Op::Write(key, value),
+            MicroOp::Append(key, value) => Op::Append(key, value),
+            MicroOp::Cas(key, expect, new) => Op::Cas(key, expect, new),
+            MicroOp::Delete(key) => Op::Delete(key),
+            MicroOp::Incr(key, delta) => Op::Incr(key, delta),
+        }
+    }
+}
+
+/// Serialized in the JSON shape, with the function as a [`Keyword`], so it's
+/// the micro-op itself in EDN.
+impl Serialize for MicroOp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_mop().serialize(serializer)
+    }
+}
+
+impl MicroOp {
+    fn to_mop(&self) -> Mop<'_> {
+        let (f, key, value) = match self {
+            MicroOp::Read(key, None) => ("r", key, MopValue::Nil),
+            MicroOp::Read(key, Some(ReadValue::Register(v))) => ("r", key, MopValue::Int(*v)),
+            MicroOp::Read(key, Some(ReadValue::List(list))) => ("r", key, MopValue::List(list)),
+            MicroOp::Write(key, value) => ("w", key, MopValue::Int(*value)),
+            MicroOp::Append(key, value) => ("append", key, MopValue::Int(*value)),
+            MicroOp::Cas(key, expect, new) => ("cas", key, MopValue::Pair(*expect, *new)),
+            MicroOp::Delete(key) => ("delete", key, MopValue::Nil),
+            MicroOp::Incr(key, delta) => ("incr", key, MopValue::Signed(*delta)),
+        };
+        Mop {
+            f,
+            key: *key,
+            value,
+        }
+    }
+}
+
+/// A borrowed micro-op, serialized as its [`Form`] without building it, as
+/// ops are serialized for every history item.
+struct Mop<'a> {
+    f: &'static str,
+    key: u64,
+    value: MopValue<'a>,
+}
+
+enum MopValue<'a> {
+    Nil,
+    Int(u64),
+    Signed(i64),
+    List(&'a [u64]),
+    Pair(u64, u64),
+}
+
+impl Mop<'_> {
+    fn of(op: &Op) -> Option<Mop<'_>> {
+        let (f, key, value) = match op {
+            Op::Read(key, value) => ("r", key, value.map_or(MopValue::Nil, MopValue::Int)),
+            Op::ReadList(key, list) => (
+                "r",
+                key,
+                list.as_deref().map_or(MopValue::Nil, MopValue::List),
+            ),
+            Op::Append(key, value) => ("append", key, MopValue::Int(*value)),
+            Op::Write(key, value) => ("w", key, MopValue::Int(*value)),
+            Op::Cas(key, expect, new) => ("cas", key, MopValue::Pair(*expect, *new)),
+            Op::Delete(key) => ("delete", key, MopValue::Nil),
+            Op::Incr(key, delta) => ("incr", key, MopValue::Signed(*delta)),
+            Op::BatchRead(_) | Op::ScanRange(..) | Op::Txn(_) => return None,
+        };
+        Some(Mop {
+            f,
+            key: *key,
+            value,
+        })
+    }
+}
+
+impl Serialize for Mop<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(3))?;
+        seq.serialize_element(&Keyword(self.f))?;
+        seq.serialize_element(&self.key)?;
+        seq.serialize_element(&self.value)?;
+        seq.end()
+    }
+}
+
+impl Serialize for MopValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            MopValue::Nil => serializer.serialize_unit(),
+            MopValue::Int(v) => serializer.serialize_u64(v),
+            MopValue::Signed(v) => match u64::try_from(v) {
+                Ok(v) => serializer.serialize_u64(v),
+                Err(_) => serializer.serialize_i64(v),
+            },
+            MopValue::List(list) => serializer.collect_seq(list),
+            MopValue::Pair(a, b) => serializer.collect_seq([a, b]),
+        }
+    }
+}
+
+/// A vector of the items of an iterator, serialized without collecting them.
+struct SeqOf<F>(F);
+
+impl<F, I> Serialize for SeqOf<F>
+where
+    F: Fn() -> I,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq((self.0)())
+    }
+}
+
+/// Serialize an [`Op`] in the shape of [`op_to_json`], with the functions of
+/// the micro-ops as [`Keyword`]s, but without building the JSON, so it
+/// doesn't allocate.
+pub(crate) fn serialize_op<S: Serializer>(op: &Op, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::Error;
+
+    match op {
+        Op::Txn(ops) => {
+            let mut seq = serializer.serialize_seq(Some(ops.len()))?;
+            for op in ops {
+                let mop = Mop::of(op).ok_or_else(|| match op {
+                    Op::Txn(_) => S::Error::custom("txns cannot be nested"),
+                    _ => S::Error::custom("a multi-key read is not a micro-op"),
+                })?;
+                seq.serialize_element(&mop)?;
+            }
+            seq.end()
+        }
+        Op::BatchRead(reads) => {
+            let mut seq = serializer.serialize_seq(Some(3))?;
+            seq.serialize_element(&Keyword("batch-read"))?;
+            seq.serialize_element(&SeqOf(|| reads.iter().map(|(k, _)| k)))?;
+            seq.serialize_element(&SeqOf(|| {
+                reads
+                    .iter()
+                    .map(|(_, v)| v.map_or(MopValue::Nil, MopValue::Int))
+            }))?;
+            seq.end()
+        }
+        Op::ScanRange(start, end, results) => {
+            let mut seq = serializer.serialize_seq(Some(3))?;
+            seq.serialize_element(&Keyword("scan"))?;
+            seq.serialize_element(&MopValue::Pair(*start, *end))?;
+            match results {
+                None => seq.serialize_element(&MopValue::Nil)?,
+                Some(results) => seq.serialize_element(&SeqOf(|| {
+                    results.iter().map(|(k, v)| MopValue::Pair(*k, *v))
+                }))?,
+            }
+            seq.end()
+        }
+        op => Mop::of(op)
+            .expect("a single-key op is a micro-op")
+            .serialize(serializer),
+    }
+}
+
+impl<'de> Deserialize<'de> for MicroOp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = Value::deserialize(deserializer)?;
+        Form::from_json(&json)
+            .and_then(|form| MicroOp::from_form(&form))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Form {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Form::Vector(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Form::Keyword(k) => Keyword(k).serialize(serializer),
+            Form::Int(v) => serializer.serialize_u64(*v),
+            Form::Neg(v) => serializer.serialize_i64(*v),
+            Form::Nil => serializer.serialize_unit(),
+        }
+    }
+}
+
+fn op_to_form(op: &Op) -> Result<Form> {
+    let int = |v: &u64| Form::Int(*v);
+    let opt = |v: &Option<u64>| v.as_ref().map_or(Form::Nil, int);
+    let keyword = |f: &str| Form::Keyword(f.to_string());
+    match op {
+        Op::Txn(ops) => Ok(Form::Vector(
+            ops.iter()
+                .map(|op| match op {
+                    Op::Txn(_) => bail!("txns cannot be nested"),
+                    op => Ok(MicroOp::try_from(op)?.to_form()),
+                })
+                .collect::<Result<_>>()?,
+        )),
+        Op::BatchRead(reads) => Ok(Form::Vector(vec![
+            keyword("batch-read"),
+            Form::Vector(reads.iter().map(|(k, _)| int(k)).collect()),
+            Form::Vector(reads.iter().map(|(_, v)| opt(v)).collect()),
+        ])),
+        Op::ScanRange(start, end, results) => Ok(Form::Vector(vec![
+            keyword("scan"),
+            Form::Vector(vec![int(start), int(end)]),
+            match results {
+                None => Form::Nil,
+                Some(results) => Form::Vector(
+                    results
+                        .iter()
+                        .map(|(k, v)| Form::Vector(vec![int(k), int(v)]))
+                        .collect(),
+                ),
+            },
+        ])),
+        op => Ok(MicroOp::try_from(op)?.to_form()),
+    }
+}
+
+/// Parse a multi-key read, returns `None` if the form is not one.
+fn multi_read_from_form(form: &Form) -> Result<Option<Op>> {
+    let Form::Vector(items) = form else {
+        return Ok(None);
+    };
+    let [Form::Keyword(f), keys, values] = items.as_slice() else {
+        return Ok(None);
+    };
+    let ints = |form: &Form| -> Result<Vec<Form>> {
+        match form {
+            Form::Vector(items) => Ok(items.clone()),
+            _ => bail!("expected a vector in `{}`, got {:?}", f, form),
+        }
+    };
+    let int = |form: &Form| match form {
+        Form::Int(v) => Ok(*v),
+        _ => Err(anyhow!("expected an integer in `{}`, got {:?}", f, form)),
+    };
+    Ok(Some(match f.as_str() {
+        "batch-read" => {
+            let (keys, values) = (ints(keys)?, ints(values)?);
+            if keys.len() != values.len() {
+                bail!("the keys and values of `batch-read` should be of the same length");
+            }
+            let reads = keys.iter().zip(&values).map(|(k, v)| {
+                let v = match v {
+                    Form::Nil => None,
+                    v => Some(int(v)?),
+                };
+                Ok((int(k)?, v))
+            });
+            Op::BatchRead(reads.collect::<Result<_>>()?)
+        }
+        "scan" => {
+            let range = ints(keys)?;
+            let [start, end] = range.as_slice() else {
+                bail!("the range of `scan` should be `[start end]`");
+            };
+            let results = match values {
+                Form::Nil => None,
+                values => Some(
+                    ints(values)?
+                        .iter()
+                        .map(|pair| match ints(pair)?.as_slice() {
+                            [k, v] => Ok((int(k)?, int(v)?)),
+                            _ => bail!("the results of `scan` should be `[key value]`s"),
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+            };
+            Op::ScanRange(int(start)?, int(end)?, results)
+        }
+        _ => return Ok(None),
+    }))
+}
+
+fn op_from_form(form: &Form) -> Result<Op> {
+    match form {
+        // a txn is a vector of micro-ops, including the empty txn
+        Form::Vector(items) if items.iter().all(|x| matches!(x, Form::Vector(_))) => Ok(Op::Txn(
+            items
+                .iter()
+                .map(|x| Ok(MicroOp::from_form(x)?.into()))
+                .collect::<Result<_>>()?,
+        )),
+        form => match multi_read_from_form(form)? {
+            Some(op) => Ok(op),
+            None => Ok(MicroOp::from_form(form)?.into()),
+        },
+    }
+}
+
+impl Form {
+    fn signed(v: i64) -> Self {
+        match u64::try_from(v) {
+            Ok(v) => Form::Int(v),
+            Err(_) => Form::Neg(v),
+        }
+    }
+
+    fn to_edn(&self, out: &mut String) {
+        match self {
+            Form::Vector(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    item.to_edn(out);
+                }
+                out.push(']');
+            }
+            Form::Keyword(k) => {
+                out.push(':');
+                out.push_str(k);
+            }
+            Form::Int(v) => out.push_str(&v.to_string()),
+            Form::Neg(v) => out.push_str(&v.to_string()),
+            Form::Nil => out.push_str("nil"),
+        }
+    }
+
+    fn from_edn(s: &str) -> Result<Self> {
+        let s = s.replace('[', " [ ").replace(']', " ] ").replace(',', " ");
+        let tokens: Vec<_> = s.split_whitespace().collect();
+        let mut pos = 0;
+        let form = Self::parse_edn(&tokens, &mut pos)?;
+        if let Some(token) = tokens.get(pos) {
+            bail!("unexpected trailing token `{}`", token);
+        }
+        Ok(form)
+    }
+
+    fn parse_edn(tokens: &[&str], pos: &mut usize) -> Result<Self> {
+        let token = *tokens
+            .get(*pos)
+            .ok_or_else(|| anyhow!("unexpected end of EDN"))?;
+        *pos += 1;
+        Ok(match token {
+            "[" => {
+                let mut items = vec![];
+                loop {
+                    match tokens.get(*pos) {
+                        Some(&"]") => {
+                            *pos += 1;
+                            break;
+                        }
+                        Some(_) => items.push(Self::parse_edn(tokens, pos)?),
+                        None => bail!("unclosed vector"),
+                    }
+                }
+                Form::Vector(items)
+            }
+            "nil" => Form::Nil,
+            token => match token.strip_prefix(':') {
+                Some(k) => Form::Keyword(k.to_string()),
+                None => match (token.parse(), token.parse::<i64>()) {
+                    (Ok(v), _) => Form::Int(v),
+                    (_, Ok(v)) => Form::Neg(v),
+                    _ => bail!("unexpected EDN token `{}`", token),
+                },
+            },
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Form::Vector(items) => Value::Array(items.iter().map(Form::to_json).collect()),
+            Form::Keyword(k) => Value::String(k.clone()),
+            Form::Int(v) => Value::from(*v),
+            Form::Neg(v) => Value::from(*v),
+            Form::Nil => Value::Null,
+        }
+    }
+
+    fn from_json(json: &Value) -> Result<Self> {
+        Ok(match json {
+            Value::Array(items) => {
+                Form::Vector(items.iter().map(Form::from_json).collect::<Result<_>>()?)
+            }
+            Value::String(k) => Form::Keyword(k.clone()),
+            Value::Number(v) => match (v.as_u64(), v.as_i64()) {
+                (Some(v), _) => Form::Int(v),
+                (_, Some(v)) => Form::Neg(v),
+                _ => bail!("expected an integer, got {}", v),
+            },
+            Value::Null => Form::Nil,
+            json => bail!("unexpected JSON value {}", json),
+        })
+    }
+}
+
+/// Convert an [`Op`] to EDN, e.g. `[[:w 1 2] [:r 1 nil]]`.
+pub fn op_to_edn(op: &Op) -> Result<String> {
+    let mut out = String::new();
+    op_to_form(op)?.to_edn(&mut out);
+    Ok(out)
+}
+
+/// Parse an [`Op`] from EDN.
+pub fn op_from_edn(s: &str) -> Result<Op> {
+    op_from_form(&Form::from_edn(s)?)
+}
+
+/// Convert an [`Op`] to JSON, e.g. `[["w",1,2],["r",1,null]]`.
+pub fn op_to_json(op: &Op) -> Result<Value> {
+    Ok(op_to_form(op)?.to_json())
+}
+
+/// Parse an [`Op`] from JSON.
+pub fn op_from_json(json: &Value) -> Result<Op> {
+    op_from_form(&Form::from_json(json)?)
+}
+
+/// Convert a txn of micro-ops to EDN.
+pub fn mops_to_edn(mops: &[MicroOp]) -> String {
+    let mut out = String::new();
+    Form::Vector(mops.iter().map(MicroOp::to_form).collect()).to_edn(&mut out);
+    out
+}
+
+/// Parse a txn of micro-ops from EDN.
+pub fn mops_from_edn(s: &str) -> Result<Vec<MicroOp>> {
+    match Form::from_edn(s)? {
+        Form::Vector(items) => items.iter().map(MicroOp::from_form).collect(),
+        form => bail!("a txn should be a vector, got {:?}", form),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_edn_round_trip() -> Result<()> {
+        let cases = [
+            ("[:r 8 nil]", Op::Read(8, None)),
+            ("[:r 8 1]", Op::Read(8, Some(1))),
+            ("[:w 6 1]", Op::Write(6, 1)),
+            ("[:cas 6 [1 2]]", Op::Cas(6, 1, 2)),
+            ("[:delete 6 nil]", Op::Delete(6)),
+            ("[:incr 6 2]", Op::Incr(6, 2)),
+            ("[:incr 6 -1]", Op::Incr(6, -1)),
+            ("[:append 6 1]", Op::Append(6, 1)),
+            ("[:r 6 [1 2]]", Op::ReadList(6, Some(vec![1, 2]))),
+            ("[:r 6 []]", Op::ReadList(6, Some(vec![]))),
+            (
+                "[:batch-read [1 2] [nil 3]]",
+                Op::BatchRead(vec![(1, None), (2, Some(3))]),
+            ),
+            ("[:scan [1 4] nil]", Op::ScanRange(1, 4, None)),
+            (
+                "[:scan [1 4] [[1 2] [3 4]]]",
+                Op::ScanRange(1, 4, Some(vec![(1, 2), (3, 4)])),
+            ),
+            ("[]", Op::Txn(vec![])),
+            (
+                "[[:w 6 1] [:r 8 nil]]",
+                Op::Txn(vec![Op::Write(6, 1), Op::Read(8, None)]),
+            ),
+        ];
+        for (edn, op) in cases {
+            assert_eq!(op_to_edn(&op)?, edn);
+            assert_eq!(op_from_edn(edn)?, op);
+            assert_eq!(op_from_json(&op_to_json(&op)?)?, op);
+            // serialized in place the same as by the forms
+            assert_eq!(serde_json::to_value(&op)?, op_to_json(&op)?);
+            assert_eq!(crate::convert::edn::to_edn(&op)?, edn);
+        }
+        assert_eq!(
+            op_from_edn("[[:w, 6, 1]\n [:r 8 nil]]")?,
+            Op::Txn(vec![Op::Write(6, 1), Op::Read(8, None)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_ops() {
+        let nested = Op::Txn(vec![Op::Txn(vec![Op::Write(1, 1)])]);
+        assert!(op_to_edn(&nested).is_err());
+        assert!(serde_json::to_string(&nested).is_err());
+        assert!(serde_json::to_string(&Op::Txn(vec![Op::ScanRange(1, 2, None)])).is_err());
+        for edn in [
+            "[[[:w 1 1]]]",
+            "[:x 1 1]",
+            "[:w 1 nil]",
+            "[:w -1 1]",
+            "[:r 1 [1 nil]]",
+            "[:append 1 nil]",
+            "[:cas 1 1]",
+            "[:cas 1 [1]]",
+            "[:delete 1 1]",
+            "[:incr 1 nil]",
+            "[:incr -1 1]",
+            "[:batch-read [1 2] [nil]]",
+            "[:scan [1] nil]",
+            "[:scan [1 2] [[1]]]",
+            "[[:scan [1 2] nil]]",
+            "[:r 1 nil",
+            "[:r 1 nil] 1",
+        ] {
+            assert!(op_from_edn(edn).is_err(), "{} should be invalid", edn);
+        }
+    }
+
+    #[test]
+    fn test_list_append_mops() -> Result<()> {
+        let edn = "[[:append 1 3] [:r 1 [1 2 3]] [:r 2 nil]]";
+        let mops = vec![
+            MicroOp::Append(1, 3),
+            MicroOp::Read(1, Some(ReadValue::List(vec![1, 2, 3]))),
+            MicroOp::Read(2, None),
+        ];
+        assert_eq!(mops_from_edn(edn)?, mops);
+        assert_eq!(mops_to_edn(&mops), edn);
+        let json = serde_json::to_string(&mops)?;
+        assert_eq!(json, r#"[["append",1,3],["r",1,[1,2,3]],["r",2,null]]"#);
+        assert_eq!(serde_json::from_str::<Vec<MicroOp>>(&json)?, mops);
+        assert_eq!(crate::convert::edn::to_edn(&mops)?, edn);
+        // the same txn as an op, with the read not completed as a register read
+        let op = op_from_edn(edn)?;
+        assert_eq!(
+            op,
+            Op::Txn(vec![
+                Op::Append(1, 3),
+                Op::ReadList(1, Some(vec![1, 2, 3])),
+                Op::Read(2, None)
+            ])
+        );
+        assert_eq!(op_to_edn(&op)?, edn);
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+//! This module provides explicit conversions between the rust types and the
+//! shapes expected by the jepsen / elle side.
+
+pub mod edn;
+pub mod elle;
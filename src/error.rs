@@ -0,0 +1,122 @@
+//! The error of the public APIs of the crate, so the applications embedding
+//! it can tell the failures apart and react to them instead of crashing:
+//!
+//! ```ignore
+//! match TestBuilder::new(cluster, workload).build().run() {
+//!     Ok(report) => println!("{}", report.is_valid()),
+//!     Err(Error::Client(err)) => eprintln!("the cluster is broken: {}", err),
+//!     Err(err) => return Err(err.into()),
+//! }
+//! ```
+//!
+//! The internals still use [`anyhow`], whose errors convert into
+//! [`Error::Other`] unless they wrap an [`Error`].
+
+use std::{io, sync::PoisonError};
+
+use crate::preflight::PreflightReport;
+#[cfg(feature = "clojure")]
+use crate::utils::FfiError;
+
+/// The result of the public APIs of the crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error of the crate, by the part failing.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The JVM fails to start or to attach the thread.
+    #[cfg(feature = "clojure")]
+    #[error("failed to start the JVM: {0}")]
+    Jvm(#[from] j4rs::errors::J4RsError),
+    /// A clojure invocation fails, see [`FfiError::kind`].
+    #[cfg(feature = "clojure")]
+    #[error("clojure invocation failed: {0}")]
+    Ffi(#[from] FfiError),
+    /// The cluster client fails out of the ops, e.g. to set up the cluster.
+    #[error("cluster client failed: {0}")]
+    Client(String),
+    /// A generator fails to be built or to generate.
+    #[error("generator failed: {0}")]
+    Generator(String),
+    /// A nemesis fails to be executed or recovered.
+    #[error("nemesis failed: {0}")]
+    Nemesis(String),
+    /// The history fails to be recorded, read or converted.
+    #[error("history failed: {0:#}")]
+    History(#[source] anyhow::Error),
+    /// The history fails to be checked, which is not an invalid result.
+    #[error("check failed: {0:#}")]
+    Checker(#[source] anyhow::Error),
+    /// The environment fails the checks before running, see
+    /// [`crate::preflight`].
+    #[error("preflight failed:{}", preflight_failures(.0))]
+    Preflight(PreflightReport),
+    /// A lock is poisoned by a panic of its holder.
+    #[error("the lock of the {0} is poisoned")]
+    Poisoned(&'static str),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("serialization failed: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("{0:#}")]
+    Other(#[source] anyhow::Error),
+}
+
+impl Error {
+    /// The error of the poisoned lock of the name.
+    pub(crate) fn poisoned<T>(name: &'static str) -> impl FnOnce(PoisonError<T>) -> Self {
+        move |_| Self::Poisoned(name)
+    }
+}
+
+/// The failed checks of a preflight report, as ` name: detail;` each.
+fn preflight_failures(report: &PreflightReport) -> String {
+    report
+        .failures()
+        .map(|check| format!(" {}: {};", check.name, check.detail))
+        .collect()
+}
+
+/// Recover the typed error if the anyhow error wraps one, e.g. after it's
+/// passed through the internals.
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        #[cfg(feature = "clojure")]
+        let err = match err.downcast::<FfiError>() {
+            Ok(err) => return Self::Ffi(err),
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            Ok(err) => Self::Io(err),
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_anyhow() {
+        let err = anyhow::Error::from(Error::Client("down".to_string()));
+        assert!(matches!(Error::from(err), Error::Client(err) if err == "down"));
+        let err = anyhow::Error::from(io::Error::other("disk full"));
+        assert!(matches!(Error::from(err), Error::Io(_)));
+        let err = Error::from(anyhow::anyhow!("inner").context("outer"));
+        assert_eq!(err.to_string(), "outer: inner");
+
+        let lock = std::sync::Mutex::new(());
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = lock.lock().unwrap();
+            panic!("poison");
+        });
+        let err = lock.lock().map_err(Error::poisoned("history")).unwrap_err();
+        assert_eq!(err.to_string(), "the lock of the history is poisoned");
+    }
+}
@@ -0,0 +1,308 @@
+//! A dedicated thread owning the JVM.
+//!
+//! The JVM is attached per thread, and attaching it from the threads of a
+//! madsim runtime is fragile. The [`JvmExecutor`] owns one OS thread attached
+//! to the JVM, and runs every clojure invocation of this crate, e.g. the
+//! generation of [`ElleRwGenerator`] and the historify and check of
+//! [`ElleRwChecker`], on it, so the generator tasks never touch the JVM:
+//!
+//! ```ignore
+//! let ns = JvmExecutor::global().call(|_| CLOJURE.require("elle.rw-register")).await?;
+//! ```
+//!
+//! The values the jobs keep for later ones, e.g. the clojure instances, are
+//! kept on the JVM thread as [`Remote`]s, which are `Send` and `Sync` and are
+//! only usable through the jobs:
+//!
+//! ```ignore
+//! let handle = JvmHandle::global();
+//! let gen: RemoteInstance = handle.try_remote(|_| nsinvoke!(ns, "gen"))?;
+//! let first = gen.with(|_, gen| clj_to_string(cljinvoke!("first", gen.clone())?))?;
+//! ```
+//!
+//! [`ElleRwGenerator`]: crate::generator::elle_rw::ElleRwGenerator
+//! [`ElleRwChecker`]: crate::checker::elle_rw::ElleRwChecker
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::HashMap,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, OnceLock,
+    },
+    thread,
+};
+
+use j4rs::{Instance, Jvm};
+use log::{error, info};
+
+use crate::{init_jvm_with, jvm_config, with_jvm, Error, JvmConfig};
+
+/// The name of the JVM thread.
+const THREAD_NAME: &str = "jepsen-jvm";
+
+/// A job run on the JVM thread.
+type Job = Box<dyn FnOnce(&Jvm) + Send>;
+
+/// The executor of the process, started by the first [`JvmExecutor::global`],
+/// or the error it fails to start with.
+static EXECUTOR: OnceLock<j4rs::errors::Result<JvmExecutor>> = OnceLock::new();
+
+/// The id of the next [`Remote`].
+static NEXT_REMOTE: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The values of the [`Remote`]s, on the JVM thread.
+    static REMOTES: RefCell<HashMap<u64, Box<dyn Any>>> = RefCell::default();
+}
+
+/// The handle of the JVM thread, see the [module](self) doc.
+#[derive(Debug)]
+pub struct JvmExecutor {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl JvmExecutor {
+    /// Start the JVM thread with the config, and wait for the JVM to be
+    /// attached to it.
+    ///
+    /// In a madsim runtime, system threads must be allowed by
+    /// `Runtime::set_allow_system_thread` to start it.
+    pub fn start(config: JvmConfig) -> j4rs::errors::Result<Self> {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let (ready, init) = mpsc::channel();
+        thread::Builder::new()
+            .name(THREAD_NAME.to_string())
+            .spawn(move || {
+                let res = init_jvm_with(config);
+                let ok = res.is_ok();
+                let _ = ready.send(res);
+                if !ok {
+                    return;
+                }
+                info!("the JVM thread started");
+                for job in rx {
+                    // a panicking job fails its call only
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| with_jvm(job)));
+                }
+            })
+            .map_err(|err| j4rs::errors::J4RsError::GeneralError(err.to_string()))?;
+        init.recv().map_err(|_| {
+            j4rs::errors::J4RsError::GeneralError("the JVM thread exited".to_string())
+        })??;
+        Ok(Self { jobs })
+    }
+
+    /// The executor of the process, started with the config set by
+    /// [`init_jvm_with`] or the default one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the JVM fails to start, see [`JvmExecutor::try_global`].
+    pub fn global() -> &'static Self {
+        Self::try_global().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// The executor of the process like [`JvmExecutor::global`], or the error
+    /// the JVM fails to start with, which is returned by every later call.
+    pub fn try_global() -> crate::Result<&'static Self> {
+        EXECUTOR
+            .get_or_init(|| Self::start(jvm_config()))
+            .as_ref()
+            .map_err(|err| Error::Jvm(err.clone()))
+    }
+
+    /// Run the job on the JVM thread and wait for its result, blocking the
+    /// current thread. A job calling it again runs the inner job in place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the job panics.
+    pub fn call_blocking<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Jvm) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        if thread::current().name() == Some(THREAD_NAME) {
+            return with_jvm(f);
+        }
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let (tx, rx) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |jvm| {
+            let _ = tx.send(f(jvm));
+        });
+        if self.jobs.send(job).is_err() {
+            error!("the JVM thread exited");
+        }
+        let res = rx.recv().expect("the JVM job panicked");
+        #[cfg(feature = "metrics")]
+        crate::metrics::Metrics::global().record_ffi(start.elapsed());
+        res
+    }
+
+    /// Run the job on the JVM thread and wait for its result.
+    ///
+    /// The result is received without yielding to the runtime, so the
+    /// simulated time does not advance while the JVM works, and the runs of a
+    /// seed stay deterministic.
+    pub async fn call<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Jvm) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.call_blocking(f)
+    }
+
+    /// Run the job on the JVM thread without waiting.
+    fn spawn(&self, f: impl FnOnce(&Jvm) + Send + 'static) {
+        if thread::current().name() == Some(THREAD_NAME) {
+            return with_jvm(f);
+        }
+        let _ = self.jobs.send(Box::new(f));
+    }
+}
+
+/// A copyable handle of the [`JvmExecutor`] of the process, creating the
+/// [`Remote`]s.
+#[derive(Debug, Clone, Copy)]
+pub struct JvmHandle {
+    executor: &'static JvmExecutor,
+}
+
+impl JvmHandle {
+    /// The handle of [`JvmExecutor::global`].
+    pub fn global() -> Self {
+        Self {
+            executor: JvmExecutor::global(),
+        }
+    }
+
+    /// See [`JvmExecutor::call_blocking`].
+    pub fn call_blocking<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Jvm) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.executor.call_blocking(f)
+    }
+
+    /// See [`JvmExecutor::call`].
+    pub async fn call<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Jvm) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        self.executor.call(f).await
+    }
+
+    /// Create a value on the JVM thread and keep it there.
+    pub fn remote<T: 'static>(&self, f: impl FnOnce(&Jvm) -> T + Send + 'static) -> Remote<T> {
+        match self.try_remote(move |jvm| Ok::<_, ()>(f(jvm))) {
+            Ok(remote) => remote,
+            Err(()) => unreachable!(),
+        }
+    }
+
+    /// Like [`JvmHandle::remote`], but fails if the value cannot be created.
+    pub fn try_remote<T: 'static, E: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Jvm) -> Result<T, E> + Send + 'static,
+    ) -> Result<Remote<T>, E> {
+        let id = NEXT_REMOTE.fetch_add(1, Ordering::Relaxed);
+        self.call_blocking(move |jvm| {
+            let value = f(jvm)?;
+            REMOTES.with(|remotes| remotes.borrow_mut().insert(id, Box::new(value)));
+            Ok(())
+        })?;
+        Ok(Remote {
+            id,
+            handle: *self,
+            _value: PhantomData,
+        })
+    }
+}
+
+/// A value kept on the JVM thread, e.g. a clojure instance, used by the jobs
+/// of [`Remote::with`]. It's dropped on the JVM thread as well.
+#[derive(Debug)]
+pub struct Remote<T: 'static> {
+    id: u64,
+    handle: JvmHandle,
+    /// `Send` and `Sync` whatever the value is, as it never leaves the thread.
+    _value: PhantomData<fn() -> T>,
+}
+
+/// A clojure instance kept on the JVM thread.
+pub type RemoteInstance = Remote<Instance>;
+
+impl<T: 'static> Remote<T> {
+    /// Run the job with the value on the JVM thread and wait for its result.
+    pub fn with<R: Send + 'static>(&self, f: impl FnOnce(&Jvm, &mut T) -> R + Send + 'static) -> R {
+        let id = self.id;
+        self.handle.call_blocking(move |jvm| {
+            // taken out during the job, which may use other remotes
+            let mut value = REMOTES
+                .with(|remotes| remotes.borrow_mut().remove(&id))
+                .expect("the remote value is kept until dropped");
+            let res = f(
+                jvm,
+                value
+                    .downcast_mut()
+                    .expect("the remote value is of the type"),
+            );
+            REMOTES.with(|remotes| remotes.borrow_mut().insert(id, value));
+            res
+        })
+    }
+}
+
+impl<T: 'static> Drop for Remote<T> {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.handle.executor.spawn(move |_| {
+            // dropped out of the borrow, as it may drop other remotes
+            let value = REMOTES.with(|remotes| remotes.borrow_mut().remove(&id));
+            drop(value);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cljinvoke, utils::clj_to_string};
+
+    #[test]
+    fn test_jvm_executor() -> anyhow::Result<()> {
+        let executor = JvmExecutor::global();
+        let thread = executor.call_blocking(|_| thread::current().name().map(str::to_string));
+        assert_eq!(thread.as_deref(), Some(THREAD_NAME));
+        // an instance returned by a job is passed to a later one
+        let sum = executor.call_blocking(|_| cljinvoke!("+", 1, 2))?;
+        let sum = executor.call_blocking(move |_| clj_to_string(sum))?;
+        assert_eq!(sum, "3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote() {
+        let handle = JvmHandle::global();
+        let remote = handle.remote(|_| vec![1]);
+        remote.with(|_, v| v.push(2));
+        assert_eq!(remote.with(|_, v| v.clone()), [1, 2]);
+        // a remote in a remote, used by a nested job
+        let outer = handle.remote(move |_| remote);
+        assert_eq!(outer.with(|_, inner| inner.with(|_, v| v.len())), 2);
+        let failed = handle.try_remote(|_| Err::<(), _>("failed"));
+        assert_eq!(failed.err(), Some("failed"));
+        drop(outer);
+        assert_eq!(
+            handle.call_blocking(|_| REMOTES.with(|r| r.borrow().len())),
+            0
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Export of histories to tabular formats, CSV and Parquet by the `parquet`
+//! feature, to analyze them in e.g. pandas or DuckDB.
+//!
+//! Every history item is a row with the columns:
+//!
+//! | column         | content                                                   |
+//! |----------------|-----------------------------------------------------------|
+//! | `index`        | the index of the item                                     |
+//! | `process`      | the process number, or `nemesis`                          |
+//! | `type`         | `invoke`, `ok`, `fail` or `info`                          |
+//! | `f`            | the op or nemesis function                                |
+//! | `keys`         | the keys separated by spaces, see [`HistoryValue::keys`]  |
+//! | `value`        | the value in JSON                                         |
+//! | `time`         | the time of the item, in nanoseconds                      |
+//! | `invoke_index` | the index of the invoke of a completion                   |
+//! | `latency`      | the time from the invoke of a completion, in nanoseconds  |
+//! | `node`         | the node the op was sent to, from [`OpMeta`]              |
+//! | `retries`      | the retries of the op, from [`OpMeta`]                    |
+//! | `nemesis`      | whether the item is of the nemesis                        |
+//! | `error`        | the error in JSON                                         |
+//!
+//! The columns without a value are empty, or null in Parquet, where the
+//! integers are `UInt64` and `nemesis` is a boolean.
+//!
+//! [`HistoryValue::keys`]: crate::history::HistoryValue::keys
+//! [`OpMeta`]: crate::history::OpMeta
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    history::{HistoryProcess, SerializableHistory, SerializableHistoryList},
+    op::OpOrNemesisFuncType,
+    utils::serialized_name,
+};
+
+/// The format of an exported history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExportFormat {
+    /// Comma-separated values with a header row, quoted as RFC 4180.
+    Csv,
+    /// Parquet with typed columns, by the `parquet` feature.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+const COLUMNS: [&str; 13] = [
+    "index",
+    "process",
+    "type",
+    "f",
+    "keys",
+    "value",
+    "time",
+    "invoke_index",
+    "latency",
+    "node",
+    "retries",
+    "nemesis",
+    "error",
+];
+
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The columns of an item.
+struct Row {
+    index: u64,
+    process: String,
+    type_: String,
+    f: String,
+    keys: String,
+    value: String,
+    time: u64,
+    invoke_index: Option<u64>,
+    latency: Option<u64>,
+    node: Option<u64>,
+    retries: Option<u64>,
+    nemesis: bool,
+    error: Option<String>,
+}
+
+impl Row {
+    /// The row of an item, given the index and time of the invoke of a
+    /// completion.
+    fn new<ERR: Serialize>(
+        item: &SerializableHistory<OpOrNemesisFuncType, ERR>,
+        invoke: Option<(u64, u64)>,
+    ) -> Result<Self> {
+        let keys = item
+            .value
+            .keys()
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let error = match &item.error {
+            Some(error) => Some(serde_json::to_string(error)?),
+            None => None,
+        };
+        Ok(Self {
+            index: item.index,
+            process: serialized_name(item.process),
+            type_: serialized_name(&item.type_),
+            f: serialized_name(item.f),
+            keys,
+            value: serde_json::to_string(&item.value)?,
+            time: item.time,
+            invoke_index: invoke.map(|(index, _)| index),
+            latency: invoke.map(|(_, time)| item.time.saturating_sub(time)),
+            node: item.meta.map(|meta| meta.node),
+            retries: item.meta.map(|meta| meta.retries as u64),
+            nemesis: item.process == HistoryProcess::Nemesis,
+            error,
+        })
+    }
+
+    fn csv_fields(&self) -> [String; COLUMNS.len()] {
+        let opt = |v: Option<u64>| v.map_or_else(String::new, |v| v.to_string());
+        [
+            self.index.to_string(),
+            csv_field(&self.process),
+            csv_field(&self.type_),
+            csv_field(&self.f),
+            csv_field(&self.keys),
+            csv_field(&self.value),
+            self.time.to_string(),
+            opt(self.invoke_index),
+            opt(self.latency),
+            opt(self.node),
+            opt(self.retries),
+            self.nemesis.to_string(),
+            self.error.as_deref().map_or_else(String::new, csv_field),
+        ]
+    }
+}
+
+/// Write the rows as a Parquet file of one row group, with the integers as
+/// `UInt64`, the strings as `Utf8` and the empty columns as nulls. Every
+/// column is nullable, so the schema is the same for every history.
+#[cfg(feature = "parquet")]
+fn write_parquet(rows: &[Row], out: impl Write + Send) -> Result<()> {
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, BooleanArray, RecordBatch, StringArray, UInt64Array};
+    use arrow_schema::{Field, Schema};
+    use parquet::arrow::ArrowWriter;
+
+    let u64s = |f: fn(&Row) -> Option<u64>| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<UInt64Array>())
+    };
+    let strings = |f: fn(&Row) -> Option<&str>| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<StringArray>())
+    };
+    let columns = vec![
+        u64s(|r| Some(r.index)),
+        strings(|r| Some(&r.process)),
+        strings(|r| Some(&r.type_)),
+        strings(|r| Some(&r.f)),
+        strings(|r| Some(&r.keys)),
+        strings(|r| Some(&r.value)),
+        u64s(|r| Some(r.time)),
+        u64s(|r| r.invoke_index),
+        u64s(|r| r.latency),
+        u64s(|r| r.node),
+        u64s(|r| r.retries),
+        Arc::new(
+            rows.iter()
+                .map(|r| Some(r.nemesis))
+                .collect::<BooleanArray>(),
+        ),
+        strings(|r| r.error.as_deref()),
+    ];
+    let fields: Vec<_> = COLUMNS
+        .iter()
+        .zip(&columns)
+        .map(|(name, column)| Field::new(*name, column.data_type().clone(), true))
+        .collect();
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+    let mut writer = ArrowWriter::try_new(out, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+impl<ERR: Serialize> SerializableHistoryList<OpOrNemesisFuncType, ERR> {
+    /// Export the history to the file in the format, see the
+    /// [module](crate::export) doc for the columns. The completions are paired
+    /// with the invokes by [`SerializableHistoryList::pairs`].
+    pub fn export(&self, format: ExportFormat, path: impl AsRef<Path>) -> Result<()> {
+        let invokes: HashMap<_, _> = self
+            .pairs()
+            .into_iter()
+            .filter_map(|pair| Some((pair.completion?.index, pair.invoke)))
+            .collect();
+        let rows = self.0.iter().map(|item| {
+            let invoke = invokes.get(&item.index).map(|i| (i.index, i.time));
+            Row::new(item, invoke)
+        });
+        let mut out = BufWriter::new(File::create(path)?);
+        match format {
+            ExportFormat::Csv => {
+                writeln!(out, "{}", COLUMNS.join(","))?;
+                for row in rows {
+                    writeln!(out, "{}", row?.csv_fields().join(","))?;
+                }
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                write_parquet(&rows.collect::<Result<Vec<_>>>()?, &mut out)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_csv() -> Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 2, 1], ["r", 1, null]], "time": 3, "process": 0, "error": null },
+          { "index": 1, "type": "info", "f": "partition", "value": { "nemesis": "PartitionHalves", "servers": [0] }, "time": 4, "process": "nemesis", "error": null },
+          { "index": 2, "type": "fail", "f": "txn", "value": [["w", 2, 1], ["r", 1, null]], "time": 9, "process": 0, "error": ["aborted"], "meta": { "node": 1, "retries": 2, "latency": 5 } }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let path =
+            std::env::temp_dir().join(format!("jepsen-rs-export-{}.csv", std::process::id()));
+        history.export(ExportFormat::Csv, &path)?;
+        let csv = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "index,process,type,f,keys,value,time,invoke_index,latency,node,retries,nemesis,error",
+                r#"0,0,invoke,txn,1 2,"[[""w"",2,1],[""r"",1,null]]",3,,,,,false,"#,
+                r#"1,nemesis,info,partition,,"{""nemesis"":""PartitionHalves"",""servers"":[0]}",4,,,,,true,"#,
+                r#"2,0,fail,txn,1 2,"[[""w"",2,1],[""r"",1,null]]",9,0,6,1,2,false,"[""custom"",""aborted""]""#,
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_export_parquet() -> Result<()> {
+        use arrow_array::{Array, BooleanArray, StringArray, UInt64Array};
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, 2]], "time": 3, "process": 0, "error": null },
+          { "index": 1, "type": "ok", "f": "txn", "value": [["w", 1, 2]], "time": 9, "process": 0, "error": null, "meta": { "node": 1, "retries": 0, "latency": 5 } }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let path =
+            std::env::temp_dir().join(format!("jepsen-rs-export-{}.parquet", std::process::id()));
+        history.export(ExportFormat::Parquet, &path)?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?.build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        std::fs::remove_file(&path)?;
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let names: Vec<_> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        assert_eq!(names, COLUMNS);
+        let column = |name| batch.column_by_name(name).unwrap();
+        let latency = column("latency")
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        assert!(latency.is_null(0));
+        assert_eq!(latency.value(1), 6);
+        let f = column("f").as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(f.value(1), "txn");
+        let nemesis = column("nemesis")
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert!(!nemesis.value(0));
+        assert!(column("error").is_null(1));
+        Ok(())
+    }
+}
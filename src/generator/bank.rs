@@ -0,0 +1,71 @@
+//! A generator of the bank workload, which transfers amounts between accounts
+//! and reads all of them at once, so a lost or partial transfer shows as a
+//! read whose balances do not sum up to the initial total.
+
+use madsim::rand::{self, Rng};
+
+use super::RawGenerator;
+use crate::op::Op;
+
+/// An infinite generator of transfers and reads of all accounts. The accounts
+/// are the keys in `[0, accounts)`, which start at 0, so their balances
+/// always sum up to 0 under a serializable database.
+pub struct BankGenerator {
+    accounts: u64,
+    max_amount: i64,
+}
+
+impl BankGenerator {
+    /// Transfers between the accounts in `[0, accounts)`, with amounts in
+    /// `[1, 5]`.
+    pub fn new(accounts: u64) -> Self {
+        assert!(accounts > 1, "there must be at least two accounts");
+        Self {
+            accounts,
+            max_amount: 5,
+        }
+    }
+
+    /// Draw the amounts of the transfers from `[1, max_amount]`.
+    pub fn with_max_amount(mut self, max_amount: i64) -> Self {
+        assert!(max_amount > 0, "the max amount must be positive");
+        self.max_amount = max_amount;
+        self
+    }
+}
+
+impl RawGenerator for BankGenerator {
+    type Item = Op;
+    fn gen(&mut self) -> Self::Item {
+        let mut rng = rand::thread_rng();
+        // reads : transfers = 1 : 1
+        if rng.gen_bool(0.5) {
+            return Op::BatchRead((0..self.accounts).map(|k| (k, None)).collect());
+        }
+        let from = rng.gen_range(0..self.accounts);
+        // any other account
+        let to = (from + rng.gen_range(1..self.accounts)) % self.accounts;
+        let amount = rng.gen_range(1..=self.max_amount);
+        Op::Txn(vec![Op::Incr(from, -amount), Op::Incr(to, amount)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[madsim::test]
+    async fn test_bank_gen() {
+        let ops = BankGenerator::new(3).with_max_amount(2).gen_n(1000);
+        assert!(ops.iter().all(|op| match op {
+            Op::BatchRead(reads) => reads.iter().map(|(k, _)| *k).eq(0..3),
+            Op::Txn(ops) => match ops.as_slice() {
+                [Op::Incr(from, a), Op::Incr(to, b)] => {
+                    from != to && *to < 3 && *a == -*b && (1..=2).contains(b)
+                }
+                _ => false,
+            },
+            _ => false,
+        }));
+    }
+}
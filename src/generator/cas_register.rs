@@ -0,0 +1,77 @@
+//! A generator of compare-and-set registers, which yields single reads,
+//! writes, cas and deletes instead of txns, as the linearizability of a
+//! register is checked per key.
+
+use madsim::rand::{self, Rng};
+
+use super::RawGenerator;
+use crate::op::Op;
+
+/// An infinite generator of random ops on a few registers. The values are
+/// drawn from a small range, so that cas hits the current value often.
+pub struct CasRegisterGenerator {
+    keys: u64,
+    values: u64,
+    deletes: bool,
+}
+
+impl CasRegisterGenerator {
+    /// Ops on the keys in `[0, keys)`, with values in `[0, 5)`.
+    pub fn new(keys: u64) -> Self {
+        assert!(keys > 0, "there must be at least one key");
+        Self {
+            keys,
+            values: 5,
+            deletes: false,
+        }
+    }
+
+    /// Draw the values from `[0, values)`.
+    pub fn with_values(mut self, values: u64) -> Self {
+        assert!(values > 0, "there must be at least one value");
+        self.values = values;
+        self
+    }
+
+    /// Generate deletes too, which the cluster client must support.
+    pub fn with_deletes(mut self, deletes: bool) -> Self {
+        self.deletes = deletes;
+        self
+    }
+}
+
+impl RawGenerator for CasRegisterGenerator {
+    type Item = Op;
+    fn gen(&mut self) -> Self::Item {
+        let mut rng = rand::thread_rng();
+        let key = rng.gen_range(0..self.keys);
+        // reads : writes : cas : deletes = 4 : 3 : 3 : 1
+        let kind = rng.gen_range(0..if self.deletes { 11 } else { 10 });
+        let mut value = || rng.gen_range(0..self.values);
+        match kind {
+            0..=3 => Op::Read(key, None),
+            4..=6 => Op::Write(key, value()),
+            7..=9 => Op::Cas(key, value(), value()),
+            _ => Op::Delete(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[madsim::test]
+    async fn test_cas_register_gen() {
+        let ops = CasRegisterGenerator::new(2).with_values(3).gen_n(1000);
+        assert!(ops.iter().all(|op| match op {
+            Op::Read(k, None) => *k < 2,
+            Op::Write(k, v) => *k < 2 && *v < 3,
+            Op::Cas(k, e, n) => *k < 2 && *e < 3 && *n < 3,
+            _ => false,
+        }));
+        assert!(ops.iter().any(|op| matches!(op, Op::Cas(..))));
+        let ops = CasRegisterGenerator::new(1).with_deletes(true).gen_n(1000);
+        assert!(ops.iter().any(|op| matches!(op, Op::Delete(0))));
+    }
+}
@@ -0,0 +1,216 @@
+//! A generator wrapper that deliberately produces pairs of conflicting txns,
+//! to exercise the conflict-resolution paths (abort / retry, last-writer-wins)
+//! of the database more often than random key selection does.
+
+use std::sync::{Arc, Mutex};
+
+use default_struct_builder::DefaultBuilder;
+use madsim::rand::{self, seq::SliceRandom, Rng};
+use serde::Serialize;
+
+use super::RawGenerator;
+use crate::op::Op;
+
+/// The values written by the injected writes start from here, so that they
+/// never collide with the values written by the inner generator. (elle
+/// requires the written values of a key to be unique)
+const CONFLICT_VALUE_BASE: u64 = 1 << 32;
+
+/// Options of the [`ConflictPairGenerator`].
+#[derive(Debug, Clone, DefaultBuilder)]
+pub struct ConflictOption {
+    /// The probability of a generated txn to start a conflict pair, in
+    /// `[0, 1]`.
+    rate: f64,
+    /// The max number of keys that both txns of a pair write.
+    shared_writes: usize,
+}
+
+impl Default for ConflictOption {
+    fn default() -> Self {
+        Self {
+            rate: 0.1,
+            shared_writes: 1,
+        }
+    }
+}
+
+/// The metadata of a generated conflict pair.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ConflictPair {
+    /// The index of the first txn in the generated sequence, starts from 0.
+    pub first: u64,
+    /// The index of the second txn, which always follows the first one.
+    pub second: u64,
+    /// The keys written by both txns.
+    pub keys: Vec<u64>,
+}
+
+/// The shared log of generated conflict pairs.
+pub type ConflictLog = Arc<Mutex<Vec<ConflictPair>>>;
+
+/// Wraps a txn generator, and turns some of the generated txns into pairs of
+/// adjacent txns with overlapping write sets. Adjacent txns are dispatched
+/// at nearly the same time, so they are likely to run concurrently.
+///
+/// Every generated pair is recorded in the [`ConflictLog`], which can be
+/// fetched by [`ConflictPairGenerator::log`] before the generator is moved
+/// into the client.
+pub struct ConflictPairGenerator<G> {
+    gen: G,
+    option: ConflictOption,
+    /// The second txn of the current pair, yielded by the next `gen`.
+    pending: Option<Op>,
+    /// The number of txns generated.
+    index: u64,
+    /// The next value to be written by the injected writes.
+    next_value: u64,
+    log: ConflictLog,
+}
+
+impl<G: RawGenerator<Item = Op>> ConflictPairGenerator<G> {
+    pub fn new(gen: G, option: ConflictOption) -> Self {
+        Self {
+            gen,
+            option,
+            pending: None,
+            index: 0,
+            next_value: CONFLICT_VALUE_BASE,
+            log: ConflictLog::default(),
+        }
+    }
+
+    /// Get the log of the generated conflict pairs.
+    pub fn log(&self) -> ConflictLog {
+        Arc::clone(&self.log)
+    }
+
+    fn next_txn(&mut self) -> Vec<Op> {
+        match self.gen.gen() {
+            Op::Txn(ops) => ops,
+            op => vec![op],
+        }
+    }
+
+    /// Make the txn `a` and a new txn conflict with each other, returns the
+    /// new txn.
+    fn make_pair(&mut self, a: &mut Vec<Op>) -> Op {
+        let mut b = self.next_txn();
+        let mut keys: Vec<_> = a
+            .iter()
+            .filter_map(|op| match op {
+                Op::Read(k, _)
+                | Op::Write(k, _)
+                | Op::Cas(k, ..)
+                | Op::Delete(k)
+                | Op::Incr(k, _) => Some(*k),
+                Op::Append(..)
+                | Op::ReadList(..)
+                | Op::BatchRead(_)
+                | Op::ScanRange(..)
+                | Op::Txn(_) => None,
+            })
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let keys: Vec<_> = keys
+            .choose_multiple(&mut rand::thread_rng(), self.option.shared_writes)
+            .copied()
+            .collect();
+        for &key in &keys {
+            if !a
+                .iter()
+                .any(|op| matches!(op, Op::Write(k, _) if *k == key))
+            {
+                a.push(Op::Write(key, self.alloc_value()));
+            }
+            b.push(Op::Write(key, self.alloc_value()));
+        }
+        self.log.lock().unwrap().push(ConflictPair {
+            first: self.index,
+            second: self.index + 1,
+            keys,
+        });
+        Op::Txn(b)
+    }
+
+    fn alloc_value(&mut self) -> u64 {
+        self.next_value += 1;
+        self.next_value
+    }
+}
+
+impl<G: RawGenerator<Item = Op>> RawGenerator for ConflictPairGenerator<G> {
+    type Item = Op;
+    fn gen(&mut self) -> Self::Item {
+        let op = match self.pending.take() {
+            Some(op) => op,
+            None => {
+                let mut txn = self.next_txn();
+                if rand::thread_rng().gen_bool(self.option.rate) {
+                    let second = self.make_pair(&mut txn);
+                    self.pending = Some(second);
+                }
+                Op::Txn(txn)
+            }
+        };
+        self.index += 1;
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generates `[[:w i 1] [:r i+1 nil]]` for every `i`.
+    struct TxnGen(u64);
+
+    impl RawGenerator for TxnGen {
+        type Item = Op;
+        fn gen(&mut self) -> Self::Item {
+            self.0 += 1;
+            Op::Txn(vec![Op::Write(self.0, 1), Op::Read(self.0 + 1, None)])
+        }
+    }
+
+    fn writes(op: &Op) -> Vec<u64> {
+        let Op::Txn(ops) = op else {
+            panic!("expected a txn, got {:?}", op)
+        };
+        ops.iter()
+            .filter_map(|op| match op {
+                Op::Write(k, _) => Some(*k),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[madsim::test]
+    async fn test_conflict_pair_generator() {
+        let option = ConflictOption::default().rate(1.0).shared_writes(2);
+        let mut gen = ConflictPairGenerator::new(TxnGen(0), option);
+        let log = gen.log();
+        let ops = gen.gen_n(10);
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 5);
+        for pair in log.iter() {
+            assert_eq!(pair.second, pair.first + 1);
+            assert_eq!(pair.keys.len(), 2);
+            let (a, b) = (
+                writes(&ops[pair.first as usize]),
+                writes(&ops[pair.second as usize]),
+            );
+            assert!(pair.keys.iter().all(|k| a.contains(k) && b.contains(k)));
+        }
+    }
+
+    #[madsim::test]
+    async fn test_conflict_pair_generator_disabled() {
+        let option = ConflictOption::default().rate(0.0);
+        let mut gen = ConflictPairGenerator::new(TxnGen(0), option);
+        let mut plain = TxnGen(0);
+        assert_eq!(gen.gen_n(10), plain.gen_n(10));
+        assert!(gen.log().lock().unwrap().is_empty());
+    }
+}
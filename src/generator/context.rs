@@ -1,14 +1,25 @@
 use std::{
-    collections::BTreeSet,
-    sync::{Arc, Mutex},
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
 };
 
+use log::warn;
 use madsim::time;
+use serde::Serialize;
 
 use super::RawGenerator;
 use crate::{
-    history::{ErrorType, SerializableHistoryList},
-    op::{Op, OpFunctionType},
+    history::{
+        ErrorType, HistoryCollector, HistoryProcess, HistorySpill, HistoryType, HistoryValue,
+        HistoryWriter, NemesisValue, OpMeta, SerializableHistory, SerializableHistoryList,
+    },
+    nemesis::{active::ActiveNemeses, NemesisRecord, SerializableNemesisType},
+    op::{Op, OpOrNemesis, OpOrNemesisFuncType, OpTags},
+    progress::{Progress, ProgressCounter},
+    Error,
 };
 
 type IdSetType = Arc<Mutex<BTreeSet<u64>>>;
@@ -72,9 +83,22 @@ impl Drop for GeneratorId {
     }
 }
 
+/// A panic of the harness in a generator task, i.e. in its generator or in
+/// handling its items, after which the generator is dropped and the rest of
+/// the run goes on. See [`Global::panics`].
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct HarnessPanic {
+    /// The id of the generator.
+    pub generator: u64,
+    /// The time of the panic, in nanoseconds since the start.
+    pub time: u64,
+    pub message: String,
+}
+
 /// The global context
 #[non_exhaustive]
-pub struct Global<'a, T: Send = Op, ERR: Send = ErrorType> {
+pub struct Global<'a, T: Send = OpOrNemesis, ERR: Send = ErrorType> {
     /// The id allocator and handle pool.
     /// This is like a dispatcher, when an [`Op`] generated, it will be sent to
     /// the corresponding sender, aka a madsim thread. This thread will try
@@ -84,30 +108,345 @@ pub struct Global<'a, T: Send = Op, ERR: Send = ErrorType> {
     pub gen: Mutex<Option<Box<dyn RawGenerator<Item = T> + Send + 'a>>>,
     /// The start time of the simulation
     pub start_time: time::Instant,
-    /// The history list
-    pub history: Mutex<SerializableHistoryList<OpFunctionType, ERR>>,
+    /// The history, pushed to by [`Global::push_invoke`],
+    /// [`Global::push_result`] and [`Global::push_nemesis`].
+    pub history: HistoryCollector<ERR>,
+    /// The writer streaming every pushed history item to the disk.
+    pub history_writer: Mutex<Option<HistoryWriter>>,
+    /// The spill of the oldest history items to the disk, the whole history
+    /// is read by [`Global::history_items`].
+    pub history_spill: Mutex<Option<HistorySpill>>,
+    /// The currently active nemeses
+    pub nemeses: Mutex<ActiveNemeses>,
+    /// The processes of the generators, see [`Global::process`].
+    processes: Mutex<Processes>,
+    /// The counts of the ops pushed to the history, see [`Global::progress`].
+    pub(crate) progress: ProgressCounter,
+    /// The panics of the generator tasks, see [`Global::panics`].
+    panics: Mutex<Vec<HarnessPanic>>,
+    /// The number of the history items pushed, read without the lock of the
+    /// history, e.g. by the watchdog.
+    pub(crate) pushed: AtomicU64,
+}
+
+/// The current process of every generator, and the next process number.
+#[derive(Debug, Default)]
+struct Processes {
+    current: HashMap<u64, u64>,
+    next: u64,
 }
 
 impl<'a, T: Send + 'a, ERR: Send> Global<'a, T, ERR> {
     /// Create a new global context
     pub fn new(gen: impl RawGenerator<Item = T> + Send + 'a) -> Self {
-        let h: SerializableHistoryList<OpFunctionType, ERR> = Default::default();
         Self {
             id_set: Mutex::new(BTreeSet::new()).into(),
             gen: Mutex::new(Some(
                 Box::new(gen) as Box<dyn RawGenerator<Item = T> + Send + 'a>
             )),
             start_time: time::Instant::now(),
-            history: Mutex::new(h),
+            history: HistoryCollector::default(),
+            history_writer: Mutex::default(),
+            history_spill: Mutex::default(),
+            nemeses: Mutex::default(),
+            processes: Mutex::default(),
+            progress: ProgressCounter::default(),
+            panics: Mutex::default(),
+            pushed: AtomicU64::new(0),
         }
     }
 
+    /// The process the generator runs as, which is recorded in the history.
+    /// A generator runs as a new process after its current process crashes,
+    /// and the process numbers are never reused.
+    pub fn process(&self, id: u64) -> u64 {
+        let mut processes = self.processes.lock().expect("Failed to lock processes");
+        let Processes { current, next } = &mut *processes;
+        *current.entry(id).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    /// Crash the current process of the generator, e.g. after an
+    /// indeterminate op, like jepsen does. An indeterminate op may complete
+    /// at any time later, so the process cannot invoke another op.
+    pub fn crash_process(&self, id: u64) {
+        self.processes
+            .lock()
+            .expect("Failed to lock processes")
+            .current
+            .remove(&id);
+    }
+
+    /// Record a panic of the generator task, see [`HarnessPanic`].
+    pub fn record_panic(&self, generator: u64, message: impl Into<String>) {
+        let panic = HarnessPanic {
+            generator,
+            time: self.start_time.elapsed().as_nanos() as u64,
+            message: message.into(),
+        };
+        warn!("generator {} panicked: {}", generator, panic.message);
+        self.panics
+            .lock()
+            .expect("Failed to lock panics")
+            .push(panic);
+    }
+
+    /// The number of the history items pushed so far.
+    pub fn pushed(&self) -> u64 {
+        self.pushed.load(Ordering::Relaxed)
+    }
+
+    /// The panics of the generator tasks so far, in order.
+    pub fn panics(&self) -> Vec<HarnessPanic> {
+        self.panics.lock().expect("Failed to lock panics").clone()
+    }
+
+    /// The process number the next new process gets.
+    pub fn next_process(&self) -> u64 {
+        self.processes
+            .lock()
+            .expect("Failed to lock processes")
+            .next
+    }
+
+    /// Go on from the process number with every generator as a new process,
+    /// e.g. on resuming a run, see [`crate::checkpoint`].
+    pub(crate) fn restore_processes(&self, next: u64) {
+        let mut processes = self.processes.lock().expect("Failed to lock processes");
+        processes.current.clear();
+        processes.next = next;
+    }
+
     /// Alloc a new generator id
     pub fn get_id(&self) -> GeneratorId {
         GeneratorId::new(Arc::clone(&self.id_set))
     }
 
+    /// The records of the currently active nemeses and the time they are
+    /// activated.
+    pub fn active_records(&self) -> Vec<(NemesisRecord, time::Instant)> {
+        self.nemeses
+            .lock()
+            .expect("Failed to lock nemeses")
+            .snapshot()
+    }
+
+    /// The progress of the run so far.
+    pub fn progress(&self) -> Progress {
+        let active = self
+            .active_records()
+            .into_iter()
+            .map(|(record, _)| format!("{:?}", record))
+            .collect();
+        self.progress.progress(self.start_time.elapsed(), active)
+    }
+
+    /// Iterate the whole history in order, with the spilled items read from
+    /// the disk lazily, and the items in memory cloned.
+    pub fn history_items(
+        &self,
+    ) -> crate::Result<
+        impl Iterator<Item = crate::Result<SerializableHistory<OpOrNemesisFuncType, ERR>>>,
+    >
+    where
+        ERR: Clone + Serialize + for<'de> serde::Deserialize<'de> + 'static,
+    {
+        self.merge_history(true);
+        let history = self
+            .history
+            .merged
+            .lock()
+            .map_err(Error::poisoned("history"))?;
+        let spill = self
+            .history_spill
+            .lock()
+            .map_err(Error::poisoned("history spill"))?;
+        let spilled = spill
+            .as_ref()
+            .map(|spill| spill.iter().map(|item| item.map_err(Error::History)));
+        let in_memory = history.list.0.clone().into_iter().map(Ok);
+        Ok(spilled.into_iter().flatten().chain(in_memory))
+    }
+
+    /// Collect the whole history by [`Global::history_items`].
+    pub fn full_history(&self) -> crate::Result<SerializableHistoryList<OpOrNemesisFuncType, ERR>>
+    where
+        ERR: Clone + Serialize + for<'de> serde::Deserialize<'de> + 'static,
+    {
+        Ok(SerializableHistoryList(
+            self.history_items()?.collect::<crate::Result<_>>()?,
+        ))
+    }
+
+    /// Lend the whole history to `f`, like [`Global::full_history`] but with
+    /// the items in memory moved out instead of cloned, e.g. to check it at
+    /// the end of a run. The history is locked meanwhile, and the items are
+    /// moved back after.
+    pub fn with_full_history<R>(
+        &self,
+        f: impl FnOnce(&SerializableHistoryList<OpOrNemesisFuncType, ERR>) -> R,
+    ) -> crate::Result<R>
+    where
+        ERR: Serialize + for<'de> serde::Deserialize<'de> + 'static,
+    {
+        self.merge_history(true);
+        let mut merged = self
+            .history
+            .merged
+            .lock()
+            .map_err(Error::poisoned("history"))?;
+        let spill = self
+            .history_spill
+            .lock()
+            .map_err(Error::poisoned("history spill"))?;
+        let spilled = match spill.as_ref() {
+            Some(spill) => spill
+                .iter()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map_err(Error::History)?,
+            None => vec![],
+        };
+        let n = spilled.len();
+        let mut history = SerializableHistoryList(std::mem::take(&mut merged.list.0));
+        history.0.splice(..0, spilled);
+        let res = f(&history);
+        history.0.drain(..n);
+        merged.list = history;
+        Ok(res)
+    }
+
+    /// Merge the pushed items into the history, writing them by the history
+    /// writer and spilling the oldest ones by the history spill, see
+    /// [`HistoryCollector`]. Returns the number of the items merged so far,
+    /// including the spilled ones, or `None` if another thread is merging
+    /// and `wait` is false.
+    pub(crate) fn merge_history(&self, wait: bool) -> Option<u64>
+    where
+        ERR: Serialize,
+    {
+        let (mut merged, n) = self.history.merge(wait)?;
+        let items = &mut merged.list.0;
+        let new = &items[items.len() - n..];
+        #[cfg(feature = "metrics")]
+        for item in new {
+            crate::metrics::Metrics::global().record_item(item);
+        }
+        // a panic while writing or spilling leaves at worst a partial item on
+        // the disk, so the items after it are still recorded
+        let mut writer = self
+            .history_writer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(writer) = writer.as_mut() {
+            for item in new {
+                if let Err(err) = writer.write(item) {
+                    warn!("failed to write history item {}: {}", item.index, err);
+                }
+            }
+        }
+        let mut spill = self
+            .history_spill
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(spill) = spill.as_mut() {
+            if let Err(err) = spill.spill(items) {
+                warn!("failed to spill the history: {}", err);
+            }
+        }
+        Some(spill.as_ref().map_or(0, HistorySpill::spilled) + items.len() as u64)
+    }
+
+    /// Push an item to the history, with the time since the start, and merge
+    /// the history if no other thread is merging it.
+    fn push(&self, mut item: SerializableHistory<OpOrNemesisFuncType, ERR>) -> u64
+    where
+        ERR: Serialize,
+    {
+        item.time = self.start_time.elapsed().as_nanos() as u64;
+        if item.process != HistoryProcess::Nemesis {
+            self.progress.count(&item.type_);
+        }
+        let index = self.history.push(item);
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        self.merge_history(false);
+        index
+    }
+
+    /// Push an invoke to the history, returns its index.
+    pub fn push_invoke(&self, process: u64, value: Op, tags: OpTags) -> u64
+    where
+        ERR: Serialize,
+    {
+        self.push(SerializableHistory {
+            index: 0,
+            type_: HistoryType::Invoke,
+            f: (&value).into(),
+            value: value.into(),
+            time: 0,
+            process: HistoryProcess::Gen(process),
+            error: None,
+            meta: None,
+            tags,
+        })
+    }
+
+    /// Push a result to the history, with the metadata of its attempts if
+    /// known, and the tags of its invoke.
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_result(
+        &self,
+        process: u64,
+        result_type: HistoryType,
+        value: Op,
+        error: Option<ERR>,
+        meta: Option<OpMeta>,
+        tags: OpTags,
+    ) where
+        ERR: Serialize,
+    {
+        assert!(
+            (result_type == HistoryType::Ok) == (error.is_none()),
+            "result type mismatch"
+        );
+        self.push(SerializableHistory {
+            index: 0,
+            type_: result_type,
+            f: (&value).into(),
+            value: value.into(),
+            time: 0,
+            process: HistoryProcess::Gen(process),
+            error,
+            meta,
+            tags,
+        });
+    }
+
+    /// Push a nemesis to the history. Nemesis items are always `:info`, and
+    /// are performed by the `nemesis` process.
+    pub fn push_nemesis(&self, f: SerializableNemesisType, value: NemesisValue, error: Option<ERR>)
+    where
+        ERR: Serialize,
+    {
+        self.push(SerializableHistory {
+            index: 0,
+            type_: HistoryType::Info,
+            f: OpOrNemesisFuncType::Nemesis(f),
+            value: HistoryValue::Fault(value),
+            time: 0,
+            process: HistoryProcess::Nemesis,
+            error,
+            meta: None,
+            tags: OpTags::new(),
+        });
+    }
+
     /// Take the next `n` ops from the raw generator.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "generate", level = "debug", skip(self))
+    )]
     pub fn take_seq(&self, n: usize) -> Vec<T> {
         if let Some(gen) = self.gen.lock().expect("Failed to lock gen").as_mut() {
             gen.gen_n(n)
@@ -135,4 +474,58 @@ mod tests {
         let id1 = GeneratorId::new(id_set.clone());
         assert_eq!(id1.get(), 1);
     }
+
+    #[test]
+    fn test_process_incarnation() {
+        let global: Global<'_, i32> = Global::new(0..);
+        assert_eq!(global.process(0), 0);
+        assert_eq!(global.process(1), 1);
+        assert_eq!(global.process(0), 0);
+        global.crash_process(0);
+        assert_eq!(global.process(0), 2);
+        assert_eq!(global.process(2), 3);
+        assert_eq!(global.process(1), 1);
+    }
+
+    #[test]
+    fn test_merge_poisoned() {
+        let global: Global<'_, i32, String> = Global::new(0..);
+        let _ = std::panic::catch_unwind(|| {
+            let _writer = global.history_writer.lock().unwrap();
+            panic!("poison");
+        });
+        global.push_invoke(0, crate::op::Op::Read(1, None), crate::op::OpTags::new());
+        assert_eq!(global.merge_history(true), Some(1));
+    }
+
+    /// An error counting its clones.
+    #[derive(Debug, Serialize, serde::Deserialize)]
+    struct Counted;
+
+    static CLONES: AtomicU64 = AtomicU64::new(0);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, Ordering::Relaxed);
+            Counted
+        }
+    }
+
+    #[test]
+    fn test_with_full_history() {
+        use crate::{history::HistoryType, op::Op, op::OpTags};
+
+        let global: Global<'_, i32, Counted> = Global::new(0..);
+        for key in 0..100 {
+            global.push_invoke(0, Op::Read(key, None), OpTags::new());
+            let (op, err) = (Op::Read(key, None), Some(Counted));
+            global.push_result(0, HistoryType::Fail, op, err, None, OpTags::new());
+        }
+        let len = global.with_full_history(|history| history.0.len());
+        assert_eq!(len.unwrap(), 200);
+        assert_eq!(CLONES.load(Ordering::Relaxed), 0);
+        // the items are moved back, and cloned by the full history
+        assert_eq!(global.full_history().unwrap().0.len(), 200);
+        assert_eq!(CLONES.load(Ordering::Relaxed), 100);
+    }
 }
@@ -9,7 +9,7 @@ use crate::utils::OverflowingAddRange;
 
 /// The delay strategy of the generator. You can delay the generator when
 /// every `Op` generation.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub enum DelayStrategy {
     /// No delay.
     #[default]
@@ -0,0 +1,74 @@
+//! A generator of counters, which yields single reads and increments by
+//! signed deltas, so a counter-style system is exercised by its native
+//! increments instead of read and write pairs.
+
+use madsim::rand::{self, Rng};
+
+use super::RawGenerator;
+use crate::op::Op;
+
+/// An infinite generator of random reads and increments on a few counters.
+pub struct CounterGenerator {
+    keys: u64,
+    max_delta: i64,
+    decrements: bool,
+}
+
+impl CounterGenerator {
+    /// Ops on the keys in `[0, keys)`, with deltas in `[1, 5]`.
+    pub fn new(keys: u64) -> Self {
+        assert!(keys > 0, "there must be at least one key");
+        Self {
+            keys,
+            max_delta: 5,
+            decrements: false,
+        }
+    }
+
+    /// Draw the absolute values of the deltas from `[1, max_delta]`.
+    pub fn with_max_delta(mut self, max_delta: i64) -> Self {
+        assert!(max_delta > 0, "the max delta must be positive");
+        self.max_delta = max_delta;
+        self
+    }
+
+    /// Generate decrements too, i.e. negative deltas.
+    pub fn with_decrements(mut self, decrements: bool) -> Self {
+        self.decrements = decrements;
+        self
+    }
+}
+
+impl RawGenerator for CounterGenerator {
+    type Item = Op;
+    fn gen(&mut self) -> Self::Item {
+        let mut rng = rand::thread_rng();
+        let key = rng.gen_range(0..self.keys);
+        // reads : increments = 1 : 3
+        if rng.gen_range(0..4) == 0 {
+            return Op::Read(key, None);
+        }
+        let delta = rng.gen_range(1..=self.max_delta);
+        match self.decrements && rng.gen_bool(0.5) {
+            true => Op::Incr(key, -delta),
+            false => Op::Incr(key, delta),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[madsim::test]
+    async fn test_counter_gen() {
+        let ops = CounterGenerator::new(2).with_max_delta(3).gen_n(1000);
+        assert!(ops.iter().all(|op| match op {
+            Op::Read(k, None) => *k < 2,
+            Op::Incr(k, d) => *k < 2 && (1..=3).contains(d),
+            _ => false,
+        }));
+        let ops = CounterGenerator::new(1).with_decrements(true).gen_n(1000);
+        assert!(ops.iter().any(|op| matches!(op, Op::Incr(0, d) if *d < 0)));
+    }
+}
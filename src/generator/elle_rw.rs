@@ -1,22 +1,54 @@
-use std::sync::Mutex;
-
 use j4rs::{Instance, InvocationArg};
 
 use super::{RawGenerator, GENERATOR_CACHE_SIZE};
 use crate::{
-    cljinvoke, init_jvm, nsinvoke,
+    cljinvoke,
+    executor::{JvmExecutor, JvmHandle, Remote},
+    nsinvoke,
     op::{Op, Ops},
-    utils::{pre_serialize, ToDe},
-    with_jvm, CljNs, CLOJURE,
+    utils::java_to_string,
+    CljNs, IFn, CLOJURE,
 };
 
+/// The clojure helper taking a batch from the generator seq in an atom: it
+/// realizes `n` ops, advances the atom past them, and returns the values of
+/// the ops in JSON, so a batch costs one FFI call.
+const TAKE_BATCH: &str = "(fn [state n]
+  (let [s @state
+        batch (mapv :value (take n s))]
+    (reset! state (drop n s))
+    (clojure.data.json/write-str batch)))";
+
+/// The clojure side of the generator, kept on the [`JvmExecutor`].
+struct Batcher {
+    /// The [`TAKE_BATCH`] helper.
+    take: IFn,
+    /// The atom of the rest of the clojure generator seq.
+    state: Instance,
+}
+
+impl Batcher {
+    fn new(ns: &CljNs) -> j4rs::errors::Result<Self> {
+        CLOJURE.require("clojure.data.json")?;
+        let take = IFn::new(cljinvoke!("load-string", TAKE_BATCH)?);
+        let state = cljinvoke!("atom", nsinvoke!(ns, "gen")?)?;
+        Ok(Self { take, state })
+    }
+}
+
 /// The generator of `elle.rw-register`. This generator will only generates a
-/// batch of txns which contains read and write operations.
+/// batch of txns which contains read and write operations. The clojure
+/// generator runs on the [`JvmExecutor`].
+///
+/// [`ElleRwGenerator::list_append`] generates the txns of appends and list
+/// reads of `elle.list-append` instead.
 pub struct ElleRwGenerator {
     /// The namespace of the generator, default is `elle.rw-register`
     ns: CljNs,
-    /// The clojure generator Instance.
-    gen: Mutex<Option<Instance>>,
+    /// Whether the reads are list reads, of `elle.list-append`.
+    lists: bool,
+    /// The clojure side, created by the first batch.
+    batcher: Option<Remote<Batcher>>,
     /// The cached `Op`s of the generator. Because the clojure generator will
     /// generates infinite sequence, we can take some of them to cache. When the
     /// `Op`s run out, fetch new `Op`s from the clojure generator.
@@ -25,50 +57,83 @@ pub struct ElleRwGenerator {
 
 impl ElleRwGenerator {
     pub fn new() -> j4rs::errors::Result<Self> {
-        with_jvm(|_| {
-            let ns = CLOJURE.require("elle.rw-register")?;
-            Ok(Self {
-                ns,
-                gen: Mutex::new(None),
-                cache: Ops(Vec::with_capacity(GENERATOR_CACHE_SIZE)),
-            })
+        Self::of_ns("elle.rw-register", false)
+    }
+
+    /// The generator of `elle.list-append`.
+    pub fn list_append() -> j4rs::errors::Result<Self> {
+        Self::of_ns("elle.list-append", true)
+    }
+
+    fn of_ns(name: &'static str, lists: bool) -> j4rs::errors::Result<Self> {
+        let ns = JvmExecutor::global().call_blocking(move |_| CLOJURE.require(name))?;
+        Ok(Self {
+            ns,
+            lists,
+            batcher: None,
+            cache: Ops(Vec::with_capacity(GENERATOR_CACHE_SIZE)),
+        })
+    }
+
+    /// Take `n` ops from the clojure generator by one call of the batch helper,
+    /// and reserve the rest of it for next time to use.
+    fn fetch(&mut self, n: usize) -> anyhow::Result<Ops> {
+        let batcher = match &mut self.batcher {
+            Some(batcher) => batcher,
+            None => {
+                let ns = self.ns.clone();
+                let batcher = JvmHandle::global().try_remote(move |_| Batcher::new(&ns))?;
+                self.batcher.insert(batcher)
+            }
+        };
+        let ops: Ops = batcher.with(move |jvm, batcher| {
+            let state = InvocationArg::from(jvm.clone_instance(&batcher.state)?);
+            let json = batcher
+                .take
+                .invoke(&[state, InvocationArg::try_from(n as i32)?])?;
+            Ok::<_, anyhow::Error>(serde_json::from_str(&java_to_string(&json)?)?)
+        })?;
+        Ok(match self.lists {
+            true => Ops(ops.0.into_iter().map(list_reads).collect()),
+            false => ops,
         })
     }
 
-    /// It generates a batch of ops in one time, and reserves the gen `Instance`
-    /// for next time to use.
+    /// It generates a batch of ops in one time when the cache runs out.
     fn gen_inner(&mut self) -> anyhow::Result<Op> {
-        init_jvm();
         if let Some(op) = self.cache.pop() {
             return Ok(op);
         }
-        let mut gen = self.gen.lock().expect("Failed to lock generator");
-        if gen.is_none() {
-            gen.replace(nsinvoke!(self.ns, "gen")?);
-        }
-        let cljgen = gen
-            .take()
-            .unwrap_or_else(|| unreachable!("gen should not be `None` after replacing it"));
-
-        // avoid consuming the ownership of `two_seqs`
-        let two_seqs = [InvocationArg::from(cljinvoke!(
-            "split-at",
-            GENERATOR_CACHE_SIZE as i32,
-            cljgen
-        )?)];
-
-        let first_seq = pre_serialize(CLOJURE.var("first")?.invoke(&two_seqs)?)?;
-        let ops: Ops = first_seq.to_de()?;
-        self.cache = ops.rev();
-
-        let second_seq = CLOJURE.var("second")?.invoke(&two_seqs)?;
-        // update the elle gen
-        gen.replace(second_seq);
+        self.cache = self.fetch(GENERATOR_CACHE_SIZE)?.rev();
         Ok(self
             .cache
             .pop()
             .unwrap_or_else(|| unreachable!("cache should not be empty after supplement")))
     }
+
+    /// Generate `n` ops, the ones not in the cache by one batch.
+    fn gen_n_inner(&mut self, n: usize) -> anyhow::Result<Vec<Op>> {
+        let cached = self.cache.len().min(n);
+        let mut out: Vec<_> = (0..cached).filter_map(|_| self.cache.pop()).collect();
+        if out.len() < n {
+            // fetch at least a cache of ops, and keep the ones not needed
+            let mut ops = self.fetch((n - out.len()).max(GENERATOR_CACHE_SIZE))?.0;
+            let rest = ops.split_off(n - out.len());
+            out.extend(ops);
+            self.cache = Ops(rest).rev();
+        }
+        Ok(out)
+    }
+}
+
+/// Turn the reads into list reads, as the ones of `elle.list-append` are
+/// parsed as [`Op::Read`]s before they complete.
+fn list_reads(op: Op) -> Op {
+    match op {
+        Op::Read(key, None) => Op::ReadList(key, None),
+        Op::Txn(ops) => Op::Txn(ops.into_iter().map(list_reads).collect()),
+        op => op,
+    }
 }
 
 impl RawGenerator for ElleRwGenerator {
@@ -77,6 +142,11 @@ impl RawGenerator for ElleRwGenerator {
         self.gen_inner()
             .unwrap_or_else(|e| panic!("An error occurs from ElleRwGenerator generating: {}", e))
     }
+
+    fn gen_n(&mut self, n: usize) -> Vec<Self::Item> {
+        self.gen_n_inner(n)
+            .unwrap_or_else(|e| panic!("An error occurs from ElleRwGenerator generating: {}", e))
+    }
 }
 
 impl Iterator for ElleRwGenerator {
@@ -97,6 +167,24 @@ mod test {
         for _ in 0..GENERATOR_CACHE_SIZE * 2 + 10 {
             gen.gen();
         }
+        assert_eq!(
+            gen.gen_n(GENERATOR_CACHE_SIZE * 3 + 5).len(),
+            GENERATOR_CACHE_SIZE * 3 + 5
+        );
+        assert_eq!(gen.gen_n(3).len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn list_append_gen_should_work() -> Result<(), Box<dyn std::error::Error>> {
+        let ops = ElleRwGenerator::list_append()?.gen_n(100);
+        let mops = ops.iter().flat_map(|op| match op {
+            Op::Txn(ops) => ops.as_slice(),
+            op => std::slice::from_ref(op),
+        });
+        assert!(mops
+            .into_iter()
+            .all(|op| matches!(op, Op::Append(..) | Op::ReadList(_, None))));
         Ok(())
     }
 }
@@ -1,20 +1,30 @@
+pub mod bank;
+pub mod cas_register;
+pub mod conflict;
 pub mod context;
 pub mod controller;
+pub mod counter;
+#[cfg(feature = "clojure")]
 pub mod elle_rw;
+pub mod nemesis_mix;
 #[cfg(test)]
 use std::ops::{AddAssign, RangeFrom};
-use std::{fmt, ops::SubAssign, pin::Pin, sync::Arc};
+use std::{
+    collections::BTreeMap, fmt, ops::SubAssign, panic::AssertUnwindSafe, path::Path, pin::Pin,
+    sync::Arc,
+};
 
 use context::GeneratorId;
-pub use context::Global;
+pub use context::{Global, HarnessPanic};
 use controller::{DelayStrategy, GeneratorGroupStrategy};
+use futures_util::FutureExt as _;
 use log::{debug, trace};
 use tokio_stream::{Stream, StreamExt as _};
 
 use crate::{
     history::ErrorType,
-    op::Op,
-    utils::{AsyncIter, ExtraStreamExt},
+    op::{plan_to_edn, OpOrNemesis},
+    utils::{panic_message, AsyncIter, ExtraStreamExt},
 };
 
 /// Cache size for the generator.
@@ -42,6 +52,28 @@ impl<U> Iterator for dyn RawGenerator<Item = U> {
     }
 }
 
+/// A [`RawGenerator`] that maps every item of the inner generator by `f`.
+pub struct RawGeneratorMap<G, F> {
+    gen: G,
+    f: F,
+}
+
+impl<G, F> RawGeneratorMap<G, F> {
+    pub fn new(gen: G, f: F) -> Self {
+        Self { gen, f }
+    }
+}
+
+impl<G: RawGenerator, U, F: FnMut(G::Item) -> U> RawGenerator for RawGeneratorMap<G, F> {
+    type Item = U;
+    fn gen(&mut self) -> Self::Item {
+        (self.f)(self.gen.gen())
+    }
+    fn gen_n(&mut self, n: usize) -> Vec<Self::Item> {
+        self.gen.gen_n(n).into_iter().map(&mut self.f).collect()
+    }
+}
+
 #[cfg(test)]
 impl RawGenerator for RangeFrom<i32> {
     type Item = i32;
@@ -53,7 +85,7 @@ impl RawGenerator for RangeFrom<i32> {
 }
 
 /// The builder of generator.
-pub struct GeneratorBuilder<'a, U: Send + fmt::Debug = Op, ERR: Send + 'a = ErrorType> {
+pub struct GeneratorBuilder<'a, U: Send + fmt::Debug = OpOrNemesis, ERR: Send + 'a = ErrorType> {
     global: Arc<Global<'a, U, ERR>>,
     seq: Option<Pin<Box<dyn Stream<Item = U> + Send + 'a>>>,
     delay_strategy: Option<Pin<Box<dyn Stream<Item = DelayStrategy> + Send + 'a>>>,
@@ -161,8 +193,8 @@ impl<'a, U: Send + fmt::Debug + 'a, ERR: 'a + Send> GeneratorBuilder<'a, U, ERR>
 /// The generator. Each generator is a **FINITE** Op sequence with a same size
 /// sequence of [`DelayStrategy`]s. When generating each Op, the generator will
 /// take an element from both the sequence and the [`DelayStrategy`] sequence,
-/// returns the [`Op`] after delay for the corresponding [`DelayStrategy`].
-pub struct Generator<'a, U: Send + fmt::Debug = Op, ERR: Send + 'a = ErrorType> {
+/// returns the item after delay for the corresponding [`DelayStrategy`].
+pub struct Generator<'a, U: Send + fmt::Debug = OpOrNemesis, ERR: Send + 'a = ErrorType> {
     /// generator id
     pub id: GeneratorId,
     /// A reference to the global context
@@ -230,6 +262,23 @@ impl<'a, U: Send + fmt::Debug + 'a, ERR: 'a + Send> Generator<'a, U, ERR> {
         )
     }
 
+    /// Collect all the items without delays, and rebuild a generator that
+    /// yields the same items with the same delays.
+    pub async fn peek_all(self) -> (Vec<U>, Self)
+    where
+        U: Clone,
+    {
+        let seq: Vec<_> = self.seq.collect().await;
+        let delay: Vec<_> = self.delay_strategy.take(seq.len()).collect().await;
+        let gen = GeneratorBuilder::new(self.global)
+            .id(self.id)
+            .delay_stream(tokio_stream::iter(delay))
+            .size(seq.len())
+            .seq(tokio_stream::iter(seq.clone()))
+            .build();
+        (seq, gen)
+    }
+
     /// Chain two generators together.
     pub fn chain(self, other: Self) -> Self {
         let out_seq = self.seq.chain(other.seq);
@@ -243,9 +292,31 @@ impl<'a, U: Send + fmt::Debug + 'a, ERR: 'a + Send> Generator<'a, U, ERR> {
     }
 }
 
+impl<'a, U: Send + fmt::Debug + 'a, ERR: 'a + Send> Generator<'a, U, ERR> {
+    /// Skip the next `n` items without their delays, e.g. the items taken
+    /// before a run is resumed, see [`crate::checkpoint`].
+    pub fn skip_items(&mut self, n: usize) {
+        let n = n.min(self.size);
+        let seq = std::mem::replace(&mut self.seq, Box::pin(tokio_stream::empty()));
+        self.seq = Box::pin(seq.skip(n));
+        let delay = std::mem::replace(&mut self.delay_strategy, Box::pin(tokio_stream::empty()));
+        self.delay_strategy = Box::pin(delay.skip(n));
+        self.size -= n;
+    }
+}
+
 #[async_trait::async_trait]
 impl<'a, ERR: 'a + Send, U: Send + fmt::Debug + 'a> AsyncIter for Generator<'a, U, ERR> {
     type Item = U;
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "generate",
+            level = "trace",
+            skip(self),
+            fields(generator = self.id.get())
+        )
+    )]
     async fn next(&mut self) -> Option<Self::Item> {
         let item = self.seq.next().await;
         if item.is_none() {
@@ -273,7 +344,7 @@ impl<'a, ERR: 'a + Send, U: Send + fmt::Debug + 'a> AsyncIter for Generator<'a,
 
 /// A group of generators.
 #[derive(Default)]
-pub struct GeneratorGroup<'a, U: Send + fmt::Debug = Op, ERR: 'a + Send = ErrorType> {
+pub struct GeneratorGroup<'a, U: Send + fmt::Debug = OpOrNemesis, ERR: 'a + Send = ErrorType> {
     gens: Vec<Generator<'a, U, ERR>>,
     strategy: GeneratorGroupStrategy,
 }
@@ -302,59 +373,129 @@ impl<'a, ERR: 'a + Send, U: Send + fmt::Debug + 'a> GeneratorGroup<'a, U, ERR> {
     pub fn remove_generator(&mut self, index: usize) -> Generator<'a, U, ERR> {
         self.gens.remove(index)
     }
+
+    /// Skip the items of every generator by its id, see
+    /// [`Generator::skip_items`].
+    pub fn skip_items(&mut self, positions: &BTreeMap<u64, usize>) {
+        for gen in &mut self.gens {
+            if let Some(n) = positions.get(&gen.id.get()) {
+                gen.skip_items(*n);
+            }
+        }
+    }
+
+    /// Drop the generator of the id, which frees the id. Returns whether it's
+    /// in the group.
+    pub fn free_generator(&mut self, id: u64) -> bool {
+        let len = self.gens.len();
+        self.gens.retain(|gen| gen.id.get() != id);
+        self.gens.len() < len
+    }
+
+    /// Collect all the items of the generators, in the order of generators
+    /// rather than the order of the group strategy, and rebuild the group. See
+    /// [`Generator::peek_all`].
+    pub async fn peek_all(self) -> (Vec<U>, Self)
+    where
+        U: Clone,
+    {
+        let mut items = vec![];
+        let mut gens = Vec::with_capacity(self.gens.len());
+        for gen in self.gens {
+            let (seq, gen) = gen.peek_all().await;
+            items.extend(seq);
+            gens.push(gen);
+        }
+        (
+            items,
+            Self {
+                gens,
+                strategy: self.strategy,
+            },
+        )
+    }
 }
 
 #[async_trait::async_trait]
 impl<'a, U: Send + fmt::Debug + 'a, ERR: 'a + Send> AsyncIter for GeneratorGroup<'a, U, ERR> {
     type Item = U;
     /// Select one generator to generate `Op` by group strategy. If it's empty,
-    /// drop it and try to use another. If all [`Generator`]s in the group
-    /// are empty, returns None.
+    /// or it panics, drop it and try to use another. If all [`Generator`]s in
+    /// the group are empty, returns None. The panics are recorded by
+    /// [`Global::record_panic`].
     async fn next(&mut self) -> Option<Self::Item> {
         loop {
             if self.gens.is_empty() {
                 return None;
             }
             let selected = self.strategy.choose(0..self.gens.len());
-            match self
+            let gen = self
                 .gens
                 .get_mut(selected)
-                .expect("selected index should be in the vec")
-                .next()
-                .await
-            {
-                x @ Some(_) => return x,
-                None => {
+                .expect("selected index should be in the vec");
+            match AssertUnwindSafe(gen.next()).catch_unwind().await {
+                Ok(x @ Some(_)) => return x,
+                Ok(None) => {
                     self.remove_generator(selected);
                 }
+                Err(panic) => {
+                    let gen = self.remove_generator(selected);
+                    gen.global
+                        .record_panic(gen.id.get(), panic_message(&*panic));
+                }
             }
         }
     }
     /// Select one generator to generate `Op` by group strategy. If it's empty,
-    /// drop it and try to use another. If all [`Generator`]s in the group
-    /// are empty, returns None.
+    /// or it panics, drop it and try to use another. If all [`Generator`]s in
+    /// the group are empty, returns None. The panics are recorded by
+    /// [`Global::record_panic`].
     async fn next_with_id(&mut self) -> Option<(Self::Item, u64)> {
         loop {
             if self.gens.is_empty() {
                 return None;
             }
             let selected = self.strategy.choose(0..self.gens.len());
-            match self
+            let gen = self
                 .gens
                 .get_mut(selected)
-                .expect("selected index should be in the vec")
-                .next_with_id()
-                .await
-            {
-                x @ Some(_) => return x,
-                None => {
+                .expect("selected index should be in the vec");
+            match AssertUnwindSafe(gen.next_with_id()).catch_unwind().await {
+                Ok(x @ Some(_)) => return x,
+                Ok(None) => {
                     self.remove_generator(selected);
                 }
+                Err(panic) => {
+                    let gen = self.remove_generator(selected);
+                    gen.global
+                        .record_panic(gen.id.get(), panic_message(&*panic));
+                }
             }
         }
     }
 }
 
+/// Write the planned ops and nemeses of the group to the file before running
+/// it, one EDN item per line (see [`OpOrNemesis::to_edn`]), so the workloads
+/// can be reviewed and diffed across seeds. Returns the rebuilt group, which
+/// yields the same items.
+///
+/// The items are in the order of the generators, see
+/// [`GeneratorGroup::peek_all`], so the group should be finite.
+pub async fn dump_plan<'a, ERR: 'a + Send>(
+    group: GeneratorGroup<'a, OpOrNemesis, ERR>,
+    path: impl AsRef<Path>,
+) -> anyhow::Result<GeneratorGroup<'a, OpOrNemesis, ERR>> {
+    let (items, group) = group.peek_all().await;
+    std::fs::write(path.as_ref(), plan_to_edn(&items)?)?;
+    debug!(
+        "dumped {} planned items to {:?}",
+        items.len(),
+        path.as_ref()
+    );
+    Ok(group)
+}
+
 /// Convert a [`Generator`] to a [`GeneratorGroup`].
 impl<'a, U: Send + fmt::Debug + 'a, ERR: 'a + Send> From<Generator<'a, U, ERR>>
     for GeneratorGroup<'a, U, ERR>
@@ -396,6 +537,38 @@ mod tests {
         let mut out = gen.gen_n(10);
         out.sort();
         assert_eq!(out, vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let mut gen = RawGeneratorMap::new(0.., |x| x * 2);
+        assert_eq!(gen.gen(), 0);
+        assert_eq!(gen.gen_n(3), vec![2, 4, 6]);
+    }
+
+    #[madsim::test]
+    async fn test_dump_plan() -> anyhow::Result<()> {
+        use crate::op::{r, w};
+
+        let global = Arc::new(Global::<_, String>::new(RawGeneratorMap::new(
+            0..,
+            |k: i32| OpOrNemesis::Op(w(k as u64, 1)),
+        )));
+        let gen = GeneratorBuilder::new(Arc::clone(&global))
+            .seq(tokio_stream::iter(global.take_seq(2)))
+            .build();
+        let reads = GeneratorBuilder::new(Arc::clone(&global))
+            .seq(tokio_stream::iter([OpOrNemesis::Op(r(0))]))
+            .build();
+        let path = std::env::temp_dir().join(format!("jepsen-rs-plan-{}.edn", std::process::id()));
+        let mut group = dump_plan(GeneratorGroup::new([gen, reads]), &path).await?;
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            "{:f :w, :value [:w 0 1]}\n{:f :w, :value [:w 1 1]}\n{:f :r, :value [:r 0 nil]}\n"
+        );
+        std::fs::remove_file(&path)?;
+        let mut n = 0;
+        while group.next().await.is_some() {
+            n += 1;
+        }
+        assert_eq!(n, 3);
+        Ok(())
     }
 
     #[madsim::test]
@@ -498,6 +671,21 @@ mod tests {
         assert!(res.into_iter().all(|x| (21..=30).contains(&x)));
     }
 
+    #[madsim::test]
+    async fn test_generator_group_peek_all() {
+        let global = Arc::new(Global::<_, String>::new(1..));
+        let gen1 = GeneratorBuilder::new(Arc::clone(&global))
+            .seq(tokio_stream::iter(global.take_seq(3)))
+            .build();
+        let gen2 = GeneratorBuilder::new(Arc::clone(&global))
+            .seq(tokio_stream::iter(global.take_seq(3)))
+            .build();
+        let (items, gen_group) = GeneratorGroup::new(vec![gen1, gen2]).peek_all().await;
+        assert_eq!(items, vec![1, 2, 3, 4, 5, 6]);
+        let res = gen_group.collect().await;
+        assert_eq!(res, vec![1, 4, 2, 5, 3, 6]);
+    }
+
     #[madsim::test]
     async fn test_generator_group_into_generator() {
         let global = Arc::new(Global::new(1..));
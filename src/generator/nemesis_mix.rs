@@ -0,0 +1,129 @@
+//! A probabilistic mix of nemeses, which samples faults by weight instead of
+//! enumerating a fixed nemesis list.
+
+use madsim::{
+    rand::{self, Rng},
+    time::Duration,
+};
+
+use super::{controller::DelayStrategy, RawGenerator};
+use crate::nemesis::NemesisType;
+
+/// A template that makes a nemesis every time it's sampled.
+type NemesisTemplate = Box<dyn FnMut() -> NemesisType + Send>;
+
+/// The builder of a [`NemesisMix`].
+///
+/// ```ignore
+/// let mut mix = NemesisGeneratorBuilder::new(Duration::from_secs(10))
+///     .fault(3, NemesisType::PartitionHalves)
+///     .fault_with(1, || NemesisType::Kill(HashSet::from([random_server()])))
+///     .build();
+/// let gen = client.new_nemesis_mix(&mut mix, 20);
+/// ```
+pub struct NemesisGeneratorBuilder {
+    templates: Vec<(u32, NemesisTemplate)>,
+    interval: Duration,
+}
+
+impl NemesisGeneratorBuilder {
+    /// Faults are generated every `interval` on average.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            templates: vec![],
+            interval,
+        }
+    }
+
+    /// Add a fixed nemesis with the given weight.
+    pub fn fault(self, weight: u32, nemesis: NemesisType) -> Self {
+        self.fault_with(weight, move || nemesis.clone())
+    }
+
+    /// Add a template with the given weight, which makes a nemesis every time
+    /// it's sampled, e.g. to kill a random server.
+    pub fn fault_with(
+        mut self,
+        weight: u32,
+        template: impl FnMut() -> NemesisType + Send + 'static,
+    ) -> Self {
+        self.templates.push((weight, Box::new(template)));
+        self
+    }
+
+    /// Build the mix. Panics if the total weight is zero.
+    pub fn build(self) -> NemesisMix {
+        let total = self.templates.iter().map(|(w, _)| *w as u64).sum();
+        assert!(total > 0, "the total weight of nemeses must be positive");
+        NemesisMix {
+            templates: self.templates,
+            total,
+            interval: self.interval,
+        }
+    }
+}
+
+/// An infinite generator of nemeses sampled by weight. The randomness comes
+/// from madsim, so the samples are decided by the seed of the simulation.
+pub struct NemesisMix {
+    templates: Vec<(u32, NemesisTemplate)>,
+    total: u64,
+    interval: Duration,
+}
+
+impl NemesisMix {
+    /// The delay before every fault, which is random in `[0, 2 * interval]`.
+    pub fn delay(&self) -> DelayStrategy {
+        DelayStrategy::Random(self.interval)
+    }
+}
+
+impl RawGenerator for NemesisMix {
+    type Item = NemesisType;
+    fn gen(&mut self) -> Self::Item {
+        let mut pick = rand::thread_rng().gen_range(0..self.total);
+        for (weight, template) in self.templates.iter_mut() {
+            if pick < *weight as u64 {
+                return template();
+            }
+            pick -= *weight as u64;
+        }
+        unreachable!("the pick must be less than the total weight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[madsim::test]
+    async fn test_nemesis_mix_weights() {
+        let mut mix = NemesisGeneratorBuilder::new(Duration::from_secs(1))
+            .fault(3, NemesisType::PartitionHalves)
+            .fault(0, NemesisType::PartitionRandomNode)
+            .fault_with(1, || {
+                NemesisType::Kill(HashSet::from([rand::thread_rng().gen_range(0..3)]))
+            })
+            .build();
+        let samples = mix.gen_n(4000);
+        let halves = samples
+            .iter()
+            .filter(|n| **n == NemesisType::PartitionHalves)
+            .count();
+        assert!((2800..3200).contains(&halves), "{} halves", halves);
+        assert!(samples
+            .iter()
+            .all(|n| !matches!(n, NemesisType::PartitionRandomNode)));
+        assert!(matches!(mix.delay(), DelayStrategy::Random(t) if t == Duration::from_secs(1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_nemesis_mix() {
+        NemesisGeneratorBuilder::new(Duration::from_secs(1))
+            .fault(0, NemesisType::PartitionHalves)
+            .build();
+    }
+}
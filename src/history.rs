@@ -1,16 +1,158 @@
 use std::{
-    ops::{Deref, DerefMut},
-    sync::Arc,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    ops::{Deref, DerefMut, RangeBounds},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError, TryLockError,
+    },
+    time::Duration,
 };
 
-use madsim::time;
-use serde::{Deserialize, Serialize};
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
 use crate::{
-    generator::Global,
-    op::{Op, OpFunctionType},
+    client::{CAS_MISMATCH, FAULT_INTERVALS_FILE},
+    convert::{
+        edn::{parse_edn, to_edn, Keyword},
+        elle::MicroOp,
+    },
+    nemesis::{active::FaultInterval, NemesisRecord, SerializableNemesisType, ServerId},
+    op::{InvalidOp, Op, OpOrNemesisFuncType, OpTags},
 };
-pub type ErrorType = Vec<String>;
+pub type ErrorType = OpError;
+
+/// The error of a failed or indeterminate op, classified so the checkers and
+/// the stats can group the errors by class.
+///
+/// It's serialized as a keyword, e.g. `:timeout`, or a vector of a keyword and
+/// the detail, e.g. `[:node-down 1]` and `[:custom "message"]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OpError {
+    /// The op timed out, so it may or may not take effect.
+    Timeout,
+    /// The cluster cannot serve the op, e.g. there is no leader or the
+    /// connection is refused.
+    Unavailable,
+    /// The op conflicts with others, e.g. a cas mismatch or an aborted txn.
+    Conflict,
+    /// The node the op was sent to is down.
+    NodeDown(ServerId),
+    Custom(String),
+}
+
+impl OpError {
+    /// Classify the error message of a cluster client, by the common words of
+    /// the messages of each class. The unknown messages are custom errors.
+    pub fn classify(err: &str) -> Self {
+        let lower = err.to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| lower.contains(w));
+        if has(&["timed out", "timeout", "deadline exceeded"]) {
+            OpError::Timeout
+        } else if has(&[CAS_MISMATCH, "conflict", "abort"]) {
+            OpError::Conflict
+        } else if has(&[
+            "unavailable",
+            "connection refused",
+            "connection reset",
+            "broken pipe",
+            "not leader",
+            "no leader",
+        ]) {
+            OpError::Unavailable
+        } else {
+            OpError::Custom(err.to_string())
+        }
+    }
+
+    /// The name of the class, e.g. `node-down`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OpError::Timeout => "timeout",
+            OpError::Unavailable => "unavailable",
+            OpError::Conflict => "conflict",
+            OpError::NodeDown(_) => "node-down",
+            OpError::Custom(_) => "custom",
+        }
+    }
+}
+
+impl From<String> for OpError {
+    fn from(err: String) -> Self {
+        Self::classify(&err)
+    }
+}
+
+impl From<&str> for OpError {
+    fn from(err: &str) -> Self {
+        Self::classify(err)
+    }
+}
+
+impl std::fmt::Display for OpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpError::NodeDown(node) => write!(f, "node {} is down", node),
+            OpError::Custom(err) => f.write_str(err),
+            err => f.write_str(err.kind()),
+        }
+    }
+}
+
+impl std::error::Error for OpError {}
+
+impl Serialize for OpError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let kind = Keyword(self.kind());
+        match self {
+            OpError::NodeDown(node) => (kind, node).serialize(serializer),
+            OpError::Custom(err) => (kind, err).serialize(serializer),
+            _ => kind.serialize(serializer),
+        }
+    }
+}
+
+/// Also reads the errors of other shapes as custom errors, e.g. the vectors
+/// of strings written by older versions, which are joined by `: `.
+impl<'de> Deserialize<'de> for OpError {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let unit = |kind: &str| match kind {
+            "timeout" => Some(OpError::Timeout),
+            "unavailable" => Some(OpError::Unavailable),
+            "conflict" => Some(OpError::Conflict),
+            _ => None,
+        };
+        Ok(match value {
+            Value::String(s) => unit(&s).unwrap_or(OpError::Custom(s)),
+            Value::Array(items) => match &items[..] {
+                [Value::String(kind)] if unit(kind).is_some() => unit(kind).unwrap(),
+                [Value::String(kind), Value::Number(node)] if kind == "node-down" => {
+                    match node.as_u64() {
+                        Some(node) => OpError::NodeDown(node),
+                        None => OpError::Custom(Value::Array(items).to_string()),
+                    }
+                }
+                [Value::String(kind), Value::String(err)] if kind == "custom" => {
+                    OpError::Custom(err.clone())
+                }
+                _ if items.iter().all(Value::is_string) => OpError::Custom(
+                    items
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<Vec<_>>()
+                        .join(": "),
+                ),
+                _ => OpError::Custom(Value::Array(items).to_string()),
+            },
+            value => OpError::Custom(value.to_string()),
+        })
+    }
+}
 
 /// This struct is used to serialize the *final* history structure to json, and
 /// parse to Clojure's history data structure.
@@ -18,18 +160,179 @@ pub type ErrorType = Vec<String>;
 /// We only need to serialize the history, but here implements the Deserialize
 /// trait as well.
 ///
-/// FIXME: The deserialization in clojure site will ignore the `:` symbol, that
-/// causes the unknown check result in checker.
+/// It's passed to the clojure side as EDN by [`crate::convert::edn`], so the
+/// type, the function and the micro-ops are keywords, as jepsen expects.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct SerializableHistory<F = OpFunctionType, ERR = ErrorType> {
+pub struct SerializableHistory<F = OpOrNemesisFuncType, ERR = ErrorType> {
     pub index: u64,
     #[serde(rename = "type")]
     pub type_: HistoryType,
     pub f: F,
-    pub value: Op,
+    pub value: HistoryValue,
     pub time: u64,
-    pub process: u64,
+    pub process: HistoryProcess,
     pub error: Option<ERR>,
+    /// The metadata of the attempts of a completed op, recorded by the
+    /// client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<OpMeta>,
+    /// The tags of the op set by the generator, see [`OpTags`].
+    #[serde(default, skip_serializing_if = "OpTags::is_empty")]
+    pub tags: OpTags,
+}
+
+/// A [`SerializableHistory`] without the tags, as sent to elle.
+#[derive(Serialize)]
+struct UntaggedHistory<'a, F, ERR> {
+    index: u64,
+    #[serde(rename = "type")]
+    type_: &'a HistoryType,
+    f: &'a F,
+    value: &'a HistoryValue,
+    time: u64,
+    process: HistoryProcess,
+    error: &'a Option<ERR>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: &'a Option<OpMeta>,
+}
+
+/// How an op was attempted, recorded in the completion of the op.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OpMeta {
+    /// The node the op was sent to.
+    pub node: ServerId,
+    /// The number of retries, `0` if the op was attempted once.
+    pub retries: usize,
+    /// The time from sending the op to its completion, in nanoseconds.
+    pub latency: u64,
+}
+
+/// The value of a history item. An op for the client process, and a
+/// [`NemesisValue`] for the nemesis process.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum HistoryValue {
+    Op(Op),
+    /// A txn of raw micro-ops, e.g. `[[:append 1 2] [:r 1 [1 2]]]` of
+    /// `elle.list-append`. Every micro-op is an [`Op`], so the deserialized
+    /// txns are [`HistoryValue::Op`]s.
+    Mops(Vec<MicroOp>),
+    Fault(NemesisValue),
+    /// A bare description of the nemesis, as written by older versions.
+    Nemesis(String),
+    /// Any other value, e.g. of the ops outside the model of [`Op`] in a
+    /// history of jepsen.
+    Other(Value),
+}
+
+/// The value of a nemesis history item. A fault and the heal resolving it have
+/// the same servers and links, so the fault windows can be reconstructed from
+/// the history.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NemesisValue {
+    /// The description of the nemesis or the recovered record.
+    pub nemesis: String,
+    /// The affected servers, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<ServerId>,
+    /// The clogged links, in `(src, dst)` form.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<(ServerId, ServerId)>,
+}
+
+impl NemesisValue {
+    pub fn new(nemesis: impl Into<String>) -> Self {
+        Self {
+            nemesis: nemesis.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the affected servers.
+    pub fn with_servers(mut self, servers: impl IntoIterator<Item = ServerId>) -> Self {
+        self.servers = servers.into_iter().collect();
+        self.servers.sort_unstable();
+        self.servers.dedup();
+        self
+    }
+
+    /// Take the servers and links affected by the record.
+    pub fn with_record(mut self, record: &NemesisRecord) -> Self {
+        self.links = record.links().to_vec();
+        self.with_servers(record.servers())
+    }
+}
+
+impl From<&NemesisRecord> for NemesisValue {
+    fn from(record: &NemesisRecord) -> Self {
+        Self::new(format!("{:?}", record)).with_record(record)
+    }
+}
+
+impl From<Op> for HistoryValue {
+    fn from(op: Op) -> Self {
+        Self::Op(op)
+    }
+}
+
+impl HistoryValue {
+    /// The keys the op accesses, sorted and deduplicated, see [`Op::keys`].
+    /// Empty for the values other than ops.
+    pub fn keys(&self) -> Vec<u64> {
+        match self {
+            HistoryValue::Op(op) => op.keys(),
+            HistoryValue::Mops(mops) => {
+                let mut keys: Vec<_> = mops.iter().map(MicroOp::key).collect();
+                keys.sort_unstable();
+                keys.dedup();
+                keys
+            }
+            _ => vec![],
+        }
+    }
+}
+
+impl From<Vec<MicroOp>> for HistoryValue {
+    fn from(mops: Vec<MicroOp>) -> Self {
+        Self::Mops(mops)
+    }
+}
+
+/// The process of a history item. It's the process number for client
+/// processes (see [`Global::process`](crate::generator::Global::process)), and `nemesis` for the nemesis process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistoryProcess {
+    Gen(u64),
+    Nemesis,
+}
+
+impl<'de> Deserialize<'de> for HistoryProcess {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        match value {
+            Value::Number(n) => n
+                .as_u64()
+                .map(HistoryProcess::Gen)
+                .ok_or_else(|| serde::de::Error::custom("invalid process id")),
+            Value::String(s) if s == "nemesis" => Ok(HistoryProcess::Nemesis),
+            _ => Err(serde::de::Error::custom("invalid process")),
+        }
+    }
+}
+
+impl Serialize for HistoryProcess {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            HistoryProcess::Gen(id) => serializer.serialize_u64(*id),
+            HistoryProcess::Nemesis => Keyword("nemesis").serialize(serializer),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -43,7 +346,7 @@ pub enum HistoryType {
 
 /// A list of Serializable history
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SerializableHistoryList<F = OpFunctionType, ERR = ErrorType>(
+pub struct SerializableHistoryList<F = OpOrNemesisFuncType, ERR = ErrorType>(
     pub Vec<SerializableHistory<F, ERR>>,
 );
 
@@ -71,67 +374,783 @@ impl<F: PartialEq, ERR: PartialEq> PartialEq for SerializableHistoryList<F, ERR>
     }
 }
 
-impl<ERR: Send> SerializableHistoryList<OpFunctionType, ERR> {
-    /// Get the current timestamp.
-    fn timestamp(&self, global: &Arc<Global<Op, ERR>>) -> u64 {
-        time::Instant::now()
-            .duration_since(global.start_time)
-            .as_nanos() as u64
-    }
-    /// Push an invoke history to the history list.
-    pub fn push_invoke(&mut self, global: &Arc<Global<Op, ERR>>, process: u64, value: Op) {
-        let f: OpFunctionType = (&value).into();
-        let item = SerializableHistory {
-            index: self.0.len() as u64,
-            type_: HistoryType::Invoke,
-            f,
-            value,
-            time: self.timestamp(global),
-            process,
-            error: None,
+impl<ERR> SerializableHistoryList<OpOrNemesisFuncType, ERR> {
+    /// Whether the history has multi-key reads to expand by
+    /// [`SerializableHistoryList::expand_reads`].
+    pub fn has_multi_key_reads(&self) -> bool {
+        self.0.iter().any(|item| {
+            matches!(
+                item.value,
+                HistoryValue::Op(Op::BatchRead(_) | Op::ScanRange(..))
+            )
+        })
+    }
+
+    /// Expand the multi-key reads to txns of single reads by
+    /// [`Op::expand_reads`], so elle can check them. Fails on a scan too wide
+    /// to expand.
+    pub fn expand_reads(self) -> Result<Self, InvalidOp> {
+        self.0
+            .into_iter()
+            .map(|mut item| {
+                if let HistoryValue::Op(op @ (Op::BatchRead(_) | Op::ScanRange(..))) = item.value {
+                    let op = op.expand_reads()?;
+                    item.f = (&op).into();
+                    item.value = op.into();
+                }
+                Ok(item)
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// An invoke and its completion by the same process.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OpPair<'a, F = OpOrNemesisFuncType, ERR = ErrorType> {
+    pub invoke: &'a SerializableHistory<F, ERR>,
+    /// The `:ok`, `:fail` or `:info` completion, `None` if the op never
+    /// completed, e.g. the history ends before it.
+    pub completion: Option<&'a SerializableHistory<F, ERR>>,
+    /// The time from the invoke to the completion, in nanoseconds.
+    pub latency: Option<u64>,
+}
+
+impl<F, ERR> SerializableHistoryList<F, ERR> {
+    /// Pair every invoke with the next completion of its process, in the order
+    /// of the invokes. A completion without an invoke is skipped, and so are
+    /// the nemesis items.
+    pub fn pairs(&self) -> Vec<OpPair<'_, F, ERR>> {
+        let mut pairs: Vec<OpPair<'_, F, ERR>> = vec![];
+        // the pair of the pending invoke of every process
+        let mut pending = HashMap::new();
+        for item in self.0.iter() {
+            if item.process == HistoryProcess::Nemesis {
+                continue;
+            }
+            match item.type_ {
+                HistoryType::Invoke => {
+                    pending.insert(item.process, pairs.len());
+                    pairs.push(OpPair {
+                        invoke: item,
+                        completion: None,
+                        latency: None,
+                    });
+                }
+                _ => match pending.remove(&item.process) {
+                    Some(i) => {
+                        let pair = &mut pairs[i];
+                        pair.completion = Some(item);
+                        pair.latency = Some(item.time.saturating_sub(pair.invoke.time));
+                    }
+                    None => warn!("completion {} has no invoke", item.index),
+                },
+            }
+        }
+        pairs
+    }
+
+    /// The history without the [`OpTags`], to be serialized for elle.
+    pub fn untagged(&self) -> impl Serialize + '_
+    where
+        F: Serialize,
+        ERR: Serialize,
+    {
+        untagged(&self.0)
+    }
+}
+
+/// The items without the [`OpTags`], e.g. a chunk of a history, to be
+/// serialized for elle.
+pub(crate) fn untagged<F: Serialize, ERR: Serialize>(
+    items: &[SerializableHistory<F, ERR>],
+) -> impl Serialize + '_ {
+    items
+        .iter()
+        .map(|item| UntaggedHistory {
+            index: item.index,
+            type_: &item.type_,
+            f: &item.f,
+            value: &item.value,
+            time: item.time,
+            process: item.process,
+            error: &item.error,
+            meta: &item.meta,
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Sub-histories. The items are cloned into a new list and re-indexed from 0,
+/// so it can be checked or exported on its own.
+impl<F: Clone, ERR: Clone> SerializableHistoryList<F, ERR> {
+    /// Keep the items by the predicate.
+    pub fn filter(&self, mut keep: impl FnMut(&SerializableHistory<F, ERR>) -> bool) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|item| keep(item))
+                .cloned()
+                .enumerate()
+                .map(|(i, mut item)| {
+                    item.index = i as u64;
+                    item
+                })
+                .collect(),
+        )
+    }
+
+    /// Keep the items of the pairs selected by the predicate.
+    fn filter_pairs(&self, keep: impl Fn(&OpPair<'_, F, ERR>) -> bool) -> Self {
+        let kept: HashSet<_> = self
+            .pairs()
+            .iter()
+            .filter(|pair| keep(pair))
+            .flat_map(|pair| [Some(pair.invoke), pair.completion])
+            .flatten()
+            .map(|item| item.index)
+            .collect();
+        self.filter(|item| kept.contains(&item.index))
+    }
+
+    /// Keep the ops of the process.
+    pub fn filter_by_process(&self, process: u64) -> Self {
+        self.filter(|item| item.process == HistoryProcess::Gen(process))
+    }
+
+    /// Keep the ops accessing the key, by [`HistoryValue::keys`] of the invoke
+    /// or the completion, so a range scan is kept with its invoke.
+    pub fn filter_by_key(&self, key: u64) -> Self {
+        let accesses = |item: &SerializableHistory<F, ERR>| item.value.keys().contains(&key);
+        self.filter_pairs(|pair| accesses(pair.invoke) || pair.completion.is_some_and(accesses))
+    }
+
+    /// Split the history by the keys, see [`Self::filter_by_key`]. An op
+    /// accessing several keys is in the history of each of them.
+    pub fn split_by_key(&self) -> BTreeMap<u64, Self> {
+        let keys: BTreeSet<_> = self.0.iter().flat_map(|item| item.value.keys()).collect();
+        keys.into_iter()
+            .map(|key| (key, self.filter_by_key(key)))
+            .collect()
+    }
+
+    /// Keep the ops accessing the table, see [`Key`](crate::op::Key), to
+    /// check the tables of a run on their own.
+    pub fn filter_by_table(&self, table: u16) -> Self {
+        let accesses = |item: &SerializableHistory<F, ERR>| {
+            item.value
+                .keys()
+                .into_iter()
+                .any(|k| crate::op::Key::unpack(k).table == table)
         };
-        self.0.push(item);
+        self.filter_pairs(|pair| accesses(pair.invoke) || pair.completion.is_some_and(accesses))
     }
 
-    /// Push a result to the history list.
-    pub fn push_result(
-        &mut self,
-        global: &Arc<Global<Op, ERR>>,
-        process: u64,
-        result_type: HistoryType,
-        value: Op,
-        error: Option<ERR>,
-    ) {
-        assert!(
-            (result_type == HistoryType::Ok) == (error.is_none()),
-            "result type mismatch"
-        );
-        let f: OpFunctionType = (&value).into();
-        let item = SerializableHistory {
-            index: self.0.len() as u64,
-            type_: result_type,
-            f,
-            value,
-            time: self.timestamp(global),
-            process,
-            error,
+    /// Keep the ops tagged with the key and the value by the generator, see
+    /// [`OpTags`].
+    pub fn filter_by_tag(&self, key: &str, value: &str) -> Self {
+        self.filter_pairs(|pair| pair.invoke.tags.get(key).is_some_and(|v| v == value))
+    }
+
+    /// Keep the items in the time range, in nanoseconds since the start. An
+    /// op is cut in half if only one of its invoke and completion is in the
+    /// range.
+    pub fn between(&self, times: impl RangeBounds<u64>) -> Self {
+        self.filter(|item| times.contains(&item.time))
+    }
+
+    /// Remove the nemesis items.
+    pub fn without_nemesis(&self) -> Self {
+        self.filter(|item| item.process != HistoryProcess::Nemesis)
+    }
+
+    /// Keep the `:ok` completions and their invokes.
+    pub fn ok_only(&self) -> Self {
+        self.filter_pairs(|pair| {
+            pair.completion
+                .is_some_and(|item| item.type_ == HistoryType::Ok)
+        })
+    }
+}
+
+impl<F, ERR> SerializableHistoryList<F, ERR> {
+    /// Merge the histories of separate harnesses, e.g. load generators in
+    /// several regions, to check them as one history. The times should be
+    /// since the same instant, up to the clock skew between the harnesses.
+    ///
+    /// The items are sorted by time and re-indexed, and the processes of
+    /// every history are numbered after the processes of the previous ones.
+    /// To tolerate the skew, the invokes are moved `clock_skew_tolerance`
+    /// earlier and the completions later, so the ops less than the tolerance
+    /// apart are concurrent; the order of the items of a process is kept. The
+    /// times of the items are unchanged.
+    pub fn merge(
+        histories: impl IntoIterator<Item = Self>,
+        clock_skew_tolerance: Duration,
+    ) -> Self {
+        let tolerance = clock_skew_tolerance.as_nanos() as u64;
+        // the items with the times they are sorted by
+        let mut items = vec![];
+        let mut offset = 0;
+        for history in histories {
+            let mut last = HashMap::new();
+            let mut processes = 0;
+            for mut item in history.0 {
+                let time = match (item.process, &item.type_) {
+                    (HistoryProcess::Nemesis, _) => item.time,
+                    (_, HistoryType::Invoke) => item.time.saturating_sub(tolerance),
+                    _ => item.time.saturating_add(tolerance),
+                };
+                let last = last.entry(item.process).or_insert(0);
+                *last = time.max(*last);
+                if let HistoryProcess::Gen(process) = &mut item.process {
+                    processes = processes.max(*process + 1);
+                    *process += offset;
+                }
+                items.push((*last, item));
+            }
+            offset += processes;
+        }
+        // stable, so the items of a process stay in order
+        items.sort_by_key(|(time, _)| *time);
+        Self(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, (_, mut item))| {
+                    item.index = i as u64;
+                    item
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<ERR: Serialize> SerializableHistoryList<OpOrNemesisFuncType, ERR> {
+    /// A minimal copy of the history to attach to a bug report, without the
+    /// data of the database:
+    ///
+    /// - the keys and the values are renumbered densely from 0, in their
+    ///   order, so the range scans cover the same keys;
+    /// - every distinct custom error becomes a code, e.g. `[:custom
+    ///   "error-0"]`, and the other [`OpError`]s are kept;
+    /// - the [`OpMeta`], the [`OpTags`] and the values outside the model of [`Op`] are
+    ///   dropped.
+    ///
+    /// The anomalies of the history are kept, as the renumbering is a
+    /// bijection.
+    pub fn compact(&self) -> SerializableHistoryList {
+        let (mut keys, mut values) = (BTreeMap::new(), BTreeMap::new());
+        for item in self.0.iter() {
+            let mut key = |k| *keys.entry(k).or_insert(0);
+            let mut value = |v| *values.entry(v).or_insert(0);
+            match &item.value {
+                HistoryValue::Op(op) => {
+                    op.map(&mut key, &mut value);
+                }
+                HistoryValue::Mops(mops) => {
+                    for mop in mops {
+                        mop.map(&mut key, &mut value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (i, rank) in keys.values_mut().enumerate() {
+            *rank = i as u64;
+        }
+        for (i, rank) in values.values_mut().enumerate() {
+            *rank = i as u64;
+        }
+        let mut errors = HashMap::new();
+        SerializableHistoryList(
+            self.0
+                .iter()
+                .map(|item| SerializableHistory {
+                    index: item.index,
+                    type_: item.type_.clone(),
+                    f: item.f,
+                    value: match &item.value {
+                        HistoryValue::Op(op) => {
+                            HistoryValue::Op(op.map(&mut |k| keys[&k], &mut |v| values[&v]))
+                        }
+                        HistoryValue::Mops(mops) => HistoryValue::Mops(
+                            mops.iter()
+                                .map(|mop| mop.map(&mut |k| keys[&k], &mut |v| values[&v]))
+                                .collect(),
+                        ),
+                        HistoryValue::Other(_) => HistoryValue::Other(Value::Null),
+                        value => value.clone(),
+                    },
+                    time: item.time,
+                    process: item.process,
+                    error: item.error.as_ref().map(|error| {
+                        let error = serde_json::to_value(error)
+                            .and_then(serde_json::from_value)
+                            .unwrap_or_else(|err| OpError::Custom(err.to_string()));
+                        match error {
+                            OpError::Custom(error) => {
+                                let code = errors.len();
+                                let code = errors.entry(error).or_insert(code);
+                                OpError::Custom(format!("error-{}", code))
+                            }
+                            error => error,
+                        }
+                    }),
+                    meta: None,
+                    tags: OpTags::new(),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<F: for<'de> Deserialize<'de>, ERR: for<'de> Deserialize<'de>> SerializableHistoryList<F, ERR> {
+    /// Read a history written by [`HistoryWriter`] as JSON lines, e.g. to
+    /// check it offline. A truncated last line, left by a crash of the
+    /// harness, is ignored.
+    pub fn from_jsonl(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let lines: Vec<_> = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<Result<_, _>>()?;
+        let mut items = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(item) => items.push(item),
+                Err(err) if i + 1 == lines.len() && err.is_eof() => {
+                    warn!("ignore the truncated last history line: {}", line)
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(Self(items))
+    }
+}
+
+impl SerializableHistoryList<String, Value> {
+    /// Load the `history.edn` of a jepsen store directory, e.g.
+    /// `store/<test>/<time>` or `store/latest`, or the file itself. The `f`
+    /// of an item is the name of its keyword and the error is kept as is, so
+    /// the nemesis items and the ops outside the model of [`Op`] are loaded
+    /// as well, with their values as [`HistoryValue::Other`] unless they are
+    /// keywords or strings.
+    pub fn from_jepsen_store(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let mut path = path.as_ref().to_path_buf();
+        if path.is_dir() {
+            path.push("history.edn");
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))?;
+        let mut forms = parse_edn(&text)?;
+        // a vector of the ops, or an op per line
+        if let [Value::Array(_)] = forms.as_slice() {
+            let Some(Value::Array(ops)) = forms.pop() else {
+                unreachable!()
+            };
+            forms = ops;
+        }
+        let items = forms
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut op)| {
+                if let Value::Object(map) = &mut op {
+                    map.entry("index").or_insert(i.into());
+                    map.entry("time").or_insert(0.into());
+                }
+                serde_json::from_value(op)
+                    .map_err(|err| anyhow::anyhow!("invalid history item {}: {}", i, err))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(items))
+    }
+}
+
+/// The item of a fault, its function, and the item of its heal.
+pub(crate) type FaultWindowItem<'a, ERR> = (
+    &'a SerializableHistory<OpOrNemesisFuncType, ERR>,
+    SerializableNemesisType,
+    Option<&'a SerializableHistory<OpOrNemesisFuncType, ERR>>,
+);
+
+impl<ERR> SerializableHistoryList<OpOrNemesisFuncType, ERR> {
+    /// Reconstruct the intervals during which every fault was active from the
+    /// nemesis items, like the intervals recorded by the client during the
+    /// run. A fault is healed by the next heal of its kind with the same
+    /// servers and links, see [`NemesisValue`], and is still active at the end
+    /// if there is none. The failed nemeses are skipped.
+    pub fn fault_windows(&self) -> Vec<FaultInterval> {
+        self.fault_window_items()
+            .into_iter()
+            .map(|(fault, f, heal)| FaultInterval {
+                nemesis: match &fault.value {
+                    HistoryValue::Fault(v) => v.nemesis.clone(),
+                    HistoryValue::Nemesis(s) => s.clone(),
+                    _ => unreachable!("only the nemesis values are faults"),
+                },
+                f,
+                start: fault.time,
+                end: heal.map(|heal| heal.time),
+            })
+            .collect()
+    }
+
+    /// The item of the fault and the item of the heal of every fault window,
+    /// see [`Self::fault_windows`].
+    pub(crate) fn fault_window_items(&self) -> Vec<FaultWindowItem<'_, ERR>> {
+        let mut windows: Vec<FaultWindowItem<'_, ERR>> = vec![];
+        // the index of the window, the heal, and the servers and links
+        let mut active: Vec<(usize, SerializableNemesisType, &[ServerId], &[_])> = vec![];
+        for item in self.0.iter() {
+            let OpOrNemesisFuncType::Nemesis(f) = item.f else {
+                continue;
+            };
+            if item.error.is_some() {
+                continue;
+            }
+            let (servers, links) = match &item.value {
+                HistoryValue::Fault(v) => (&v.servers[..], &v.links[..]),
+                HistoryValue::Nemesis(_) => (&[][..], &[][..]),
+                _ => continue,
+            };
+            let heal = active
+                .iter()
+                .position(|(_, heal, s, l)| *heal == f && *s == servers && *l == links);
+            if let Some(pos) = heal {
+                let (index, ..) = active.remove(pos);
+                windows[index].2 = Some(item);
+            } else if let Some(heal) = f.healed_by() {
+                active.push((windows.len(), heal, servers, links));
+                windows.push((item, f, None));
+            }
+        }
+        windows
+    }
+
+    /// Save the [fault windows](Self::fault_windows) to the directory as
+    /// JSON, in the file and form of the intervals saved by the client.
+    pub fn save_fault_windows(&self, dir: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        let json = serde_json::to_vec_pretty(&self.fault_windows())?;
+        std::fs::write(dir.as_ref().join(FAULT_INTERVALS_FILE), json)?;
+        Ok(())
+    }
+}
+
+/// The number of the shards of a [`HistoryCollector`].
+const HISTORY_SHARDS: usize = 16;
+
+type HistoryItem<ERR> = SerializableHistory<OpOrNemesisFuncType, ERR>;
+
+/// The history of a run, collected from the concurrent pushes of the
+/// generators without serializing them on one lock. A pushed item gets the
+/// next index from a counter and is buffered in the shard of its process, and
+/// the buffered items are merged into the history in the order of their
+/// indices by the pusher that gets the lock of the history without waiting,
+/// or else by the next push or read. See [`Global::push_invoke`](crate::generator::Global::push_invoke).
+///
+/// An item is merged only after the items of lower indices, whose pushes may
+/// still be going on, and its time is raised to the time of the item before
+/// it, so the times are in the order of the indices as with one lock.
+pub struct HistoryCollector<ERR = ErrorType> {
+    /// The index of the next pushed item.
+    next: AtomicU64,
+    /// The pushed items not merged yet, by the shard of their process.
+    shards: Vec<Mutex<Vec<HistoryItem<ERR>>>>,
+    pub(crate) merged: Mutex<MergedHistory<ERR>>,
+}
+
+/// The merged part of a [`HistoryCollector`].
+pub(crate) struct MergedHistory<ERR> {
+    /// The merged items in memory, after the spilled ones.
+    pub(crate) list: SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+    /// The index of the next item to merge.
+    next: u64,
+    /// The time of the last merged item.
+    time: u64,
+    /// The buffered items waiting for the items of lower indices.
+    pending: BTreeMap<u64, HistoryItem<ERR>>,
+    /// The items taken from the shards, kept to reuse its capacity.
+    batch: Vec<HistoryItem<ERR>>,
+}
+
+impl<ERR> MergedHistory<ERR> {
+    fn append(&mut self, mut item: HistoryItem<ERR>) {
+        item.time = item.time.max(self.time);
+        self.time = item.time;
+        self.next += 1;
+        self.list.0.push(item);
+    }
+}
+
+impl<ERR> Default for HistoryCollector<ERR> {
+    fn default() -> Self {
+        Self {
+            next: AtomicU64::new(0),
+            shards: (0..HISTORY_SHARDS).map(|_| Mutex::default()).collect(),
+            merged: Mutex::new(MergedHistory {
+                list: SerializableHistoryList::default(),
+                next: 0,
+                time: 0,
+                pending: BTreeMap::new(),
+                batch: vec![],
+            }),
+        }
+    }
+}
+
+impl<ERR> HistoryCollector<ERR> {
+    /// Buffer the item with the next index, returns the index.
+    pub(crate) fn push(&self, mut item: HistoryItem<ERR>) -> u64 {
+        let shard = match item.process {
+            HistoryProcess::Gen(process) => process as usize % (HISTORY_SHARDS - 1),
+            HistoryProcess::Nemesis => HISTORY_SHARDS - 1,
         };
-        self.0.push(item);
+        let mut shard = self.shards[shard]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        item.index = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = item.index;
+        shard.push(item);
+        index
+    }
+
+    /// Merge the buffered items into the history in the order of their
+    /// indices. Returns the locked history and the number of the items
+    /// merged, which are the last ones of it, or `None` if the history is
+    /// locked and `wait` is false.
+    pub(crate) fn merge(&self, wait: bool) -> Option<(MutexGuard<'_, MergedHistory<ERR>>, usize)> {
+        let mut merged = match self.merged.try_lock() {
+            Ok(merged) => merged,
+            Err(TryLockError::Poisoned(merged)) => merged.into_inner(),
+            Err(TryLockError::WouldBlock) if wait => {
+                self.merged.lock().unwrap_or_else(PoisonError::into_inner)
+            }
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        // the shards keep their capacity, and the items in order of the
+        // indices are appended without going through `pending`
+        let history = &mut *merged;
+        let mut batch = std::mem::take(&mut history.batch);
+        for shard in &self.shards {
+            batch.append(&mut shard.lock().unwrap_or_else(PoisonError::into_inner));
+        }
+        batch.sort_unstable_by_key(|item| item.index);
+        let before = history.list.0.len();
+        for item in batch.drain(..) {
+            if item.index != history.next {
+                history.pending.insert(item.index, item);
+                continue;
+            }
+            history.append(item);
+            while let Some(item) = history.pending.remove(&history.next) {
+                history.append(item);
+            }
+        }
+        history.batch = batch;
+        let n = history.list.0.len() - before;
+        Some((merged, n))
+    }
+
+    /// Start over from the history, e.g. on resuming a run.
+    pub(crate) fn restore(&mut self, list: SerializableHistoryList<OpOrNemesisFuncType, ERR>) {
+        let merged = self
+            .merged
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        merged.next = list.0.len() as u64;
+        merged.time = list.0.last().map_or(0, |item| item.time);
+        merged.pending.clear();
+        merged.list = list;
+        *self.next.get_mut() = merged.next;
+        for shard in &mut self.shards {
+            shard
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+        }
+    }
+}
+
+/// Appends every history item to `history.jsonl`, and optionally
+/// `history.edn`, in a directory as soon as it's pushed, so the partial
+/// history survives a crash of the harness. Set it by
+/// [`JepsenClient::with_history_writer`].
+///
+/// The files are synced every `sync_every` items, when the run ends, and on
+/// drop.
+///
+/// [`JepsenClient::with_history_writer`]: crate::client::JepsenClient::with_history_writer
+#[derive(Debug)]
+pub struct HistoryWriter {
+    dir: PathBuf,
+    jsonl: BufWriter<File>,
+    /// One EDN map per line, like the `history.edn` of jepsen.
+    edn: Option<BufWriter<File>>,
+    sync_every: usize,
+    unsynced: usize,
+}
+
+impl HistoryWriter {
+    /// Create `history.jsonl` in the directory, which is created if missing.
+    pub fn create(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let jsonl = File::create(dir.as_ref().join("history.jsonl"))?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            jsonl: BufWriter::new(jsonl),
+            edn: None,
+            sync_every: 100,
+            unsynced: 0,
+        })
+    }
+
+    /// Also write `history.edn` in the directory.
+    pub fn with_edn(mut self) -> anyhow::Result<Self> {
+        let edn = File::create(self.dir.join("history.edn"))?;
+        self.edn = Some(BufWriter::new(edn));
+        Ok(self)
+    }
+
+    /// Sync the files every `n` items, 100 by default. `1` syncs every item.
+    pub fn with_sync_every(mut self, n: usize) -> Self {
+        self.sync_every = n.max(1);
+        self
+    }
+
+    /// Append an item.
+    pub fn write<F: Serialize, ERR: Serialize>(
+        &mut self,
+        item: &SerializableHistory<F, ERR>,
+    ) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.jsonl, item)?;
+        self.jsonl.write_all(b"\n")?;
+        if let Some(edn) = &mut self.edn {
+            writeln!(edn, "{}", to_edn(item)?)?;
+        }
+        self.unsynced += 1;
+        if self.unsynced >= self.sync_every {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Flush the written items and sync them to the disk.
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        self.unsynced = 0;
+        for file in std::iter::once(&mut self.jsonl).chain(self.edn.as_mut()) {
+            file.flush()?;
+            file.get_ref().sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for HistoryWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.sync() {
+            warn!("failed to sync the history: {}", err);
+        }
+    }
+}
+
+/// Bounds the items of the history kept in memory by spilling the oldest
+/// items to segment files in a directory, so the history of a long run is
+/// bounded by the disk rather than the memory. Set it by
+/// [`JepsenClient::with_history_spill`].
+///
+/// The whole history is read back segment by segment by
+/// [`Global::history_items`](crate::generator::Global::history_items).
+///
+/// [`JepsenClient::with_history_spill`]: crate::client::JepsenClient::with_history_spill
+#[derive(Debug)]
+pub struct HistorySpill {
+    dir: PathBuf,
+    max_in_memory: usize,
+    /// The segment files, in the order of the items.
+    segments: Vec<PathBuf>,
+    spilled: u64,
+}
+
+impl HistorySpill {
+    /// Keep at most `max_in_memory` items in memory, and spill the others to
+    /// the directory, which is created if missing.
+    pub fn new(dir: impl AsRef<Path>, max_in_memory: usize) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            max_in_memory: max_in_memory.max(2),
+            segments: vec![],
+            spilled: 0,
+        })
+    }
+
+    /// The number of spilled items.
+    pub fn spilled(&self) -> u64 {
+        self.spilled
+    }
+
+    /// Spill the oldest items to a new segment if there are more than the max,
+    /// keeping half of the max in memory. The items are kept if the segment
+    /// cannot be written.
+    pub(crate) fn spill<F: Serialize, ERR: Serialize>(
+        &mut self,
+        items: &mut Vec<SerializableHistory<F, ERR>>,
+    ) -> anyhow::Result<()> {
+        if items.len() <= self.max_in_memory {
+            return Ok(());
+        }
+        let n = items.len() - self.max_in_memory / 2;
+        let path = self
+            .dir
+            .join(format!("segment-{:06}.jsonl", self.segments.len()));
+        let mut out = BufWriter::new(File::create(&path)?);
+        for item in &items[..n] {
+            serde_json::to_writer(&mut out, item)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+        items.drain(..n);
+        self.segments.push(path);
+        self.spilled += n as u64;
+        Ok(())
+    }
+
+    /// Iterate the spilled items in order, reading a segment at a time.
+    pub fn iter<F, ERR>(&self) -> impl Iterator<Item = anyhow::Result<SerializableHistory<F, ERR>>>
+    where
+        F: for<'de> Deserialize<'de> + 'static,
+        ERR: for<'de> Deserialize<'de> + 'static,
+    {
+        self.segments.clone().into_iter().flat_map(|path| {
+            let lines: Box<dyn Iterator<Item = anyhow::Result<String>>> = match File::open(&path) {
+                Ok(file) => Box::new(BufReader::new(file).lines().map(|line| Ok(line?))),
+                Err(err) => Box::new(std::iter::once(Err(anyhow::anyhow!(
+                    "failed to open {}: {}",
+                    path.display(),
+                    err
+                )))),
+            };
+            lines.map(|line| Ok(serde_json::from_str(&line?)?))
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use j4rs::Instance;
+    use std::sync::Arc;
 
     use super::*;
-    use crate::{
-        read_edn,
-        utils::{print_clj, FromSerde, ToDe},
-    };
+    use crate::{generator::Global, op::OpFunctionType, txn};
 
+    #[cfg(feature = "clojure")]
     #[test]
     fn test_history_list_conversion() -> anyhow::Result<()> {
+        use j4rs::Instance;
+
+        use crate::{
+            read_edn,
+            utils::{print_clj, FromSerde, ToDe},
+        };
+
         let his_edn = read_edn(include_str!("../assets/ex_history.edn"))?;
         let res: SerializableHistoryList = his_edn.to_de()?;
         let res_json: SerializableHistoryList =
@@ -142,6 +1161,469 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_nemesis_history_serde() -> anyhow::Result<()> {
+        let json = r#"{"index":0,"type":"info","f":"bitflip-wal","value":"BitflipWal { server: 1, bits: 2 }","time":0,"process":"nemesis","error":null}"#;
+        let item: SerializableHistory = serde_json::from_str(json)?;
+        assert_eq!(
+            item.f,
+            OpOrNemesisFuncType::Nemesis(SerializableNemesisType::BitflipWal)
+        );
+        assert_eq!(item.process, HistoryProcess::Nemesis);
+        assert_eq!(serde_json::to_string(&item)?, json);
+        Ok(())
+    }
+
+    #[test]
+    fn test_op_tags() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, 1]], "time": 1, "process": 0, "error": null, "tags": {"hot-key": "true"} },
+          { "index": 1, "type": "invoke", "f": "txn", "value": [["w", 2, 1]], "time": 2, "process": 1, "error": null },
+          { "index": 2, "type": "ok", "f": "txn", "value": [["w", 1, 1]], "time": 3, "process": 0, "error": null, "tags": {"hot-key": "true"} },
+          { "index": 3, "type": "ok", "f": "txn", "value": [["w", 2, 1]], "time": 4, "process": 1, "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let hot = history.filter_by_tag("hot-key", "true");
+        assert_eq!(hot.iter().map(|i| i.time).collect::<Vec<_>>(), [1, 3]);
+        assert!(to_edn(&history)?.contains(":tags"));
+        assert!(!to_edn(&history.untagged())?.contains(":tags"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_op_meta_serde() -> anyhow::Result<()> {
+        let json = r#"{"index":1,"type":"ok","f":"txn","value":[["w",1,2]],"time":5,"process":0,"error":null,"meta":{"node":2,"retries":1,"latency":3}}"#;
+        let item: SerializableHistory = serde_json::from_str(json)?;
+        assert_eq!(
+            item.meta,
+            Some(OpMeta {
+                node: 2,
+                retries: 1,
+                latency: 3
+            })
+        );
+        assert_eq!(serde_json::to_string(&item)?, json);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nemesis_value_serde() -> anyhow::Result<()> {
+        let record = NemesisRecord::Partition(vec![(0, 1), (1, 0)]);
+        let value = NemesisValue::from(&record);
+        assert_eq!(value.links, vec![(0, 1), (1, 0)]);
+        assert!(value.servers.is_empty());
+        let json = serde_json::to_string(&HistoryValue::Fault(value.clone()))?;
+        assert_eq!(
+            serde_json::from_str::<HistoryValue>(&json)?,
+            HistoryValue::Fault(value)
+        );
+
+        // the fault and its heal have the same servers
+        let record = NemesisRecord::Kill([2, 0].into());
+        let fault = NemesisValue::new("Kill({2, 0})").with_servers([2, 0]);
+        assert_eq!(fault.servers, NemesisValue::from(&record).servers);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_reads() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "scan_range", "value": ["scan", [1, 4], null], "time": 0, "process": 0, "error": null },
+          { "index": 1, "type": "ok", "f": "scan_range", "value": ["scan", [1, 4], [[2, 5]]], "time": 1, "process": 0, "error": null },
+          { "index": 2, "type": "ok", "f": "batch_read", "value": ["batch-read", [1, 2], [null, 5]], "time": 2, "process": 1, "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let history = history.expand_reads()?;
+        let txn = OpOrNemesisFuncType::Op(OpFunctionType::Txn);
+        assert!(history.iter().all(|item| item.f == txn));
+        let values: Vec<_> = history.iter().map(|item| item.value.clone()).collect();
+        let reads = |reads: &[(u64, Option<u64>)]| {
+            HistoryValue::Op(Op::Txn(
+                reads.iter().map(|(k, v)| Op::Read(*k, *v)).collect(),
+            ))
+        };
+        assert_eq!(
+            values,
+            vec![
+                reads(&[(1, None), (2, None), (3, None)]),
+                reads(&[(1, None), (2, Some(5)), (3, None)]),
+                reads(&[(1, None), (2, Some(5))]),
+            ]
+        );
+        Ok(())
+    }
+
     // TODO: add test for the deserialization in clojure after fixing the
     // problem in the doc of [`SerializableHistory`].
+
+    #[test]
+    fn test_history_writer() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-writer-{}", std::process::id()));
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 2, 1]], "time": 3, "process": 0, "error": null },
+          { "index": 1, "type": "ok", "f": "txn", "value": [["w", 2, 1]], "time": 5, "process": 0, "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let mut writer = HistoryWriter::create(&dir)?.with_edn()?.with_sync_every(1);
+        for item in history.iter() {
+            writer.write(item)?;
+        }
+        drop(writer);
+        let read: SerializableHistoryList =
+            SerializableHistoryList::from_jsonl(dir.join("history.jsonl"))?;
+        assert_eq!(read, history);
+        let edn = std::fs::read_to_string(dir.join("history.edn"))?;
+        assert_eq!(edn.lines().count(), 2);
+
+        // the truncated last line of a crashed run is ignored
+        let jsonl = std::fs::read_to_string(dir.join("history.jsonl"))?;
+        std::fs::write(dir.join("history.jsonl"), &jsonl[..jsonl.len() - 10])?;
+        let read: SerializableHistoryList =
+            SerializableHistoryList::from_jsonl(dir.join("history.jsonl"))?;
+        assert_eq!(read.0, history.0[..1]);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_jepsen_store() -> anyhow::Result<()> {
+        let history = SerializableHistoryList::from_jepsen_store("assets/jepsen_store")?.0;
+        assert_eq!(history.len(), 8);
+        assert_eq!(history[0].f, "txn");
+        assert_eq!(
+            history[1].value,
+            HistoryValue::Op(Op::Txn(vec![Op::Write(1, 1), Op::Read(2, None)]))
+        );
+        assert_eq!(history[2].process, HistoryProcess::Nemesis);
+        assert_eq!(history[2].f, "start-partition");
+        assert_eq!(history[2].value, HistoryValue::Nemesis("majority".into()));
+        assert!(matches!(history[3].value, HistoryValue::Other(_)));
+        assert_eq!(history[4].f, "add");
+        assert_eq!(history[4].value, HistoryValue::Other(1.into()));
+        assert_eq!(
+            history[6].error,
+            Some(serde_json::json!([
+                "duplicate-key",
+                "etcdserver: duplicate key"
+            ]))
+        );
+        assert_eq!(history[7].index, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pairs() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 2, 1]], "time": 3, "process": 0, "error": null },
+          { "index": 1, "type": "invoke", "f": "txn", "value": [["r", 2, null]], "time": 4, "process": 1, "error": null },
+          { "index": 2, "type": "info", "f": "heal", "value": "Heal", "time": 5, "process": "nemesis", "error": null },
+          { "index": 3, "type": "ok", "f": "txn", "value": [["w", 2, 1]], "time": 8, "process": 0, "error": null },
+          { "index": 4, "type": "fail", "f": "txn", "value": [["w", 2, 1]], "time": 9, "process": 2, "error": ["no invoke"] }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let pairs = history.pairs();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].invoke.index, 0);
+        assert_eq!(pairs[0].completion.map(|c| c.index), Some(3));
+        assert_eq!(pairs[0].latency, Some(5));
+        assert_eq!(pairs[1].invoke.index, 1);
+        assert_eq!(pairs[1].completion, None);
+        assert_eq!(pairs[1].latency, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sub_histories() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, 1]], "time": 1, "process": 0, "error": null },
+          { "index": 1, "type": "invoke", "f": "scan_range", "value": ["scan", [0, 5], null], "time": 2, "process": 1, "error": null },
+          { "index": 2, "type": "info", "f": "heal", "value": "Heal", "time": 3, "process": "nemesis", "error": null },
+          { "index": 3, "type": "ok", "f": "txn", "value": [["w", 1, 1]], "time": 4, "process": 0, "error": null },
+          { "index": 4, "type": "fail", "f": "scan_range", "value": ["scan", [0, 5], [[2, 1]]], "time": 5, "process": 1, "error": ["x"] }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let times = |h: SerializableHistoryList| h.iter().map(|i| i.time).collect::<Vec<_>>();
+        assert_eq!(times(history.filter_by_process(1)), [2, 5]);
+        assert_eq!(times(history.filter_by_key(2)), [2, 5]);
+        assert_eq!(times(history.filter_by_key(1)), [1, 4]);
+        assert_eq!(times(history.filter_by_table(0)), [1, 2, 4, 5]);
+        let split = history.split_by_key();
+        assert_eq!(split.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(times(split[&2].clone()), [2, 5]);
+        assert!(history.filter_by_table(1).is_empty());
+        assert_eq!(times(history.between(2..4)), [2, 3]);
+        assert_eq!(times(history.without_nemesis()), [1, 2, 4, 5]);
+        assert_eq!(times(history.ok_only()), [1, 4]);
+        // re-indexed
+        let sub = history.without_nemesis().between(3..);
+        assert_eq!(sub.iter().map(|i| i.index).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(sub[0].time, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_op_error() -> anyhow::Result<()> {
+        assert_eq!(OpError::from("timed out after 1s"), OpError::Timeout);
+        assert_eq!(OpError::from(CAS_MISMATCH), OpError::Conflict);
+        assert_eq!(OpError::from("Connection refused"), OpError::Unavailable);
+        assert_eq!(
+            OpError::from("bad key"),
+            OpError::Custom("bad key".to_string())
+        );
+
+        let errors = [
+            OpError::Timeout,
+            OpError::NodeDown(2),
+            OpError::Custom("bad key".to_string()),
+        ];
+        let json = serde_json::to_string(&errors)?;
+        assert_eq!(json, r#"["timeout",["node-down",2],["custom","bad key"]]"#);
+        assert_eq!(serde_json::from_str::<Vec<OpError>>(&json)?, errors);
+        assert_eq!(
+            to_edn(&errors)?,
+            r#"[:timeout [:node-down 2] [:custom "bad key"]]"#
+        );
+        // the errors of older versions
+        let old: OpError =
+            serde_json::from_str(r#"["duplicate-key", "etcdserver: duplicate key"]"#)?;
+        assert_eq!(
+            old,
+            OpError::Custom("duplicate-key: etcdserver: duplicate key".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_append_history() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["append", 1, 3], ["r", 2, null]], "time": 1, "process": 0, "error": null },
+          { "index": 1, "type": "ok", "f": "txn", "value": [["append", 1, 3], ["r", 2, [1, 2]]], "time": 2, "process": 0, "error": null },
+          { "index": 2, "type": "ok", "f": "txn", "value": [["w", 1, 3], ["r", 2, 4]], "time": 3, "process": 0, "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        assert_eq!(
+            history[0].value,
+            HistoryValue::Op(txn![Op::Append(1, 3), Op::Read(2, None)])
+        );
+        assert_eq!(
+            history[1].value,
+            HistoryValue::Op(txn![Op::Append(1, 3), Op::ReadList(2, Some(vec![1, 2]))])
+        );
+        assert!(matches!(history[2].value, HistoryValue::Op(_)));
+        assert_eq!(history[1].value.keys(), [1, 2]);
+        assert_eq!(
+            to_edn(&history[1])?,
+            "{:index 1, :type :ok, :f :txn, :value [[:append 1 3] [:r 2 [1 2]]], :time 2, :process 0, :error nil}"
+        );
+        assert_eq!(
+            serde_json::from_str::<SerializableHistoryList>(&serde_json::to_string(&history)?)?,
+            history
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> anyhow::Result<()> {
+        let history = |start: u64, f: &str| -> anyhow::Result<SerializableHistoryList> {
+            let json = format!(
+                r#"[
+                  {{ "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, {start}]], "time": {start}, "process": 0, "error": null }},
+                  {{ "index": 1, "type": "info", "f": "{f}", "value": "{f}", "time": {}, "process": "nemesis", "error": null }},
+                  {{ "index": 2, "type": "ok", "f": "txn", "value": [["w", 1, {start}]], "time": {}, "process": 0, "error": null }}
+                ]"#,
+                start + 1,
+                start + 10,
+            );
+            Ok(serde_json::from_str(&json)?)
+        };
+        let (a, b) = (history(0, "kill")?, history(12, "resume")?);
+        let order = |h: &SerializableHistoryList| {
+            h.iter()
+                .map(|i| (i.index, i.time, i.process))
+                .collect::<Vec<_>>()
+        };
+        use HistoryProcess::*;
+        let merged = SerializableHistoryList::merge([a.clone(), b.clone()], Duration::ZERO);
+        assert_eq!(
+            order(&merged),
+            [
+                (0, 0, Gen(0)),
+                (1, 1, Nemesis),
+                (2, 10, Gen(0)),
+                (3, 12, Gen(1)),
+                (4, 13, Nemesis),
+                (5, 22, Gen(1))
+            ]
+        );
+        // the ops 2ns apart become concurrent
+        let merged = SerializableHistoryList::merge([a, b], Duration::from_nanos(5));
+        assert_eq!(
+            order(&merged),
+            [
+                (0, 0, Gen(0)),
+                (1, 1, Nemesis),
+                (2, 12, Gen(1)),
+                (3, 13, Nemesis),
+                (4, 10, Gen(0)),
+                (5, 22, Gen(1))
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 100, 7], ["r", 30, null]], "time": 1, "process": 0, "error": null },
+          { "index": 1, "type": "fail", "f": "txn", "value": [["w", 100, 7], ["r", 30, null]], "time": 2, "process": 0, "error": ["secret row"], "meta": { "node": 1, "retries": 0, "latency": 1 } },
+          { "index": 2, "type": "invoke", "f": "scan_range", "value": ["scan", [20, 200], null], "time": 3, "process": 1, "error": null },
+          { "index": 3, "type": "fail", "f": "scan_range", "value": ["scan", [20, 200], [[100, 9]]], "time": 4, "process": 1, "error": ["timeout"] },
+          { "index": 4, "type": "info", "f": "heal", "value": "Heal", "time": 5, "process": "nemesis", "error": ["secret row"] }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let compact = history.compact();
+        let values: Vec<_> = compact.iter().map(|i| i.value.clone()).collect();
+        assert_eq!(
+            values[..4],
+            [
+                Op::Txn(vec![Op::Write(2, 0), Op::Read(1, None)]).into(),
+                Op::Txn(vec![Op::Write(2, 0), Op::Read(1, None)]).into(),
+                // the last key 199 of the scan is renumbered too
+                Op::ScanRange(0, 4, None).into(),
+                Op::ScanRange(0, 4, Some(vec![(2, 1)])).into(),
+            ]
+        );
+        let errors: Vec<_> = compact.iter().map(|i| i.error.clone()).collect();
+        let code = |c: &str| Some(OpError::Custom(c.to_string()));
+        assert_eq!(
+            errors,
+            [
+                None,
+                code("error-0"),
+                None,
+                Some(OpError::Timeout),
+                code("error-0")
+            ]
+        );
+        assert!(compact.iter().all(|i| i.meta.is_none()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_collector() {
+        let collector: Arc<HistoryCollector> = Arc::default();
+        let threads: Vec<_> = (0..8u64)
+            .map(|process| {
+                let collector = collector.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        collector.push(SerializableHistory {
+                            index: 0,
+                            type_: HistoryType::Invoke,
+                            f: OpOrNemesisFuncType::Op(OpFunctionType::Write),
+                            value: Op::Write(process, i).into(),
+                            // the later pushes of a process are earlier
+                            time: 1000 - i,
+                            process: HistoryProcess::Gen(process),
+                            error: None,
+                            meta: None,
+                            tags: OpTags::new(),
+                        });
+                        collector.merge(false);
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        let (merged, _) = collector.merge(true).unwrap();
+        let history = &merged.list.0;
+        assert_eq!(history.len(), 800);
+        assert!(history
+            .iter()
+            .enumerate()
+            .all(|(i, item)| item.index == i as u64));
+        assert!(history.windows(2).all(|w| w[0].time <= w[1].time));
+        // the items of a process are in the order of its pushes
+        for process in 0..8 {
+            let values: Vec<_> = history
+                .iter()
+                .filter(|item| item.process == HistoryProcess::Gen(process))
+                .map(|item| item.value.clone())
+                .collect();
+            let pushed: Vec<HistoryValue> =
+                (0..100).map(|i| Op::Write(process, i).into()).collect();
+            assert_eq!(values, pushed);
+        }
+    }
+
+    #[madsim::test]
+    async fn test_history_spill() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-spill-{}", std::process::id()));
+        let global: Arc<Global<'_, i32>> = Arc::new(Global::new(0..));
+        *global.history_spill.lock().unwrap() = Some(HistorySpill::new(&dir, 4)?);
+        for i in 0..10 {
+            global.push_invoke(0, Op::Write(i, i), OpTags::new());
+            assert!(global.history.merged.lock().unwrap().list.0.len() <= 4);
+        }
+        let spilled = global
+            .history_spill
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .spilled();
+        assert_eq!(
+            spilled + global.history.merged.lock().unwrap().list.0.len() as u64,
+            10
+        );
+        let history = global.full_history()?;
+        assert_eq!(history.len(), 10);
+        assert!(history
+            .iter()
+            .enumerate()
+            .all(|(i, item)| item.index == i as u64
+                && item.value == HistoryValue::Op(Op::Write(i as u64, i as u64))));
+        // the same history is lent, and the items in memory are kept
+        let in_memory = global.history.merged.lock().unwrap().list.0.len();
+        assert!(global.with_full_history(|lent| lent.0 == history.0)?);
+        assert_eq!(
+            global.history.merged.lock().unwrap().list.0.len(),
+            in_memory
+        );
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_fault_windows() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "info", "f": "kill", "value": { "nemesis": "Kill", "servers": [1] }, "time": 1, "process": "nemesis", "error": null },
+          { "index": 1, "type": "info", "f": "partition", "value": { "nemesis": "PartitionHalves", "links": [[0, 1]] }, "time": 2, "process": "nemesis", "error": null },
+          { "index": 2, "type": "info", "f": "kill", "value": { "nemesis": "Kill", "servers": [2] }, "time": 3, "process": "nemesis", "error": ["failed"] },
+          { "index": 3, "type": "info", "f": "bitflip-wal", "value": { "nemesis": "BitflipWal", "servers": [0] }, "time": 4, "process": "nemesis", "error": null },
+          { "index": 4, "type": "info", "f": "resume", "value": { "nemesis": "Kill", "servers": [1] }, "time": 5, "process": "nemesis", "error": null },
+          { "index": 5, "type": "info", "f": "heal", "value": { "nemesis": "Partition", "links": [[1, 0]] }, "time": 6, "process": "nemesis", "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let windows = history.fault_windows();
+        assert_eq!(
+            windows,
+            [
+                FaultInterval {
+                    nemesis: "Kill".to_string(),
+                    f: SerializableNemesisType::Kill,
+                    start: 1,
+                    end: Some(5),
+                },
+                // the heal of other links does not heal the partition
+                FaultInterval {
+                    nemesis: "PartitionHalves".to_string(),
+                    f: SerializableNemesisType::Partition,
+                    start: 2,
+                    end: None,
+                },
+            ]
+        );
+        Ok(())
+    }
 }
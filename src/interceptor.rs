@@ -0,0 +1,118 @@
+//! Interceptors of the ops handled by the client, see
+//! [`JepsenClient::with_interceptor`](crate::client::JepsenClient::with_interceptor).
+//!
+//! An interceptor is called before an op is invoked and after it completes,
+//! and may modify the op and the result. The interceptors are called in the
+//! order they are added before invocation, and in the reverse order after
+//! completion, like the layers of an onion.
+
+use std::time::Duration;
+
+use log::{log, Level};
+use madsim::rand::{self, Rng};
+
+use crate::{history::HistoryType, op::Op};
+
+/// The result of an op: the completed op, or the type to record the op as
+/// and the error.
+pub type OpResult = Result<Op, (HistoryType, String)>;
+
+/// A layer around the ops of every process.
+#[async_trait::async_trait]
+pub trait OpInterceptor: Send + Sync {
+    /// Called before the op of the process is recorded and invoked, returns
+    /// the op to invoke.
+    async fn before(&self, _process: u64, op: Op) -> Op {
+        op
+    }
+    /// Called after the invoked op completes and before the result is
+    /// recorded, returns the result to record.
+    async fn after(&self, _process: u64, _invoked: &Op, result: OpResult) -> OpResult {
+        result
+    }
+}
+
+/// Delays the completion of every op by a random duration, like a slow
+/// client or network, which widens the window of the op and makes ops more
+/// concurrent.
+#[derive(Debug, Clone)]
+pub struct Latency {
+    min: Duration,
+    max: Duration,
+}
+
+impl Latency {
+    /// Delays from `min` to `max`.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "the min latency must not exceed the max");
+        Self { min, max }
+    }
+}
+
+#[async_trait::async_trait]
+impl OpInterceptor for Latency {
+    async fn after(&self, _process: u64, _invoked: &Op, result: OpResult) -> OpResult {
+        let delay = rand::thread_rng().gen_range(self.min..=self.max);
+        madsim::time::sleep(delay).await;
+        result
+    }
+}
+
+/// Logs the invocation and completion of every op.
+#[derive(Debug, Clone)]
+pub struct OpLogger {
+    level: Level,
+}
+
+impl OpLogger {
+    pub fn new(level: Level) -> Self {
+        Self { level }
+    }
+}
+
+#[async_trait::async_trait]
+impl OpInterceptor for OpLogger {
+    async fn before(&self, process: u64, op: Op) -> Op {
+        log!(self.level, "process {} invokes {:?}", process, op);
+        op
+    }
+
+    async fn after(&self, process: u64, _invoked: &Op, result: OpResult) -> OpResult {
+        match &result {
+            Ok(op) => log!(self.level, "process {} completes {:?}", process, op),
+            Err((type_, err)) => {
+                log!(
+                    self.level,
+                    "process {} completes {:?}: {}",
+                    process,
+                    type_,
+                    err
+                )
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[madsim::test]
+    async fn test_interceptors() {
+        let latency = Latency::new(Duration::from_millis(10), Duration::from_millis(20));
+        let op = Op::Write(1, 1);
+        for _ in 0..10 {
+            let start = madsim::time::Instant::now();
+            let res = latency.after(0, &op, Ok(op.clone())).await;
+            assert_eq!(res, Ok(op.clone()));
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(10) && elapsed <= Duration::from_millis(21));
+        }
+
+        let logger = OpLogger::new(Level::Debug);
+        assert_eq!(logger.before(0, op.clone()).await, op);
+        let err: OpResult = Err((HistoryType::Fail, "busy".to_string()));
+        assert_eq!(logger.after(0, &op, err.clone()).await, err);
+    }
+}
@@ -27,7 +27,7 @@ macro_rules! cljinvoke {
     };
     ($name:expr, $($args:expr),*) => {
         || -> j4rs::errors::Result<j4rs::Instance> {
-            $crate::CLOJURE.var($name)?.invoke(&[$(j4rs::InvocationArg::try_from($args)?),*])
+            Ok($crate::CLOJURE.var($name)?.invoke(&[$(j4rs::InvocationArg::try_from($args)?),*])?)
         } ()
     };
 }
@@ -51,11 +51,11 @@ macro_rules! cljeval {
 /// https://clojure.github.io/clojure/javadoc/clojure/java/api/Clojure.html
 macro_rules! cljinvoke_java_api {
     ($name:expr) => {
-        $crate::invoke_clojure_java_api($name, &[])
+        $crate::clojure::invoke_clojure_java_api($name, &[])
     };
     ($name:expr, $($args:expr),*) => {
         || -> j4rs::errors::Result<j4rs::Instance> {
-            $crate::invoke_clojure_java_api($name, &[$(j4rs::InvocationArg::try_from($args)?),*])
+            $crate::clojure::invoke_clojure_java_api($name, &[$(j4rs::InvocationArg::try_from($args)?),*])
         } ()
     };
 }
@@ -72,12 +72,12 @@ macro_rules! cljinvoke_java_api {
 macro_rules! nsinvoke {
     ($ns:expr, $var:expr) => {
         || -> j4rs::errors::Result<j4rs::Instance> {
-            $ns.var($var)?.invoke(&[] as &[j4rs::InvocationArg])
+            Ok($ns.var($var)?.invoke(&[] as &[j4rs::InvocationArg])?)
         } ()
     };
     ($ns:expr, $var:expr, $($args:expr),*) => {
         || -> j4rs::errors::Result<j4rs::Instance> {
-            $ns.var($var)?.invoke(&[$(j4rs::InvocationArg::try_from($args)?),*])
+            Ok($ns.var($var)?.invoke(&[$(j4rs::InvocationArg::try_from($args)?),*])?)
         } ()
     };
 }
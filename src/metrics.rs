@@ -0,0 +1,374 @@
+//! Metrics of the harness in the text format of Prometheus, enabled by the
+//! `metrics` feature, so the fleets running jepsen-rs continuously can monitor
+//! it. The metrics of the process are collected in [`Metrics::global`]:
+//!
+//! | metric                              | type      | labels          |
+//! |-------------------------------------|-----------|-----------------|
+//! | `jepsen_ops_total`                  | counter   | `f`, `type`     |
+//! | `jepsen_op_latency_seconds`         | histogram | `f`             |
+//! | `jepsen_nemesis_executions_total`   | counter   | `f`, `result`   |
+//! | `jepsen_ffi_call_duration_seconds`  | histogram |                 |
+//! | `jepsen_history_items`              | gauge     |                 |
+//!
+//! Serve them by a [`MetricsServer`], or render them by [`Metrics::render`]
+//! for another exporter:
+//!
+//! ```ignore
+//! let server = MetricsServer::start("127.0.0.1:9464")?;
+//! // curl http://127.0.0.1:9464/metrics
+//! ```
+//!
+//! The exporter needs no extra dependency. The op latencies are in simulated
+//! time under madsim, and the FFI calls in real time.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write as _},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    history::{HistoryProcess, HistoryType, SerializableHistory},
+    utils::serialized_name,
+};
+
+/// The upper bounds of the buckets of the latency histograms, in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// A histogram of the buckets of [`LATENCY_BUCKETS`].
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// The count of every bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|b| secs <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    /// Render the samples of the histogram with the labels, e.g. `f="txn",`.
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let labels = labels.trim_end_matches(',');
+        let labels = match labels {
+            "" => String::new(),
+            labels => format!("{{{}}}", labels),
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+/// The metrics of the harness, see the [module](self) doc.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// The ops by function and type.
+    ops: Mutex<BTreeMap<(String, &'static str), u64>>,
+    op_latency: Mutex<BTreeMap<String, Histogram>>,
+    /// The nemesis executions by function and whether they succeed.
+    nemeses: Mutex<BTreeMap<(String, bool), u64>>,
+    ffi: Mutex<Histogram>,
+    history_items: AtomicU64,
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// The metrics of the process.
+    pub fn global() -> &'static Self {
+        METRICS.get_or_init(Self::default)
+    }
+
+    /// Count a history item pushed, whose index is the size of the history.
+    pub(crate) fn record_item<F: Serialize, ERR>(&self, item: &SerializableHistory<F, ERR>) {
+        self.history_items.store(item.index + 1, Ordering::Relaxed);
+        let f = serialized_name(&item.f);
+        if item.process == HistoryProcess::Nemesis {
+            let ok = item.error.is_none();
+            *self.nemeses.lock().unwrap().entry((f, ok)).or_default() += 1;
+            return;
+        }
+        let type_ = match item.type_ {
+            HistoryType::Invoke => "invoke",
+            HistoryType::Ok => "ok",
+            HistoryType::Fail => "fail",
+            HistoryType::Info => "info",
+        };
+        if let Some(meta) = item.meta {
+            let secs = Duration::from_nanos(meta.latency).as_secs_f64();
+            let mut latency = self.op_latency.lock().unwrap();
+            latency.entry(f.clone()).or_default().observe(secs);
+        }
+        *self.ops.lock().unwrap().entry((f, type_)).or_default() += 1;
+    }
+
+    /// Observe the duration of a call to the JVM.
+    #[cfg_attr(not(feature = "clojure"), allow(dead_code))]
+    pub(crate) fn record_ffi(&self, duration: Duration) {
+        self.ffi.lock().unwrap().observe(duration.as_secs_f64());
+    }
+
+    /// Render the metrics in the text format of Prometheus.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP jepsen_ops_total The ops in the history by function and type.\n");
+        out.push_str("# TYPE jepsen_ops_total counter\n");
+        for ((f, type_), count) in self.ops.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "jepsen_ops_total{{f=\"{}\",type=\"{}\"}} {}",
+                escape(f),
+                type_,
+                count
+            );
+        }
+        out.push_str("# HELP jepsen_op_latency_seconds The latencies of the completed ops.\n");
+        out.push_str("# TYPE jepsen_op_latency_seconds histogram\n");
+        for (f, histogram) in self.op_latency.lock().unwrap().iter() {
+            let labels = format!("f=\"{}\",", escape(f));
+            histogram.render(&mut out, "jepsen_op_latency_seconds", &labels);
+        }
+        out.push_str(
+            "# HELP jepsen_nemesis_executions_total The nemesis items in the history by \
+             function and result.\n",
+        );
+        out.push_str("# TYPE jepsen_nemesis_executions_total counter\n");
+        for ((f, ok), count) in self.nemeses.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "jepsen_nemesis_executions_total{{f=\"{}\",result=\"{}\"}} {}",
+                escape(f),
+                if *ok { "ok" } else { "error" },
+                count
+            );
+        }
+        out.push_str("# HELP jepsen_ffi_call_duration_seconds The durations of the JVM calls.\n");
+        out.push_str("# TYPE jepsen_ffi_call_duration_seconds histogram\n");
+        self.ffi
+            .lock()
+            .unwrap()
+            .render(&mut out, "jepsen_ffi_call_duration_seconds", "");
+        out.push_str("# HELP jepsen_history_items The items in the history of the run.\n");
+        out.push_str("# TYPE jepsen_history_items gauge\n");
+        let _ = writeln!(
+            out,
+            "jepsen_history_items {}",
+            self.history_items.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+/// An HTTP server serving [`Metrics::global`] at `/metrics` on a thread of
+/// its own, stopped when dropped. In a madsim runtime, system threads must be
+/// allowed by `Runtime::set_allow_system_thread` to start it.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Start the server at the address, e.g. `127.0.0.1:9464`, or a free port
+    /// if the port is 0.
+    pub fn start(addr: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_ = stop.clone();
+        let thread = thread::Builder::new()
+            .name("jepsen-metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stop_.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let res = stream.and_then(serve);
+                    if let Err(err) = res {
+                        warn!("failed to serve the metrics: {}", err);
+                    }
+                }
+            })?;
+        info!("metrics served on http://{}/metrics", addr);
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// Serve a request, `GET /metrics` only.
+fn serve(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let (status, body) = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => ("200 OK", Metrics::global().render()),
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // wake up the accepting thread
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        history::{HistoryValue, NemesisValue, OpMeta},
+        nemesis::SerializableNemesisType,
+        op::{Op, OpFunctionType, OpOrNemesisFuncType},
+    };
+
+    fn item(
+        index: u64,
+        type_: HistoryType,
+        f: OpOrNemesisFuncType,
+        value: HistoryValue,
+        process: HistoryProcess,
+    ) -> SerializableHistory<OpOrNemesisFuncType, String> {
+        SerializableHistory {
+            index,
+            type_,
+            f,
+            value,
+            time: 0,
+            process,
+            error: None,
+            meta: None,
+            tags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        let f = OpOrNemesisFuncType::Op(OpFunctionType::Write);
+        let write = || HistoryValue::Op(Op::Write(1, 1));
+        metrics.record_item(&item(
+            0,
+            HistoryType::Invoke,
+            f,
+            write(),
+            HistoryProcess::Gen(0),
+        ));
+        let mut ok = item(1, HistoryType::Ok, f, write(), HistoryProcess::Gen(0));
+        ok.meta = Some(OpMeta {
+            node: 0,
+            retries: 0,
+            latency: 2_000_000,
+        });
+        metrics.record_item(&ok);
+        let mut kill = item(
+            2,
+            HistoryType::Info,
+            OpOrNemesisFuncType::Nemesis(SerializableNemesisType::Kill),
+            HistoryValue::Fault(NemesisValue::new("Kill({1})")),
+            HistoryProcess::Nemesis,
+        );
+        kill.error = Some("refused".to_string());
+        metrics.record_item(&kill);
+        metrics.record_ffi(Duration::from_millis(30));
+
+        let text = metrics.render();
+        assert!(text.contains("jepsen_ops_total{f=\"w\",type=\"invoke\"} 1\n"));
+        assert!(text.contains("jepsen_ops_total{f=\"w\",type=\"ok\"} 1\n"));
+        assert!(text.contains("jepsen_op_latency_seconds_bucket{f=\"w\",le=\"0.001\"} 0\n"));
+        assert!(text.contains("jepsen_op_latency_seconds_bucket{f=\"w\",le=\"0.0025\"} 1\n"));
+        assert!(text.contains("jepsen_op_latency_seconds_count{f=\"w\"} 1\n"));
+        assert!(text.contains("jepsen_nemesis_executions_total{f=\"kill\",result=\"error\"} 1\n"));
+        assert!(text.contains("jepsen_ffi_call_duration_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(text.contains("jepsen_ffi_call_duration_seconds_count 1\n"));
+        assert!(text.contains("jepsen_history_items 3\n"));
+    }
+
+    #[test]
+    fn test_metrics_server() -> Result<()> {
+        let server = MetricsServer::start("127.0.0.1:0")?;
+        let get = |path: &str| -> Result<String> {
+            let mut stream = TcpStream::connect(server.addr())?;
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        };
+        let response = get("/metrics")?;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("# TYPE jepsen_ops_total counter"));
+        assert!(get("/")?.starts_with("HTTP/1.1 404"));
+        drop(server);
+        Ok(())
+    }
+}
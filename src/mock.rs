@@ -0,0 +1,415 @@
+//! An in-memory cluster with injectable consistency defects, to validate that
+//! a checker configuration actually detects the anomalies of interest before
+//! pointing it at a real database.
+//!
+//! Without defects, every op and txn of [`MockCluster`] is atomic and applied
+//! in a total order, so its history is strict serializable. The defects are:
+//! - stale reads, which return the value before the latest write of the key;
+//! - lost writes, which are acknowledged but dropped while a fault is active;
+//! - dirty reads, where a txn exposes its writes and then aborts, so the
+//!   aborted writes are read until they are overwritten.
+//!
+//! A fault is active while a disk stress nemesis is executed on the cluster,
+//! or while it's started by the [`FaultSwitch`] of the cluster, e.g. from a
+//! hook of [`JepsenClient::on_nemesis_start`].
+//!
+//! [`JepsenClient::on_nemesis_start`]: crate::client::JepsenClient::on_nemesis_start
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use madsim::rand::{self, Rng};
+
+use crate::{
+    client::{ClusterLifecycle, ElleRwClusterClient, CAS_MISMATCH},
+    nemesis::{DiskStressMode, NemesisClusterClient, ServerId, StorageFaultInjector},
+    op::Op,
+};
+
+/// The error of a txn aborted by the dirty read defect.
+pub const DIRTY_ABORT: &str = "aborted by the mock cluster";
+
+/// Starts and heals the faults of a [`MockCluster`], faults can overlap.
+#[derive(Debug, Clone, Default)]
+pub struct FaultSwitch(Arc<AtomicUsize>);
+
+impl FaultSwitch {
+    pub fn start(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Heal a started fault.
+    pub fn heal(&self) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+    }
+
+    /// Whether any fault is active.
+    pub fn is_active(&self) -> bool {
+        self.0.load(Ordering::SeqCst) > 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The values of every key in the order they are written, `None` for
+    /// deletes.
+    versions: HashMap<u64, Vec<Option<u64>>>,
+    /// The writes of the aborted txns, visible until the key is written.
+    dirty: HashMap<u64, u64>,
+    /// The lists of the list-append ops.
+    lists: HashMap<u64, Vec<u64>>,
+}
+
+impl State {
+    fn latest(&self, key: u64) -> Option<u64> {
+        self.versions
+            .get(&key)
+            .and_then(|v| v.last().copied().flatten())
+    }
+
+    fn read(&self, key: u64, stale: bool) -> Option<u64> {
+        if let Some(value) = self.dirty.get(&key) {
+            return Some(*value);
+        }
+        match self.versions.get(&key) {
+            Some(v) if stale && v.len() >= 2 => v[v.len() - 2],
+            Some(_) if stale => None,
+            _ => self.latest(key),
+        }
+    }
+
+    fn write(&mut self, key: u64, value: Option<u64>) {
+        self.dirty.remove(&key);
+        self.versions.entry(key).or_default().push(value);
+    }
+
+    /// Read the list of the key, a stale read misses the last element.
+    fn read_list(&self, key: u64, stale: bool) -> Vec<u64> {
+        let list = self.lists.get(&key).map_or(&[][..], Vec::as_slice);
+        match list.split_last() {
+            Some((_, prefix)) if stale => prefix.to_vec(),
+            _ => list.to_vec(),
+        }
+    }
+}
+
+/// An in-memory cluster of `size` servers sharing one store, see the
+/// [module](self) doc.
+#[derive(Debug)]
+pub struct MockCluster {
+    size: usize,
+    state: Mutex<State>,
+    stale_reads: f64,
+    lost_writes: f64,
+    dirty_reads: f64,
+    faults: FaultSwitch,
+}
+
+impl MockCluster {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            state: Mutex::default(),
+            stale_reads: 0.0,
+            lost_writes: 0.0,
+            dirty_reads: 0.0,
+            faults: FaultSwitch::default(),
+        }
+    }
+
+    /// Make a read stale with probability `p`.
+    pub fn with_stale_reads(mut self, p: f64) -> Self {
+        self.stale_reads = p;
+        self
+    }
+
+    /// Lose a write with probability `p` while a fault is active.
+    pub fn with_lost_writes(mut self, p: f64) -> Self {
+        self.lost_writes = p;
+        self
+    }
+
+    /// Abort a txn that writes after exposing its writes, with probability
+    /// `p`.
+    pub fn with_dirty_reads(mut self, p: f64) -> Self {
+        self.dirty_reads = p;
+        self
+    }
+
+    /// The switch of the faults of the cluster, which should be taken before
+    /// the cluster is moved into the client.
+    pub fn fault_switch(&self) -> FaultSwitch {
+        self.faults.clone()
+    }
+
+    fn happens(p: f64) -> bool {
+        p > 0.0 && rand::thread_rng().gen_bool(p.min(1.0))
+    }
+
+    fn stale(&self) -> bool {
+        Self::happens(self.stale_reads)
+    }
+
+    /// Whether a write is lost.
+    fn lost(&self) -> bool {
+        self.faults.is_active() && Self::happens(self.lost_writes)
+    }
+}
+
+#[async_trait::async_trait]
+impl ElleRwClusterClient for MockCluster {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        Ok(self.state.lock().unwrap().read(key, self.stale()))
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        if !self.lost() {
+            self.state.lock().unwrap().write(key, Some(value));
+        }
+        Ok(())
+    }
+
+    async fn cas(&self, key: u64, expect: u64, new: u64) -> Result<bool, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.read(key, false) != Some(expect) {
+            return Ok(false);
+        }
+        if !self.lost() {
+            state.write(key, Some(new));
+        }
+        Ok(true)
+    }
+
+    async fn delete(&self, key: u64) -> Result<(), String> {
+        if !self.lost() {
+            self.state.lock().unwrap().write(key, None);
+        }
+        Ok(())
+    }
+
+    async fn incr(&self, key: u64, delta: i64) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let value = state
+            .read(key, false)
+            .unwrap_or(0)
+            .wrapping_add_signed(delta);
+        if !self.lost() {
+            state.write(key, Some(value));
+        }
+        Ok(())
+    }
+
+    async fn append(&self, key: u64, value: u64) -> Result<(), String> {
+        if !self.lost() {
+            let mut state = self.state.lock().unwrap();
+            state.lists.entry(key).or_default().push(value);
+        }
+        Ok(())
+    }
+
+    async fn get_list(&self, key: u64) -> Result<Vec<u64>, String> {
+        Ok(self.state.lock().unwrap().read_list(key, self.stale()))
+    }
+
+    async fn batch_get(&self, keys: Vec<u64>) -> Result<Vec<Option<u64>>, String> {
+        let state = self.state.lock().unwrap();
+        Ok(keys
+            .into_iter()
+            .map(|k| state.read(k, self.stale()))
+            .collect())
+    }
+
+    async fn scan(&self, start: u64, end: u64) -> Result<Vec<(u64, u64)>, String> {
+        let state = self.state.lock().unwrap();
+        let found: BTreeMap<_, _> = state
+            .versions
+            .keys()
+            .chain(state.dirty.keys())
+            .filter(|k| (start..end).contains(*k))
+            .filter_map(|k| Some((*k, state.read(*k, false)?)))
+            .collect();
+        Ok(found.into_iter().collect())
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let mut state = self.state.lock().unwrap();
+        let mut writes = HashMap::new();
+        let mut appends: HashMap<u64, Vec<u64>> = HashMap::new();
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            match op {
+                Op::Read(key, _) => {
+                    let value = match writes.get(&key) {
+                        Some(value) => *value,
+                        None => state.read(key, self.stale()),
+                    };
+                    results.push(Op::Read(key, value));
+                }
+                Op::Write(key, value) => {
+                    writes.insert(key, Some(value));
+                    results.push(op);
+                }
+                Op::Cas(key, expect, new) => {
+                    let value = match writes.get(&key) {
+                        Some(value) => *value,
+                        None => state.read(key, false),
+                    };
+                    if value != Some(expect) {
+                        return Err(CAS_MISMATCH.to_string());
+                    }
+                    writes.insert(key, Some(new));
+                    results.push(op);
+                }
+                Op::Delete(key) => {
+                    writes.insert(key, None);
+                    results.push(op);
+                }
+                Op::Incr(key, delta) => {
+                    let value = match writes.get(&key) {
+                        Some(value) => *value,
+                        None => state.read(key, false),
+                    };
+                    writes.insert(key, Some(value.unwrap_or(0).wrapping_add_signed(delta)));
+                    results.push(op);
+                }
+                Op::Append(key, value) => {
+                    appends.entry(key).or_default().push(value);
+                    results.push(op);
+                }
+                Op::ReadList(key, _) => {
+                    let mut list = state.read_list(key, self.stale());
+                    list.extend(appends.get(&key).into_iter().flatten());
+                    results.push(Op::ReadList(key, Some(list)));
+                }
+                Op::BatchRead(_) | Op::ScanRange(..) => {
+                    return Err("multi-key reads cannot be in txns".to_string())
+                }
+                Op::Txn(_) => return Err("txns cannot be nested".to_string()),
+            }
+        }
+        if !writes.is_empty() && Self::happens(self.dirty_reads) {
+            for (key, value) in writes {
+                if let Some(value) = value {
+                    state.dirty.insert(key, value);
+                }
+            }
+            return Err(DIRTY_ABORT.to_string());
+        }
+        for (key, value) in writes {
+            if !self.lost() {
+                state.write(key, value);
+            }
+        }
+        for (key, values) in appends {
+            if !self.lost() {
+                state.lists.entry(key).or_default().extend(values);
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl ClusterLifecycle for MockCluster {}
+
+/// Every disk stress is a fault of the cluster.
+#[async_trait::async_trait]
+impl StorageFaultInjector for MockCluster {
+    async fn inject(&self, _server: ServerId, _mode: &DiskStressMode) -> Result<(), String> {
+        self.faults.start();
+        Ok(())
+    }
+
+    async fn clear(&self, _server: ServerId, _mode: &DiskStressMode) -> Result<(), String> {
+        self.faults.heal();
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl NemesisClusterClient for MockCluster {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    #[cfg(madsim)]
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+
+    async fn get_leader_without_term(&self) -> ServerId {
+        0
+    }
+
+    fn storage_fault_injector(&self) -> Option<&(dyn StorageFaultInjector + Sync)> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[madsim::test]
+    async fn test_mock_cluster() {
+        let cluster = MockCluster::new(3);
+        cluster.put(1, 10).await.unwrap();
+        assert!(cluster.cas(1, 10, 11).await.unwrap());
+        assert!(!cluster.cas(1, 10, 12).await.unwrap());
+        let res = cluster
+            .txn(vec![Op::Write(2, 20), Op::Read(2, None), Op::Read(1, None)])
+            .await
+            .unwrap();
+        assert_eq!(res[1..], [Op::Read(2, Some(20)), Op::Read(1, Some(11))]);
+        assert_eq!(cluster.scan(0, 3).await.unwrap(), vec![(1, 11), (2, 20)]);
+        cluster.delete(1).await.unwrap();
+        assert_eq!(cluster.get(1).await.unwrap(), None);
+        cluster.incr(3, 2).await.unwrap();
+        cluster
+            .txn(vec![Op::Incr(3, -1), Op::Incr(3, 5)])
+            .await
+            .unwrap();
+        assert_eq!(cluster.get(3).await.unwrap(), Some(6));
+    }
+
+    #[madsim::test]
+    async fn test_mock_defects() {
+        let cluster = MockCluster::new(1).with_stale_reads(1.0);
+        cluster.put(1, 10).await.unwrap();
+        assert_eq!(cluster.get(1).await.unwrap(), None);
+        cluster.put(1, 11).await.unwrap();
+        assert_eq!(cluster.get(1).await.unwrap(), Some(10));
+
+        // writes are lost only during faults
+        let cluster = MockCluster::new(1).with_lost_writes(1.0);
+        let switch = cluster.fault_switch();
+        cluster.put(1, 10).await.unwrap();
+        switch.start();
+        cluster.put(1, 11).await.unwrap();
+        assert_eq!(cluster.get(1).await.unwrap(), Some(10));
+        switch.heal();
+        switch.heal();
+        assert!(!switch.is_active());
+        cluster
+            .storage_fault_injector()
+            .unwrap()
+            .inject(0, &DiskStressMode::Enospc)
+            .await
+            .unwrap();
+        assert!(switch.is_active());
+
+        // the aborted write is read until overwritten
+        let cluster = MockCluster::new(1).with_dirty_reads(1.0);
+        let res = cluster.txn(vec![Op::Write(1, 10)]).await;
+        assert_eq!(res, Err(DIRTY_ABORT.to_string()));
+        assert_eq!(cluster.get(1).await.unwrap(), Some(10));
+        cluster.put(1, 11).await.unwrap();
+        assert_eq!(cluster.get(1).await.unwrap(), Some(11));
+    }
+}
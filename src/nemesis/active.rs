@@ -0,0 +1,94 @@
+use madsim::time::Instant;
+use serde::Serialize;
+
+use super::{NemesisRecord, NemesisType, SerializableNemesisType};
+
+/// The activation interval of a nemesis. The times are in nanoseconds since
+/// the start of the test, the same as the times in history.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct FaultInterval {
+    /// The executed nemesis, in debug form.
+    pub nemesis: String,
+    pub f: SerializableNemesisType,
+    pub start: u64,
+    /// `None` if the nemesis is still active.
+    pub end: Option<u64>,
+}
+
+/// The currently active nemeses, and the activation intervals of all the
+/// nemeses ever activated.
+#[derive(Debug, Default)]
+pub struct ActiveNemeses {
+    /// The active records, the time they are activated, and the index of
+    /// their intervals.
+    active: Vec<(NemesisRecord, Instant, usize)>,
+    intervals: Vec<FaultInterval>,
+}
+
+impl ActiveNemeses {
+    /// Mark the record of an executed nemesis as active. `start_time` is the
+    /// start time of the test.
+    pub fn activate(&mut self, nemesis: &NemesisType, record: NemesisRecord, start_time: Instant) {
+        let now = Instant::now();
+        self.intervals.push(FaultInterval {
+            nemesis: format!("{:?}", nemesis),
+            f: nemesis.into(),
+            start: now.duration_since(start_time).as_nanos() as u64,
+            end: None,
+        });
+        self.active.push((record, now, self.intervals.len() - 1));
+    }
+
+    /// Mark the record as recovered. Returns `false` if it's not active.
+    pub fn deactivate(&mut self, record: &NemesisRecord, start_time: Instant) -> bool {
+        let Some(pos) = self.active.iter().position(|(r, _, _)| r == record) else {
+            return false;
+        };
+        let (_, _, index) = self.active.remove(pos);
+        self.intervals[index].end =
+            Some(Instant::now().duration_since(start_time).as_nanos() as u64);
+        true
+    }
+
+    /// The active records and the time they are activated, from the oldest
+    /// one.
+    pub fn snapshot(&self) -> Vec<(NemesisRecord, Instant)> {
+        self.active
+            .iter()
+            .map(|(r, t, _)| (r.clone(), *t))
+            .collect()
+    }
+
+    /// The activation intervals of all the nemeses ever activated.
+    pub fn intervals(&self) -> &[FaultInterval] {
+        &self.intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use super::*;
+
+    #[madsim::test]
+    async fn test_active_nemeses() {
+        let start = Instant::now();
+        let mut active = ActiveNemeses::default();
+        let kill = NemesisType::Kill(HashSet::from([0]));
+        let record = NemesisRecord::Kill(HashSet::from([0]));
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        active.activate(&kill, record.clone(), start);
+        assert_eq!(active.snapshot().len(), 1);
+        assert_eq!(active.snapshot()[0].0, record);
+
+        madsim::time::sleep(Duration::from_secs(2)).await;
+        assert!(active.deactivate(&record, start));
+        assert!(!active.deactivate(&record, start));
+        assert!(active.snapshot().is_empty());
+        let interval = &active.intervals()[0];
+        assert_eq!(interval.f, SerializableNemesisType::Kill);
+        let elapsed = Duration::from_nanos(interval.end.unwrap() - interval.start);
+        assert!(elapsed >= Duration::from_secs(2) && elapsed < Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,823 @@
+//! The execution and recovery of nemeses.
+
+use std::{collections::HashSet, time::Duration};
+
+use log::{debug, warn};
+
+use super::{
+    partition_groups, partition_halves, partition_majorities_ring, partition_random_node,
+    FaultExecutor, MembershipClusterClient, NemesisClusterClient, NemesisRecord, NemesisType,
+    ServerId, StorageFaultInjector, StorageFile,
+};
+
+/// The interval of polling a new leader when chasing it.
+const LEADER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Stop chasing if no new leader is elected in time.
+const LEADER_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The running slice of a lagging server.
+#[cfg(madsim)]
+const LAG_SLICE: Duration = Duration::from_millis(10);
+
+#[cfg(madsim)]
+thread_local! {
+    /// The madsim nodes that are lagging. Each simulation runs on a thread of
+    /// its own, so the nodes of different simulations never mix.
+    static LAGGING: std::cell::RefCell<HashSet<madsim::task::NodeId>> = Default::default();
+}
+
+/// A fault action applied on the madsim simulated cluster, or on the local
+/// processes with the `os` feature.
+#[cfg_attr(not(any(madsim, feature = "os")), allow(dead_code))]
+pub(super) enum SimAction<'a> {
+    Kill(&'a HashSet<ServerId>),
+    Restart(&'a HashSet<ServerId>),
+    Pause(&'a HashSet<ServerId>),
+    Resume(&'a HashSet<ServerId>),
+    Clog(&'a [(ServerId, ServerId)]),
+    Unclog(&'a [(ServerId, ServerId)]),
+}
+
+async fn apply(
+    client: &(impl NemesisClusterClient + Sync),
+    action: SimAction<'_>,
+) -> Result<(), String> {
+    if let Some(executor) = client.fault_executor() {
+        return apply_delegated(executor, action).await;
+    }
+    #[cfg(feature = "os")]
+    if let Some(os) = client.os() {
+        return super::os::apply(os, action);
+    }
+    apply_sim(client, action)
+}
+
+/// Apply the action by the fault executor of the cluster, server by server
+/// and link by link.
+async fn apply_delegated(
+    executor: &(dyn FaultExecutor + Sync),
+    action: SimAction<'_>,
+) -> Result<(), String> {
+    match action {
+        SimAction::Kill(servers) => {
+            for s in servers {
+                executor.kill(*s).await?;
+            }
+        }
+        SimAction::Restart(servers) => {
+            for s in servers {
+                executor.restart(*s).await?;
+            }
+        }
+        SimAction::Pause(servers) => {
+            for s in servers {
+                executor.pause(*s).await?;
+            }
+        }
+        SimAction::Resume(servers) => {
+            for s in servers {
+                executor.resume(*s).await?;
+            }
+        }
+        SimAction::Clog(links) => {
+            for (src, dst) in links {
+                executor.clog(*src, *dst).await?;
+            }
+        }
+        SimAction::Unclog(links) => {
+            for (src, dst) in links {
+                executor.unclog(*src, *dst).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(madsim)]
+fn apply_sim(client: &impl NemesisClusterClient, action: SimAction<'_>) -> Result<(), String> {
+    let node = |id: &ServerId| {
+        client
+            .get_node_id(*id)
+            .ok_or_else(|| format!("cannot find the madsim node of server {}", id))
+    };
+    let handle = madsim::runtime::Handle::current();
+    let net = madsim::net::NetSim::current();
+    match action {
+        SimAction::Kill(servers) => {
+            for s in servers {
+                handle.kill(node(s)?);
+            }
+        }
+        SimAction::Restart(servers) => {
+            for s in servers {
+                handle.restart(node(s)?);
+            }
+        }
+        SimAction::Pause(servers) => {
+            for s in servers {
+                handle.pause(node(s)?);
+            }
+        }
+        SimAction::Resume(servers) => {
+            for s in servers {
+                handle.resume(node(s)?);
+            }
+        }
+        SimAction::Clog(links) => {
+            for (src, dst) in links {
+                net.clog_link(node(src)?, node(dst)?);
+            }
+        }
+        SimAction::Unclog(links) => {
+            for (src, dst) in links {
+                net.unclog_link(node(src)?, node(dst)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(madsim))]
+fn apply_sim(_client: &impl NemesisClusterClient, _action: SimAction<'_>) -> Result<(), String> {
+    Err(
+        "nemeses on simulated nodes require `--cfg madsim`, or a `FaultExecutor` of the cluster"
+            .to_string(),
+    )
+}
+
+/// Start a background task that pauses the server for `factor - 1` slices
+/// after every running slice, until [`stop_lag`] is called.
+#[cfg(madsim)]
+fn start_lag(
+    client: &impl NemesisClusterClient,
+    server: ServerId,
+    factor: u32,
+) -> Result<(), String> {
+    if factor < 2 {
+        return Err(format!(
+            "the lag factor should be at least 2, got {}",
+            factor
+        ));
+    }
+    let node = client
+        .get_node_id(server)
+        .ok_or_else(|| format!("cannot find the madsim node of server {}", server))?;
+    if !LAGGING.with(|l| l.borrow_mut().insert(node)) {
+        return Err(format!("server {} is already lagging", server));
+    }
+    let lagging = move || LAGGING.with(|l| l.borrow().contains(&node));
+    madsim::task::spawn(async move {
+        let handle = madsim::runtime::Handle::current();
+        loop {
+            madsim::time::sleep(LAG_SLICE).await;
+            if !lagging() {
+                break;
+            }
+            handle.pause(node);
+            madsim::time::sleep(LAG_SLICE * (factor - 1)).await;
+            handle.resume(node);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(madsim))]
+fn start_lag(
+    _client: &impl NemesisClusterClient,
+    _server: ServerId,
+    _factor: u32,
+) -> Result<(), String> {
+    Err("lagging simulated nodes requires `--cfg madsim`".to_string())
+}
+
+/// Stop the lag of the server, and resume it if it's paused by the lag.
+#[cfg(madsim)]
+fn stop_lag(client: &impl NemesisClusterClient, server: ServerId) -> Result<(), String> {
+    let node = client
+        .get_node_id(server)
+        .ok_or_else(|| format!("cannot find the madsim node of server {}", server))?;
+    if !LAGGING.with(|l| l.borrow_mut().remove(&node)) {
+        return Err(format!("server {} is not lagging", server));
+    }
+    madsim::runtime::Handle::current().resume(node);
+    Ok(())
+}
+
+#[cfg(not(madsim))]
+fn stop_lag(_client: &impl NemesisClusterClient, _server: ServerId) -> Result<(), String> {
+    Err("lagging simulated nodes requires `--cfg madsim`".to_string())
+}
+
+/// Jump the simulated clock forward by `offset` milliseconds.
+#[cfg(madsim)]
+pub(super) fn advance_sim_clock(offset: i64) -> Result<(), String> {
+    let offset =
+        u64::try_from(offset).map_err(|_| "the simulated clock cannot go backwards".to_string())?;
+    madsim::time::advance(std::time::Duration::from_millis(offset));
+    Ok(())
+}
+
+#[cfg(not(madsim))]
+pub(super) fn advance_sim_clock(_offset: i64) -> Result<(), String> {
+    Err("skewing clocks of simulated nodes requires `--cfg madsim`".to_string())
+}
+
+impl NemesisType {
+    /// Execute the nemesis on the cluster. Returns the record to recover it
+    /// later, or `None` if the nemesis cannot be recovered.
+    pub async fn execute(
+        &self,
+        client: &(impl NemesisClusterClient + Sync),
+    ) -> Result<Option<NemesisRecord>, String> {
+        debug!("execute nemesis: {:?}", self);
+        let size = client.size();
+        let record = match self {
+            NemesisType::Kill(servers) => {
+                apply(client, SimAction::Kill(servers)).await?;
+                NemesisRecord::Kill(servers.clone())
+            }
+            NemesisType::Pause(servers) => {
+                apply(client, SimAction::Pause(servers)).await?;
+                NemesisRecord::Pause(servers.clone())
+            }
+            NemesisType::Wipe(server) => {
+                let servers = HashSet::from([*server]);
+                apply(client, SimAction::Kill(&servers)).await?;
+                if let Err(err) = client.wipe_storage(*server).await {
+                    apply(client, SimAction::Restart(&servers)).await?;
+                    return Err(err);
+                }
+                NemesisRecord::Kill(servers)
+            }
+            NemesisType::KillLeader { chase } => {
+                let servers = chase_leader(client, *chase, |s| SimAction::Kill(s)).await?;
+                NemesisRecord::Kill(servers)
+            }
+            NemesisType::PauseLeader { chase } => {
+                let servers = chase_leader(client, *chase, |s| SimAction::Pause(s)).await?;
+                NemesisRecord::Pause(servers)
+            }
+            NemesisType::PartitionHalves => clog(client, partition_halves(size)).await?,
+            NemesisType::PartitionMajoritiesRing => {
+                clog(client, partition_majorities_ring(size)).await?
+            }
+            NemesisType::PartitionRandomNode => clog(client, partition_random_node(size)).await?,
+            NemesisType::PartitionNamed { groups } => {
+                clog(client, partition_groups(size, groups)?).await?
+            }
+            NemesisType::BitflipWal { server, bits } => {
+                client.bitflip(*server, StorageFile::Wal, *bits).await?;
+                return Ok(None);
+            }
+            NemesisType::BitflipSnap { server, bits } => {
+                client
+                    .bitflip(*server, StorageFile::Snapshot, *bits)
+                    .await?;
+                return Ok(None);
+            }
+            NemesisType::TruncateWal { server, bytes } => {
+                client.truncate_wal(*server, *bytes).await?;
+                return Ok(None);
+            }
+            NemesisType::DiskStress { server, mode } => {
+                injector(client)?.inject(*server, mode).await?;
+                NemesisRecord::DiskStress {
+                    server: *server,
+                    mode: mode.clone(),
+                }
+            }
+            NemesisType::RemoveNode(server) => {
+                membership(client)?.remove_node(*server).await?;
+                NemesisRecord::RemoveNode(*server)
+            }
+            NemesisType::AddNode(server) => {
+                membership(client)?.add_node(*server).await?;
+                return Ok(None);
+            }
+            NemesisType::Lag { server, factor } => {
+                start_lag(client, *server, *factor)?;
+                NemesisRecord::Lag(*server)
+            }
+            NemesisType::ClockSkew { servers, offset } => {
+                client.skew_clock(servers, *offset).await?;
+                NemesisRecord::Clock(servers.clone())
+            }
+            NemesisType::ClockStrobe {
+                servers,
+                delta,
+                period,
+                duration,
+            } => {
+                client
+                    .strobe_clock(servers, *delta, *period, *duration)
+                    .await?;
+                NemesisRecord::Clock(servers.clone())
+            }
+            NemesisType::ClockDrift { servers, ppm } => {
+                client.drift_clock(servers, *ppm).await?;
+                NemesisRecord::Clock(servers.clone())
+            }
+        };
+        Ok(Some(record))
+    }
+}
+
+fn injector(
+    client: &impl NemesisClusterClient,
+) -> Result<&(dyn StorageFaultInjector + Sync), String> {
+    client
+        .storage_fault_injector()
+        .ok_or_else(|| "the cluster has no storage fault injector".to_string())
+}
+
+fn membership(
+    client: &impl NemesisClusterClient,
+) -> Result<&(dyn MembershipClusterClient + Sync), String> {
+    client
+        .membership()
+        .ok_or_else(|| "the cluster does not support membership changes".to_string())
+}
+
+/// Apply `action` on the current leader, then on every newly elected leader
+/// for `chase` more times. Returns all the servers applied.
+async fn chase_leader(
+    client: &(impl NemesisClusterClient + Sync),
+    chase: usize,
+    action: for<'a> fn(&'a HashSet<ServerId>) -> SimAction<'a>,
+) -> Result<HashSet<ServerId>, String> {
+    let mut servers = HashSet::new();
+    for i in 0..=chase {
+        let start = madsim::time::Instant::now();
+        let leader = loop {
+            let leader = client.get_leader_without_term().await;
+            if !servers.contains(&leader) {
+                break Some(leader);
+            }
+            if start.elapsed() > LEADER_WAIT_TIMEOUT {
+                break None;
+            }
+            madsim::time::sleep(LEADER_POLL_INTERVAL).await;
+        };
+        let Some(leader) = leader else {
+            warn!("no new leader elected, stop chasing after {} times", i);
+            break;
+        };
+        debug!("nemesis targets the leader {}", leader);
+        apply(client, action(&HashSet::from([leader]))).await?;
+        servers.insert(leader);
+    }
+    Ok(servers)
+}
+
+async fn clog(
+    client: &(impl NemesisClusterClient + Sync),
+    links: Vec<(ServerId, ServerId)>,
+) -> Result<NemesisRecord, String> {
+    apply(client, SimAction::Clog(&links)).await?;
+    Ok(NemesisRecord::Partition(links))
+}
+
+impl NemesisRecord {
+    /// Recover the cluster from the executed nemesis.
+    pub async fn recover(&self, client: &(impl NemesisClusterClient + Sync)) -> Result<(), String> {
+        debug!("recover nemesis: {:?}", self);
+        match self {
+            NemesisRecord::Kill(servers) => apply(client, SimAction::Restart(servers)).await,
+            NemesisRecord::Pause(servers) => apply(client, SimAction::Resume(servers)).await,
+            NemesisRecord::Partition(links) => apply(client, SimAction::Unclog(links)).await,
+            NemesisRecord::Clock(servers) => client.reset_clock(servers).await,
+            NemesisRecord::RemoveNode(server) => membership(client)?.add_node(*server).await,
+            NemesisRecord::Lag(server) => stop_lag(client, *server),
+            NemesisRecord::DiskStress { server, mode } => {
+                injector(client)?.clear(*server, mode).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+    use super::*;
+    use crate::nemesis::DiskStressMode;
+
+    /// A disk that records the active stresses.
+    #[derive(Default)]
+    struct MockDisk(Mutex<HashMap<ServerId, DiskStressMode>>);
+
+    #[async_trait::async_trait]
+    impl StorageFaultInjector for MockDisk {
+        async fn inject(&self, server: ServerId, mode: &DiskStressMode) -> Result<(), String> {
+            self.0.lock().unwrap().insert(server, mode.clone());
+            Ok(())
+        }
+        async fn clear(&self, server: ServerId, mode: &DiskStressMode) -> Result<(), String> {
+            match self.0.lock().unwrap().remove(&server) {
+                Some(m) if &m == mode => Ok(()),
+                _ => Err(format!("server {} is not stressed by {:?}", server, mode)),
+            }
+        }
+    }
+
+    /// The membership of the cluster.
+    #[derive(Default)]
+    struct MockMembership(Mutex<HashSet<ServerId>>);
+
+    #[async_trait::async_trait]
+    impl MembershipClusterClient for MockMembership {
+        async fn add_node(&self, server: ServerId) -> Result<(), String> {
+            self.0.lock().unwrap().insert(server);
+            Ok(())
+        }
+        async fn remove_node(&self, server: ServerId) -> Result<(), String> {
+            if self.0.lock().unwrap().remove(&server) {
+                Ok(())
+            } else {
+                Err(format!("server {} is not a member", server))
+            }
+        }
+    }
+
+    /// Records the faults it executes.
+    #[derive(Default)]
+    struct MockExecutor(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl FaultExecutor for MockExecutor {
+        async fn kill(&self, server: ServerId) -> Result<(), String> {
+            self.0.lock().unwrap().push(format!("kill {}", server));
+            Ok(())
+        }
+        async fn restart(&self, server: ServerId) -> Result<(), String> {
+            self.0.lock().unwrap().push(format!("restart {}", server));
+            Ok(())
+        }
+        async fn pause(&self, server: ServerId) -> Result<(), String> {
+            self.0.lock().unwrap().push(format!("pause {}", server));
+            Ok(())
+        }
+        async fn resume(&self, server: ServerId) -> Result<(), String> {
+            self.0.lock().unwrap().push(format!("resume {}", server));
+            Ok(())
+        }
+        async fn clog(&self, src: ServerId, dst: ServerId) -> Result<(), String> {
+            self.0.lock().unwrap().push(format!("clog {} {}", src, dst));
+            Ok(())
+        }
+        async fn unclog(&self, src: ServerId, dst: ServerId) -> Result<(), String> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("unclog {} {}", src, dst));
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct StorageCluster {
+        wal: Option<PathBuf>,
+        disk: Option<MockDisk>,
+        members: Option<MockMembership>,
+        executor: Option<MockExecutor>,
+        #[cfg(madsim)]
+        nodes: Vec<madsim::runtime::NodeHandle>,
+    }
+
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for StorageCluster {
+        fn size(&self) -> usize {
+            3
+        }
+        #[cfg(madsim)]
+        fn get_node_id(&self, id: ServerId) -> Option<madsim::task::NodeId> {
+            self.nodes.get(id as usize).map(|n| n.id())
+        }
+        /// The first alive server is the leader.
+        async fn get_leader_without_term(&self) -> ServerId {
+            #[cfg(madsim)]
+            {
+                let handle = madsim::runtime::Handle::current();
+                if let Some(i) = self.nodes.iter().position(|n| !handle.is_exit(n.id())) {
+                    return i as ServerId;
+                }
+            }
+            0
+        }
+        fn membership(&self) -> Option<&(dyn MembershipClusterClient + Sync)> {
+            self.members.as_ref().map(|m| m as _)
+        }
+        fn storage_fault_injector(&self) -> Option<&(dyn StorageFaultInjector + Sync)> {
+            self.disk.as_ref().map(|d| d as _)
+        }
+        fn fault_executor(&self) -> Option<&(dyn FaultExecutor + Sync)> {
+            self.executor.as_ref().map(|e| e as _)
+        }
+        async fn locate_file(&self, server: ServerId, file: StorageFile) -> Option<PathBuf> {
+            self.wal
+                .clone()
+                .filter(|_| server == 0 && file == StorageFile::Wal)
+        }
+    }
+
+    #[madsim::test]
+    async fn test_bitflip_execution() {
+        let cluster = StorageCluster {
+            wal: Some(std::env::temp_dir().join("jepsen_rs_test_bitflip_wal")),
+            ..Default::default()
+        };
+        let path = cluster.locate_file(0, StorageFile::Wal).await.unwrap();
+        std::fs::write(&path, [0u8; 16]).unwrap();
+        let record = NemesisType::BitflipWal { server: 0, bits: 1 }
+            .execute(&cluster)
+            .await
+            .unwrap();
+        assert!(record.is_none());
+        let flipped: u32 = std::fs::read(&path)
+            .unwrap()
+            .iter()
+            .map(|x| x.count_ones())
+            .sum();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(flipped, 1);
+        // the snapshot file cannot be located
+        assert!(NemesisType::BitflipSnap { server: 0, bits: 1 }
+            .execute(&cluster)
+            .await
+            .is_err());
+    }
+
+    #[madsim::test]
+    async fn test_disk_stress_execution() {
+        let mode = DiskStressMode::SlowFsync(Duration::from_millis(100));
+        let nemesis = NemesisType::DiskStress {
+            server: 1,
+            mode: mode.clone(),
+        };
+        assert!(nemesis.execute(&StorageCluster::default()).await.is_err());
+
+        let cluster = StorageCluster {
+            disk: Some(MockDisk::default()),
+            ..Default::default()
+        };
+        let record = nemesis.execute(&cluster).await.unwrap().unwrap();
+        let disk = cluster.disk.as_ref().unwrap();
+        assert_eq!(disk.0.lock().unwrap().get(&1), Some(&mode));
+        record.recover(&cluster).await.unwrap();
+        assert!(disk.0.lock().unwrap().is_empty());
+    }
+
+    #[madsim::test]
+    async fn test_delegated_execution() {
+        // no simulated nodes, so the faults go to the executor
+        let cluster = StorageCluster {
+            executor: Some(MockExecutor::default()),
+            ..Default::default()
+        };
+        let record = NemesisType::Kill(HashSet::from([1]))
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .unwrap();
+        record.recover(&cluster).await.unwrap();
+        let record = NemesisType::PartitionNamed {
+            groups: vec![HashSet::from([2])],
+        }
+        .execute(&cluster)
+        .await
+        .unwrap()
+        .unwrap();
+        record.recover(&cluster).await.unwrap();
+        let mut log = cluster.executor.unwrap().0.into_inner().unwrap();
+        log[2..6].sort();
+        log[6..].sort();
+        assert_eq!(
+            log,
+            [
+                "kill 1",
+                "restart 1",
+                "clog 0 2",
+                "clog 1 2",
+                "clog 2 0",
+                "clog 2 1",
+                "unclog 0 2",
+                "unclog 1 2",
+                "unclog 2 0",
+                "unclog 2 1"
+            ]
+        );
+    }
+
+    #[madsim::test]
+    async fn test_membership_change() {
+        assert!(NemesisType::RemoveNode(0)
+            .execute(&StorageCluster::default())
+            .await
+            .is_err());
+
+        let cluster = StorageCluster {
+            members: Some(MockMembership(Mutex::new(HashSet::from([0, 1])))),
+            ..Default::default()
+        };
+        let members = &cluster.members.as_ref().unwrap().0;
+        let record = NemesisType::RemoveNode(1)
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*members.lock().unwrap(), HashSet::from([0]));
+        assert!(NemesisType::RemoveNode(1).execute(&cluster).await.is_err());
+        record.recover(&cluster).await.unwrap();
+        assert_eq!(*members.lock().unwrap(), HashSet::from([0, 1]));
+        assert!(NemesisType::AddNode(2)
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(*members.lock().unwrap(), HashSet::from([0, 1, 2]));
+    }
+
+    #[madsim::test]
+    async fn test_truncate_wal_execution() {
+        let cluster = StorageCluster {
+            wal: Some(std::env::temp_dir().join("jepsen_rs_test_truncate_wal")),
+            ..Default::default()
+        };
+        let path = cluster.locate_file(0, StorageFile::Wal).await.unwrap();
+        std::fs::write(&path, [0u8; 16]).unwrap();
+        let record = NemesisType::TruncateWal {
+            server: 0,
+            bytes: 6,
+        }
+        .execute(&cluster)
+        .await
+        .unwrap();
+        assert!(record.is_none());
+        let len = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(len, 10);
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_clock_skew_and_reset() {
+        let cluster = StorageCluster::default();
+        let servers = HashSet::from([0]);
+        let start = madsim::time::Instant::now();
+        let record = NemesisType::ClockSkew {
+            servers: servers.clone(),
+            offset: 5000,
+        }
+        .execute(&cluster)
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_secs(5));
+        assert_eq!(record, NemesisRecord::Clock(servers.clone()));
+        record.recover(&cluster).await.unwrap();
+        assert!(NemesisType::ClockSkew {
+            servers: servers.clone(),
+            offset: -5000,
+        }
+        .execute(&cluster)
+        .await
+        .is_err());
+        assert!(NemesisType::ClockDrift { servers, ppm: 100 }
+            .execute(&cluster)
+            .await
+            .is_err());
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_kill_and_recover() {
+        use std::sync::{Arc, Mutex};
+
+        let handle = madsim::runtime::Handle::current();
+        let alive = Arc::new(Mutex::new(0));
+        let nodes = (0..3)
+            .map(|_| {
+                let alive = Arc::clone(&alive);
+                handle
+                    .create_node()
+                    .init(move || {
+                        let alive = Arc::clone(&alive);
+                        async move { *alive.lock().unwrap() += 1 }
+                    })
+                    .build()
+            })
+            .collect();
+        let cluster = StorageCluster {
+            nodes,
+            ..Default::default()
+        };
+        madsim::time::sleep(std::time::Duration::from_secs(1)).await;
+        assert_eq!(*alive.lock().unwrap(), 3);
+        let record = NemesisType::Kill(HashSet::from([1]))
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(handle.is_exit(cluster.nodes[1].id()));
+        record.recover(&cluster).await.unwrap();
+        madsim::time::sleep(std::time::Duration::from_secs(1)).await;
+        assert_eq!(*alive.lock().unwrap(), 4);
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_lag_and_recover() {
+        use std::sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        };
+
+        let handle = madsim::runtime::Handle::current();
+        let ticks = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&ticks);
+        let nodes = (0..3).map(|_| handle.create_node().build()).collect();
+        let cluster = StorageCluster {
+            nodes,
+            ..Default::default()
+        };
+        cluster.nodes[1].spawn(async move {
+            loop {
+                madsim::time::sleep(Duration::from_millis(1)).await;
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let nemesis = NemesisType::Lag {
+            server: 1,
+            factor: 5,
+        };
+        let record = nemesis.execute(&cluster).await.unwrap().unwrap();
+        assert!(nemesis.execute(&cluster).await.is_err());
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        let lagged = ticks.swap(0, Ordering::Relaxed);
+        record.recover(&cluster).await.unwrap();
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        let normal = ticks.load(Ordering::Relaxed);
+        assert!(lagged * 3 < normal, "lagged {} normal {}", lagged, normal);
+        assert!(record.recover(&cluster).await.is_err());
+        assert!(NemesisType::Lag {
+            server: 0,
+            factor: 1
+        }
+        .execute(&cluster)
+        .await
+        .is_err());
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_wipe_and_restart() {
+        let handle = madsim::runtime::Handle::current();
+        let nodes = (0..3).map(|_| handle.create_node().build()).collect();
+        let cluster = StorageCluster {
+            wal: Some(std::env::temp_dir().join("jepsen_rs_test_wipe_wal")),
+            nodes,
+            ..Default::default()
+        };
+        let path = cluster.locate_file(0, StorageFile::Wal).await.unwrap();
+        std::fs::write(&path, [0u8; 16]).unwrap();
+        let record = NemesisType::Wipe(0)
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record, NemesisRecord::Kill(HashSet::from([0])));
+        assert!(handle.is_exit(cluster.nodes[0].id()));
+        assert!(!path.exists());
+        record.recover(&cluster).await.unwrap();
+
+        // the server is restarted if its storage cannot be wiped
+        assert!(NemesisType::Wipe(1).execute(&cluster).await.is_err());
+        assert!(!handle.is_exit(cluster.nodes[1].id()));
+    }
+
+    #[cfg(madsim)]
+    #[madsim::test]
+    async fn test_kill_leader_chase() {
+        let handle = madsim::runtime::Handle::current();
+        let nodes = (0..3).map(|_| handle.create_node().build()).collect();
+        let cluster = StorageCluster {
+            nodes,
+            ..Default::default()
+        };
+        let record = NemesisType::KillLeader { chase: 1 }
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record, NemesisRecord::Kill(HashSet::from([0, 1])));
+        record.recover(&cluster).await.unwrap();
+
+        // stop chasing when no new leader is elected
+        let record = NemesisType::KillLeader { chase: 5 }
+            .execute(&cluster)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record, NemesisRecord::Kill(HashSet::from([0, 1, 2])));
+    }
+}
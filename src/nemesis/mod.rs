@@ -0,0 +1,693 @@
+//! This module defines the nemeses (faults) that can be injected into a
+//! cluster, and the interface a cluster should implement to be nemesized.
+
+pub mod active;
+pub mod implementation;
+#[cfg(feature = "os")]
+pub mod os;
+pub mod plan;
+pub mod policy;
+pub mod register;
+pub mod schedule;
+#[cfg(feature = "ssh")]
+pub mod ssh;
+
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use madsim::rand::{self, seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+/// The id of a server in the cluster. Servers are numbered from `0` to
+/// `size - 1`, see [`NemesisClusterClient::size`].
+pub type ServerId = u64;
+
+/// A nemesis that can be executed on the cluster. Generated by the nemesis
+/// generators and executed by the jepsen client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NemesisType {
+    /// Kill the given servers. They will be restarted on recovery.
+    Kill(HashSet<ServerId>),
+    /// Pause the given servers. They will be resumed on recovery.
+    Pause(HashSet<ServerId>),
+    /// Kill the server and wipe its storage by
+    /// [`NemesisClusterClient::wipe_storage`], so it comes back empty when
+    /// restarted on recovery.
+    Wipe(ServerId),
+    /// Kill the leader at the time of execution. Then wait for a new leader
+    /// to be elected and kill it too, `chase` more times.
+    KillLeader { chase: usize },
+    /// Pause the leader at the time of execution. Then wait for a new leader
+    /// to be elected and pause it too, `chase` more times.
+    PauseLeader { chase: usize },
+    /// Split the cluster into two random halves.
+    PartitionHalves,
+    /// Every server can only see a majority of servers, which are its
+    /// neighbours in a random ring. No two servers see the same majority.
+    PartitionMajoritiesRing,
+    /// Isolate a random server from all the others.
+    PartitionRandomNode,
+    /// Split the cluster into the given groups, e.g. a 3-way split or an
+    /// isolated pair. Servers in no group form another group together.
+    PartitionNamed { groups: Vec<HashSet<ServerId>> },
+    /// Flip `bits` random bits in the write-ahead log of `server`.
+    BitflipWal { server: ServerId, bits: usize },
+    /// Flip `bits` random bits in the latest snapshot of `server`.
+    BitflipSnap { server: ServerId, bits: usize },
+    /// Truncate the last `bytes` bytes of the write-ahead log of `server`, to
+    /// simulate a torn write.
+    TruncateWal { server: ServerId, bytes: u64 },
+    /// Stress the disk of `server`, see [`DiskStressMode`]. Executed by the
+    /// [`StorageFaultInjector`] of the cluster.
+    DiskStress {
+        server: ServerId,
+        mode: DiskStressMode,
+    },
+    /// Remove the server from the cluster membership. It will be added back on
+    /// recovery. Executed by the [`MembershipClusterClient`] of the cluster.
+    RemoveNode(ServerId),
+    /// Add the server into the cluster membership. Executed by the
+    /// [`MembershipClusterClient`] of the cluster.
+    AddNode(ServerId),
+    /// Slow the server down by `factor` times, like an overloaded machine.
+    /// The server is paused for `factor - 1` slices after every running
+    /// slice, until recovery. Requires `--cfg madsim`.
+    Lag { server: ServerId, factor: u32 },
+    /// Shift the clocks of the given servers by `offset` milliseconds.
+    ClockSkew {
+        servers: HashSet<ServerId>,
+        offset: i64,
+    },
+    /// Repeatedly shift the clocks of the given servers by `delta`
+    /// milliseconds back and forth every `period`, lasting for `duration`.
+    ClockStrobe {
+        servers: HashSet<ServerId>,
+        delta: i64,
+        period: Duration,
+        duration: Duration,
+    },
+    /// Make the clocks of the given servers run faster (or slower, if
+    /// negative) by `ppm` parts per million.
+    ClockDrift {
+        servers: HashSet<ServerId>,
+        ppm: i64,
+    },
+}
+
+/// A record of an executed nemesis, which holds everything needed to recover
+/// from it. Nemeses that cannot be recovered (e.g. bitflip) produce no record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NemesisRecord {
+    Kill(HashSet<ServerId>),
+    Pause(HashSet<ServerId>),
+    /// The clogged links, in `(src, dst)` form.
+    Partition(Vec<(ServerId, ServerId)>),
+    /// The servers whose clocks are changed.
+    Clock(HashSet<ServerId>),
+    DiskStress {
+        server: ServerId,
+        mode: DiskStressMode,
+    },
+    /// The server removed from the membership.
+    RemoveNode(ServerId),
+    /// The lagging server.
+    Lag(ServerId),
+}
+
+/// The nemesis function type recorded in history, corresponds to the `:f` of a
+/// jepsen nemesis operation.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum SerializableNemesisType {
+    Kill,
+    Pause,
+    Resume,
+    Partition,
+    Heal,
+    Clock,
+    /// Reset the clocks.
+    Reset,
+    BitflipWal,
+    BitflipSnap,
+    TruncateWal,
+    DiskStress,
+    AddNode,
+    RemoveNode,
+    Lag,
+    Wipe,
+    /// A server is observed up by [`NemesisClusterClient::health`].
+    NodeUp,
+    /// A server is observed down by [`NemesisClusterClient::health`].
+    NodeDown,
+}
+
+impl From<&NemesisType> for SerializableNemesisType {
+    fn from(nemesis: &NemesisType) -> Self {
+        match nemesis {
+            NemesisType::Kill(_) | NemesisType::KillLeader { .. } => Self::Kill,
+            NemesisType::Pause(_) | NemesisType::PauseLeader { .. } => Self::Pause,
+            NemesisType::PartitionHalves
+            | NemesisType::PartitionMajoritiesRing
+            | NemesisType::PartitionRandomNode
+            | NemesisType::PartitionNamed { .. } => Self::Partition,
+            NemesisType::BitflipWal { .. } => Self::BitflipWal,
+            NemesisType::BitflipSnap { .. } => Self::BitflipSnap,
+            NemesisType::TruncateWal { .. } => Self::TruncateWal,
+            NemesisType::DiskStress { .. } => Self::DiskStress,
+            NemesisType::RemoveNode(_) => Self::RemoveNode,
+            NemesisType::AddNode(_) => Self::AddNode,
+            NemesisType::Lag { .. } => Self::Lag,
+            NemesisType::Wipe(_) => Self::Wipe,
+            NemesisType::ClockSkew { .. }
+            | NemesisType::ClockStrobe { .. }
+            | NemesisType::ClockDrift { .. } => Self::Clock,
+        }
+    }
+}
+
+impl SerializableNemesisType {
+    /// The function of the heal of the fault, `None` if it's not a fault
+    /// healed later, e.g. a bitflip or a heal itself.
+    pub fn healed_by(&self) -> Option<Self> {
+        match self {
+            Self::Kill | Self::Pause => Some(Self::Resume),
+            Self::Partition | Self::DiskStress | Self::Lag => Some(Self::Heal),
+            Self::Clock => Some(Self::Reset),
+            Self::RemoveNode => Some(Self::AddNode),
+            Self::NodeDown => Some(Self::NodeUp),
+            _ => None,
+        }
+    }
+}
+
+impl NemesisType {
+    /// The servers named by the nemesis. Servers chosen at execution time (e.g.
+    /// the leader) are not included.
+    pub fn named_servers(&self) -> Vec<ServerId> {
+        match self {
+            NemesisType::Kill(servers)
+            | NemesisType::Pause(servers)
+            | NemesisType::ClockSkew { servers, .. }
+            | NemesisType::ClockStrobe { servers, .. }
+            | NemesisType::ClockDrift { servers, .. } => servers.iter().copied().collect(),
+            NemesisType::PartitionNamed { groups } => groups.iter().flatten().copied().collect(),
+            NemesisType::BitflipWal { server, .. }
+            | NemesisType::BitflipSnap { server, .. }
+            | NemesisType::TruncateWal { server, .. }
+            | NemesisType::DiskStress { server, .. }
+            | NemesisType::Lag { server, .. }
+            | NemesisType::Wipe(server)
+            | NemesisType::RemoveNode(server) => vec![*server],
+            // a new server may be out of the current cluster
+            NemesisType::AddNode(_)
+            | NemesisType::KillLeader { .. }
+            | NemesisType::PauseLeader { .. }
+            | NemesisType::PartitionHalves
+            | NemesisType::PartitionMajoritiesRing
+            | NemesisType::PartitionRandomNode => vec![],
+        }
+    }
+}
+
+impl NemesisRecord {
+    /// The servers affected by the record. Empty for partitions, see
+    /// [`NemesisRecord::links`].
+    pub fn servers(&self) -> Vec<ServerId> {
+        match self {
+            NemesisRecord::Kill(servers)
+            | NemesisRecord::Pause(servers)
+            | NemesisRecord::Clock(servers) => servers.iter().copied().collect(),
+            NemesisRecord::DiskStress { server, .. }
+            | NemesisRecord::RemoveNode(server)
+            | NemesisRecord::Lag(server) => vec![*server],
+            NemesisRecord::Partition(_) => vec![],
+        }
+    }
+
+    /// The clogged links of a partition record.
+    pub fn links(&self) -> &[(ServerId, ServerId)] {
+        match self {
+            NemesisRecord::Partition(links) => links,
+            _ => &[],
+        }
+    }
+}
+
+/// The function type of the recovery of a [`NemesisRecord`].
+impl From<&NemesisRecord> for SerializableNemesisType {
+    fn from(record: &NemesisRecord) -> Self {
+        match record {
+            NemesisRecord::Kill(_) | NemesisRecord::Pause(_) => Self::Resume,
+            NemesisRecord::Partition(_)
+            | NemesisRecord::DiskStress { .. }
+            | NemesisRecord::Lag(_) => Self::Heal,
+            NemesisRecord::Clock(_) => Self::Reset,
+            NemesisRecord::RemoveNode(_) => Self::AddNode,
+        }
+    }
+}
+
+/// The kind of a server's on-disk storage file that a storage nemesis targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageFile {
+    /// The write-ahead log.
+    Wal,
+    /// The latest snapshot.
+    Snapshot,
+}
+
+/// The way to stress the disk of a server.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiskStressMode {
+    /// Fill the disk until it's full.
+    FillDisk,
+    /// Make every write fail with `ENOSPC`.
+    Enospc,
+    /// Delay every fsync by the given duration.
+    SlowFsync(Duration),
+}
+
+/// The interface to inject storage faults into a cluster, which can be
+/// optionally implemented by the external user, see
+/// [`NemesisClusterClient::storage_fault_injector`].
+#[async_trait::async_trait]
+pub trait StorageFaultInjector {
+    /// Start stressing the disk of the server.
+    async fn inject(&self, server: ServerId, mode: &DiskStressMode) -> Result<(), String>;
+    /// Stop stressing the disk of the server, e.g. free the filled space.
+    async fn clear(&self, server: ServerId, mode: &DiskStressMode) -> Result<(), String>;
+}
+
+/// The interface to kill, pause and partition the servers of a real cluster,
+/// which can be optionally implemented by the external user, see
+/// [`NemesisClusterClient::fault_executor`]. It's how the nemeses reach the
+/// servers when the test runs on tokio instead of madsim, see
+/// [`crate::runtime`].
+#[async_trait::async_trait]
+pub trait FaultExecutor {
+    async fn kill(&self, server: ServerId) -> Result<(), String>;
+    /// Restart the killed server.
+    async fn restart(&self, server: ServerId) -> Result<(), String>;
+    async fn pause(&self, server: ServerId) -> Result<(), String>;
+    async fn resume(&self, server: ServerId) -> Result<(), String>;
+    /// Drop the packets from `src` to `dst`.
+    async fn clog(&self, src: ServerId, dst: ServerId) -> Result<(), String>;
+    async fn unclog(&self, src: ServerId, dst: ServerId) -> Result<(), String>;
+}
+
+/// The interface to change the membership of a cluster, which can be
+/// optionally implemented by the external user, see
+/// [`NemesisClusterClient::membership`].
+#[async_trait::async_trait]
+pub trait MembershipClusterClient {
+    /// Add the server into the cluster, returns after the membership change is
+    /// committed.
+    async fn add_node(&self, server: ServerId) -> Result<(), String>;
+    /// Remove the server from the cluster, returns after the membership change
+    /// is committed.
+    async fn remove_node(&self, server: ServerId) -> Result<(), String>;
+}
+
+/// The health of a server, as observed by [`NemesisClusterClient::health`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeHealth {
+    /// The server is serving requests.
+    Up,
+    /// The server is unreachable or not serving requests.
+    Down,
+    /// The health cannot be told, which is never recorded.
+    Unknown,
+}
+
+/// The interface of a cluster that can be nemesized, needs to be implemented
+/// by the external user.
+#[async_trait::async_trait]
+pub trait NemesisClusterClient {
+    /// The number of servers in the cluster.
+    fn size(&self) -> usize;
+
+    /// Get the madsim node id of the given server.
+    #[cfg(madsim)]
+    fn get_node_id(&self, id: ServerId) -> Option<madsim::task::NodeId>;
+
+    /// Get the current leader of the cluster, without waiting for a term.
+    async fn get_leader_without_term(&self) -> ServerId;
+
+    /// Get the storage fault injector of the cluster. Returns `None` by
+    /// default, which means the cluster cannot be disk-stressed.
+    fn storage_fault_injector(&self) -> Option<&(dyn StorageFaultInjector + Sync)> {
+        None
+    }
+
+    /// Get the executor of the faults of the cluster. If it returns `Some`,
+    /// kill, pause and partition nemeses are delegated to it rather than
+    /// applied on the local processes or madsim nodes. Returns `None` by
+    /// default.
+    fn fault_executor(&self) -> Option<&(dyn FaultExecutor + Sync)> {
+        None
+    }
+
+    /// Get the client of the local processes of the cluster. If it returns
+    /// `Some`, kill, pause and partition nemeses are executed on the
+    /// processes rather than madsim nodes. Returns `None` by default.
+    #[cfg(feature = "os")]
+    fn os(&self) -> Option<&(dyn os::OsClusterClient + Sync)> {
+        None
+    }
+
+    /// Probe the health of a server, e.g. by a ping or a status endpoint. It's
+    /// polled during the test if
+    /// [`JepsenClient::with_health_probe`](crate::client::JepsenClient::with_health_probe)
+    /// is set. Returns [`NodeHealth::Unknown`] by default.
+    async fn health(&self, _server: ServerId) -> NodeHealth {
+        NodeHealth::Unknown
+    }
+
+    /// Get the membership client of the cluster. Returns `None` by default,
+    /// which means the membership of the cluster cannot be changed.
+    fn membership(&self) -> Option<&(dyn MembershipClusterClient + Sync)> {
+        None
+    }
+
+    /// Locate the given storage file of a server on disk. Returns `None` if the
+    /// server has no such file.
+    async fn locate_file(&self, _server: ServerId, _file: StorageFile) -> Option<PathBuf> {
+        None
+    }
+
+    /// Flip `bits` random bits in the given storage file of a server. The
+    /// default implementation corrupts the file found by
+    /// [`NemesisClusterClient::locate_file`]; override it to corrupt a
+    /// simulated storage directly.
+    async fn bitflip(
+        &self,
+        server: ServerId,
+        file: StorageFile,
+        bits: usize,
+    ) -> Result<(), String> {
+        let path = self
+            .locate_file(server, file)
+            .await
+            .ok_or_else(|| format!("cannot locate {:?} file of server {}", file, server))?;
+        flip_bits(&path, bits).map_err(|e| format!("failed to bitflip {:?}: {}", path, e))
+    }
+
+    /// Truncate the last `bytes` bytes of the write-ahead log of a server. The
+    /// default implementation truncates the file found by
+    /// [`NemesisClusterClient::locate_file`]; override it to truncate a
+    /// simulated storage directly.
+    async fn truncate_wal(&self, server: ServerId, bytes: u64) -> Result<(), String> {
+        let path = self
+            .locate_file(server, StorageFile::Wal)
+            .await
+            .ok_or_else(|| format!("cannot locate wal file of server {}", server))?;
+        truncate_tail(&path, bytes).map_err(|e| format!("failed to truncate {:?}: {}", path, e))
+    }
+
+    /// Clear the storage of a killed server. The default implementation
+    /// removes the files found by [`NemesisClusterClient::locate_file`];
+    /// override it to clear a simulated storage directly.
+    async fn wipe_storage(&self, server: ServerId) -> Result<(), String> {
+        let mut wiped = false;
+        for file in [StorageFile::Wal, StorageFile::Snapshot] {
+            let Some(path) = self.locate_file(server, file).await else {
+                continue;
+            };
+            std::fs::remove_file(&path)
+                .map_err(|e| format!("failed to remove {:?}: {}", path, e))?;
+            wiped = true;
+        }
+        if wiped {
+            Ok(())
+        } else {
+            Err(format!("cannot locate storage files of server {}", server))
+        }
+    }
+
+    /// Shift the clocks of the servers by `offset` milliseconds. The default
+    /// implementation jumps the simulated clock forward, which is shared by
+    /// all the madsim nodes and cannot go backwards; override it for a real
+    /// cluster.
+    async fn skew_clock(&self, _servers: &HashSet<ServerId>, offset: i64) -> Result<(), String> {
+        implementation::advance_sim_clock(offset)
+    }
+
+    /// Strobe the clocks of the servers by `delta` milliseconds every `period`,
+    /// lasting for `duration`. The default implementation skews the clocks
+    /// back and forth by [`NemesisClusterClient::skew_clock`]; under madsim,
+    /// it jumps the simulated clock forward every period instead.
+    async fn strobe_clock(
+        &self,
+        servers: &HashSet<ServerId>,
+        delta: i64,
+        period: Duration,
+        duration: Duration,
+    ) -> Result<(), String> {
+        let start = madsim::time::Instant::now();
+        let mut sign = 1;
+        while start.elapsed() < duration {
+            self.skew_clock(servers, sign * delta).await?;
+            if cfg!(not(madsim)) {
+                sign = -sign;
+            }
+            madsim::time::sleep(period).await;
+        }
+        Ok(())
+    }
+
+    /// Make the clocks of the servers drift by `ppm` parts per million. Not
+    /// supported by default, as the simulated clock cannot drift.
+    async fn drift_clock(&self, _servers: &HashSet<ServerId>, _ppm: i64) -> Result<(), String> {
+        Err("clock drift is not supported by this cluster".to_string())
+    }
+
+    /// Reset the clocks of the servers to the real time. The default
+    /// implementation does nothing under madsim, as the simulated clock has no
+    /// skew to reset.
+    async fn reset_clock(&self, _servers: &HashSet<ServerId>) -> Result<(), String> {
+        if cfg!(madsim) {
+            Ok(())
+        } else {
+            Err("resetting clocks is not supported by this cluster".to_string())
+        }
+    }
+}
+
+/// Flip `bits` random bits of the file in place.
+pub fn flip_bits(path: impl AsRef<Path>, bits: usize) -> io::Result<()> {
+    let mut data = std::fs::read(path.as_ref())?;
+    if data.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "file is empty"));
+    }
+    let mut rng = rand::thread_rng();
+    for _ in 0..bits {
+        let pos = rng.gen_range(0..data.len() * 8);
+        data[pos / 8] ^= 1 << (pos % 8);
+    }
+    std::fs::write(path, data)
+}
+
+/// Remove the last `bytes` bytes of the file. The file will be empty if it's
+/// shorter than `bytes`.
+pub fn truncate_tail(path: impl AsRef<Path>, bytes: u64) -> io::Result<()> {
+    let file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    file.set_len(len.saturating_sub(bytes))
+}
+
+/// All the servers of a cluster with the given size.
+fn servers(size: usize) -> Vec<ServerId> {
+    (0..size as ServerId).collect()
+}
+
+/// Returns the bidirectional links between the two groups.
+fn links_between(a: &[ServerId], b: &[ServerId]) -> Vec<(ServerId, ServerId)> {
+    a.iter()
+        .flat_map(|&x| b.iter().flat_map(move |&y| [(x, y), (y, x)]))
+        .collect()
+}
+
+/// Links to clog to split the cluster into two random halves.
+pub fn partition_halves(size: usize) -> Vec<(ServerId, ServerId)> {
+    let mut servers = servers(size);
+    servers.shuffle(&mut rand::thread_rng());
+    let (a, b) = servers.split_at(size / 2);
+    links_between(a, b)
+}
+
+/// Links to clog to make every server see only the majority around it in a
+/// random ring.
+pub fn partition_majorities_ring(size: usize) -> Vec<(ServerId, ServerId)> {
+    let mut ring = servers(size);
+    ring.shuffle(&mut rand::thread_rng());
+    let distance = (size / 2).div_ceil(2);
+    let mut links = vec![];
+    for i in 0..size {
+        for j in 0..size {
+            let d = i.abs_diff(j);
+            if d.min(size - d) > distance {
+                links.push((ring[i], ring[j]));
+            }
+        }
+    }
+    links
+}
+
+/// Links to clog to isolate a random server from the others.
+pub fn partition_random_node(size: usize) -> Vec<(ServerId, ServerId)> {
+    let mut servers = servers(size);
+    servers.shuffle(&mut rand::thread_rng());
+    let (a, b) = servers.split_at(1.min(size));
+    links_between(a, b)
+}
+
+/// Links to clog to split the cluster into the given groups. The servers in no
+/// group form another group. Returns an error if a server is out of the
+/// cluster or in more than one group.
+pub fn partition_groups(
+    size: usize,
+    groups: &[HashSet<ServerId>],
+) -> Result<Vec<(ServerId, ServerId)>, String> {
+    let mut seen = HashSet::new();
+    for server in groups.iter().flatten() {
+        if *server >= size as ServerId {
+            return Err(format!(
+                "server {} is out of the cluster of size {}",
+                server, size
+            ));
+        }
+        if !seen.insert(*server) {
+            return Err(format!("server {} is in more than one group", server));
+        }
+    }
+    let mut groups: Vec<Vec<_>> = groups.iter().map(|g| g.iter().copied().collect()).collect();
+    let rest: Vec<_> = servers(size)
+        .into_iter()
+        .filter(|s| !seen.contains(s))
+        .collect();
+    groups.push(rest);
+    let mut links = vec![];
+    for (i, a) in groups.iter().enumerate() {
+        for b in &groups[i + 1..] {
+            links.extend(links_between(a, b));
+        }
+    }
+    Ok(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Count the servers that each server can still send to.
+    fn reachable(size: usize, links: &[(ServerId, ServerId)]) -> HashMap<ServerId, usize> {
+        let links: HashSet<_> = links.iter().collect();
+        servers(size)
+            .into_iter()
+            .map(|x| {
+                let n = servers(size)
+                    .into_iter()
+                    .filter(|&y| !links.contains(&(x, y)))
+                    .count();
+                (x, n)
+            })
+            .collect()
+    }
+
+    #[madsim::test]
+    async fn test_partition_halves() {
+        let links = partition_halves(5);
+        assert_eq!(links.len(), 2 * 2 * 3);
+        let mut r: Vec<_> = reachable(5, &links).into_values().collect();
+        r.sort();
+        assert_eq!(r, vec![2, 2, 3, 3, 3]);
+    }
+
+    #[madsim::test]
+    async fn test_partition_majorities_ring() {
+        for size in [5, 7] {
+            let links = partition_majorities_ring(size);
+            assert!(!links.is_empty());
+            assert!(reachable(size, &links)
+                .into_values()
+                .all(|n| n > size / 2 && n < size));
+        }
+    }
+
+    #[madsim::test]
+    async fn test_partition_random_node() {
+        let links = partition_random_node(3);
+        assert_eq!(links.len(), 4);
+        let mut r: Vec<_> = reachable(3, &links).into_values().collect();
+        r.sort();
+        assert_eq!(r, vec![1, 2, 2]);
+    }
+
+    #[test]
+    fn test_partition_groups() {
+        let groups = [HashSet::from([0, 1]), HashSet::from([2, 3])];
+        let links = partition_groups(5, &groups).unwrap();
+        let r = reachable(5, &links);
+        assert_eq!(r[&0], 2);
+        assert_eq!(r[&3], 2);
+        assert_eq!(r[&4], 1);
+        assert!(partition_groups(3, &[HashSet::from([3])]).is_err());
+        assert!(partition_groups(3, &[HashSet::from([0]), HashSet::from([0, 1])]).is_err());
+    }
+
+    #[madsim::test]
+    async fn test_flip_bits() -> io::Result<()> {
+        let path = std::env::temp_dir().join("jepsen_rs_test_flip_bits");
+        std::fs::write(&path, [0u8; 64])?;
+        flip_bits(&path, 1)?;
+        let data = std::fs::read(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(data.iter().map(|x| x.count_ones()).sum::<u32>(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_tail() -> io::Result<()> {
+        let path = std::env::temp_dir().join("jepsen_rs_test_truncate_tail");
+        std::fs::write(&path, [1u8; 10])?;
+        truncate_tail(&path, 3)?;
+        assert_eq!(std::fs::read(&path)?, [1u8; 7]);
+        truncate_tail(&path, 100)?;
+        assert!(std::fs::read(&path)?.is_empty());
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_nemesis_type_serialization() {
+        let tags = [
+            (SerializableNemesisType::Kill, r#""kill""#),
+            (SerializableNemesisType::BitflipWal, r#""bitflip-wal""#),
+            (SerializableNemesisType::TruncateWal, r#""truncate-wal""#),
+            (SerializableNemesisType::Reset, r#""reset""#),
+            (SerializableNemesisType::DiskStress, r#""disk-stress""#),
+            (SerializableNemesisType::RemoveNode, r#""remove-node""#),
+        ];
+        for (tag, json) in tags {
+            assert_eq!(serde_json::to_string(&tag).unwrap(), json);
+        }
+        let bitflip = NemesisType::BitflipSnap { server: 1, bits: 2 };
+        assert_eq!(
+            SerializableNemesisType::from(&bitflip),
+            SerializableNemesisType::BitflipSnap
+        );
+        let record = NemesisRecord::Kill(HashSet::from([1]));
+        assert_eq!(
+            SerializableNemesisType::from(&record),
+            SerializableNemesisType::Resume
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Nemeses on real local processes instead of madsim nodes: Pause via
+//! `SIGSTOP`/`SIGCONT`, Kill via `SIGKILL` plus a restart command, and
+//! Partition via `iptables`. Enabled by the `os` feature, and selected by
+//! returning an [`OsClusterClient`] from [`NemesisClusterClient::os`].
+//!
+//! Partitions drop the packets between the addresses of servers, so each
+//! server should listen on an address of its own, e.g. `127.0.0.1`,
+//! `127.0.0.2`... Modifying iptables requires root.
+//!
+//! The commands run on the host of the server by [`OsClusterClient::command`],
+//! which is the local host by default, and a remote one over ssh for the
+//! servers of the `SshCluster` of the `ssh` feature.
+//!
+//! [`NemesisClusterClient::os`]: super::NemesisClusterClient::os
+
+use std::{collections::HashSet, net::IpAddr, process::Command};
+
+use log::debug;
+
+use super::{implementation::SimAction, ServerId};
+
+/// A cluster of real local processes.
+pub trait OsClusterClient {
+    /// The pid of the running process of the server. It's looked up every
+    /// time (e.g. from a pid file) as it changes after restart.
+    fn pid(&self, server: ServerId) -> Result<u32, String>;
+    /// The command to restart the killed server.
+    fn restart_command(&self, server: ServerId) -> Command;
+    /// The address the server listens on.
+    fn address(&self, server: ServerId) -> IpAddr;
+    /// The command running the program on the host of the server, the local
+    /// host by default.
+    fn command(&self, server: ServerId, program: &str) -> Command {
+        let _ = server;
+        Command::new(program)
+    }
+}
+
+/// Run a command to its end, and fail if it exits abnormally.
+fn run(command: Command) -> Result<(), String> {
+    output(command).map(|_| ())
+}
+
+/// Run a command to its end, returns its stdout, and fail if it exits
+/// abnormally.
+pub(super) fn output(mut command: Command) -> Result<String, String> {
+    debug!("run command: {:?}", command);
+    let output = command
+        .output()
+        .map_err(|e| format!("failed to run {:?}: {}", command, e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!(
+            "{:?} exited with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Send the signal by the `kill` command.
+fn signal(mut command: Command, pid: u32, signal: &str) -> Command {
+    command.arg(format!("-{}", signal)).arg(pid.to_string());
+    command
+}
+
+/// Add (or delete) the rule dropping the packets from `src` to `dst` by the
+/// `iptables` command, which runs on the host of `src`.
+fn iptables(mut command: Command, src: IpAddr, dst: IpAddr, add: bool) -> Command {
+    command
+        .arg(if add { "-A" } else { "-D" })
+        .arg("OUTPUT")
+        .args(["-s", &src.to_string(), "-d", &dst.to_string()])
+        .args(["-j", "DROP"]);
+    command
+}
+
+/// Apply the action on the local processes.
+pub(super) fn apply(
+    client: &(dyn OsClusterClient + Sync),
+    action: SimAction<'_>,
+) -> Result<(), String> {
+    let signal_all = |servers: &HashSet<ServerId>, sig| -> Result<(), String> {
+        for s in servers {
+            run(signal(client.command(*s, "kill"), client.pid(*s)?, sig))?;
+        }
+        Ok(())
+    };
+    let clog_all = |links: &[(ServerId, ServerId)], add| -> Result<(), String> {
+        for (src, dst) in links {
+            let command = client.command(*src, "iptables");
+            run(iptables(
+                command,
+                client.address(*src),
+                client.address(*dst),
+                add,
+            ))?;
+        }
+        Ok(())
+    };
+    match action {
+        SimAction::Kill(servers) => signal_all(servers, "KILL"),
+        SimAction::Pause(servers) => signal_all(servers, "STOP"),
+        SimAction::Resume(servers) => signal_all(servers, "CONT"),
+        SimAction::Restart(servers) => {
+            for s in servers {
+                let mut command = client.restart_command(*s);
+                command
+                    .spawn()
+                    .map_err(|e| format!("failed to restart server {}: {}", s, e))?;
+            }
+            Ok(())
+        }
+        SimAction::Clog(links) => clog_all(links, true),
+        SimAction::Unclog(links) => clog_all(links, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|x| x.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_os_commands() {
+        assert_eq!(
+            args(&signal(Command::new("kill"), 42, "STOP")),
+            ["-STOP", "42"]
+        );
+        let src = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let dst = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        assert_eq!(
+            args(&iptables(Command::new("iptables"), src, dst, false)),
+            [
+                "-D",
+                "OUTPUT",
+                "-s",
+                "127.0.0.1",
+                "-d",
+                "127.0.0.2",
+                "-j",
+                "DROP"
+            ]
+        );
+        assert!(run(Command::new("false")).is_err());
+        assert!(run(Command::new("true")).is_ok());
+        assert_eq!(output(Command::new("echo")).as_deref(), Ok("\n"));
+    }
+}
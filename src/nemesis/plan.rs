@@ -0,0 +1,309 @@
+//! Static validation of the nemeses a test would execute, which finds the
+//! invalid and no-op nemeses before launching an expensive run.
+
+use super::{
+    partition_groups, policy::NemesisPolicy, NemesisClusterClient, NemesisType, ServerId,
+    StorageFile,
+};
+
+/// The kind of a [`PlanIssue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanIssueKind {
+    /// The nemesis will fail to execute.
+    Invalid,
+    /// The nemesis will execute but changes nothing.
+    NoOp,
+}
+
+/// An invalid or no-op nemesis found in the plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanIssue {
+    /// The index of the nemesis in the plan.
+    pub index: usize,
+    pub nemesis: NemesisType,
+    pub kind: PlanIssueKind,
+    pub reason: String,
+}
+
+/// The report of validating a nemesis plan.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NemesisPlanReport {
+    /// The number of nemeses checked.
+    pub checked: usize,
+    pub issues: Vec<PlanIssue>,
+}
+
+impl NemesisPlanReport {
+    /// Validate the nemeses against the cluster and the policy, without
+    /// executing them.
+    pub async fn validate(
+        nemeses: impl IntoIterator<Item = NemesisType>,
+        client: &(impl NemesisClusterClient + Sync),
+        policy: Option<&NemesisPolicy>,
+    ) -> Self {
+        let mut report = Self::default();
+        for (index, nemesis) in nemeses.into_iter().enumerate() {
+            report.checked += 1;
+            if let Err((kind, reason)) = validate_nemesis(&nemesis, client, policy).await {
+                report.issues.push(PlanIssue {
+                    index,
+                    nemesis,
+                    kind,
+                    reason,
+                });
+            }
+        }
+        report
+    }
+
+    /// Whether no issue is found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// The nemeses that will fail to execute.
+    pub fn invalid(&self) -> impl Iterator<Item = &PlanIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.kind == PlanIssueKind::Invalid)
+    }
+}
+
+/// Check a nemesis statically, returns the issue if any.
+async fn validate_nemesis(
+    nemesis: &NemesisType,
+    client: &(impl NemesisClusterClient + Sync),
+    policy: Option<&NemesisPolicy>,
+) -> Result<(), (PlanIssueKind, String)> {
+    use PlanIssueKind::*;
+
+    let size = client.size();
+    let servers = nemesis.named_servers();
+    if let Some(server) = servers.iter().find(|s| **s >= size as ServerId) {
+        return Err((
+            Invalid,
+            format!("server {} is out of the cluster of size {}", server, size),
+        ));
+    }
+    // the servers whose madsim nodes are needed
+    let sim_servers: Option<Vec<ServerId>> = match nemesis {
+        NemesisType::Kill(_)
+        | NemesisType::Pause(_)
+        | NemesisType::Lag { .. }
+        | NemesisType::Wipe(_) => Some(servers.clone()),
+        NemesisType::KillLeader { .. }
+        | NemesisType::PauseLeader { .. }
+        | NemesisType::PartitionHalves
+        | NemesisType::PartitionMajoritiesRing
+        | NemesisType::PartitionRandomNode
+        | NemesisType::PartitionNamed { .. } => Some((0..size as ServerId).collect()),
+        _ => None,
+    };
+    if let Some(sim_servers) = sim_servers {
+        check_sim_nodes(client, &sim_servers).map_err(|e| (Invalid, e))?;
+    }
+    match nemesis {
+        NemesisType::Kill(servers) | NemesisType::Pause(servers) if servers.is_empty() => {
+            return Err((NoOp, "targets no server".to_string()));
+        }
+        NemesisType::ClockSkew { servers, .. }
+        | NemesisType::ClockStrobe { servers, .. }
+        | NemesisType::ClockDrift { servers, .. }
+            if servers.is_empty() =>
+        {
+            return Err((NoOp, "targets no server".to_string()));
+        }
+        NemesisType::ClockSkew { offset: 0, .. }
+        | NemesisType::ClockStrobe { delta: 0, .. }
+        | NemesisType::ClockDrift { ppm: 0, .. } => {
+            return Err((NoOp, "shifts the clocks by 0".to_string()));
+        }
+        NemesisType::ClockStrobe { duration, .. } if duration.is_zero() => {
+            return Err((NoOp, "strobes the clocks for no time".to_string()));
+        }
+        NemesisType::PartitionHalves
+        | NemesisType::PartitionMajoritiesRing
+        | NemesisType::PartitionRandomNode
+            if size < 2 =>
+        {
+            return Err((NoOp, "partitions no link".to_string()));
+        }
+        NemesisType::PartitionNamed { groups } => match partition_groups(size, groups) {
+            Err(err) => return Err((Invalid, err)),
+            Ok(links) if links.is_empty() => return Err((NoOp, "partitions no link".to_string())),
+            Ok(_) => {}
+        },
+        NemesisType::BitflipWal { bits: 0, .. } | NemesisType::BitflipSnap { bits: 0, .. } => {
+            return Err((NoOp, "flips no bit".to_string()));
+        }
+        NemesisType::TruncateWal { bytes: 0, .. } => {
+            return Err((NoOp, "truncates no byte".to_string()));
+        }
+        NemesisType::BitflipWal { server, .. }
+        | NemesisType::BitflipSnap { server, .. }
+        | NemesisType::TruncateWal { server, .. } => {
+            let file = match nemesis {
+                NemesisType::BitflipSnap { .. } => StorageFile::Snapshot,
+                _ => StorageFile::Wal,
+            };
+            if client.locate_file(*server, file).await.is_none() {
+                return Err((
+                    Invalid,
+                    format!("cannot locate {:?} file of server {}", file, server),
+                ));
+            }
+        }
+        NemesisType::DiskStress { .. } if client.storage_fault_injector().is_none() => {
+            return Err((
+                Invalid,
+                "the cluster has no storage fault injector".to_string(),
+            ));
+        }
+        NemesisType::RemoveNode(_) | NemesisType::AddNode(_) if client.membership().is_none() => {
+            return Err((
+                Invalid,
+                "the cluster does not support membership changes".to_string(),
+            ));
+        }
+        NemesisType::Lag { factor, .. } if *factor < 2 => {
+            return Err((
+                Invalid,
+                format!("the lag factor should be at least 2, got {}", factor),
+            ));
+        }
+        _ => {}
+    }
+    // rejected even if no other nemesis is active
+    if let Some(policy) = policy {
+        policy.check(nemesis, &[]).map_err(|err| {
+            (
+                Invalid,
+                format!("always rejected by nemesis policy: {}", err),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(madsim)]
+fn check_sim_nodes(client: &impl NemesisClusterClient, servers: &[ServerId]) -> Result<(), String> {
+    match servers.iter().find(|s| client.get_node_id(**s).is_none()) {
+        Some(server) => Err(format!("cannot find the madsim node of server {}", server)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(madsim))]
+fn check_sim_nodes(
+    _client: &impl NemesisClusterClient,
+    _servers: &[ServerId],
+) -> Result<(), String> {
+    Err("nemeses on simulated nodes require `--cfg madsim`".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::nemesis::{DiskStressMode, MembershipClusterClient};
+
+    /// A cluster of 3 servers without any nemesis handle.
+    struct BareCluster;
+
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for BareCluster {
+        fn size(&self) -> usize {
+            3
+        }
+        #[cfg(madsim)]
+        fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+            None
+        }
+        async fn get_leader_without_term(&self) -> ServerId {
+            0
+        }
+    }
+
+    #[madsim::test]
+    async fn test_validate_nemesis_plan() {
+        let plan = vec![
+            NemesisType::ClockSkew {
+                servers: HashSet::from([0]),
+                offset: 100,
+            },
+            NemesisType::ClockSkew {
+                servers: HashSet::from([3]),
+                offset: 100,
+            },
+            NemesisType::ClockDrift {
+                servers: HashSet::from([1]),
+                ppm: 0,
+            },
+            NemesisType::DiskStress {
+                server: 0,
+                mode: DiskStressMode::Enospc,
+            },
+            NemesisType::Kill(HashSet::from([0])),
+            NemesisType::BitflipWal { server: 0, bits: 0 },
+            NemesisType::RemoveNode(1),
+        ];
+        let report = NemesisPlanReport::validate(plan, &BareCluster, None).await;
+        assert_eq!(report.checked, 7);
+        let issues: Vec<_> = report.issues.iter().map(|i| (i.index, i.kind)).collect();
+        assert_eq!(
+            issues,
+            vec![
+                (1, PlanIssueKind::Invalid),
+                (2, PlanIssueKind::NoOp),
+                (3, PlanIssueKind::Invalid),
+                (4, PlanIssueKind::Invalid),
+                (5, PlanIssueKind::NoOp),
+                (6, PlanIssueKind::Invalid),
+            ]
+        );
+        assert_eq!(report.invalid().count(), 4);
+    }
+
+    /// A cluster whose membership changes always succeed.
+    struct ElasticCluster;
+
+    #[async_trait::async_trait]
+    impl MembershipClusterClient for ElasticCluster {
+        async fn add_node(&self, _server: ServerId) -> Result<(), String> {
+            Ok(())
+        }
+        async fn remove_node(&self, _server: ServerId) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for ElasticCluster {
+        fn size(&self) -> usize {
+            3
+        }
+        #[cfg(madsim)]
+        fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+            None
+        }
+        async fn get_leader_without_term(&self) -> ServerId {
+            0
+        }
+        fn membership(&self) -> Option<&(dyn MembershipClusterClient + Sync)> {
+            Some(self)
+        }
+    }
+
+    #[madsim::test]
+    async fn test_validate_against_policy() {
+        let plan = [NemesisType::RemoveNode(0), NemesisType::AddNode(3)];
+        let report = NemesisPlanReport::validate(plan.clone(), &ElasticCluster, None).await;
+        assert!(report.is_ok());
+        let policy = NemesisPolicy::new(0);
+        let report = NemesisPlanReport::validate(plan, &ElasticCluster, Some(&policy)).await;
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].index, 0);
+        assert!(report.issues[0].reason.contains("policy"));
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+
+use log::trace;
+use madsim::time::{Duration, Instant};
+
+use super::{NemesisRecord, NemesisType, ServerId};
+
+/// The interval of checking whether a deferred nemesis is allowed.
+const DEFER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to do with a nemesis that violates the [`NemesisPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OnViolation {
+    /// Reject the nemesis, which is recorded in the history with an error.
+    #[default]
+    Reject,
+    /// Wait at most the duration for the active nemeses to be recovered, and
+    /// reject the nemesis if it still violates the policy.
+    Defer(Duration),
+}
+
+/// The exclusion rules of nemeses, which prevent the cluster from entering an
+/// unrecoverable state (e.g. all servers killed), in which the test can only
+/// produce `:unknown` results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NemesisPolicy {
+    /// The max number of servers that are killed, paused or removed at the
+    /// same time.
+    max_down: usize,
+    on_violation: OnViolation,
+}
+
+impl NemesisPolicy {
+    /// At most `max_down` servers can be down at the same time.
+    pub fn new(max_down: usize) -> Self {
+        Self {
+            max_down,
+            on_violation: OnViolation::default(),
+        }
+    }
+
+    /// At most a minority of the cluster can be down at the same time.
+    pub fn minority(size: usize) -> Self {
+        Self::new(size.saturating_sub(1) / 2)
+    }
+
+    pub fn with_on_violation(mut self, on_violation: OnViolation) -> Self {
+        self.on_violation = on_violation;
+        self
+    }
+
+    pub fn on_violation(&self) -> &OnViolation {
+        &self.on_violation
+    }
+
+    /// Check whether the nemesis can be executed when the given records are
+    /// active. Returns the reason if it cannot.
+    pub fn check<'a>(
+        &self,
+        nemesis: &NemesisType,
+        active: impl IntoIterator<Item = &'a NemesisRecord>,
+    ) -> Result<(), String> {
+        let mut down: HashSet<ServerId> = HashSet::new();
+        for record in active {
+            match record {
+                NemesisRecord::Kill(servers) | NemesisRecord::Pause(servers) => {
+                    down.extend(servers)
+                }
+                NemesisRecord::RemoveNode(server) => {
+                    down.insert(*server);
+                }
+                _ => {}
+            }
+        }
+        let before = down.len();
+        // the targets of leader nemeses are unknown until execution, assume
+        // they are not down yet
+        let unknown = match nemesis {
+            NemesisType::Kill(servers) | NemesisType::Pause(servers) => {
+                down.extend(servers);
+                0
+            }
+            NemesisType::RemoveNode(server) | NemesisType::Wipe(server) => {
+                down.insert(*server);
+                0
+            }
+            NemesisType::KillLeader { chase } | NemesisType::PauseLeader { chase } => chase + 1,
+            _ => 0,
+        };
+        let after = down.len() + unknown;
+        if after > before && after > self.max_down {
+            return Err(format!(
+                "{} servers would be down, exceeds the limit {}",
+                after, self.max_down
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check the nemesis like [`NemesisPolicy::check`], but wait for the active
+    /// records to be recovered according to [`OnViolation`].
+    pub async fn admit(
+        &self,
+        nemesis: &NemesisType,
+        active: impl Fn() -> Vec<NemesisRecord>,
+    ) -> Result<(), String> {
+        let timeout = match self.on_violation {
+            OnViolation::Reject => Duration::ZERO,
+            OnViolation::Defer(timeout) => timeout,
+        };
+        let start = Instant::now();
+        loop {
+            match self.check(nemesis, &active()) {
+                Err(err) if start.elapsed() < timeout => {
+                    trace!("nemesis {:?} deferred: {}", nemesis, err);
+                    madsim::time::sleep(DEFER_POLL_INTERVAL).await;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nemesis_policy() {
+        let policy = NemesisPolicy::minority(5);
+        let kill = |x: &[ServerId]| NemesisType::Kill(x.iter().copied().collect());
+        let killed = |x: &[ServerId]| NemesisRecord::Kill(x.iter().copied().collect());
+        assert!(policy.check(&kill(&[0, 1]), &[]).is_ok());
+        assert!(policy.check(&kill(&[0, 1, 2]), &[]).is_err());
+        assert!(policy.check(&kill(&[1]), &[killed(&[0])]).is_ok());
+        assert!(policy.check(&kill(&[0]), &[killed(&[0, 1])]).is_ok());
+        assert!(policy
+            .check(
+                &NemesisType::Pause(HashSet::from([2])),
+                &[killed(&[0]), NemesisRecord::RemoveNode(1)]
+            )
+            .is_err());
+        assert!(policy
+            .check(&NemesisType::KillLeader { chase: 1 }, &[killed(&[0])])
+            .is_err());
+        // nemeses which take no server down are always allowed
+        assert!(policy
+            .check(&NemesisType::PartitionHalves, &[killed(&[0, 1])])
+            .is_ok());
+    }
+
+    #[madsim::test]
+    async fn test_nemesis_policy_defer() {
+        use std::sync::{Arc, Mutex};
+
+        let policy =
+            NemesisPolicy::new(1).with_on_violation(OnViolation::Defer(Duration::from_secs(2)));
+        let nemesis = NemesisType::Kill(HashSet::from([1]));
+        let active = Arc::new(Mutex::new(vec![NemesisRecord::Kill(HashSet::from([0]))]));
+        let recover = Arc::clone(&active);
+        madsim::task::spawn(async move {
+            madsim::time::sleep(Duration::from_secs(1)).await;
+            recover.lock().unwrap().clear();
+        });
+        let get = || active.lock().unwrap().clone();
+        assert!(policy.admit(&nemesis, get).await.is_ok());
+
+        active
+            .lock()
+            .unwrap()
+            .push(NemesisRecord::Kill(HashSet::from([0])));
+        assert!(policy.admit(&nemesis, get).await.is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use madsim::{
+    rand::{self, Rng},
+    time::{Duration, Instant},
+};
+
+use super::NemesisRecord;
+
+/// The strategy of choosing which [`NemesisRecord`] to recover, when the
+/// register is full.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum NemesisRegisterStrategy {
+    /// Keep at most `usize` records, recover the oldest one first.
+    FIFO(usize),
+    /// Keep at most `usize` records, recover a random one.
+    RandomQueue(usize),
+    /// Recover every record after it has been active for the duration, no
+    /// matter how many records are in the register. The expired records are
+    /// taken by [`NemesisRegister::take_expired`].
+    TimeBased(Duration),
+}
+
+impl Default for NemesisRegisterStrategy {
+    fn default() -> Self {
+        Self::FIFO(1)
+    }
+}
+
+/// The register of the executed nemeses. Every executed nemesis which can be
+/// recovered is recorded here, and when the register is full, some records
+/// will be popped out and be recovered.
+#[derive(Debug, Default)]
+pub struct NemesisRegister {
+    /// The active records and the time they are put.
+    records: VecDeque<(NemesisRecord, Instant)>,
+    strategy: NemesisRegisterStrategy,
+}
+
+impl NemesisRegister {
+    pub fn new(strategy: NemesisRegisterStrategy) -> Self {
+        Self {
+            records: VecDeque::new(),
+            strategy,
+        }
+    }
+
+    /// The number of active records.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Iterate over the active records, from the oldest one.
+    pub fn iter(&self) -> impl Iterator<Item = &NemesisRecord> {
+        self.records.iter().map(|(r, _)| r)
+    }
+
+    pub fn strategy(&self) -> &NemesisRegisterStrategy {
+        &self.strategy
+    }
+
+    /// Put a new record into the register, returns the records that should be
+    /// recovered now.
+    pub fn put(&mut self, record: NemesisRecord) -> Vec<NemesisRecord> {
+        self.records.push_back((record, Instant::now()));
+        let mut out = vec![];
+        match self.strategy {
+            NemesisRegisterStrategy::FIFO(size) => {
+                while self.records.len() > size {
+                    out.extend(self.records.pop_front().map(|(r, _)| r));
+                }
+            }
+            NemesisRegisterStrategy::RandomQueue(size) => {
+                while self.records.len() > size {
+                    let index = rand::thread_rng().gen_range(0..self.records.len());
+                    out.extend(self.records.remove(index).map(|(r, _)| r));
+                }
+            }
+            NemesisRegisterStrategy::TimeBased(_) => {}
+        }
+        out
+    }
+
+    /// Take the records that have been active for the duration of the
+    /// [`NemesisRegisterStrategy::TimeBased`] strategy. Returns nothing for
+    /// the other strategies.
+    pub fn take_expired(&mut self) -> Vec<NemesisRecord> {
+        let NemesisRegisterStrategy::TimeBased(lifetime) = self.strategy else {
+            return vec![];
+        };
+        let mut out = vec![];
+        // records are put in time order
+        while self
+            .records
+            .front()
+            .is_some_and(|(_, put)| put.elapsed() >= lifetime)
+        {
+            out.extend(self.records.pop_front().map(|(r, _)| r));
+        }
+        out
+    }
+
+    /// The active records, oldest first.
+    pub fn records(&self) -> Vec<NemesisRecord> {
+        self.records.iter().map(|(r, _)| r.clone()).collect()
+    }
+
+    /// Take all the active records, e.g. to heal them at the end of a run.
+    pub fn take_all(&mut self) -> Vec<NemesisRecord> {
+        self.records.drain(..).map(|(r, _)| r).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[madsim::test]
+    async fn test_nemesis_register() {
+        let kill = |x| NemesisRecord::Kill(HashSet::from([x]));
+        let mut register = NemesisRegister::new(NemesisRegisterStrategy::FIFO(2));
+        assert!(register.put(kill(0)).is_empty());
+        assert!(register.put(kill(1)).is_empty());
+        assert_eq!(register.put(kill(2)), vec![kill(0)]);
+        assert_eq!(register.len(), 2);
+
+        let mut register = NemesisRegister::new(NemesisRegisterStrategy::RandomQueue(1));
+        assert!(register.put(kill(0)).is_empty());
+        let out = register.put(kill(1));
+        assert_eq!(out.len(), 1);
+        assert!(out[0] == kill(0) || out[0] == kill(1));
+        assert_eq!(register.len(), 1);
+    }
+
+    #[madsim::test]
+    async fn test_time_based_register() {
+        let kill = |x| NemesisRecord::Kill(HashSet::from([x]));
+        let mut register =
+            NemesisRegister::new(NemesisRegisterStrategy::TimeBased(Duration::from_secs(2)));
+        assert!(register.put(kill(0)).is_empty());
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        assert!(register.put(kill(1)).is_empty());
+        assert!(register.take_expired().is_empty());
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(register.take_expired(), vec![kill(0)]);
+        madsim::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(register.take_expired(), vec![kill(1)]);
+        assert!(register.is_empty());
+    }
+}
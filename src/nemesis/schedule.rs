@@ -0,0 +1,113 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use madsim::time::Duration;
+
+use super::NemesisType;
+use crate::generator::controller::DelayStrategy;
+
+/// The id allocator of scheduled fault windows.
+static NEXT_WINDOW_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A fault window schedule, like `nemesis/cycle` in jepsen: execute the fault,
+/// keep it for `duration`, recover it, then keep quiet for `quiet_period`
+/// before the next window.
+///
+/// Unlike the nemeses recovered by the
+/// [`NemesisRegister`](super::register::NemesisRegister), the recovery time of
+/// a scheduled fault is explicit, so the fault windows are clear in the
+/// history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NemesisSchedule {
+    pub fault: NemesisType,
+    pub duration: Duration,
+    pub quiet_period: Duration,
+}
+
+/// An item of a [`NemesisSchedule`], which starts or heals a fault window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduledNemesis {
+    /// Execute the fault of window `id`.
+    Start { id: u64, fault: NemesisType },
+    /// Recover the fault of window `id`.
+    Heal { id: u64 },
+}
+
+/// Allocate the id of a fault window.
+pub(crate) fn next_window_id() -> u64 {
+    NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl NemesisSchedule {
+    pub fn new(fault: NemesisType, duration: Duration, quiet_period: Duration) -> Self {
+        Self {
+            fault,
+            duration,
+            quiet_period,
+        }
+    }
+
+    /// The items of `cycles` fault windows, and the delay before each item.
+    pub fn cycle(&self, cycles: usize) -> Vec<(ScheduledNemesis, DelayStrategy)> {
+        let mut out = Vec::with_capacity(cycles * 2);
+        for i in 0..cycles {
+            let id = next_window_id();
+            let quiet = if i == 0 {
+                DelayStrategy::None
+            } else {
+                DelayStrategy::Fixed(self.quiet_period)
+            };
+            out.push((
+                ScheduledNemesis::Start {
+                    id,
+                    fault: self.fault.clone(),
+                },
+                quiet,
+            ));
+            out.push((
+                ScheduledNemesis::Heal { id },
+                DelayStrategy::Fixed(self.duration),
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_nemesis_schedule_cycle() {
+        let schedule = NemesisSchedule::new(
+            NemesisType::Kill(HashSet::from([0])),
+            Duration::from_secs(5),
+            Duration::from_secs(10),
+        );
+        let items = schedule.cycle(2);
+        assert_eq!(items.len(), 4);
+        let ids: Vec<_> = items
+            .iter()
+            .map(|(item, _)| match item {
+                ScheduledNemesis::Start { id, fault } => {
+                    assert_eq!(fault, &schedule.fault);
+                    *id
+                }
+                ScheduledNemesis::Heal { id } => *id,
+            })
+            .collect();
+        assert_eq!(ids[0], ids[1]);
+        assert_eq!(ids[2], ids[3]);
+        assert_ne!(ids[0], ids[2]);
+        let delays: Vec<_> = items
+            .iter()
+            .map(|(_, delay)| match delay {
+                DelayStrategy::None => 0,
+                DelayStrategy::Fixed(t) => t.as_secs(),
+                DelayStrategy::Random(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(delays, vec![0, 5, 10, 5]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Nemeses on the servers of remote hosts, by running the commands of
+//! [`os`](super::os) over `ssh`. Enabled by the `ssh` feature.
+//!
+//! Every command runs as `ssh -o BatchMode=yes <options> <destination>
+//! <program> <args>`, so the key of the destination must be authorized ahead,
+//! and its user must be allowed to signal the server and modify iptables,
+//! e.g. root.
+
+use std::{net::IpAddr, process::Command};
+
+use super::{
+    os::{output, OsClusterClient},
+    ServerId,
+};
+
+/// A server on a remote host.
+#[derive(Debug, Clone)]
+pub struct SshHost {
+    /// The destination of ssh, e.g. `root@n1`.
+    destination: String,
+    address: IpAddr,
+    /// The shell command printing the pid of the server on the host.
+    pid: String,
+    /// The shell command restarting the server on the host.
+    start: String,
+}
+
+impl SshHost {
+    /// The server listening on `address` on the host of `destination`, whose
+    /// pid is printed by `pidof <name>`, and which is restarted by
+    /// `systemctl restart <name>`.
+    pub fn new(destination: impl Into<String>, address: IpAddr, name: &str) -> Self {
+        Self {
+            destination: destination.into(),
+            address,
+            pid: format!("pidof {}", name),
+            start: format!("systemctl restart {}", name),
+        }
+    }
+
+    /// Set the shell command printing the pid of the server, e.g.
+    /// `cat /var/run/etcd.pid`.
+    pub fn with_pid_command(mut self, command: impl Into<String>) -> Self {
+        self.pid = command.into();
+        self
+    }
+
+    /// Set the shell command restarting the killed server.
+    pub fn with_start_command(mut self, command: impl Into<String>) -> Self {
+        self.start = command.into();
+        self
+    }
+}
+
+/// The servers of remote hosts, indexed by [`ServerId`], see the
+/// [module](self) doc.
+#[derive(Debug, Clone)]
+pub struct SshCluster {
+    hosts: Vec<SshHost>,
+    /// The extra arguments of ssh.
+    options: Vec<String>,
+}
+
+impl SshCluster {
+    pub fn new(hosts: impl IntoIterator<Item = SshHost>) -> Self {
+        Self {
+            hosts: hosts.into_iter().collect(),
+            options: vec![],
+        }
+    }
+
+    /// Set the extra arguments of ssh, e.g. `["-i", "~/.ssh/jepsen"]`.
+    pub fn with_options(mut self, options: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.options = options.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn host(&self, server: ServerId) -> &SshHost {
+        self.hosts
+            .get(server as usize)
+            .unwrap_or_else(|| panic!("server {} is not in the ssh cluster", server))
+    }
+
+    /// The ssh command running the shell command on the host of the server.
+    fn ssh(&self, server: ServerId, command: &str) -> Command {
+        let mut ssh = Command::new("ssh");
+        ssh.args(["-o", "BatchMode=yes"])
+            .args(&self.options)
+            .arg(&self.host(server).destination)
+            .arg(command);
+        ssh
+    }
+}
+
+impl OsClusterClient for SshCluster {
+    fn pid(&self, server: ServerId) -> Result<u32, String> {
+        let out = output(self.ssh(server, &self.host(server).pid))?;
+        // `pidof` prints every pid of the name, the first one is taken
+        out.split_whitespace()
+            .next()
+            .ok_or_else(|| format!("server {} is not running", server))?
+            .parse()
+            .map_err(|e| format!("invalid pid of server {}: {}", server, e))
+    }
+
+    fn restart_command(&self, server: ServerId) -> Command {
+        self.ssh(server, &self.host(server).start)
+    }
+
+    fn address(&self, server: ServerId) -> IpAddr {
+        self.host(server).address
+    }
+
+    fn command(&self, server: ServerId, program: &str) -> Command {
+        self.ssh(server, program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_ssh_commands() {
+        let address = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let cluster = SshCluster::new([
+            SshHost::new("root@n1", address, "etcd"),
+            SshHost::new("root@n2", address, "etcd").with_start_command("/opt/etcd/start"),
+        ])
+        .with_options(["-p", "2222"]);
+        let args = |command: Command| -> Vec<String> {
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|x| x.to_string_lossy().into_owned())
+                .collect()
+        };
+        let mut kill = cluster.command(0, "kill");
+        kill.arg("-STOP").arg("42");
+        assert_eq!(
+            args(kill),
+            [
+                "ssh",
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "root@n1",
+                "kill",
+                "-STOP",
+                "42"
+            ]
+        );
+        assert_eq!(
+            args(cluster.restart_command(1)),
+            [
+                "ssh",
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "root@n2",
+                "/opt/etcd/start"
+            ]
+        );
+        assert_eq!(cluster.address(1), address);
+    }
+}
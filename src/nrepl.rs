@@ -0,0 +1,119 @@
+//! An nREPL server in the JVM running jepsen and elle, enabled by the `nrepl`
+//! feature, to connect from an editor during a long run, inspect the history
+//! built so far and try the checker options without stopping the harness:
+//!
+//! ```ignore
+//! let server = NreplServer::start(7888)?;
+//! let client: &'static JepsenClient<_> = ...;
+//! madsim::task::spawn(async move {
+//!     loop {
+//!         madsim::time::sleep(Duration::from_secs(10)).await;
+//!         // `user/history` in the REPL
+//!         let _ = client.publish_history(&server);
+//!     }
+//! });
+//! ```
+//!
+//! The server is bound to the loopback, and stopped when dropped.
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::{
+    convert::edn::to_edn,
+    executor::{JvmHandle, RemoteInstance},
+    history::SerializableHistoryList,
+    utils::{clj_eval, clj_from_edn, clj_to_string, historify, FfiError},
+    IFn,
+};
+
+/// The nREPL server, see the [module](self) doc.
+pub struct NreplServer {
+    port: u16,
+    server: Option<RemoteInstance>,
+}
+
+impl NreplServer {
+    /// Start the server on `127.0.0.1` at the port, or a free port if it's 0.
+    pub fn start(port: u16) -> Result<Self> {
+        let server = JvmHandle::global().try_remote(move |_| {
+            clj_eval(&format!(
+                "(require 'nrepl.server) (nrepl.server/start-server :bind \"127.0.0.1\" :port {})",
+                port
+            ))
+        })?;
+        let port = server.with(|jvm, server| -> Result<u16> {
+            let port = IFn::new(clj_eval("(fn [server] (:port server))")?)
+                .invoke1(jvm.clone_instance(server)?)?;
+            Ok(clj_to_string(port)?.parse()?)
+        })?;
+        info!("nREPL server started on 127.0.0.1:{}", port);
+        Ok(Self {
+            port,
+            server: Some(server),
+        })
+    }
+
+    /// The port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Bind the value, converted through EDN, to the var `user/<name>`.
+    pub fn def<T: Serialize + ?Sized>(&self, name: &str, value: &T) -> Result<()> {
+        self.def_edn(name, to_edn(value)?, false)
+    }
+
+    /// Bind the history, as a jepsen history, to the var `user/history`.
+    pub fn def_history<F: Serialize, ERR: Serialize>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+    ) -> Result<()> {
+        self.def_edn("history", to_edn(history)?, true)
+    }
+
+    fn def_edn(&self, name: &str, edn: String, historified: bool) -> Result<()> {
+        let intern = format!("(fn [v] (intern 'user '{} v))", name);
+        JvmHandle::global().call_blocking(move |_| -> Result<(), FfiError> {
+            let mut value = clj_from_edn(&edn)?;
+            if historified {
+                value = historify(value)?;
+            }
+            IFn::new(clj_eval(&intern)?).invoke1(value)?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
+
+impl Drop for NreplServer {
+    fn drop(&mut self) {
+        let Some(server) = self.server.take() else {
+            return;
+        };
+        let res = server.with(|jvm, server| -> Result<(), FfiError> {
+            IFn::new(clj_eval("nrepl.server/stop-server")?).invoke1(jvm.clone_instance(server)?)?;
+            Ok(())
+        });
+        if let Err(err) = res {
+            warn!("failed to stop the nREPL server: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clj_eval_to;
+
+    #[test]
+    fn test_nrepl_server() -> Result<()> {
+        let server = NreplServer::start(0)?;
+        assert_ne!(server.port(), 0);
+        server.def("answer", &[42])?;
+        let answer: Vec<i64> = JvmHandle::global().call_blocking(|_| clj_eval_to("user/answer"))?;
+        assert_eq!(answer, [42]);
+        Ok(())
+    }
+}
@@ -1,17 +1,24 @@
 //! This module defines operation that can be executed on a database, and
 //! provides the serialization / deserialization method of [`Op`] and [`Ops`].
 
+pub mod edn;
+
 use std::{
+    collections::BTreeMap,
     fmt,
     ops::{Deref, DerefMut},
 };
 
-use anyhow::{anyhow, Result};
 use serde::{
     de::{SeqAccess, Visitor},
     Deserialize, Serialize,
 };
-use serde_json::{json, Value};
+use serde_json::Value;
+
+use crate::{
+    convert::{edn::to_edn, elle},
+    nemesis::{schedule::ScheduledNemesis, NemesisType, SerializableNemesisType},
+};
 
 /// An operation that can be executed on a database. Generatored by jepsen
 /// Generator.
@@ -19,31 +26,595 @@ use serde_json::{json, Value};
 pub enum Op {
     Read(u64, Option<u64>),
     Write(u64, u64),
+    /// Set the key to the new value if its value is the expected one, or
+    /// fail.
+    Cas(u64, u64, u64),
+    Delete(u64),
+    /// Add the delta to the counter of the key, a negative delta decrements
+    /// it.
+    Incr(u64, i64),
+    /// Append the value to the list of the key, as in elle list-append.
+    Append(u64, u64),
+    /// Read the list of the key, with its elements filled when completed. A
+    /// list read not completed is `[:r key nil]` like a register read, so it's
+    /// parsed as an [`Op::Read`].
+    ReadList(u64, Option<Vec<u64>>),
+    /// Read the keys at once, with the values filled when completed.
+    BatchRead(Vec<(u64, Option<u64>)>),
+    /// Read the keys in `[start, end)`, with the existing keys and their
+    /// values filled when completed.
+    ScanRange(u64, u64, Option<Vec<(u64, u64)>>),
+    /// The micro-ops of a txn, on the heap: a small vec of ops in an op would
+    /// have an infinite size, and the allocations are under 5% of the history
+    /// path, see the `txn_alloc` benchmark.
     Txn(Vec<Op>),
 }
 
+/// A key in a logical table, so a single run can exercise several tables.
+///
+/// The ops keep `u64` keys, as elle requires, with the table packed in the
+/// high [`Key::TABLE_BITS`] bits, see [`Key::pack`]. The keys of table 0 are
+/// the plain keys, so the ops of a single table are unchanged. A
+/// [`TypedClusterClient`](crate::typed::TypedClusterClient) with `Key` keys
+/// gets the tables back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Key {
+    pub table: u16,
+    pub id: u64,
+}
+
+impl Key {
+    pub const TABLE_BITS: u32 = 16;
+    /// The max id of a key, exclusive.
+    pub const MAX_ID: u64 = 1 << (u64::BITS - Self::TABLE_BITS);
+
+    pub fn new(table: u16, id: u64) -> Self {
+        Self { table, id }
+    }
+
+    /// Pack into the `u64` key of ops, fails if the id is not below
+    /// [`Key::MAX_ID`].
+    pub fn pack(&self) -> Result<u64, String> {
+        match self.id < Self::MAX_ID {
+            true => Ok(((self.table as u64) << (u64::BITS - Self::TABLE_BITS)) | self.id),
+            false => Err(format!("invalid {:?}: id out of range", self)),
+        }
+    }
+
+    pub fn unpack(key: u64) -> Self {
+        Self {
+            table: (key >> (u64::BITS - Self::TABLE_BITS)) as u16,
+            id: key & (Self::MAX_ID - 1),
+        }
+    }
+}
+
+/// A structural error of an [`Op`], see [`Op::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidOp {
+    /// A txn in a txn.
+    NestedTxn,
+    /// A txn without ops.
+    EmptyTxn,
+    /// A batch read or a range scan in a txn.
+    MultiKeyReadInTxn,
+    /// A range scan of more than [`Op::MAX_EXPANDED_SCAN`] keys to expand,
+    /// see [`Op::expand_reads`].
+    WideScan(u64, u64),
+}
+
+impl fmt::Display for InvalidOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidOp::NestedTxn => f.write_str("txns cannot be nested"),
+            InvalidOp::EmptyTxn => f.write_str("txns cannot be empty"),
+            InvalidOp::MultiKeyReadInTxn => f.write_str("multi-key reads cannot be in txns"),
+            InvalidOp::WideScan(start, end) => write!(
+                f,
+                "the scan of [{}, {}) is too wide to expand, at most {} keys",
+                start,
+                end,
+                Op::MAX_EXPANDED_SCAN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidOp {}
+
+/// The ops of a valid txn: not empty, and only single-key ops, so no nested
+/// txns. Converted into an [`Op::Txn`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Txn(Vec<Op>);
+
+impl Txn {
+    pub fn try_new(ops: Vec<Op>) -> Result<Self, InvalidOp> {
+        if ops.is_empty() {
+            return Err(InvalidOp::EmptyTxn);
+        }
+        for op in &ops {
+            match op {
+                Op::Txn(_) => return Err(InvalidOp::NestedTxn),
+                Op::BatchRead(_) | Op::ScanRange(..) => return Err(InvalidOp::MultiKeyReadInTxn),
+                _ => {}
+            }
+        }
+        Ok(Self(ops))
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.0
+    }
+
+    pub fn into_ops(self) -> Vec<Op> {
+        self.0
+    }
+}
+
+impl TryFrom<Vec<Op>> for Txn {
+    type Error = InvalidOp;
+    fn try_from(ops: Vec<Op>) -> Result<Self, InvalidOp> {
+        Self::try_new(ops)
+    }
+}
+
+impl From<Txn> for Op {
+    fn from(txn: Txn) -> Self {
+        Op::Txn(txn.0)
+    }
+}
+
+impl Op {
+    /// The max number of keys in the range of a scan expanded by
+    /// [`Op::expand_reads`].
+    pub const MAX_EXPANDED_SCAN: u64 = 1 << 16;
+
+    /// A txn of the ops, validated by [`Txn::try_new`].
+    pub fn txn(ops: Vec<Op>) -> Result<Op, InvalidOp> {
+        Txn::try_new(ops).map(Op::from)
+    }
+
+    /// Check the structural invariants of the op, which every op sent to a
+    /// cluster client holds, see [`Txn::try_new`].
+    pub fn validate(&self) -> Result<(), InvalidOp> {
+        match self {
+            Op::Txn(ops) if ops.is_empty() => Err(InvalidOp::EmptyTxn),
+            Op::Txn(ops) => ops.iter().try_for_each(|op| match op {
+                Op::Txn(_) => Err(InvalidOp::NestedTxn),
+                Op::BatchRead(_) | Op::ScanRange(..) => Err(InvalidOp::MultiKeyReadInTxn),
+                _ => Ok(()),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Convert the multi-key reads to txns of single reads, which elle can
+    /// check: a scan reads every key in its range, and the keys not found are
+    /// read as `None`, so phantoms show up as anomalies of the reads. Other
+    /// ops are unchanged.
+    ///
+    /// Fails on a scan of more than [`Op::MAX_EXPANDED_SCAN`] keys, which
+    /// would be expanded to as many reads.
+    pub fn expand_reads(self) -> Result<Op, InvalidOp> {
+        let op = match self {
+            Op::BatchRead(reads) => {
+                Op::Txn(reads.into_iter().map(|(k, v)| Op::Read(k, v)).collect())
+            }
+            Op::ScanRange(start, end, _) if end.saturating_sub(start) > Self::MAX_EXPANDED_SCAN => {
+                return Err(InvalidOp::WideScan(start, end))
+            }
+            Op::ScanRange(start, end, None) => {
+                Op::Txn((start..end).map(|k| Op::Read(k, None)).collect())
+            }
+            Op::ScanRange(start, end, Some(results)) => {
+                let found: std::collections::HashMap<_, _> = results.into_iter().collect();
+                Op::Txn(
+                    (start..end)
+                        .map(|k| Op::Read(k, found.get(&k).copied()))
+                        .collect(),
+                )
+            }
+            op => op,
+        };
+        Ok(op)
+    }
+
+    /// The keys the op accesses, sorted and deduplicated. A range scan
+    /// accesses the keys it found, so none before it completes.
+    pub fn keys(&self) -> Vec<u64> {
+        let mut keys = match self {
+            Op::Read(k, _)
+            | Op::Write(k, _)
+            | Op::Cas(k, ..)
+            | Op::Delete(k)
+            | Op::Incr(k, _)
+            | Op::Append(k, _)
+            | Op::ReadList(k, _) => vec![*k],
+            Op::BatchRead(reads) => reads.iter().map(|(k, _)| *k).collect(),
+            Op::ScanRange(.., results) => results.iter().flatten().map(|(k, _)| *k).collect(),
+            Op::Txn(ops) => ops.iter().flat_map(Op::keys).collect(),
+        };
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /// The keys the op reads, sorted and deduplicated, including the keys of
+    /// the ops in a txn. A cas reads its key, and a range scan reads the keys
+    /// it found.
+    pub fn read_set(&self) -> Vec<u64> {
+        let mut keys = match self {
+            Op::Read(k, _) | Op::Cas(k, ..) | Op::ReadList(k, _) => vec![*k],
+            Op::Write(..) | Op::Delete(_) | Op::Incr(..) | Op::Append(..) => vec![],
+            Op::BatchRead(_) | Op::ScanRange(..) => self.keys(),
+            Op::Txn(ops) => ops.iter().flat_map(Op::read_set).collect(),
+        };
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /// The keys the op writes, sorted and deduplicated, including the keys of
+    /// the ops in a txn. A cas writes its key even if it may fail.
+    pub fn write_set(&self) -> Vec<u64> {
+        let mut keys = match self {
+            Op::Write(k, _)
+            | Op::Cas(k, ..)
+            | Op::Delete(k)
+            | Op::Incr(k, _)
+            | Op::Append(k, _) => {
+                vec![*k]
+            }
+            Op::Read(..) | Op::ReadList(..) | Op::BatchRead(_) | Op::ScanRange(..) => vec![],
+            Op::Txn(ops) => ops.iter().flat_map(Op::write_set).collect(),
+        };
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /// Whether applying the op again leaves the same state, so it's safe to
+    /// retry after an indeterminate error, see
+    /// [`RetryPolicy::with_idempotent`]. Reads, writes and deletes are
+    /// idempotent, but a cas, an incr or an append may apply twice, and so may
+    /// a txn of them.
+    ///
+    /// A retried write may still overwrite the writes of others in between,
+    /// which is harmless when every written value is unique, as in elle
+    /// rw-register.
+    ///
+    /// [`RetryPolicy::with_idempotent`]: crate::retry::RetryPolicy::with_idempotent
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            Op::Read(..)
+            | Op::ReadList(..)
+            | Op::BatchRead(_)
+            | Op::ScanRange(..)
+            | Op::Write(..)
+            | Op::Delete(_) => true,
+            Op::Cas(..) | Op::Incr(..) | Op::Append(..) => false,
+            Op::Txn(ops) => ops.iter().all(Op::is_idempotent),
+        }
+    }
+
+    /// The tables of the keys the op accesses, sorted and deduplicated, see
+    /// [`Key`].
+    pub fn tables(&self) -> Vec<u16> {
+        let mut tables: Vec<_> = self
+            .keys()
+            .into_iter()
+            .map(|k| Key::unpack(k).table)
+            .collect();
+        tables.dedup();
+        tables
+    }
+
+    /// Move the keys of the op to the table, e.g. in [`Generator::map`]
+    /// to spread the ops of a workload over several tables. The ids are
+    /// truncated to [`Key::MAX_ID`].
+    ///
+    /// [`Generator::map`]: crate::generator::Generator::map
+    pub fn with_table(&self, table: u16) -> Op {
+        let mut key = |k: u64| {
+            Key::new(table, Key::unpack(k).id)
+                .pack()
+                .expect("the id is in range")
+        };
+        self.map(&mut key, &mut |v| v)
+    }
+
+    /// Map the keys, including the bounds of a range scan, and the values of
+    /// the op, including the elements of a list. The delta of an incr is not a value, and is kept. The exclusive
+    /// end of a scan is mapped as its last key plus one, so a scan to the end
+    /// of a table stays in the table, and an empty scan stays empty.
+    pub fn map(&self, key: &mut impl FnMut(u64) -> u64, value: &mut impl FnMut(u64) -> u64) -> Op {
+        match self {
+            Op::Read(k, v) => Op::Read(key(*k), v.map(&mut *value)),
+            Op::Write(k, v) => Op::Write(key(*k), value(*v)),
+            Op::Cas(k, expect, new) => Op::Cas(key(*k), value(*expect), value(*new)),
+            Op::Delete(k) => Op::Delete(key(*k)),
+            Op::Incr(k, delta) => Op::Incr(key(*k), *delta),
+            Op::Append(k, v) => Op::Append(key(*k), value(*v)),
+            Op::ReadList(k, list) => Op::ReadList(
+                key(*k),
+                list.as_ref()
+                    .map(|list| list.iter().map(|v| value(*v)).collect()),
+            ),
+            Op::BatchRead(reads) => Op::BatchRead(
+                reads
+                    .iter()
+                    .map(|(k, v)| (key(*k), v.map(&mut *value)))
+                    .collect(),
+            ),
+            Op::ScanRange(start, end, results) => Op::ScanRange(
+                key(*start),
+                match end.checked_sub(1) {
+                    Some(last) if last >= *start => key(last).saturating_add(1),
+                    _ => key(*start),
+                },
+                results
+                    .as_ref()
+                    .map(|results| results.iter().map(|(k, v)| (key(*k), value(*v))).collect()),
+            ),
+            Op::Txn(ops) => Op::Txn(ops.iter().map(|op| op.map(key, value)).collect()),
+        }
+    }
+}
+
+// Short constructors of the ops, for tests and custom generators, e.g.
+// `txn![r(1), w(1, 2)]`.
+
+/// A read of the key.
+pub fn r(key: u64) -> Op {
+    Op::Read(key, None)
+}
+
+/// A write of the value to the key.
+pub fn w(key: u64, value: u64) -> Op {
+    Op::Write(key, value)
+}
+
+/// A cas of the key from `expect` to `new`.
+pub fn cas(key: u64, expect: u64, new: u64) -> Op {
+    Op::Cas(key, expect, new)
+}
+
+/// A delete of the key.
+pub fn del(key: u64) -> Op {
+    Op::Delete(key)
+}
+
+/// An increment of the counter of the key by the delta.
+pub fn incr(key: u64, delta: i64) -> Op {
+    Op::Incr(key, delta)
+}
+
+/// An append of the value to the list of the key.
+pub fn append(key: u64, value: u64) -> Op {
+    Op::Append(key, value)
+}
+
+/// A read of the list of the key.
+pub fn read_list(key: u64) -> Op {
+    Op::ReadList(key, None)
+}
+
+/// Build an [`Op::Txn`] of the ops, validated by [`Txn::try_new`].
+///
+/// # Panics
+///
+/// Panics if the txn is invalid, e.g. empty or nested.
+///
+/// ```
+/// use jepsen_rs::{op::{r, w, Op}, txn};
+/// assert_eq!(txn![r(1), w(1, 2)], Op::Txn(vec![Op::Read(1, None), Op::Write(1, 2)]));
+/// ```
+#[macro_export]
+macro_rules! txn {
+    ($($op:expr),+ $(,)?) => {
+        $crate::op::Op::txn(vec![$($op),+]).unwrap_or_else(|err| panic!("invalid txn: {}", err))
+    };
+}
+
+/// Build [`Ops`] of the ops, e.g. the expected ops of a generator.
+///
+/// ```
+/// use jepsen_rs::{ops, op::{r, w, Op, Ops}, txn};
+/// let ops = ops![r(1), txn![w(1, 2)]];
+/// assert_eq!(ops, Ops(vec![Op::Read(1, None), Op::Txn(vec![Op::Write(1, 2)])]));
+/// ```
+#[macro_export]
+macro_rules! ops {
+    ($($op:expr),* $(,)?) => {
+        $crate::op::Ops(vec![$($op),*])
+    };
+}
+
 /// Op type of functions that being applied to db, for serialization and
 /// deserialization.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum OpFunctionType {
     #[serde(rename = "r")]
     Read,
     #[serde(rename = "w")]
     Write,
+    Cas,
+    Delete,
+    Incr,
+    Append,
+    BatchRead,
+    ScanRange,
     Txn,
 }
 
 impl From<&Op> for OpFunctionType {
     fn from(op: &Op) -> Self {
         match op {
-            Op::Read(_, _) => OpFunctionType::Read,
+            Op::Read(_, _) | Op::ReadList(..) => OpFunctionType::Read,
             Op::Write(_, _) => OpFunctionType::Write,
+            Op::Cas(..) => OpFunctionType::Cas,
+            Op::Delete(_) => OpFunctionType::Delete,
+            Op::Incr(..) => OpFunctionType::Incr,
+            Op::Append(..) => OpFunctionType::Append,
+            Op::BatchRead(_) => OpFunctionType::BatchRead,
+            Op::ScanRange(..) => OpFunctionType::ScanRange,
             Op::Txn(_) => OpFunctionType::Txn,
         }
     }
 }
 
+/// Small metadata attached to an op at generation time, e.g. `hot-key` or
+/// `conflict-group` of the intent of the generator. They are recorded in the
+/// invoke and the completion of the op, so the results can be sliced by them,
+/// but not sent to the cluster or elle.
+pub type OpTags = BTreeMap<String, String>;
+
+impl Op {
+    /// Attach a tag to the op, see [`OpTags`].
+    pub fn tag(self, key: impl Into<String>, value: impl Into<String>) -> OpOrNemesis {
+        OpOrNemesis::Op(self).tag(key, value)
+    }
+}
+
+/// The item generated by generators, which is either an [`Op`] to be sent to
+/// the cluster, or a [`NemesisType`] to be executed on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpOrNemesis {
+    Op(Op),
+    /// An op with tags.
+    Tagged(Op, OpTags),
+    Nemesis(NemesisType),
+    /// A start or heal of a scheduled fault window.
+    Scheduled(ScheduledNemesis),
+}
+
+impl OpOrNemesis {
+    /// Attach a tag to the op, replacing the tag of the same key. Nemeses are
+    /// not tagged.
+    pub fn tag(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        match self {
+            OpOrNemesis::Op(op) => {
+                OpOrNemesis::Tagged(op, OpTags::from([(key.into(), value.into())]))
+            }
+            OpOrNemesis::Tagged(op, mut tags) => {
+                tags.insert(key.into(), value.into());
+                OpOrNemesis::Tagged(op, tags)
+            }
+            nemesis => nemesis,
+        }
+    }
+}
+
+/// The EDN of an [`OpOrNemesis`], in the shape of a history item without the
+/// process and the time, e.g. `{:f :txn, :value [[:w 1 2]]}` or `{:f :kill,
+/// :value "Kill({1})", :window 3}`.
+#[derive(Serialize)]
+struct PlanItem<'a> {
+    f: OpOrNemesisFuncType,
+    value: Option<PlanValue<'a>>,
+    /// The fault window of a scheduled nemesis.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a OpTags>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum PlanValue<'a> {
+    Op(&'a Op),
+    Nemesis(String),
+}
+
+impl OpOrNemesis {
+    /// Convert to EDN, see [`PlanItem`].
+    pub fn to_edn(&self) -> anyhow::Result<String> {
+        let nemesis = |nemesis: &NemesisType, window| PlanItem {
+            f: OpOrNemesisFuncType::Nemesis(nemesis.into()),
+            value: Some(PlanValue::Nemesis(format!("{:?}", nemesis))),
+            window,
+            tags: None,
+        };
+        let item = match self {
+            OpOrNemesis::Op(op) => PlanItem {
+                f: op.into(),
+                value: Some(PlanValue::Op(op)),
+                window: None,
+                tags: None,
+            },
+            OpOrNemesis::Tagged(op, tags) => PlanItem {
+                f: op.into(),
+                value: Some(PlanValue::Op(op)),
+                window: None,
+                tags: Some(tags),
+            },
+            OpOrNemesis::Nemesis(n) => nemesis(n, None),
+            OpOrNemesis::Scheduled(ScheduledNemesis::Start { id, fault }) => {
+                nemesis(fault, Some(*id))
+            }
+            OpOrNemesis::Scheduled(ScheduledNemesis::Heal { id }) => PlanItem {
+                f: OpOrNemesisFuncType::Nemesis(SerializableNemesisType::Heal),
+                value: None,
+                window: Some(*id),
+                tags: None,
+            },
+        };
+        to_edn(&item)
+    }
+}
+
+/// The EDN of the item, see [`OpOrNemesis::to_edn`].
+impl fmt::Display for OpOrNemesis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_edn().map_err(|_| fmt::Error)?)
+    }
+}
+
+/// The items as EDN, one per line, e.g. the planned sequence of a generator
+/// to be reviewed or diffed across seeds.
+pub fn plan_to_edn<'a>(items: impl IntoIterator<Item = &'a OpOrNemesis>) -> anyhow::Result<String> {
+    let mut out = String::new();
+    for item in items {
+        out.push_str(&item.to_edn()?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+impl From<Op> for OpOrNemesis {
+    fn from(op: Op) -> Self {
+        Self::Op(op)
+    }
+}
+
+impl From<NemesisType> for OpOrNemesis {
+    fn from(nemesis: NemesisType) -> Self {
+        Self::Nemesis(nemesis)
+    }
+}
+
+impl From<ScheduledNemesis> for OpOrNemesis {
+    fn from(scheduled: ScheduledNemesis) -> Self {
+        Self::Scheduled(scheduled)
+    }
+}
+
+/// The function type of a history item, which is either an op function or a
+/// nemesis function.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum OpOrNemesisFuncType {
+    Op(OpFunctionType),
+    Nemesis(SerializableNemesisType),
+}
+
+impl From<&Op> for OpOrNemesisFuncType {
+    fn from(op: &Op) -> Self {
+        Self::Op(op.into())
+    }
+}
+
 /// A list of [`Op`]s
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Ops(pub Vec<Op>);
@@ -66,54 +637,42 @@ impl Ops {
     pub fn rev(self) -> Self {
         Self(self.0.into_iter().rev().collect())
     }
+
+    /// Convert to EDN, e.g. `[[[:w 1 2]] [:r 1 nil]]`.
+    pub fn to_edn(&self) -> anyhow::Result<String> {
+        to_edn(&self.0)
+    }
 }
 
-// Serialize and Deserialize
-
-/// Parse an [`Op`] from JSON
-fn parse_op(json: &Value) -> Result<Op> {
-    match json {
-        Value::Array(arr) => {
-            // If the first value is a string, it must not be a Txn, whose first element is
-            // Vec
-            if let Some(op_type) = arr[0].as_str() {
-                // Handle Read or Write
-                let key = arr[1].as_u64().ok_or(anyhow!("Invalid key"))?;
-                let value = arr[2].as_u64();
-                match op_type {
-                    "r" => Ok(Op::Read(key, value)),
-                    "w" => Ok(Op::Write(key, value.ok_or(anyhow!("Invalid value"))?)),
-                    _ => Err(anyhow!("Unknown op type")),
-                }
-            } else {
-                // Handle Txn
-                let ops = arr.iter().map(parse_op).collect::<Result<Vec<_>, _>>()?;
-                Ok(Op::Txn(ops))
-            }
-        }
-        _ => Err(anyhow!("Invalid JSON format")),
+impl fmt::Display for Ops {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_edn().map_err(|_| fmt::Error)?)
     }
 }
 
-/// Convert an [`Op`] to JSON
-fn op_to_json(op: &Op) -> Value {
-    match op {
-        Op::Read(key, value) => json!(["r", key, value]),
-        Op::Write(key, value) => json!(["w", key, value]),
-        Op::Txn(ops) => {
-            let json_ops: Vec<Value> = ops.iter().map(op_to_json).collect();
-            Value::Array(json_ops)
-        }
+impl Op {
+    /// Convert to EDN, e.g. `[[:w 1 2] [:r 1 nil]]`, see
+    /// [`elle::op_to_edn`].
+    pub fn to_edn(&self) -> anyhow::Result<String> {
+        elle::op_to_edn(self)
     }
 }
 
+/// The EDN of the op, see [`Op::to_edn`].
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_edn().map_err(|_| fmt::Error)?)
+    }
+}
+
+// Serialize and Deserialize, see [`crate::convert::elle`] for the shapes.
+
 impl Serialize for Op {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let json_value = op_to_json(self);
-        json_value.serialize(serializer)
+        elle::serialize_op(self, serializer)
     }
 }
 
@@ -132,7 +691,7 @@ impl<'de> Visitor<'de> for OpVisitor {
         while let Some(value) = seq.next_element()? {
             extract_arr.push(value);
         }
-        parse_op(&serde_json::Value::Array(extract_arr)).map_err(serde::de::Error::custom)
+        elle::op_from_json(&Value::Array(extract_arr)).map_err(serde::de::Error::custom)
     }
 }
 
@@ -147,16 +706,18 @@ impl<'de> Deserialize<'de> for Op {
 
 #[cfg(test)]
 mod test {
-    use j4rs::Instance;
-
     use super::*;
-    use crate::utils::{FromSerde, ToDe};
 
     #[test]
     fn test_op_serde() {
         let res = [
             (r#"["w",6,1]"#, Op::Write(6, 1)),
             (r#"["r",8,null]"#, Op::Read(8, None)),
+            (r#"["cas",8,[1,2]]"#, Op::Cas(8, 1, 2)),
+            (r#"["delete",8,null]"#, Op::Delete(8)),
+            (r#"["incr",8,-2]"#, Op::Incr(8, -2)),
+            (r#"["append",8,3]"#, Op::Append(8, 3)),
+            (r#"["r",8,[1,3]]"#, Op::ReadList(8, Some(vec![1, 3]))),
             (
                 r#"[["w",6,1],["r",8,null]]"#,
                 Op::Txn(vec![Op::Write(6, 1), Op::Read(8, None)]),
@@ -168,6 +729,149 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_validate() {
+        let txn = Op::txn(vec![Op::Write(1, 1), Op::Read(1, None)]).unwrap();
+        assert_eq!(txn.validate(), Ok(()));
+        assert_eq!(Op::Read(1, None).validate(), Ok(()));
+        let cases = [
+            (vec![], InvalidOp::EmptyTxn),
+            (vec![Op::Write(1, 1), txn], InvalidOp::NestedTxn),
+            (
+                vec![Op::BatchRead(vec![(1, None)])],
+                InvalidOp::MultiKeyReadInTxn,
+            ),
+        ];
+        for (ops, err) in cases {
+            assert_eq!(Op::Txn(ops.clone()).validate(), Err(err));
+            assert_eq!(Txn::try_new(ops), Err(err));
+        }
+    }
+
+    #[test]
+    fn test_read_write_sets() {
+        let op = txn![r(3), w(1, 2), cas(2, 1, 3), incr(4, 1), r(1)];
+        assert_eq!(op.read_set(), [1, 2, 3]);
+        assert_eq!(op.write_set(), [1, 2, 4]);
+        assert!(!op.is_idempotent());
+        assert!(txn![r(1), w(1, 2), del(2)].is_idempotent());
+        let scan = Op::ScanRange(0, 5, Some(vec![(2, 1)]));
+        assert_eq!(scan.read_set(), [2]);
+        assert!(scan.write_set().is_empty());
+    }
+
+    #[test]
+    fn test_tables() {
+        let key = Key::new(3, 42).pack().unwrap();
+        assert_eq!(Key::unpack(key), Key::new(3, 42));
+        assert_eq!(Key::new(0, 42).pack(), Ok(42));
+        assert!(Key::new(1, Key::MAX_ID).pack().is_err());
+
+        let op = txn![r(1), w(2, 5)].with_table(3);
+        assert_eq!(op.tables(), [3]);
+        assert_eq!(
+            op,
+            txn![
+                r(Key::new(3, 1).pack().unwrap()),
+                w(Key::new(3, 2).pack().unwrap(), 5)
+            ]
+        );
+        assert_eq!(op.with_table(0), txn![r(1), w(2, 5)]);
+
+        // a scan to the end of table 0, which ends at the first key of table 1
+        let end = Key::new(1, 0).pack().unwrap();
+        let scan = Op::ScanRange(5, end, Some(vec![(Key::MAX_ID - 1, 1)]));
+        let key = |table, id| Key::new(table, id).pack().unwrap();
+        assert_eq!(
+            scan.with_table(3),
+            Op::ScanRange(
+                key(3, 5),
+                key(4, 0),
+                Some(vec![(key(3, Key::MAX_ID - 1), 1)])
+            )
+        );
+        assert_eq!(scan.with_table(3).with_table(0), scan);
+        let empty = Op::ScanRange(5, 5, None).with_table(3);
+        assert_eq!(empty, Op::ScanRange(key(3, 5), key(3, 5), None));
+    }
+
+    #[test]
+    fn test_expand_wide_scan() {
+        let scan = Op::ScanRange(0, u64::MAX, Some(vec![(2, 1)]));
+        assert_eq!(scan.expand_reads(), Err(InvalidOp::WideScan(0, u64::MAX)));
+        let scan = Op::ScanRange(7, 7 + Op::MAX_EXPANDED_SCAN, None);
+        assert!(matches!(
+            scan.expand_reads(),
+            Ok(Op::Txn(reads)) if reads.len() == Op::MAX_EXPANDED_SCAN as usize
+        ));
+    }
+
+    #[test]
+    fn test_tags() {
+        let tagged = r(1)
+            .tag("hot-key", "true")
+            .tag("group", "1")
+            .tag("group", "3");
+        assert_eq!(
+            tagged,
+            OpOrNemesis::Tagged(
+                r(1),
+                OpTags::from([
+                    ("group".to_string(), "3".to_string()),
+                    ("hot-key".to_string(), "true".to_string())
+                ])
+            )
+        );
+        let nemesis = OpOrNemesis::Nemesis(NemesisType::Kill([1].into()));
+        assert_eq!(nemesis.clone().tag("group", "1"), nemesis);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(txn![w(1, 2), r(1)].to_string(), "[[:w 1 2] [:r 1 nil]]");
+        assert_eq!(
+            r(1).tag("hot-key", "true").to_string(),
+            r#"{:f :r, :value [:r 1 nil], :tags {:hot-key "true"}}"#
+        );
+        let plan = [
+            OpOrNemesis::Op(w(1, 2)),
+            OpOrNemesis::Scheduled(ScheduledNemesis::Start {
+                id: 3,
+                fault: NemesisType::Kill([1].into()),
+            }),
+            OpOrNemesis::Scheduled(ScheduledNemesis::Heal { id: 3 }),
+        ];
+        assert_eq!(
+            plan_to_edn(&plan).unwrap(),
+            "{:f :w, :value [:w 1 2]}\n\
+             {:f :kill, :value \"Kill({1})\", :window 3}\n\
+             {:f :heal, :value nil, :window 3}\n"
+        );
+    }
+
+    #[test]
+    fn test_builders() {
+        let ops = crate::ops![r(1), txn![w(1, 2), cas(1, 2, 3), del(1), incr(2, -1)]];
+        assert_eq!(
+            ops,
+            Ops(vec![
+                Op::Read(1, None),
+                Op::Txn(vec![
+                    Op::Write(1, 2),
+                    Op::Cas(1, 2, 3),
+                    Op::Delete(1),
+                    Op::Incr(2, -1)
+                ])
+            ])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "txns cannot be nested")]
+    fn test_nested_txn_macro() {
+        let _ = txn![r(1), txn![w(1, 2)]];
+    }
+
     #[test]
     fn test_ops_serde() {
         let json_str = r#"
@@ -186,8 +890,13 @@ mod test {
         assert_eq!(serde_json::from_str::<Ops>(json_str).unwrap(), ops);
     }
 
+    #[cfg(feature = "clojure")]
     #[test]
     fn test_convertion_between_ops_and_instance() {
+        use j4rs::Instance;
+
+        use crate::utils::{FromSerde, ToDe};
+
         let ops = Ops(vec![
             Op::Txn(vec![Op::Write(6, 1), Op::Write(8, 1)]),
             Op::Txn(vec![Op::Write(9, 1), Op::Read(8, None)]),
@@ -0,0 +1,75 @@
+//! Conversions between [`Op`] / [`Txn`] and the micro-op vectors of elle, e.g.
+//! `[[:r 1 nil] [:w 1 2]]`, for the clojure interop written by users and the
+//! histories imported from other tools.
+//!
+//! The shapes are documented in [`convert::elle`](crate::convert::elle),
+//! which this module gathers the conversions of.
+
+use anyhow::{bail, Result};
+
+pub use crate::convert::elle::{
+    mops_from_edn, mops_to_edn, op_from_edn as from_edn, op_from_json as from_json,
+    op_to_edn as to_edn, op_to_json as to_json, MicroOp, ReadValue,
+};
+use crate::op::{Op, Txn};
+
+/// Convert the txn to EDN, e.g. `[[:r 1 nil] [:w 1 2]]`.
+pub fn txn_to_edn(txn: &Txn) -> Result<String> {
+    to_edn(&Op::Txn(txn.ops().to_vec()))
+}
+
+/// Parse a txn from EDN, which should be a valid [`Txn`].
+pub fn txn_from_edn(s: &str) -> Result<Txn> {
+    match from_edn(s)? {
+        Op::Txn(ops) => Ok(Txn::try_new(ops)?),
+        op => bail!("expected a txn, got {:?}", op),
+    }
+}
+
+/// The micro-ops of the op, one for a single-key op and the ops of a txn.
+/// Multi-key reads are not micro-ops, see [`Op::expand_reads`].
+pub fn to_mops(op: &Op) -> Result<Vec<MicroOp>> {
+    match op {
+        Op::Txn(ops) => ops.iter().map(MicroOp::try_from).collect(),
+        op => Ok(vec![MicroOp::try_from(op)?]),
+    }
+}
+
+/// The txn of the micro-ops, fails if it's empty.
+pub fn from_mops(mops: impl IntoIterator<Item = MicroOp>) -> Result<Op> {
+    let ops = mops.into_iter().map(Op::from).collect();
+    Ok(Txn::try_new(ops)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        op::{r, w},
+        txn,
+    };
+
+    #[test]
+    fn test_txn_edn() -> Result<()> {
+        let txn = Txn::try_new(vec![r(1), w(1, 2)])?;
+        let edn = txn_to_edn(&txn)?;
+        assert_eq!(edn, "[[:r 1 nil] [:w 1 2]]");
+        assert_eq!(txn_from_edn(&edn)?, txn);
+        assert!(txn_from_edn("[:r 1 nil]").is_err());
+        assert!(txn_from_edn("[]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mops() -> Result<()> {
+        let op = txn![r(1), w(1, 2)];
+        let mops = to_mops(&op)?;
+        assert_eq!(mops, [MicroOp::Read(1, None), MicroOp::Write(1, 2)]);
+        assert_eq!(from_mops(mops)?, op);
+        assert_eq!(to_mops(&w(3, 4))?, [MicroOp::Write(3, 4)]);
+        assert_eq!(from_mops([MicroOp::Append(1, 2)])?, txn![Op::Append(1, 2)]);
+        assert!(from_mops([]).is_err());
+        assert!(to_mops(&Op::BatchRead(vec![(1, None)])).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+//! This module analyzes the performance of histories, e.g. compares the latency
+//! distributions of two runs under the same seeded schedule.
+
+use std::collections::BTreeMap;
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+
+use crate::{
+    history::{HistoryType, SerializableHistoryList},
+    nemesis::ServerId,
+    op::{OpFunctionType, OpOrNemesisFuncType},
+};
+
+/// The minimal number of samples in each run to test the significance.
+const MIN_SAMPLES: usize = 10;
+/// The z-score bound of a two-tailed test with `p < 0.05`.
+const SIGNIFICANT_Z: f64 = 1.96;
+
+/// Latency statistics of one kind of op in a run, in nanoseconds.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl From<&Histogram<u64>> for LatencyStats {
+    fn from(h: &Histogram<u64>) -> Self {
+        Self {
+            count: h.len(),
+            mean: h.mean(),
+            p50: h.value_at_quantile(0.5),
+            p90: h.value_at_quantile(0.9),
+            p99: h.value_at_quantile(0.99),
+            max: h.max(),
+        }
+    }
+}
+
+/// Whether the latency distributions of two runs differ significantly.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Significance {
+    Significant,
+    NotSignificant,
+    /// There are too few samples to tell.
+    InsufficientData,
+}
+
+/// The latency difference of one kind of op between run a and run b.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LatencyDelta {
+    pub a: LatencyStats,
+    pub b: LatencyStats,
+    /// Relative change of the median, `(b - a) / a`.
+    pub p50_change: f64,
+    /// Relative change of the 99th percentile, `(b - a) / a`.
+    pub p99_change: f64,
+    /// The z-score of the Mann-Whitney U test, positive if run b is slower.
+    pub z: Option<f64>,
+    pub significance: Significance,
+}
+
+/// The latency comparison of two runs, by op function type. Only the op types
+/// that appear in both runs are compared.
+#[derive(Debug, Clone, Serialize, PartialEq, Default)]
+pub struct PerfComparison(pub BTreeMap<OpFunctionType, LatencyDelta>);
+
+/// Collect the latencies of the `:ok` ops in the history by op function type,
+/// in nanoseconds, paired by [`SerializableHistoryList::pairs`].
+pub fn latencies<ERR>(
+    history: &SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+) -> BTreeMap<OpFunctionType, Vec<u64>> {
+    let mut out: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for pair in history.pairs() {
+        let (Some(completion), Some(latency)) = (pair.completion, pair.latency) else {
+            continue;
+        };
+        if let (OpOrNemesisFuncType::Op(f), HistoryType::Ok) = (&completion.f, &completion.type_) {
+            out.entry(*f).or_default().push(latency);
+        }
+    }
+    out
+}
+
+/// The latency statistics of the `:ok` ops in the history by op function
+/// type, see [`latencies`].
+pub fn latency_stats<ERR>(
+    history: &SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+) -> BTreeMap<OpFunctionType, LatencyStats> {
+    latencies(history)
+        .into_iter()
+        .map(|(f, samples)| (f, (&histogram(&samples)).into()))
+        .collect()
+}
+
+/// The outcomes of the ops sent to one node.
+#[derive(Debug, Clone, Serialize, PartialEq, Default)]
+pub struct NodeStats {
+    pub ok: u64,
+    pub fail: u64,
+    pub info: u64,
+    /// The total number of retries.
+    pub retries: u64,
+    /// The latencies of the `:ok` ops, `None` if there is none.
+    pub latency: Option<LatencyStats>,
+}
+
+/// Break down the completed ops in the history by the node they were sent to,
+/// by their [`OpMeta`](crate::history::OpMeta). Completions without metadata
+/// are skipped.
+pub fn node_stats<ERR>(
+    history: &SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+) -> BTreeMap<ServerId, NodeStats> {
+    let mut out: BTreeMap<_, NodeStats> = BTreeMap::new();
+    let mut samples: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for item in history.0.iter() {
+        let Some(meta) = item.meta else {
+            continue;
+        };
+        let stats = out.entry(meta.node).or_default();
+        stats.retries += meta.retries as u64;
+        match item.type_ {
+            HistoryType::Ok => {
+                stats.ok += 1;
+                samples.entry(meta.node).or_default().push(meta.latency);
+            }
+            HistoryType::Fail => stats.fail += 1,
+            HistoryType::Info => stats.info += 1,
+            HistoryType::Invoke => {}
+        }
+    }
+    for (node, samples) in samples {
+        out.get_mut(&node).unwrap().latency = Some((&histogram(&samples)).into());
+    }
+    out
+}
+
+fn histogram(samples: &[u64]) -> Histogram<u64> {
+    let mut h = Histogram::new(3).expect("3 significant figures should be valid");
+    for &x in samples {
+        h.record(x)
+            .expect("auto-resized histogram should accept any value");
+    }
+    h
+}
+
+fn relative_change(a: u64, b: u64) -> f64 {
+    (b as f64 - a as f64) / a.max(1) as f64
+}
+
+/// The z-score of the Mann-Whitney U test of `b` against `a`, positive if
+/// values in `b` tend to be larger.
+fn mann_whitney_z(a: &[u64], b: &[u64]) -> f64 {
+    let mut all: Vec<_> = a
+        .iter()
+        .map(|&x| (x, false))
+        .chain(b.iter().map(|&x| (x, true)))
+        .collect();
+    all.sort_unstable();
+    // sum of ranks of b, ties get the average rank
+    let mut rank_sum = 0.0;
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j < all.len() && all[j].0 == all[i].0 {
+            j += 1;
+        }
+        let rank = (i + j + 1) as f64 / 2.0;
+        rank_sum += rank * all[i..j].iter().filter(|x| x.1).count() as f64;
+        i = j;
+    }
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let u = rank_sum - nb * (nb + 1.0) / 2.0;
+    let mean = na * nb / 2.0;
+    let sd = (na * nb * (na + nb + 1.0) / 12.0).sqrt();
+    (u - mean) / sd
+}
+
+/// Compare the latency distributions of two runs by op function type.
+pub fn compare<ERR>(
+    run_a: &SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+    run_b: &SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+) -> PerfComparison {
+    let mut b = latencies(run_b);
+    let deltas = latencies(run_a)
+        .into_iter()
+        .filter_map(|(f, a)| {
+            let b = b.remove(&f)?;
+            let (ha, hb) = (histogram(&a), histogram(&b));
+            let (sa, sb) = (LatencyStats::from(&ha), LatencyStats::from(&hb));
+            let z =
+                (a.len() >= MIN_SAMPLES && b.len() >= MIN_SAMPLES).then(|| mann_whitney_z(&a, &b));
+            let significance = match z {
+                None => Significance::InsufficientData,
+                Some(z) if z.abs() > SIGNIFICANT_Z => Significance::Significant,
+                Some(_) => Significance::NotSignificant,
+            };
+            let delta = LatencyDelta {
+                p50_change: relative_change(sa.p50, sb.p50),
+                p99_change: relative_change(sa.p99, sb.p99),
+                a: sa,
+                b: sb,
+                z,
+                significance,
+            };
+            Some((f, delta))
+        })
+        .collect();
+    PerfComparison(deltas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history::{HistoryProcess, OpMeta, SerializableHistory},
+        op::Op,
+    };
+
+    /// A history of `n` sequential write txns whose latencies are given by
+    /// `latency(i)`.
+    fn history(n: u64, latency: impl Fn(u64) -> u64) -> SerializableHistoryList {
+        let mut h = SerializableHistoryList::default();
+        let mut time = 0;
+        for i in 0..n {
+            for (type_, t) in [(HistoryType::Invoke, 0), (HistoryType::Ok, latency(i))] {
+                time += t;
+                let index = h.len() as u64;
+                h.push(SerializableHistory {
+                    index,
+                    type_,
+                    f: OpOrNemesisFuncType::Op(OpFunctionType::Txn),
+                    value: Op::Txn(vec![Op::Write(1, i)]).into(),
+                    time,
+                    process: HistoryProcess::Gen(0),
+                    error: None,
+                    meta: None,
+                    tags: Default::default(),
+                });
+            }
+        }
+        h
+    }
+
+    #[test]
+    fn test_latencies() {
+        let h = history(3, |i| (i + 1) * 10);
+        assert_eq!(
+            latencies(&h),
+            BTreeMap::from([(OpFunctionType::Txn, vec![10, 20, 30])])
+        );
+        let stats = &latency_stats(&h)[&OpFunctionType::Txn];
+        assert_eq!((stats.count, stats.p50, stats.max), (3, 20, 30));
+    }
+
+    #[test]
+    fn test_node_stats() {
+        let mut h = history(4, |_| 10);
+        for (i, item) in h
+            .iter_mut()
+            .filter(|x| x.type_ == HistoryType::Ok)
+            .enumerate()
+        {
+            item.meta = Some(OpMeta {
+                node: i as u64 % 2,
+                retries: i,
+                latency: 10 * (i as u64 + 1),
+            });
+        }
+        h[7].type_ = HistoryType::Info;
+        let stats = node_stats(&h);
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[&0].ok, stats[&0].retries), (2, 2));
+        assert_eq!(stats[&0].latency.as_ref().unwrap().max, 30);
+        assert_eq!((stats[&1].ok, stats[&1].info), (1, 1));
+        assert_eq!(stats[&1].latency.as_ref().unwrap().count, 1);
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = history(100, |i| 1000 + i);
+        let b = history(100, |i| 2000 + i);
+        let res = compare(&a, &b);
+        let delta = &res.0[&OpFunctionType::Txn];
+        assert_eq!(delta.significance, Significance::Significant);
+        assert!(delta.z.unwrap() > 0.0);
+        assert!(delta.p50_change > 0.9 && delta.p50_change < 1.1);
+
+        let res = compare(&a, &history(100, |i| 1000 + (i * 7) % 100));
+        assert_eq!(
+            res.0[&OpFunctionType::Txn].significance,
+            Significance::NotSignificant
+        );
+
+        let res = compare(&a, &history(3, |_| 1000));
+        assert_eq!(
+            res.0[&OpFunctionType::Txn].significance,
+            Significance::InsufficientData
+        );
+    }
+}
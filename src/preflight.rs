@@ -0,0 +1,303 @@
+//! The preflight checks of the environment of a test, so a broken one fails
+//! the test before it starts with a clear error, rather than by a panic of
+//! j4rs in the middle of the run:
+//!
+//! ```ignore
+//! let report = TestBuilder::new(cluster, workload).out_dir("store").build().preflight();
+//! print!("{}", report);
+//! ```
+//!
+//! The checks are:
+//! - `java`: java [`MIN_JAVA`] or later, at `$JAVA_HOME` or on the `PATH`;
+//! - `classpath`: the `jassets` of j4rs, and the jars of clojure, jepsen and
+//!   elle in it or on the classpath of the JVM config;
+//! - `madsim`: whether the test is simulated, only a warning if it's not;
+//! - `out-dir`: the output directory is writable;
+//! - `cluster`: a read of the cluster, after setting it up, replies in
+//!   [`PING_TIMEOUT`].
+//!
+//! The checks of java and the jars are only run for the workloads
+//! [requiring the JVM](crate::workload::Workload::requires_jvm).
+//! [`Test::run`](crate::runner::Test::run) runs the checks, unless it's
+//! disabled by [`TestBuilder::preflight`](crate::runner::TestBuilder::preflight),
+//! and fails with [`Error::Preflight`](crate::Error::Preflight) if any fails.
+
+use std::{fmt, path::Path, process::Command};
+
+use madsim::time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::client::ElleRwClusterClient;
+
+/// The minimum major version of java, see
+/// <https://github.com/jepsen-io/jepsen/issues/585>.
+pub const MIN_JAVA: u32 = 21;
+
+/// The timeout of the read of the `cluster` check.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PreflightStatus {
+    Pass,
+    /// Not a failure, but likely not what's intended.
+    Warn,
+    Fail,
+}
+
+/// A check of the environment.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PreflightCheck {
+    pub name: &'static str,
+    pub status: PreflightStatus,
+    pub detail: String,
+}
+
+impl PreflightCheck {
+    fn new(name: &'static str, status: PreflightStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for PreflightCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            PreflightStatus::Pass => "pass",
+            PreflightStatus::Warn => "warn",
+            PreflightStatus::Fail => "FAIL",
+        };
+        write!(f, "[{}] {}: {}", status, self.name, self.detail)
+    }
+}
+
+/// The checks of the environment of a test, in order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// Whether no check fails.
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// The failed checks.
+    pub fn failures(&self) -> impl Iterator<Item = &PreflightCheck> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == PreflightStatus::Fail)
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.checks
+            .iter()
+            .try_for_each(|check| writeln!(f, "{}", check))
+    }
+}
+
+/// Check the environment out of the cluster: the runtime, the output
+/// directory if any, and java and the jars if the test requires the JVM.
+pub fn environment(out_dir: Option<&Path>, jvm: bool) -> PreflightReport {
+    let mut checks = vec![];
+    if jvm {
+        checks.push(java());
+        checks.push(classpath());
+    }
+    checks.push(madsim());
+    checks.extend(out_dir.map(writable));
+    PreflightReport { checks }
+}
+
+/// The major version in the output of `java -version`, e.g. 21 of
+/// `openjdk version "21.0.2" 2024-01-16`, and 8 of `java version "1.8.0_402"`.
+fn java_major(output: &str) -> Option<u32> {
+    let version = output.split('"').nth(1)?;
+    let mut parts = version.split(['.', '_', '-', '+']);
+    match parts.next()? {
+        "1" => parts.next()?.parse().ok(),
+        major => major.parse().ok(),
+    }
+}
+
+fn java() -> PreflightCheck {
+    let fail = |detail| PreflightCheck::new("java", PreflightStatus::Fail, detail);
+    let java = match std::env::var_os("JAVA_HOME") {
+        Some(home) => Path::new(&home).join("bin").join("java"),
+        None => "java".into(),
+    };
+    let output = match Command::new(&java).arg("-version").output() {
+        Ok(output) => output,
+        Err(err) => return fail(format!("failed to run {}: {}", java.display(), err)),
+    };
+    // `java -version` prints to stderr
+    let output = String::from_utf8_lossy(&output.stderr);
+    match java_major(&output) {
+        Some(major) if major >= MIN_JAVA => PreflightCheck::new(
+            "java",
+            PreflightStatus::Pass,
+            format!("java {} at {}", major, java.display()),
+        ),
+        Some(major) => fail(format!(
+            "java {} at {}, {} or later is required",
+            major,
+            java.display(),
+            MIN_JAVA
+        )),
+        None => fail(format!(
+            "unknown version of {}: {}",
+            java.display(),
+            output.lines().next().unwrap_or_default()
+        )),
+    }
+}
+
+#[cfg(feature = "clojure")]
+fn classpath() -> PreflightCheck {
+    use std::collections::HashSet;
+
+    let fail = |detail| PreflightCheck::new("classpath", PreflightStatus::Fail, detail);
+    let config = crate::clojure::jvm_config();
+    let Some(jassets) = config.jassets().filter(|dir| dir.is_dir()) else {
+        return fail("no `jassets` of j4rs next to the executable or in the jar dir".to_string());
+    };
+    let in_jassets = std::fs::read_dir(&jassets)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.file_name()));
+    let on_classpath = config
+        .classpath_jars()
+        .iter()
+        .filter_map(|jar| jar.file_name().map(ToOwned::to_owned));
+    let found: HashSet<_> = in_jassets.chain(on_classpath).collect();
+    let artifacts = crate::bootstrap::artifacts();
+    let missing: Vec<_> = artifacts
+        .iter()
+        .map(|artifact| artifact.file_name())
+        .filter(|jar| !found.contains(std::ffi::OsStr::new(jar)))
+        .collect();
+    match missing.first() {
+        None => PreflightCheck::new(
+            "classpath",
+            PreflightStatus::Pass,
+            format!("{} jars, j4rs at {}", artifacts.len(), jassets.display()),
+        ),
+        Some(jar) => fail(format!(
+            "{} of {} jars are missing, e.g. {}, deploy them by the build script or the \
+             `bootstrap` feature",
+            missing.len(),
+            artifacts.len(),
+            jar
+        )),
+    }
+}
+
+#[cfg(not(feature = "clojure"))]
+fn classpath() -> PreflightCheck {
+    PreflightCheck::new(
+        "classpath",
+        PreflightStatus::Fail,
+        "the JVM requires the `clojure` feature",
+    )
+}
+
+fn madsim() -> PreflightCheck {
+    if cfg!(madsim) {
+        PreflightCheck::new(
+            "madsim",
+            PreflightStatus::Pass,
+            "simulated by madsim, deterministic by the seed",
+        )
+    } else {
+        PreflightCheck::new(
+            "madsim",
+            PreflightStatus::Warn,
+            "built without `--cfg madsim`, running on tokio against a real cluster, not \
+             deterministic by the seed",
+        )
+    }
+}
+
+fn writable(dir: &Path) -> PreflightCheck {
+    let probe = dir.join(".preflight");
+    let res = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b""))
+        .and_then(|_| std::fs::remove_file(&probe));
+    match res {
+        Ok(()) => PreflightCheck::new(
+            "out-dir",
+            PreflightStatus::Pass,
+            format!("{} is writable", dir.display()),
+        ),
+        Err(err) => PreflightCheck::new(
+            "out-dir",
+            PreflightStatus::Fail,
+            format!("{} is not writable: {}", dir.display(), err),
+        ),
+    }
+}
+
+/// Check the cluster, which is set up, by a read of the key 0 in the
+/// timeout.
+pub async fn ping(cluster: &impl ElleRwClusterClient, timeout: Duration) -> PreflightCheck {
+    let start = Instant::now();
+    let (status, detail) = match madsim::time::timeout(timeout, cluster.get(0)).await {
+        Ok(Ok(_)) => (
+            PreflightStatus::Pass,
+            format!("read the key 0 in {:?}", start.elapsed()),
+        ),
+        Ok(Err(err)) => (
+            PreflightStatus::Fail,
+            format!("failed to read the key 0: {}", err),
+        ),
+        Err(_) => (
+            PreflightStatus::Fail,
+            format!("no reply to a read of the key 0 in {:?}", timeout),
+        ),
+    };
+    PreflightCheck::new("cluster", status, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_java_major() {
+        let java = |line| java_major(line);
+        assert_eq!(java(r#"openjdk version "21.0.2" 2024-01-16"#), Some(21));
+        assert_eq!(java(r#"openjdk version "17.0.15" 2025-04-15"#), Some(17));
+        assert_eq!(java(r#"java version "1.8.0_402""#), Some(8));
+        assert_eq!(java(r#"openjdk version "22-ea" 2024-03-19"#), Some(22));
+        assert_eq!(java("command not found"), None);
+    }
+
+    #[test]
+    fn test_environment() {
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-preflight-{}", std::process::id()));
+        let report = environment(Some(&dir), false);
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(
+            report.checks.iter().map(|c| c.name).collect::<Vec<_>>(),
+            ["madsim", "out-dir"]
+        );
+        assert!(!dir.join(".preflight").exists());
+
+        // a file is not a writable directory
+        let file = dir.join("file");
+        std::fs::write(&file, b"").unwrap();
+        let report = environment(Some(&file.join("out")), false);
+        assert!(!report.is_ok());
+        assert_eq!(report.failures().next().unwrap().name, "out-dir");
+        assert!(report.to_string().contains("[FAIL] out-dir"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! Live progress of a run, so a long run isn't a black box until the final
+//! check. The counts are kept by the [`Global`] context as the history is
+//! pushed, and reported periodically by
+//! [`JepsenClient::with_progress`](crate::client::JepsenClient::with_progress):
+//!
+//! ```ignore
+//! let client = JepsenClient::new(cluster, gen)
+//!     .with_progress(Duration::from_secs(5), ProgressReporter::stderr());
+//! ```
+
+use std::{
+    fmt,
+    io::{IsTerminal, Write},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use madsim::time::Duration;
+use serde::Serialize;
+
+use crate::history::HistoryType;
+
+/// A snapshot of the progress of a run, see [`Global::progress`].
+///
+/// [`Global::progress`]: crate::generator::Global::progress
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Progress {
+    /// The time since the start of the run, simulated under madsim.
+    pub elapsed: Duration,
+    pub invoked: u64,
+    pub ok: u64,
+    pub fail: u64,
+    pub info: u64,
+    /// The currently active nemeses, in debug form.
+    pub active_nemeses: Vec<String>,
+}
+
+impl Progress {
+    /// The number of completed ops.
+    pub fn completed(&self) -> u64 {
+        self.ok + self.fail + self.info
+    }
+
+    /// The ratio of the `:fail` and `:info` ops in the completed ops.
+    pub fn error_rate(&self) -> f64 {
+        (self.fail + self.info) as f64 / self.completed().max(1) as f64
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>8.1}s] {} ops completed (ok {}, fail {}, info {}, {:.1}% errors), {} in flight",
+            self.elapsed.as_secs_f64(),
+            self.completed(),
+            self.ok,
+            self.fail,
+            self.info,
+            self.error_rate() * 100.0,
+            self.invoked.saturating_sub(self.completed()),
+        )?;
+        if !self.active_nemeses.is_empty() {
+            write!(f, ", nemeses: {}", self.active_nemeses.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The counts of the ops pushed to the history of a run.
+#[derive(Debug, Default)]
+pub(crate) struct ProgressCounter {
+    invoked: AtomicU64,
+    ok: AtomicU64,
+    fail: AtomicU64,
+    info: AtomicU64,
+}
+
+impl ProgressCounter {
+    /// Count an op pushed to the history.
+    pub(crate) fn count(&self, type_: &HistoryType) {
+        let counter = match type_ {
+            HistoryType::Invoke => &self.invoked,
+            HistoryType::Ok => &self.ok,
+            HistoryType::Fail => &self.fail,
+            HistoryType::Info => &self.info,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The progress with the counts.
+    pub(crate) fn progress(&self, elapsed: Duration, active_nemeses: Vec<String>) -> Progress {
+        Progress {
+            elapsed,
+            invoked: self.invoked.load(Ordering::Relaxed),
+            ok: self.ok.load(Ordering::Relaxed),
+            fail: self.fail.load(Ordering::Relaxed),
+            info: self.info.load(Ordering::Relaxed),
+            active_nemeses,
+        }
+    }
+}
+
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// Where the progress is reported.
+pub enum ProgressReporter {
+    /// Call the callback with every progress, and the final one.
+    Callback(ProgressCallback),
+    /// Print every progress to stderr, on one line kept rewritten if stderr
+    /// is a terminal.
+    Stderr,
+}
+
+impl ProgressReporter {
+    pub fn callback(f: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        Self::Callback(Box::new(f))
+    }
+
+    pub fn stderr() -> Self {
+        Self::Stderr
+    }
+
+    /// Report the progress, `last` if it's the final one of the run.
+    pub(crate) fn report(&self, progress: &Progress, last: bool) {
+        match self {
+            Self::Callback(f) => f(progress),
+            Self::Stderr => {
+                let mut stderr = std::io::stderr().lock();
+                let _ = if !stderr.is_terminal() {
+                    writeln!(stderr, "{}", progress)
+                } else if last {
+                    writeln!(stderr, "\r\x1b[2K{}", progress)
+                } else {
+                    write!(stderr, "\r\x1b[2K{}", progress)
+                };
+                let _ = stderr.flush();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let counter = ProgressCounter::default();
+        for type_ in [
+            HistoryType::Invoke,
+            HistoryType::Invoke,
+            HistoryType::Invoke,
+            HistoryType::Ok,
+            HistoryType::Info,
+        ] {
+            counter.count(&type_);
+        }
+        let progress = counter.progress(Duration::from_millis(1500), vec!["Kill({1})".into()]);
+        assert_eq!(progress.completed(), 2);
+        assert_eq!(progress.error_rate(), 0.5);
+        assert_eq!(
+            progress.to_string(),
+            "[     1.5s] 2 ops completed (ok 1, fail 0, info 1, 50.0% errors), 1 in flight, \
+             nemeses: Kill({1})"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Replay of a recorded history, to reproduce a failure found before, e.g. on
+//! a patched build.
+//!
+//! A [`Replay`] holds the ops of the invokes of a history and its fault
+//! windows, at their times since the start of the test. Run it on a fresh
+//! cluster by
+//! [`JepsenClient::new_replay`](crate::client::JepsenClient::new_replay), which
+//! issues them with the same relative timing:
+//!
+//! ```ignore
+//! let history = SerializableHistoryList::from_jsonl("out/history.jsonl")?;
+//! let replay = Replay::from_history(&history, cluster.size());
+//! let client = Box::leak(Box::new(JepsenClient::new(cluster, 0..)));
+//! let gen = client.new_replay(&replay);
+//! client.run(GeneratorGroup::new([gen])).await?;
+//! ```
+//!
+//! The ops are replayed in the order of their invokes, but the processes of
+//! the history are not kept. A fault is replayed on the servers it affected,
+//! e.g. a [`NemesisType::KillLeader`] kills the same server as a
+//! [`NemesisType::Kill`]; the faults that cannot be rebuilt from the history,
+//! and the nemeses not healed later such as bitflips, are skipped.
+
+use std::collections::HashSet;
+
+use log::warn;
+use madsim::time::Duration;
+
+use crate::{
+    generator::controller::DelayStrategy,
+    history::{HistoryType, HistoryValue, NemesisValue, SerializableHistoryList},
+    nemesis::{
+        schedule::{next_window_id, ScheduledNemesis},
+        NemesisType, SerializableNemesisType, ServerId,
+    },
+    op::{Op, OpOrNemesis, OpOrNemesisFuncType},
+};
+
+/// The ops and the nemeses to replay, see the [module](self) doc.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replay {
+    /// The items and their times since the start, in nanoseconds, sorted by
+    /// time.
+    items: Vec<(u64, OpOrNemesis)>,
+}
+
+/// Rebuild the fault of a fault item on a cluster of `size` servers.
+fn fault(f: SerializableNemesisType, value: &NemesisValue, size: usize) -> Option<NemesisType> {
+    let servers: HashSet<_> = value.servers.iter().copied().collect();
+    match f {
+        SerializableNemesisType::Kill if !servers.is_empty() => Some(NemesisType::Kill(servers)),
+        SerializableNemesisType::Pause if !servers.is_empty() => Some(NemesisType::Pause(servers)),
+        SerializableNemesisType::RemoveNode => {
+            value.servers.first().map(|s| NemesisType::RemoveNode(*s))
+        }
+        SerializableNemesisType::Partition => partition(&value.links, size),
+        _ => None,
+    }
+}
+
+/// The partition into groups clogging exactly the links, `None` if there is
+/// none, e.g. for the overlapping majorities of a ring.
+fn partition(links: &[(ServerId, ServerId)], size: usize) -> Option<NemesisType> {
+    let clogged: HashSet<_> = links.iter().copied().collect();
+    let mut groups: Vec<HashSet<ServerId>> = vec![];
+    for server in 0..size as ServerId {
+        let connected = |group: &HashSet<ServerId>| {
+            group
+                .iter()
+                .any(|s| !clogged.contains(&(server, *s)) && !clogged.contains(&(*s, server)))
+        };
+        match groups.iter_mut().find(|group| connected(group)) {
+            Some(group) => {
+                group.insert(server);
+            }
+            None => groups.push(HashSet::from([server])),
+        }
+    }
+    let group_of = |server: ServerId| groups.iter().position(|g| g.contains(&server));
+    let mut expected = HashSet::new();
+    for a in 0..size as ServerId {
+        for b in 0..size as ServerId {
+            if group_of(a) != group_of(b) {
+                expected.insert((a, b));
+            }
+        }
+    }
+    (groups.len() > 1 && expected == clogged).then_some(NemesisType::PartitionNamed { groups })
+}
+
+impl Replay {
+    /// Replay the ops at their times since the start, in nanoseconds.
+    pub fn from_invocations(ops: impl IntoIterator<Item = (u64, Op)>) -> Self {
+        let mut items: Vec<_> = ops
+            .into_iter()
+            .map(|(time, op)| (time, OpOrNemesis::Op(op)))
+            .collect();
+        items.sort_by_key(|(time, _)| *time);
+        Self { items }
+    }
+
+    /// Replay the invokes and the fault windows of the history, on a cluster
+    /// of `size` servers.
+    pub fn from_history<ERR>(
+        history: &SerializableHistoryList<OpOrNemesisFuncType, ERR>,
+        size: usize,
+    ) -> Self {
+        let mut replay = Self::from_invocations(history.0.iter().filter_map(|item| {
+            match (&item.type_, &item.value) {
+                (HistoryType::Invoke, HistoryValue::Op(op)) => Some((item.time, op.clone())),
+                _ => None,
+            }
+        }));
+        for (start, f, heal) in history.fault_window_items() {
+            let HistoryValue::Fault(value) = &start.value else {
+                warn!("fault {} has no servers to replay, skipped", start.index);
+                continue;
+            };
+            let Some(fault) = fault(f, value, size) else {
+                warn!("fault {} cannot be replayed: {:?}", start.index, value);
+                continue;
+            };
+            let id = next_window_id();
+            replay.items.push((
+                start.time,
+                OpOrNemesis::Scheduled(ScheduledNemesis::Start { id, fault }),
+            ));
+            if let Some(heal) = heal {
+                replay.items.push((
+                    heal.time,
+                    OpOrNemesis::Scheduled(ScheduledNemesis::Heal { id }),
+                ));
+            }
+        }
+        replay.items.sort_by_key(|(time, _)| *time);
+        replay
+    }
+
+    /// The items to replay and their times since the start, in nanoseconds.
+    pub fn items(&self) -> &[(u64, OpOrNemesis)] {
+        &self.items
+    }
+
+    /// The items and the delay before each of them, from the previous one.
+    pub(crate) fn with_delays(&self) -> Vec<(OpOrNemesis, DelayStrategy)> {
+        let mut last = 0;
+        self.items
+            .iter()
+            .map(|(time, item)| {
+                let delay = Duration::from_nanos(time.saturating_sub(last));
+                last = *time;
+                let delay = DelayStrategy::Fixed(delay);
+                (item.clone(), delay)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_from_history() -> anyhow::Result<()> {
+        let json = r#"[
+          { "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, 1]], "time": 10, "process": 0, "error": null },
+          { "index": 1, "type": "info", "f": "kill", "value": { "nemesis": "KillLeader { chase: 0 }", "servers": [2] }, "time": 15, "process": "nemesis", "error": null },
+          { "index": 2, "type": "ok", "f": "txn", "value": [["w", 1, 1]], "time": 20, "process": 0, "error": null },
+          { "index": 3, "type": "info", "f": "partition", "value": { "nemesis": "PartitionRandomNode", "links": [[0, 1], [0, 2], [1, 0], [2, 0]] }, "time": 25, "process": "nemesis", "error": null },
+          { "index": 4, "type": "info", "f": "resume", "value": { "nemesis": "Kill({2})", "servers": [2] }, "time": 30, "process": "nemesis", "error": null },
+          { "index": 5, "type": "invoke", "f": "txn", "value": [["r", 1, null]], "time": 40, "process": 1, "error": null },
+          { "index": 6, "type": "info", "f": "partition", "value": { "nemesis": "PartitionMajoritiesRing", "links": [[0, 1]] }, "time": 45, "process": "nemesis", "error": null }
+        ]"#;
+        let history: SerializableHistoryList = serde_json::from_str(json)?;
+        let replay = Replay::from_history(&history, 3);
+        let items: Vec<_> = replay.items().iter().map(|(t, item)| (*t, item)).collect();
+        let [(10, OpOrNemesis::Op(w)), (15, OpOrNemesis::Scheduled(ScheduledNemesis::Start { id, fault: kill })), (
+            25,
+            OpOrNemesis::Scheduled(ScheduledNemesis::Start {
+                fault: partition, ..
+            }),
+        ), (30, OpOrNemesis::Scheduled(ScheduledNemesis::Heal { id: healed })), (40, OpOrNemesis::Op(r))] =
+            items[..]
+        else {
+            panic!("unexpected replay {:?}", items);
+        };
+        assert_eq!(*w, Op::Txn(vec![Op::Write(1, 1)]));
+        assert_eq!(*r, Op::Txn(vec![Op::Read(1, None)]));
+        assert_eq!(*kill, NemesisType::Kill(HashSet::from([2])));
+        assert_eq!(id, healed);
+        assert_eq!(
+            *partition,
+            NemesisType::PartitionNamed {
+                groups: vec![HashSet::from([0]), HashSet::from([1, 2])]
+            }
+        );
+
+        let delays: Vec<_> = replay.with_delays().into_iter().map(|(_, d)| d).collect();
+        let ns = |n| DelayStrategy::Fixed(Duration::from_nanos(n));
+        assert_eq!(delays, [ns(10), ns(5), ns(10), ns(5), ns(10)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+//! Retries of the ops which fail transiently, see
+//! [`JepsenClient::with_retry_policy`](crate::client::JepsenClient::with_retry_policy).
+//!
+//! The outcome of an attempt is one of:
+//! - ok, which is recorded as `:ok`;
+//! - a definite error returned by the cluster client, which means the op did
+//!   not happen, so the op is always safe to retry. It's recorded as `:fail`.
+//! - an indeterminate error, i.e. the attempt timed out or its error is
+//!   classified as indeterminate, which means the op may or may not happen. The
+//!   op is retried only if it's idempotent, and it's recorded as `:info`
+//!   unless it's read-only, as a lost read has no effect.
+
+use std::{future::Future, time::Duration};
+
+use log::debug;
+
+use crate::{history::HistoryType, op::Op};
+
+/// Whether an op only reads, i.e. its [`Op::write_set`] is empty.
+pub fn is_read_only(op: &Op) -> bool {
+    op.write_set().is_empty()
+}
+
+type OpClassifier = Box<dyn Fn(&Op) -> bool + Send + Sync>;
+type ErrorClassifier = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// The policy of retrying an op.
+pub struct RetryPolicy {
+    max_attempts: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+    timeout: Option<Duration>,
+    idempotent: OpClassifier,
+    indeterminate: ErrorClassifier,
+}
+
+impl RetryPolicy {
+    /// Try an op at most `max_attempts` times, with an exponential backoff
+    /// from 10ms to 1s. Only the read-only ops are idempotent, and no error is
+    /// indeterminate by default.
+    pub fn new(max_attempts: usize) -> Self {
+        assert!(max_attempts > 0, "an op must be attempted at least once");
+        Self {
+            max_attempts,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            timeout: None,
+            idempotent: Box::new(is_read_only),
+            indeterminate: Box::new(|_| false),
+        }
+    }
+
+    /// Set the backoff before the first retry, which is doubled on every
+    /// retry up to `max`.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Give up an attempt after the timeout, which makes its outcome
+    /// indeterminate.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set which ops are idempotent, i.e. safe to retry after an
+    /// indeterminate error. For example, the writes of elle rw-register are
+    /// idempotent as every written value is unique, so [`Op::is_idempotent`]
+    /// fits it.
+    pub fn with_idempotent(mut self, f: impl Fn(&Op) -> bool + Send + Sync + 'static) -> Self {
+        self.idempotent = Box::new(f);
+        self
+    }
+
+    /// Set which errors of the cluster client are indeterminate, e.g. a
+    /// connection reset after the request is sent.
+    pub fn with_indeterminate(mut self, f: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.indeterminate = Box::new(f);
+        self
+    }
+
+    /// Execute the op by `attempt` under the policy. Returns the result op,
+    /// or the type to record the op as and the last error.
+    pub async fn execute<F, Fut>(
+        &self,
+        op: &Op,
+        mut attempt: F,
+    ) -> Result<Op, (HistoryType, String)>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Op, String>>,
+    {
+        let mut backoff = self.backoff;
+        let mut i = 1;
+        loop {
+            let res = match self.timeout {
+                Some(timeout) => match madsim::time::timeout(timeout, attempt()).await {
+                    Ok(res) => res.map_err(|err| self.classify(err)),
+                    Err(_) => Err((true, format!("timed out after {:?}", timeout))),
+                },
+                None => attempt().await.map_err(|err| self.classify(err)),
+            };
+            let (indeterminate, err) = match res {
+                Ok(op) => return Ok(op),
+                Err(err) => err,
+            };
+            let retryable = !indeterminate || (self.idempotent)(op);
+            if i >= self.max_attempts || !retryable {
+                let type_ = if indeterminate && !is_read_only(op) {
+                    HistoryType::Info
+                } else {
+                    HistoryType::Fail
+                };
+                return Err((type_, err));
+            }
+            debug!("attempt {} of op {:?} failed: {}, retry", i, op, err);
+            madsim::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+            i += 1;
+        }
+    }
+
+    fn classify(&self, err: String) -> (bool, String) {
+        ((self.indeterminate)(&err), err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// An attempt that fails with the errors in turn, `None` for hanging.
+    fn flaky<'a>(
+        errors: &'a [Option<&'a str>],
+        count: &'a AtomicUsize,
+        op: &'a Op,
+    ) -> impl FnMut() -> futures_util::future::BoxFuture<'a, Result<Op, String>> {
+        move || {
+            Box::pin(async move {
+                match errors.get(count.fetch_add(1, Ordering::SeqCst)) {
+                    Some(Some(err)) => Err(err.to_string()),
+                    Some(None) => futures_util::future::pending().await,
+                    None => Ok(op.clone()),
+                }
+            })
+        }
+    }
+
+    #[madsim::test]
+    async fn test_retry_policy() {
+        let policy = RetryPolicy::new(3).with_timeout(Duration::from_secs(1));
+        let read = Op::Read(1, None);
+        let write = Op::Write(1, 1);
+
+        // definite errors are retried
+        let count = AtomicUsize::new(0);
+        let errors = [Some("busy"), Some("busy")];
+        let res = policy.execute(&write, flaky(&errors, &count, &write)).await;
+        assert_eq!(res, Ok(write.clone()));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // the attempts are exhausted
+        let count = AtomicUsize::new(0);
+        let errors = [Some("busy"); 3];
+        let res = policy.execute(&write, flaky(&errors, &count, &write)).await;
+        assert_eq!(res, Err((HistoryType::Fail, "busy".to_string())));
+
+        // a timed-out write is not retried, and is indeterminate
+        let count = AtomicUsize::new(0);
+        let errors = [None];
+        let res = policy.execute(&write, flaky(&errors, &count, &write)).await;
+        assert_eq!(res.unwrap_err().0, HistoryType::Info);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // a timed-out read is retried
+        let count = AtomicUsize::new(0);
+        let res = policy.execute(&read, flaky(&errors, &count, &read)).await;
+        assert_eq!(res, Ok(read.clone()));
+
+        // a read that keeps timing out fails
+        let count = AtomicUsize::new(0);
+        let errors = [None; 3];
+        let res = policy.execute(&read, flaky(&errors, &count, &read)).await;
+        assert_eq!(res.unwrap_err().0, HistoryType::Fail);
+
+        // classified errors and idempotent writes
+        let policy = RetryPolicy::new(2)
+            .with_indeterminate(|err| err.contains("reset"))
+            .with_idempotent(|_| true);
+        let count = AtomicUsize::new(0);
+        let errors = [Some("connection reset"); 2];
+        let res = policy.execute(&write, flaky(&errors, &count, &write)).await;
+        assert_eq!(res.unwrap_err().0, HistoryType::Info);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,677 @@
+//! A whole test in one place: the cluster, the workload, the nemeses, the
+//! concurrency, the length, the seed and the output directory, assembled by a
+//! [`TestBuilder`] into a [`Test`] to run.
+//!
+//! ```ignore
+//! let report = TestBuilder::new(|| MockCluster::new(3), RwRegisterWorkload::default())
+//!     .concurrency(5)
+//!     .ops(100)
+//!     .duration(Duration::from_secs(10))
+//!     .nemesis(NemesisSchedule::new(fault, Duration::from_secs(2), Duration::from_secs(1)), 3)
+//!     .seed(1)
+//!     .out_dir("./store")
+//!     .build()
+//!     .run()?;
+//! assert!(report.is_valid());
+//! ```
+//!
+//! The cluster is created by a closure in the runtime of the test, as the
+//! nodes of a madsim cluster must be created there. Without `--cfg madsim`,
+//! the test runs on tokio against a real cluster, see [`crate::runtime`].
+//!
+//! The [`TestReport`] of a run saved in a store is also written to its
+//! `results.json` and `results.edn`, so CI can gate on one structured file.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use madsim::time::{Duration, Instant};
+use serde::Serialize;
+
+use crate::{
+    checker::{Check, SerializableCheckResult, ValidType},
+    checkpoint::Checkpoint,
+    client::{ClusterLifecycle, ElleRwClusterClient, JepsenClient},
+    generator::{
+        controller::DelayStrategy, nemesis_mix::NemesisMix, GeneratorBuilder, GeneratorGroup,
+        HarnessPanic,
+    },
+    nemesis::{active::FaultInterval, schedule::NemesisSchedule, NemesisClusterClient},
+    op::OpFunctionType,
+    perf::{self, LatencyStats},
+    preflight::{self, PreflightCheck, PreflightReport, PreflightStatus},
+    progress::ProgressReporter,
+    runtime::{self, TimeScale},
+    store::Store,
+    workload::Workload,
+    Error,
+};
+
+/// The bounds of the cluster client of a [`JepsenClient`].
+pub trait TestCluster:
+    ElleRwClusterClient + NemesisClusterClient + ClusterLifecycle + Send + Sync + 'static
+{
+}
+
+impl<EC: ElleRwClusterClient + NemesisClusterClient + ClusterLifecycle + Send + Sync + 'static>
+    TestCluster for EC
+{
+}
+
+type Configure<EC> = Box<dyn FnOnce(JepsenClient<EC>) -> JepsenClient<EC>>;
+
+/// The builder of a [`Test`], see the [module](self) doc.
+pub struct TestBuilder<EC: TestCluster, W> {
+    cluster: Box<dyn FnOnce() -> EC>,
+    workload: W,
+    name: String,
+    concurrency: usize,
+    ops: usize,
+    duration: Option<Duration>,
+    nemeses: Vec<(NemesisSchedule, usize)>,
+    mixes: Vec<(NemesisMix, usize)>,
+    seed: u64,
+    out_dir: Option<PathBuf>,
+    progress: Option<(Duration, ProgressReporter)>,
+    config_hash: Option<String>,
+    time_scale: TimeScale,
+    deadline: Option<Duration>,
+    checkpoint: Option<Duration>,
+    preflight: bool,
+    configure: Option<Configure<EC>>,
+}
+
+impl<EC: TestCluster, W: Workload> TestBuilder<EC, W> {
+    /// A test of the workload on the cluster created by `cluster`, with 3
+    /// processes of 100 ops each by default.
+    pub fn new(cluster: impl FnOnce() -> EC + 'static, workload: W) -> Self {
+        Self {
+            cluster: Box::new(cluster),
+            workload,
+            name: "test".to_string(),
+            concurrency: 3,
+            ops: 100,
+            duration: None,
+            nemeses: vec![],
+            mixes: vec![],
+            seed: 0,
+            out_dir: None,
+            progress: None,
+            config_hash: None,
+            time_scale: TimeScale::default(),
+            deadline: None,
+            checkpoint: None,
+            preflight: true,
+            configure: None,
+        }
+    }
+
+    /// The name of the test, which names its runs in the store.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The number of concurrent processes.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// The number of ops of every process.
+    pub fn ops(mut self, ops: usize) -> Self {
+        self.ops = ops;
+        self
+    }
+
+    /// Spread the ops of every process evenly over the duration, the ops are
+    /// sent back to back if unset.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Run `cycles` fault windows of the schedule alongside the ops. Several
+    /// schedules run concurrently.
+    pub fn nemesis(mut self, schedule: NemesisSchedule, cycles: usize) -> Self {
+        self.nemeses.push((schedule, cycles));
+        self
+    }
+
+    /// Run `n` nemeses sampled from the mix alongside the ops, with the
+    /// delays of the mix.
+    pub fn nemesis_mix(mut self, mix: NemesisMix, n: usize) -> Self {
+        self.mixes.push((mix, n));
+        self
+    }
+
+    /// The seed of the simulation, see [`Test::run`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Save the runs in a jepsen store at the directory, see [`Store`].
+    pub fn out_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(dir.into());
+        self
+    }
+
+    /// Report the progress of the run every `interval`, see
+    /// [`crate::progress`].
+    pub fn progress(mut self, interval: Duration, reporter: ProgressReporter) -> Self {
+        self.progress = Some((interval, reporter));
+        self
+    }
+
+    /// How fast the simulated time runs, as fast as possible by default, see
+    /// [`TimeScale`].
+    pub fn time_scale(mut self, scale: TimeScale) -> Self {
+        self.time_scale = scale;
+        self
+    }
+
+    /// Finish the test early once it has run for the duration of the wall
+    /// clock, and check the partial history, e.g. to bound a paced run in CI.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Take a checkpoint of the run every `interval` in its directory, to
+    /// resume it by [`Test::resume`] if it's interrupted. Requires
+    /// [`TestBuilder::out_dir`].
+    pub fn checkpoint(mut self, interval: Duration) -> Self {
+        self.checkpoint = Some(interval);
+        self
+    }
+
+    /// Check the environment and the cluster before running, and fail with
+    /// [`Error::Preflight`] if a check fails, see [`crate::preflight`].
+    /// Enabled by default.
+    pub fn preflight(mut self, preflight: bool) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    /// The hash of the config the test is built from, recorded in the
+    /// report, see [`TestConfig`](crate::config::TestConfig).
+    pub fn config_hash(mut self, hash: impl Into<String>) -> Self {
+        self.config_hash = Some(hash.into());
+        self
+    }
+
+    /// Configure the client before running, e.g. to set a retry policy or
+    /// hooks.
+    pub fn client(
+        mut self,
+        configure: impl FnOnce(JepsenClient<EC>) -> JepsenClient<EC> + 'static,
+    ) -> Self {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
+    pub fn build(self) -> Test<EC, W> {
+        Test(self)
+    }
+}
+
+/// A test assembled by a [`TestBuilder`].
+pub struct Test<EC: TestCluster, W>(TestBuilder<EC, W>);
+
+impl<EC: TestCluster, W: Workload> Test<EC, W> {
+    /// Run the test in a new runtime of the seed, see [`crate::runtime`],
+    /// and check its history by the checker of the workload.
+    pub fn run(self) -> crate::Result<TestReport> {
+        self.check_environment(self.0.out_dir.as_deref())?;
+        let seed = self.0.seed;
+        runtime::block_on(seed, self.run_in_place())
+    }
+
+    /// Resume the interrupted run in the directory from its last checkpoint,
+    /// with the seed of the run, see [`crate::checkpoint`]. The test must be
+    /// built the same as the run.
+    pub fn resume(mut self, dir: impl AsRef<Path>) -> crate::Result<TestReport> {
+        let checkpoint = Checkpoint::load(&dir)?;
+        self.check_environment(Some(dir.as_ref()))?;
+        if checkpoint.seed != self.0.seed {
+            warn!(
+                "resume the run of seed {} instead of {}",
+                checkpoint.seed, self.0.seed
+            );
+            self.0.seed = checkpoint.seed;
+        }
+        let dir = dir.as_ref().to_path_buf();
+        runtime::block_on(checkpoint.seed, self.run_from(Some((dir, checkpoint))))
+    }
+
+    /// Check the environment and the cluster of the test without running it,
+    /// see [`crate::preflight`]. The cluster is created, set up, read and
+    /// torn down in a runtime of the seed.
+    pub fn preflight(self) -> PreflightReport {
+        let test = self.0;
+        let mut report =
+            preflight::environment(test.out_dir.as_deref(), test.workload.requires_jvm());
+        let cluster = test.cluster;
+        let ping = runtime::block_on(test.seed, async move {
+            let cluster = cluster();
+            if let Err(err) = cluster.setup().await {
+                return PreflightCheck {
+                    name: "cluster",
+                    status: PreflightStatus::Fail,
+                    detail: format!("failed to set up the cluster: {}", err),
+                };
+            }
+            let ping = preflight::ping(&cluster, preflight::PING_TIMEOUT).await;
+            if let Err(err) = cluster.teardown().await {
+                warn!("failed to tear down the cluster: {}", err);
+            }
+            ping
+        });
+        report.checks.push(ping);
+        report
+    }
+
+    /// Run the environment checks of [`Test::preflight`] out of the cluster,
+    /// which is checked when the run starts.
+    fn check_environment(&self, out_dir: Option<&Path>) -> crate::Result<()> {
+        if !self.0.preflight {
+            return Ok(());
+        }
+        let report = preflight::environment(out_dir, self.0.workload.requires_jvm());
+        for check in &report.checks {
+            match check.status {
+                PreflightStatus::Pass => info!("preflight {}", check),
+                _ => warn!("preflight {}", check),
+            }
+        }
+        if !report.is_ok() {
+            return Err(Error::Preflight(report));
+        }
+        Ok(())
+    }
+
+    /// Run the test in the current runtime, see [`Test::run`].
+    pub async fn run_in_place(self) -> crate::Result<TestReport> {
+        self.run_from(None).await
+    }
+
+    /// Run the test, or resume the run in the directory from the checkpoint.
+    async fn run_from(self, resume: Option<(PathBuf, Checkpoint)>) -> crate::Result<TestReport> {
+        let test = self.0;
+        let gen = test
+            .workload
+            .generator()
+            .map_err(|err| Error::Generator(format!("{:#}", err)))?;
+        let mut client = JepsenClient::new((test.cluster)(), gen).with_time_scale(test.time_scale);
+        if let Some(deadline) = test.deadline {
+            client = client.with_deadline(deadline);
+        }
+        if let Some((interval, reporter)) = test.progress {
+            client = client.with_progress(interval, reporter);
+        }
+        if let Some(configure) = test.configure {
+            client = configure(client);
+        }
+        let store = match &resume {
+            Some((dir, _)) => Some(Store::open(dir)?),
+            None => test
+                .out_dir
+                .map(|dir| Store::create(dir, &test.name))
+                .transpose()?,
+        };
+        if let Some(store) = &store {
+            client = client.with_store(store.clone());
+        }
+        if let Some(interval) = test.checkpoint {
+            client = client.with_checkpoint(interval);
+        }
+        if test.preflight {
+            client = client.with_ping(preflight::PING_TIMEOUT);
+        }
+        if let Some((_, checkpoint)) = resume {
+            client = client.with_resume(checkpoint)?;
+        }
+        let client: &'static _ = Box::leak(client.into());
+
+        let delay = match test.duration {
+            Some(duration) => DelayStrategy::Fixed(duration / test.ops.max(1) as u32),
+            None => DelayStrategy::None,
+        };
+        let ops = (0..test.concurrency).map(|_| {
+            GeneratorBuilder::new(client.global.clone())
+                .seq(tokio_stream::iter(client.global.take_seq(test.ops)))
+                .delay(delay.clone())
+                .build()
+        });
+        let nemeses = test
+            .nemeses
+            .iter()
+            .map(|(schedule, cycles)| client.new_schedule(schedule, *cycles));
+        let mixes = test
+            .mixes
+            .into_iter()
+            .map(|(mut mix, n)| client.new_nemesis_mix(&mut mix, n));
+        let gen = GeneratorGroup::new(ops.chain(nemeses).chain(mixes).collect::<Vec<_>>());
+
+        info!("test `{}` started with seed {}", test.name, test.seed);
+        let start = Instant::now();
+        let workload = test.workload;
+        let result = client
+            .run_checked(gen, workload.check_option(), |history, option| {
+                workload.checker()?.check(history, option)
+            })
+            .await?;
+        let elapsed = start.elapsed();
+
+        let progress = client.global.progress();
+        let stats = OpStats {
+            invoked: progress.invoked,
+            ok: progress.ok,
+            fail: progress.fail,
+            info: progress.info,
+            latency: perf::latency_stats(&client.global.full_history()?),
+        };
+        let faults = client.global.nemeses.lock().unwrap().intervals().to_vec();
+        let mut report = TestReport {
+            name: test.name,
+            seed: test.seed,
+            elapsed,
+            config_hash: test.config_hash,
+            stats,
+            faults,
+            panics: client.global.panics(),
+            out_dir: store.as_ref().map(|store| store.dir().to_path_buf()),
+            artifacts: vec![],
+            result,
+        };
+        if let Some(store) = &store {
+            report.artifacts = artifacts(store)?;
+            store.save_results(&report)?;
+        }
+        Ok(report)
+    }
+}
+
+/// The files in the directory of the run, including the results to save.
+fn artifacts(store: &Store) -> Result<Vec<PathBuf>> {
+    let dir = store.dir();
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    paths.extend(["results.json", "results.edn"].map(|f| dir.join(f)));
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// The counts of the ops of a run, and their latencies.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct OpStats {
+    pub invoked: u64,
+    pub ok: u64,
+    pub fail: u64,
+    pub info: u64,
+    /// The latencies of the `:ok` ops, see [`perf::latency_stats`].
+    pub latency: BTreeMap<OpFunctionType, LatencyStats>,
+}
+
+/// The outcome of a [`Test`]. It's serialized with the check result at the
+/// top level, as the results of jepsen are.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TestReport {
+    pub name: String,
+    pub seed: u64,
+    /// The time of the run, simulated under madsim.
+    pub elapsed: Duration,
+    /// The hash of the config the test is built from, if any.
+    pub config_hash: Option<String>,
+    pub stats: OpStats,
+    /// The fault windows of the nemeses, in nanoseconds since the start.
+    pub faults: Vec<FaultInterval>,
+    /// The panics of the generator tasks, which the rest of the run survived.
+    pub panics: Vec<HarnessPanic>,
+    /// The directory of the run in the store, if saved.
+    pub out_dir: Option<PathBuf>,
+    /// The files in the directory of the run.
+    pub artifacts: Vec<PathBuf>,
+    #[serde(flatten)]
+    pub result: SerializableCheckResult,
+}
+
+impl TestReport {
+    /// Whether the history is checked valid.
+    pub fn is_valid(&self) -> bool {
+        matches!(self.result.valid(), ValidType::True)
+    }
+}
+
+#[cfg(all(test, madsim))]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+    use crate::{
+        history::{HistoryProcess, HistoryType, OpError, SerializableHistoryList},
+        mock::MockCluster,
+        nemesis::{DiskStressMode, NemesisType, ServerId},
+        op::OpOrNemesisFuncType,
+        utils::testing::WriteWorkload,
+    };
+
+    #[test]
+    fn test_run() -> Result<()> {
+        let checked = Arc::new(AtomicUsize::new(0));
+        let reports = Arc::new(Mutex::new(vec![]));
+        let reports_ = reports.clone();
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-runner-{}", std::process::id()));
+        let schedule = NemesisSchedule::new(
+            NemesisType::DiskStress {
+                server: 0,
+                mode: DiskStressMode::Enospc,
+            },
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        );
+        let report = TestBuilder::new(|| MockCluster::new(3), WriteWorkload(checked.clone()))
+            .name("writes")
+            .concurrency(2)
+            .ops(10)
+            .duration(Duration::from_secs(5))
+            .nemesis(schedule, 2)
+            .seed(7)
+            .out_dir(&dir)
+            .progress(
+                Duration::from_secs(1),
+                ProgressReporter::callback(move |p| reports_.lock().unwrap().push(p.clone())),
+            )
+            .build()
+            .run()?;
+        assert!(report.is_valid());
+        assert_eq!(report.seed, 7);
+        assert!(report.elapsed >= Duration::from_secs(4));
+        // an invoke and a completion of every op, and a start and a heal of
+        // every window
+        assert_eq!(checked.load(Ordering::SeqCst), 2 * 2 * 10 + 2 * 2);
+        assert_eq!(report.stats.ok, 20);
+        assert_eq!(report.stats.latency[&OpFunctionType::Write].count, 20);
+        assert_eq!(report.faults.len(), 2);
+        let out_dir = report.out_dir.clone().unwrap();
+        assert!(report.artifacts.contains(&out_dir.join("history.edn")));
+        let results: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(out_dir.join("results.json"))?)?;
+        assert_eq!(results["valid?"], true);
+        assert_eq!(results["seed"], 7);
+        assert_eq!(results["stats"]["ok"], 20);
+        let edn = std::fs::read_to_string(out_dir.join("results.edn"))?;
+        assert!(edn.contains(":valid? true") && edn.contains(":config-hash nil"));
+        // every second of the 5s run, and the final one
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() >= 5);
+        assert!(reports
+            .windows(2)
+            .all(|w| w[0].completed() <= w[1].completed()));
+        let last = reports.last().unwrap();
+        assert_eq!((last.invoked, last.ok), (20, 20));
+        assert!(last.active_nemeses.is_empty());
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    /// A cluster whose reads fail, as if it's unreachable.
+    struct DownCluster;
+
+    #[async_trait::async_trait]
+    impl ElleRwClusterClient for DownCluster {
+        async fn get(&self, _key: u64) -> std::result::Result<Option<u64>, String> {
+            Err("connection refused".to_string())
+        }
+        async fn put(&self, _key: u64, _value: u64) -> std::result::Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl ClusterLifecycle for DownCluster {}
+
+    #[async_trait::async_trait]
+    impl NemesisClusterClient for DownCluster {
+        fn size(&self) -> usize {
+            3
+        }
+        #[cfg(madsim)]
+        fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+            None
+        }
+        async fn get_leader_without_term(&self) -> ServerId {
+            0
+        }
+    }
+
+    #[test]
+    fn test_preflight() -> Result<()> {
+        let checked = Arc::new(AtomicUsize::new(0));
+        let dir =
+            std::env::temp_dir().join(format!("jepsen-rs-runner-preflight-{}", std::process::id()));
+        let report = TestBuilder::new(|| MockCluster::new(3), WriteWorkload(checked.clone()))
+            .out_dir(&dir)
+            .build()
+            .preflight();
+        assert!(report.is_ok(), "{}", report);
+        let names: Vec<_> = report.checks.iter().map(|check| check.name).collect();
+        assert_eq!(names, ["madsim", "out-dir", "cluster"]);
+
+        let test = || {
+            TestBuilder::new(|| DownCluster, WriteWorkload(checked.clone()))
+                .concurrency(1)
+                .ops(5)
+        };
+        let report = test().build().preflight();
+        assert_eq!(report.failures().next().unwrap().name, "cluster");
+        // the run fails before any op
+        let err = test().build().run().unwrap_err();
+        assert!(matches!(&err, Error::Preflight(_)), "{}", err);
+        assert!(err.to_string().contains("connection refused"));
+        assert_eq!(checked.load(Ordering::SeqCst), 0);
+        // unless the checks are disabled
+        let report = test().preflight(false).build().run()?;
+        assert_eq!(report.stats.invoked, 5);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_scale() -> Result<()> {
+        let checked = Arc::new(AtomicUsize::new(0));
+        let run = |scale, deadline: Option<Duration>| {
+            let mut builder =
+                TestBuilder::new(|| MockCluster::new(3), WriteWorkload(checked.clone()))
+                    .concurrency(1)
+                    .ops(20)
+                    .duration(Duration::from_millis(400))
+                    .time_scale(scale);
+            if let Some(deadline) = deadline {
+                builder = builder.deadline(deadline);
+            }
+            let start = std::time::Instant::now();
+            let report = builder.build().run()?;
+            anyhow::Ok((report, start.elapsed()))
+        };
+        let (_, accelerated) = run(TimeScale::Accelerated, None)?;
+        assert!(accelerated < Duration::from_millis(200));
+        let (report, paced) = run(TimeScale::Paced(1.0), None)?;
+        assert!(paced >= Duration::from_millis(350));
+        assert_eq!(report.stats.ok, 20);
+        // twice as fast
+        let (_, paced) = run(TimeScale::Paced(2.0), None)?;
+        assert!(paced >= Duration::from_millis(150) && paced < Duration::from_millis(350));
+
+        // finished early by the deadline
+        let (report, elapsed) = run(TimeScale::Paced(1.0), Some(Duration::from_millis(100)))?;
+        assert!(elapsed < Duration::from_millis(300));
+        assert!(report.stats.ok < 20);
+        assert!(report.is_valid());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume() -> Result<()> {
+        let checked = Arc::new(AtomicUsize::new(0));
+        let base = std::env::temp_dir().join(format!("jepsen-rs-resume-{}", std::process::id()));
+        let test = || {
+            TestBuilder::new(|| MockCluster::new(3), WriteWorkload(checked.clone()))
+                .name("resume")
+                .concurrency(2)
+                .ops(20)
+                .duration(Duration::from_secs(20))
+                .seed(7)
+                .out_dir(&base)
+                .checkpoint(Duration::from_secs(3))
+                .build()
+        };
+        // the harness is interrupted in the middle of the run
+        let interrupted = runtime::block_on(7, async {
+            madsim::time::timeout(Duration::from_secs(10), test().run_in_place()).await
+        });
+        assert!(interrupted.is_err());
+        let dir = base.join("resume").join("latest");
+        let checkpoint = Checkpoint::load(&dir)?;
+        let taken: usize = checkpoint.positions.values().sum();
+        assert!((5..35).contains(&taken));
+        assert_eq!(checkpoint.history_offset, 2 * taken as u64);
+
+        let report = test().resume(&dir)?;
+        assert!(report.is_valid());
+        assert_eq!((report.stats.invoked, report.stats.ok), (40, 40));
+        let history: SerializableHistoryList<OpOrNemesisFuncType, OpError> =
+            SerializableHistoryList::from_jsonl(dir.join("history.jsonl"))?;
+        assert_eq!(history.0.len(), 80);
+        assert!(history
+            .0
+            .iter()
+            .enumerate()
+            .all(|(i, item)| item.index == i as u64));
+        assert!(history.0.windows(2).all(|w| w[0].time <= w[1].time));
+        // every value is written once, by the new processes after resuming
+        let mut values: Vec<_> = history
+            .0
+            .iter()
+            .filter(|item| item.type_ == HistoryType::Ok)
+            .map(|item| format!("{:?}", item.value))
+            .collect();
+        values.sort();
+        values.dedup();
+        assert_eq!(values.len(), 40);
+        let resumed = &history.0[checkpoint.history_offset as usize];
+        assert_eq!(resumed.process, HistoryProcess::Gen(2));
+        std::fs::remove_dir_all(base)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+//! The runtime the tests run on, decided at build time:
+//!
+//! - under `--cfg madsim`, a madsim simulation of the seed, where the nemeses
+//!   are applied on the simulated nodes;
+//! - otherwise, plain tokio against real external systems, where there are no
+//!   simulated nodes, so the nemeses are delegated to the cluster client by
+//!   [`NemesisClusterClient::fault_executor`], or applied on local processes
+//!   by [`NemesisClusterClient::os`] with the `os` feature.
+//!
+//! The client, the generators and the histories are the same on both, as
+//! `madsim` falls back to tokio without `--cfg madsim`.
+//!
+//! The simulated time runs as fast as possible by default, see [`TimeScale`]
+//! to pace it for external systems.
+//!
+//! [`NemesisClusterClient::fault_executor`]: crate::nemesis::NemesisClusterClient::fault_executor
+//! [`NemesisClusterClient::os`]: crate::nemesis::NemesisClusterClient
+
+use std::{future::Future, time::Instant};
+
+/// Whether the tests run in the madsim simulation.
+pub const fn is_simulated() -> bool {
+    cfg!(madsim)
+}
+
+/// The seed of the current runtime, `0` without madsim.
+pub fn seed() -> u64 {
+    #[cfg(madsim)]
+    return madsim::runtime::Handle::current().seed();
+    #[cfg(not(madsim))]
+    0
+}
+
+/// Run the future to completion in a new madsim runtime of the seed.
+#[cfg(madsim)]
+pub fn block_on<F: Future>(seed: u64, future: F) -> F::Output {
+    let mut rt = madsim::runtime::Runtime::with_seed_and_config(seed, Default::default());
+    // the JVM runs on threads of its own
+    rt.set_allow_system_thread(true);
+    rt.block_on(future)
+}
+
+/// Run the future to completion in a new tokio runtime. The seed only
+/// decides a simulation, so it's ignored.
+#[cfg(not(madsim))]
+pub fn block_on<F: Future>(seed: u64, future: F) -> F::Output {
+    log::info!("running on tokio, the seed {} is ignored", seed);
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build the tokio runtime")
+        .block_on(future)
+}
+
+/// How fast the simulated time runs against the wall clock, set by
+/// [`TestBuilder::time_scale`](crate::runner::TestBuilder::time_scale).
+/// Without madsim, the time is always real.
+///
+/// The pacing blocks the simulation, and reads the wall clock on a system
+/// thread, so the system threads must be allowed, as [`block_on`] does.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TimeScale {
+    /// Run as fast as possible, e.g. a logical hour of the test in seconds.
+    #[default]
+    Accelerated,
+    /// Run at most the factor times as fast as the wall clock, `1.0` for the
+    /// real time, e.g. for external systems.
+    Paced(f64),
+    /// Run in the real time while the ops to the cluster are in flight, e.g.
+    /// the calls to external systems, and as fast as possible otherwise, e.g.
+    /// the delays of the generators and the nemesis windows.
+    Hybrid,
+}
+
+/// The current wall clock, which is the simulated clock in a madsim runtime
+/// unless read on a system thread.
+pub(crate) fn wall_clock() -> Instant {
+    if is_simulated() {
+        std::thread::spawn(Instant::now)
+            .join()
+            .expect("failed to read the wall clock")
+    } else {
+        Instant::now()
+    }
+}
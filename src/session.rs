@@ -0,0 +1,156 @@
+//! A test session owns the JVM and the required namespaces, and is shared by
+//! multiple sequential runs in the same process, so that every run does not
+//! pay for the JVM startup and `require`s again.
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::Result;
+use log::info;
+
+use crate::{
+    checker::CheckOption, executor::JvmExecutor, init_jvm, preload_namespaces, CljNs, CLOJURE,
+};
+
+/// The namespaces required by the checkers and generators of this crate.
+const DEFAULT_NAMESPACES: [&str; 3] = ["elle.rw-register", "jepsen.history", "clojure.data.json"];
+
+/// A proof that the JVM is initialized and attached to the current thread.
+///
+/// The JVM is attached per thread, so the guard is neither `Send` nor `Sync`:
+/// keep it (and everything calling into the JVM) on the thread that acquired
+/// it.
+#[derive(Debug)]
+pub struct JvmGuard {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl JvmGuard {
+    /// Initialize the JVM if it's not initialized yet, and attach it to the
+    /// current thread.
+    pub fn acquire() -> Self {
+        init_jvm();
+        Self {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+/// A session of sequential test runs sharing one warm JVM.
+///
+/// ```ignore
+/// let session = TestSession::new("./out")?;
+/// for (name, cluster) in clusters {
+///     let run = session.new_run(name)?;
+///     let client = JepsenClient::new(cluster, ElleRwGenerator::new()?)
+///         .with_check_option(run.check_option());
+///     // ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TestSession {
+    /// The root of the output directories of runs.
+    root: PathBuf,
+    namespaces: Mutex<HashMap<String, CljNs>>,
+    runs: AtomicU64,
+    executor: &'static JvmExecutor,
+}
+
+impl TestSession {
+    /// Start a session whose runs output to sub directories of `root`. The
+    /// [`JvmExecutor`] is started and the default namespaces are preloaded
+    /// here.
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let session = Self {
+            root: root.into(),
+            namespaces: Mutex::default(),
+            runs: AtomicU64::new(0),
+            executor: JvmExecutor::global(),
+        };
+        preload_namespaces(&DEFAULT_NAMESPACES)?;
+        for ns in DEFAULT_NAMESPACES {
+            session.require(ns)?;
+        }
+        info!("test session started, output to {:?}", session.root);
+        Ok(session)
+    }
+
+    /// Require a namespace, or get it from the cache if it's already required
+    /// in this session.
+    pub fn require(&self, ns: &str) -> j4rs::errors::Result<CljNs> {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        if let Some(ns) = namespaces.get(ns) {
+            return Ok(ns.clone());
+        }
+        let name = ns.to_string();
+        let required = self
+            .executor
+            .call_blocking(move |_| CLOJURE.require(&name))?;
+        namespaces.insert(ns.to_string(), required.clone());
+        Ok(required)
+    }
+
+    /// Start a new run. Each run gets an output directory of its own, which is
+    /// cleared if it's left by a previous process.
+    pub fn new_run(&self, name: &str) -> Result<TestRun> {
+        let id = self.runs.fetch_add(1, Ordering::Relaxed);
+        let directory = self.root.join(format!("{:03}-{}", id, name));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory)?;
+        }
+        std::fs::create_dir_all(&directory)?;
+        info!("test run {} `{}` started in {:?}", id, name, directory);
+        Ok(TestRun {
+            id,
+            name: name.to_string(),
+            directory,
+        })
+    }
+}
+
+/// A run in a [`TestSession`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestRun {
+    /// The sequence number of the run in the session, starts from 0.
+    pub id: u64,
+    pub name: String,
+    /// The output directory of the run.
+    pub directory: PathBuf,
+}
+
+impl TestRun {
+    /// The check option that outputs to the directory of this run.
+    pub fn check_option(&self) -> CheckOption {
+        CheckOption::default().directory(self.directory.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_runs() -> Result<()> {
+        let root = std::env::temp_dir().join("jepsen_rs_test_session");
+        let session = TestSession::new(&root)?;
+        let a = session.new_run("a")?;
+        std::fs::write(a.directory.join("history.edn"), "[]")?;
+        let b = session.new_run("b")?;
+        assert_eq!((a.id, b.id), (0, 1));
+        assert_ne!(a.directory, b.directory);
+        assert!(std::fs::read_dir(&b.directory)?.next().is_none());
+        assert_eq!(
+            session.require("elle.rw-register")?,
+            CLOJURE.require("elle.rw-register")?
+        );
+        std::fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+//! The output of a run in the layout of the store of jepsen, so the tools of
+//! jepsen browsing `store/` can read it:
+//!
+//! ```text
+//! store/
+//! ├── latest -> <test-name>/<timestamp>
+//! └── <test-name>/
+//!     ├── latest -> <timestamp>
+//!     └── <timestamp>/
+//!         ├── history.edn     one op per line, also as history.jsonl
+//!         ├── results.edn     the check result or the test report, also as results.json
+//!         ├── test.edn        the test metadata, instead of test.fressian
+//!         └── jepsen.log      written by the [`StoreLogger`]
+//! ```
+//!
+//! The timestamp is the creation time in UTC, formatted as jepsen does, e.g.
+//! `20241014T093012.345Z`. Set the store by
+//! [`JepsenClient::with_store`](crate::client::JepsenClient::with_store), and
+//! the elle output and the fault intervals go into the run directory as well.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::{warn, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+use crate::{
+    checker::CheckOption, convert::edn::to_edn, history::SerializableHistoryList, nemesis::ServerId,
+};
+
+/// The log file of a run.
+pub const LOG_FILE: &str = "jepsen.log";
+
+/// The directory of a run in a jepsen store.
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+    name: String,
+    start_time: String,
+}
+
+/// The test metadata, saved as `test.edn`.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TestMeta<'a> {
+    name: &'a str,
+    start_time: &'a str,
+    nodes: Vec<ServerId>,
+    check_option: &'a CheckOption,
+}
+
+/// Format the time as the basic date time of jepsen, in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+    // the civil date of the days since the epoch, by Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since.subsec_millis()
+    )
+}
+
+/// Point the `latest` link in the directory to the target, replacing the old
+/// link.
+fn link_latest(dir: &Path, target: &Path) {
+    let link = dir.join("latest");
+    let _ = std::fs::remove_file(&link);
+    #[cfg(unix)]
+    let res = std::os::unix::fs::symlink(target, &link);
+    #[cfg(not(unix))]
+    let res: std::io::Result<()> = Ok(());
+    if let Err(err) = res {
+        warn!("failed to link {:?} to {:?}: {}", link, target, err);
+    }
+}
+
+impl Store {
+    /// Create the directory of a new run of the test in the store at `base`,
+    /// e.g. `./store`, with an empty `jepsen.log`, and point the `latest`
+    /// links to it.
+    pub fn create(base: impl AsRef<Path>, name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+        let start_time = timestamp(SystemTime::now());
+        let base = base.as_ref();
+        let dir = base.join(&name).join(&start_time);
+        std::fs::create_dir_all(&dir)?;
+        File::create(dir.join(LOG_FILE))?;
+        link_latest(&base.join(&name), Path::new(&start_time));
+        link_latest(base, &Path::new(&name).join(&start_time));
+        Ok(Self {
+            dir,
+            name,
+            start_time,
+        })
+    }
+
+    /// Open the directory of an existing run, e.g. to resume it, see
+    /// [`crate::checkpoint`]. It's `<base>/<name>/<start time>` as created by
+    /// [`Store::create`], links resolved.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = std::fs::canonicalize(dir)?;
+        let file_name = |path: Option<&Path>| {
+            path.and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| anyhow::anyhow!("{:?} is not the directory of a run", dir))
+        };
+        let start_time = file_name(Some(&dir))?;
+        let name = file_name(dir.parent())?;
+        File::options()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE))?;
+        Ok(Self {
+            dir,
+            name,
+            start_time,
+        })
+    }
+
+    /// The directory of the run.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Save the test metadata of a cluster of `nodes` servers.
+    pub fn save_test(&self, nodes: usize, option: &CheckOption) -> Result<()> {
+        let meta = TestMeta {
+            name: &self.name,
+            start_time: &self.start_time,
+            nodes: (0..nodes as ServerId).collect(),
+            check_option: option,
+        };
+        std::fs::write(self.dir.join("test.edn"), to_edn(&meta)? + "\n")?;
+        Ok(())
+    }
+
+    /// Save the history as `history.edn` and `history.jsonl`, one item per
+    /// line.
+    pub fn save_history<F: Serialize, ERR: Serialize>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+    ) -> Result<()> {
+        let mut edn = BufWriter::new(File::create(self.dir.join("history.edn"))?);
+        let mut jsonl = BufWriter::new(File::create(self.dir.join("history.jsonl"))?);
+        for item in history.0.iter() {
+            writeln!(edn, "{}", to_edn(item)?)?;
+            serde_json::to_writer(&mut jsonl, item)?;
+            writeln!(jsonl)?;
+        }
+        edn.flush()?;
+        jsonl.flush()?;
+        Ok(())
+    }
+
+    /// Save the check result, or a report including it, as `results.edn` and
+    /// `results.json`.
+    pub fn save_results<T: Serialize>(&self, result: &T) -> Result<()> {
+        std::fs::write(self.dir.join("results.edn"), to_edn(result)? + "\n")?;
+        std::fs::write(
+            self.dir.join("results.json"),
+            serde_json::to_vec_pretty(result)?,
+        )?;
+        Ok(())
+    }
+
+    /// A logger appending to the `jepsen.log` of the run, install it by e.g.
+    /// [`log::set_boxed_logger`].
+    pub fn logger(&self, level: LevelFilter) -> Result<StoreLogger> {
+        let file = File::options().append(true).open(self.dir.join(LOG_FILE))?;
+        Ok(StoreLogger {
+            file: Mutex::new(BufWriter::new(file)),
+            level,
+        })
+    }
+}
+
+/// Writes the log records to the `jepsen.log` of a [`Store`], one per line.
+#[derive(Debug)]
+pub struct StoreLogger {
+    file: Mutex<BufWriter<File>>,
+    level: LevelFilter,
+}
+
+impl Log for StoreLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(
+            file,
+            "{} {} {} - {}",
+            timestamp(SystemTime::now()),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101T000000.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_728_898_212_345);
+        assert_eq!(timestamp(time), "20241014T093012.345Z");
+        let leap = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(timestamp(leap), "20000229T000000.000Z");
+    }
+
+    #[madsim::test]
+    async fn test_store() -> Result<()> {
+        let base = std::env::temp_dir().join(format!("jepsen-rs-store-{}", std::process::id()));
+        let store = Store::create(&base, "rw-register")?;
+        assert!(store.dir().starts_with(base.join("rw-register")));
+        let history: SerializableHistoryList = serde_json::from_str(
+            r#"[{ "index": 0, "type": "invoke", "f": "txn", "value": [["w", 1, 2]], "time": 1, "process": 0, "error": null }]"#,
+        )?;
+        store.save_history(&history)?;
+        store.save_test(3, &CheckOption::default())?;
+        let logger = store.logger(LevelFilter::Info)?;
+        logger.log(
+            &Record::builder()
+                .args(format_args!("hello"))
+                .level(log::Level::Info)
+                .target("t")
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .args(format_args!("hidden"))
+                .level(log::Level::Debug)
+                .build(),
+        );
+        drop(logger);
+
+        // read back through the latest link
+        let latest = base.join("latest");
+        let read = SerializableHistoryList::from_jepsen_store(&latest)?;
+        assert_eq!(read.0.len(), 1);
+        let test = std::fs::read_to_string(latest.join("test.edn"))?;
+        assert!(test.contains(":name \"rw-register\""), "{}", test);
+        assert!(test.contains(":nodes [0 1 2]"), "{}", test);
+        let log = std::fs::read_to_string(latest.join(LOG_FILE))?;
+        assert!(log.trim_end().ends_with("INFO t - hello"), "{}", log);
+        assert!(!log.contains("hidden"));
+        std::fs::remove_dir_all(&base)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,562 @@
+//! Keys and values of other types than `u64`, e.g. the string keys of a real
+//! database.
+//!
+//! The ops are generated and checked with `u64` keys and values, as elle
+//! requires. A [`TypedClusterClient`] gets them as [`GenericOp`]s of its own
+//! key and value types, converted by [`OpValue`], which is a bijection, so
+//! the values read from the database are mapped back without loss. Every
+//! typed cluster client is an [`ElleRwClusterClient`].
+
+use std::fmt::Debug;
+
+use serde::{
+    de::DeserializeOwned,
+    ser::{SerializeSeq, SerializeTuple},
+    Deserialize, Serialize,
+};
+use serde_json::Value;
+
+use crate::{
+    client::{ElleRwClusterClient, CAS_MISMATCH},
+    convert::edn::Keyword,
+    op::{Key, Op},
+};
+
+/// A key or value type, converted from and to the `u64` of the generated ops.
+/// The conversion is a bijection on the values it produces, and preserves the
+/// order, so range scans cover the same keys.
+pub trait OpValue: Debug + Clone + PartialEq + Send + Sync + 'static {
+    fn from_u64(n: u64) -> Self;
+    /// Convert back, fails if the value is not produced by
+    /// [`OpValue::from_u64`].
+    fn to_u64(&self) -> Result<u64, String>;
+}
+
+impl OpValue for u64 {
+    fn from_u64(n: u64) -> Self {
+        n
+    }
+
+    fn to_u64(&self) -> Result<u64, String> {
+        Ok(*self)
+    }
+}
+
+/// Signed values, ordered as `u64` for the generated values below
+/// [`i64::MAX`].
+impl OpValue for i64 {
+    fn from_u64(n: u64) -> Self {
+        n as i64
+    }
+
+    fn to_u64(&self) -> Result<u64, String> {
+        Ok(*self as u64)
+    }
+}
+
+/// Zero-padded decimal strings, e.g. `00000000000000000042`, so they are
+/// ordered as the numbers.
+impl OpValue for String {
+    fn from_u64(n: u64) -> Self {
+        format!("{:020}", n)
+    }
+
+    fn to_u64(&self) -> Result<u64, String> {
+        match self.len() == 20 {
+            true => self
+                .parse()
+                .map_err(|err| format!("invalid {:?}: {}", self, err)),
+            false => Err(format!("invalid {:?}: not 20 digits", self)),
+        }
+    }
+}
+
+/// Big-endian bytes, so they are ordered as the numbers.
+impl OpValue for Vec<u8> {
+    fn from_u64(n: u64) -> Self {
+        n.to_be_bytes().to_vec()
+    }
+
+    fn to_u64(&self) -> Result<u64, String> {
+        let bytes: [u8; 8] = self
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("invalid {:?}: not 8 bytes", self))?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+/// Keys of tables, packed as [`Key::pack`].
+impl OpValue for Key {
+    fn from_u64(n: u64) -> Self {
+        Key::unpack(n)
+    }
+
+    fn to_u64(&self) -> Result<u64, String> {
+        self.pack()
+    }
+}
+
+/// An [`Op`] with keys of `K` and values of `V`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenericOp<K, V> {
+    Read(K, Option<V>),
+    Write(K, V),
+    Cas(K, V, V),
+    Delete(K),
+    Incr(K, i64),
+    Append(K, V),
+    ReadList(K, Option<Vec<V>>),
+    BatchRead(Vec<(K, Option<V>)>),
+    ScanRange(K, K, Option<Vec<(K, V)>>),
+    Txn(Vec<GenericOp<K, V>>),
+}
+
+impl<K: OpValue, V: OpValue> From<&Op> for GenericOp<K, V> {
+    fn from(op: &Op) -> Self {
+        let (k, v) = (K::from_u64, V::from_u64);
+        match op {
+            Op::Read(key, value) => Self::Read(k(*key), value.map(v)),
+            Op::Write(key, value) => Self::Write(k(*key), v(*value)),
+            Op::Cas(key, expect, new) => Self::Cas(k(*key), v(*expect), v(*new)),
+            Op::Delete(key) => Self::Delete(k(*key)),
+            Op::Incr(key, delta) => Self::Incr(k(*key), *delta),
+            Op::Append(key, value) => Self::Append(k(*key), v(*value)),
+            Op::ReadList(key, list) => Self::ReadList(
+                k(*key),
+                list.as_ref()
+                    .map(|list| list.iter().copied().map(v).collect()),
+            ),
+            Op::BatchRead(reads) => Self::BatchRead(
+                reads
+                    .iter()
+                    .map(|(key, value)| (k(*key), value.map(v)))
+                    .collect(),
+            ),
+            Op::ScanRange(start, end, results) => Self::ScanRange(
+                k(*start),
+                k(*end),
+                results.as_ref().map(|results| {
+                    results
+                        .iter()
+                        .map(|(key, value)| (k(*key), v(*value)))
+                        .collect()
+                }),
+            ),
+            Op::Txn(ops) => Self::Txn(ops.iter().map(Self::from).collect()),
+        }
+    }
+}
+
+impl<K: OpValue, V: OpValue> TryFrom<GenericOp<K, V>> for Op {
+    type Error = String;
+
+    fn try_from(op: GenericOp<K, V>) -> Result<Self, String> {
+        let opt = |value: Option<V>| value.map(|v| v.to_u64()).transpose();
+        Ok(match op {
+            GenericOp::Read(key, value) => Op::Read(key.to_u64()?, opt(value)?),
+            GenericOp::Write(key, value) => Op::Write(key.to_u64()?, value.to_u64()?),
+            GenericOp::Cas(key, expect, new) => {
+                Op::Cas(key.to_u64()?, expect.to_u64()?, new.to_u64()?)
+            }
+            GenericOp::Delete(key) => Op::Delete(key.to_u64()?),
+            GenericOp::Incr(key, delta) => Op::Incr(key.to_u64()?, delta),
+            GenericOp::Append(key, value) => Op::Append(key.to_u64()?, value.to_u64()?),
+            GenericOp::ReadList(key, list) => Op::ReadList(
+                key.to_u64()?,
+                list.map(|list| list.iter().map(V::to_u64).collect::<Result<_, _>>())
+                    .transpose()?,
+            ),
+            GenericOp::BatchRead(reads) => Op::BatchRead(
+                reads
+                    .into_iter()
+                    .map(|(key, value)| Ok((key.to_u64()?, opt(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            GenericOp::ScanRange(start, end, results) => Op::ScanRange(
+                start.to_u64()?,
+                end.to_u64()?,
+                results
+                    .map(|results| {
+                        results
+                            .into_iter()
+                            .map(|(key, value)| Ok((key.to_u64()?, value.to_u64()?)))
+                            .collect::<Result<_, String>>()
+                    })
+                    .transpose()?,
+            ),
+            GenericOp::Txn(ops) => Op::Txn(
+                ops.into_iter()
+                    .map(Op::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+}
+
+/// Serialized in the shape of the JSON of [`Op`], e.g. `["w", key, value]`,
+/// with the function as a [`Keyword`], so it's a micro-op in EDN.
+impl<K: Serialize, V: Serialize> Serialize for GenericOp<K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn triple<S: serde::Serializer>(
+            serializer: S,
+            f: &str,
+            a: impl Serialize,
+            b: impl Serialize,
+        ) -> Result<S::Ok, S::Error> {
+            let mut tuple = serializer.serialize_tuple(3)?;
+            tuple.serialize_element(&Keyword(f))?;
+            tuple.serialize_element(&a)?;
+            tuple.serialize_element(&b)?;
+            tuple.end()
+        }
+        match self {
+            GenericOp::Read(k, v) => triple(serializer, "r", k, v),
+            GenericOp::Write(k, v) => triple(serializer, "w", k, v),
+            GenericOp::Cas(k, expect, new) => triple(serializer, "cas", k, (expect, new)),
+            GenericOp::Delete(k) => triple(serializer, "delete", k, ()),
+            GenericOp::Incr(k, delta) => triple(serializer, "incr", k, delta),
+            GenericOp::Append(k, v) => triple(serializer, "append", k, v),
+            GenericOp::ReadList(k, list) => triple(serializer, "r", k, list),
+            GenericOp::BatchRead(reads) => {
+                let keys: Vec<_> = reads.iter().map(|(k, _)| k).collect();
+                let values: Vec<_> = reads.iter().map(|(_, v)| v).collect();
+                triple(serializer, "batch-read", keys, values)
+            }
+            GenericOp::ScanRange(start, end, results) => {
+                triple(serializer, "scan", (start, end), results)
+            }
+            GenericOp::Txn(ops) => {
+                let mut seq = serializer.serialize_seq(Some(ops.len()))?;
+                for op in ops {
+                    seq.serialize_element(op)?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+impl<'de, K: DeserializeOwned, V: DeserializeOwned> Deserialize<'de> for GenericOp<K, V> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<K: DeserializeOwned, V: DeserializeOwned> GenericOp<K, V> {
+    fn from_json(value: Value) -> Result<Self, String> {
+        fn de<T: DeserializeOwned>(value: Value) -> Result<T, String> {
+            serde_json::from_value(value).map_err(|err| err.to_string())
+        }
+        let Value::Array(items) = value else {
+            return Err(format!("expected an op, got {}", value));
+        };
+        let f = match items.first() {
+            Some(Value::String(f)) => f.clone(),
+            // a txn is a vector of micro-ops, including the empty txn
+            _ => {
+                return Ok(GenericOp::Txn(
+                    items
+                        .into_iter()
+                        .map(Self::from_json)
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+        };
+        let [_, a, b]: [Value; 3] = items
+            .try_into()
+            .map_err(|_| format!("expected `[{} _ _]`", f))?;
+        Ok(match f.as_str() {
+            // a list read if the value is an array of values, which is tried
+            // first, as values may be arrays themselves, e.g. bytes
+            "r" if b.is_array() => match de(b.clone()) {
+                Ok(list) => GenericOp::ReadList(de(a)?, Some(list)),
+                Err(_) => GenericOp::Read(de(a)?, de(b)?),
+            },
+            "r" => GenericOp::Read(de(a)?, de(b)?),
+            "w" => GenericOp::Write(de(a)?, de(b)?),
+            "cas" => {
+                let (expect, new) = de(b)?;
+                GenericOp::Cas(de(a)?, expect, new)
+            }
+            "delete" => GenericOp::Delete(de(a)?),
+            "incr" => GenericOp::Incr(de(a)?, de(b)?),
+            "append" => GenericOp::Append(de(a)?, de(b)?),
+            "batch-read" => {
+                let (keys, values): (Vec<K>, Vec<Option<V>>) = (de(a)?, de(b)?);
+                if keys.len() != values.len() {
+                    return Err(
+                        "the keys and values of `batch-read` should be of the same length"
+                            .to_string(),
+                    );
+                }
+                GenericOp::BatchRead(keys.into_iter().zip(values).collect())
+            }
+            "scan" => {
+                let (start, end) = de(a)?;
+                GenericOp::ScanRange(start, end, de(b)?)
+            }
+            f => return Err(format!("unknown op function `{}`", f)),
+        })
+    }
+}
+
+/// A cluster client with keys of [`TypedClusterClient::Key`] and values of
+/// [`TypedClusterClient::Value`], see the [module](self) doc. The unsupported
+/// ops fail by default, like [`ElleRwClusterClient`].
+#[async_trait::async_trait]
+pub trait TypedClusterClient: Sync {
+    type Key: OpValue;
+    type Value: OpValue;
+
+    async fn get(&self, key: Self::Key) -> Result<Option<Self::Value>, String>;
+    async fn put(&self, key: Self::Key, value: Self::Value) -> Result<(), String>;
+    /// Set the key to `new` if its value is `expect`, returns whether it's
+    /// set.
+    async fn cas(
+        &self,
+        key: Self::Key,
+        expect: Self::Value,
+        new: Self::Value,
+    ) -> Result<bool, String> {
+        let _ = (key, expect, new);
+        Err("cas is not supported by the cluster client".to_string())
+    }
+    async fn delete(&self, key: Self::Key) -> Result<(), String> {
+        let _ = key;
+        Err("delete is not supported by the cluster client".to_string())
+    }
+    /// Add the delta to the counter of the key.
+    async fn incr(&self, key: Self::Key, delta: i64) -> Result<(), String> {
+        let _ = (key, delta);
+        Err("incr is not supported by the cluster client".to_string())
+    }
+    /// Append the value to the list of the key.
+    async fn append(&self, key: Self::Key, value: Self::Value) -> Result<(), String> {
+        let _ = (key, value);
+        Err("append is not supported by the cluster client".to_string())
+    }
+    /// Read the list of the key, empty if it does not exist.
+    async fn get_list(&self, key: Self::Key) -> Result<Vec<Self::Value>, String> {
+        let _ = key;
+        Err("list reads are not supported by the cluster client".to_string())
+    }
+    /// Read the existing keys in `[start, end)` with their values, ordered by
+    /// key.
+    async fn scan(
+        &self,
+        start: Self::Key,
+        end: Self::Key,
+    ) -> Result<Vec<(Self::Key, Self::Value)>, String> {
+        let _ = (start, end);
+        Err("scan is not supported by the cluster client".to_string())
+    }
+    /// Execute the reads and writes of a txn, returns them with the read
+    /// values filled. The default implementation executes them one by one,
+    /// which is not atomic; override it if the cluster supports
+    /// transactions.
+    async fn txn(
+        &self,
+        ops: Vec<GenericOp<Self::Key, Self::Value>>,
+    ) -> Result<Vec<GenericOp<Self::Key, Self::Value>>, String> {
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            results.push(match op {
+                GenericOp::Read(key, _) => {
+                    let value = self.get(key.clone()).await?;
+                    GenericOp::Read(key, value)
+                }
+                GenericOp::Write(key, value) => {
+                    self.put(key.clone(), value.clone()).await?;
+                    GenericOp::Write(key, value)
+                }
+                GenericOp::Cas(key, expect, new) => {
+                    if !self.cas(key.clone(), expect.clone(), new.clone()).await? {
+                        return Err(CAS_MISMATCH.to_string());
+                    }
+                    GenericOp::Cas(key, expect, new)
+                }
+                GenericOp::Delete(key) => {
+                    self.delete(key.clone()).await?;
+                    GenericOp::Delete(key)
+                }
+                GenericOp::Incr(key, delta) => {
+                    self.incr(key.clone(), delta).await?;
+                    GenericOp::Incr(key, delta)
+                }
+                GenericOp::Append(key, value) => {
+                    self.append(key.clone(), value.clone()).await?;
+                    GenericOp::Append(key, value)
+                }
+                GenericOp::ReadList(key, _) => {
+                    let list = self.get_list(key.clone()).await?;
+                    GenericOp::ReadList(key, Some(list))
+                }
+                GenericOp::BatchRead(_) | GenericOp::ScanRange(..) => {
+                    return Err("multi-key reads cannot be in txns".to_string())
+                }
+                GenericOp::Txn(_) => return Err("txns cannot be nested".to_string()),
+            });
+        }
+        Ok(results)
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: TypedClusterClient> ElleRwClusterClient for C {
+    async fn get(&self, key: u64) -> Result<Option<u64>, String> {
+        let value = TypedClusterClient::get(self, C::Key::from_u64(key)).await?;
+        value.map(|v| v.to_u64()).transpose()
+    }
+
+    async fn put(&self, key: u64, value: u64) -> Result<(), String> {
+        TypedClusterClient::put(self, C::Key::from_u64(key), C::Value::from_u64(value)).await
+    }
+
+    async fn cas(&self, key: u64, expect: u64, new: u64) -> Result<bool, String> {
+        let (expect, new) = (C::Value::from_u64(expect), C::Value::from_u64(new));
+        TypedClusterClient::cas(self, C::Key::from_u64(key), expect, new).await
+    }
+
+    async fn delete(&self, key: u64) -> Result<(), String> {
+        TypedClusterClient::delete(self, C::Key::from_u64(key)).await
+    }
+
+    async fn incr(&self, key: u64, delta: i64) -> Result<(), String> {
+        TypedClusterClient::incr(self, C::Key::from_u64(key), delta).await
+    }
+
+    async fn append(&self, key: u64, value: u64) -> Result<(), String> {
+        TypedClusterClient::append(self, C::Key::from_u64(key), C::Value::from_u64(value)).await
+    }
+
+    async fn get_list(&self, key: u64) -> Result<Vec<u64>, String> {
+        TypedClusterClient::get_list(self, C::Key::from_u64(key))
+            .await?
+            .iter()
+            .map(C::Value::to_u64)
+            .collect()
+    }
+
+    async fn scan(&self, start: u64, end: u64) -> Result<Vec<(u64, u64)>, String> {
+        let (start, end) = (C::Key::from_u64(start), C::Key::from_u64(end));
+        TypedClusterClient::scan(self, start, end)
+            .await?
+            .into_iter()
+            .map(|(k, v)| Ok((k.to_u64()?, v.to_u64()?)))
+            .collect()
+    }
+
+    async fn txn(&self, ops: Vec<Op>) -> Result<Vec<Op>, String> {
+        let ops = ops.iter().map(GenericOp::from).collect();
+        TypedClusterClient::txn(self, ops)
+            .await?
+            .into_iter()
+            .map(Op::try_from)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Mutex};
+
+    use super::*;
+    use crate::convert::edn::to_edn;
+
+    /// A store of string keys and byte values.
+    #[derive(Default)]
+    struct StringStore(Mutex<BTreeMap<String, Vec<u8>>>);
+
+    #[async_trait::async_trait]
+    impl TypedClusterClient for StringStore {
+        type Key = String;
+        type Value = Vec<u8>;
+
+        async fn get(&self, key: String) -> Result<Option<Vec<u8>>, String> {
+            Ok(self.0.lock().unwrap().get(&key).cloned())
+        }
+
+        async fn put(&self, key: String, value: Vec<u8>) -> Result<(), String> {
+            self.0.lock().unwrap().insert(key, value);
+            Ok(())
+        }
+
+        async fn scan(&self, start: String, end: String) -> Result<Vec<(String, Vec<u8>)>, String> {
+            let store = self.0.lock().unwrap();
+            Ok(store
+                .range(start..end)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_op_values() {
+        for n in [0, 7, 100, u64::MAX] {
+            assert_eq!(String::from_u64(n).to_u64(), Ok(n));
+            assert_eq!(Vec::<u8>::from_u64(n).to_u64(), Ok(n));
+            assert_eq!(i64::from_u64(n).to_u64(), Ok(n));
+        }
+        assert!(String::from_u64(9) < String::from_u64(10));
+        assert!(Vec::<u8>::from_u64(255) < Vec::<u8>::from_u64(256));
+        assert!("42".to_string().to_u64().is_err());
+    }
+
+    #[test]
+    fn test_generic_op_serde() -> anyhow::Result<()> {
+        let op = Op::Txn(vec![Op::Write(1, 2), Op::Read(3, None)]);
+        let generic: GenericOp<String, i64> = (&op).into();
+        let json = serde_json::to_string(&generic)?;
+        assert_eq!(
+            json,
+            r#"[["w","00000000000000000001",2],["r","00000000000000000003",null]]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<GenericOp<String, i64>>(&json)?,
+            generic
+        );
+        assert_eq!(
+            to_edn(&generic)?,
+            r#"[[:w "00000000000000000001" 2] [:r "00000000000000000003" nil]]"#
+        );
+        assert_eq!(Op::try_from(generic), Ok(op));
+
+        let scan: GenericOp<u64, u64> = (&Op::ScanRange(1, 3, Some(vec![(2, 5)]))).into();
+        assert_eq!(serde_json::to_string(&scan)?, r#"["scan",[1,3],[[2,5]]]"#);
+
+        let op = Op::Txn(vec![Op::Append(1, 2), Op::ReadList(1, Some(vec![2]))]);
+        let generic: GenericOp<u64, Vec<u8>> = (&op).into();
+        let json = serde_json::to_string(&generic)?;
+        assert_eq!(
+            serde_json::from_str::<GenericOp<u64, Vec<u8>>>(&json)?,
+            generic
+        );
+        assert_eq!(Op::try_from(generic), Ok(op));
+        let read: GenericOp<u64, Vec<u8>> = (&Op::Read(1, Some(2))).into();
+        let json = serde_json::to_string(&read)?;
+        assert_eq!(
+            serde_json::from_str::<GenericOp<u64, Vec<u8>>>(&json)?,
+            read
+        );
+        Ok(())
+    }
+
+    #[madsim::test]
+    async fn test_typed_cluster_client() {
+        let store = StringStore::default();
+        ElleRwClusterClient::put(&store, 1, 10).await.unwrap();
+        let res = ElleRwClusterClient::txn(&store, vec![Op::Write(2, 20), Op::Read(1, None)])
+            .await
+            .unwrap();
+        assert_eq!(res, [Op::Write(2, 20), Op::Read(1, Some(10))]);
+        assert_eq!(
+            store.0.lock().unwrap().get("00000000000000000002"),
+            Some(&20u64.to_be_bytes().to_vec())
+        );
+        let scan = ElleRwClusterClient::scan(&store, 0, 10).await.unwrap();
+        assert_eq!(scan, [(1, 10), (2, 20)]);
+        assert!(ElleRwClusterClient::delete(&store, 1).await.is_err());
+    }
+}
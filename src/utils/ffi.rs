@@ -1,8 +1,10 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use j4rs::{errors::Result as jResult, Instance, InvocationArg};
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 
-use crate::{cljinvoke, nsinvoke, with_jvm, CLOJURE};
+use super::FfiError;
+use crate::{cljinvoke, convert::edn::parse_edn, nsinvoke, with_jvm, IFn, CLOJURE};
 
 /// print a java instance
 pub fn print(inst: Instance) {
@@ -62,6 +64,7 @@ pub fn pre_serialize(i: Instance) -> jResult<Instance> {
 }
 
 /// This function converts a clojure edn instance to jepsen history instance.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
 pub fn historify(i: Instance) -> jResult<Instance> {
     with_jvm(|_| {
         let h = CLOJURE.require("jepsen.history")?;
@@ -85,11 +88,45 @@ pub fn clj_from_json(s: &str) -> jResult<Instance> {
     })
 }
 
+/// Convert an EDN string to clojure instance
+pub fn clj_from_edn(s: &str) -> jResult<Instance> {
+    with_jvm(|_| {
+        let edn = CLOJURE.require("clojure.edn")?;
+        nsinvoke!(edn, "read-string", s)
+    })
+}
+
+/// Evaluate the forms of the clojure code on the current thread, and return
+/// the value of the last one, e.g. a custom checker defined in the code.
+///
+/// ```ignore
+/// let res: bool = clj_eval_to("(require 'jepsen.history) (empty? (jepsen.history/history []))")?;
+/// ```
+pub fn clj_eval(code: &str) -> Result<Instance, FfiError> {
+    with_jvm(|_| {
+        CLOJURE
+            .var("load-string")?
+            .invoke1(InvocationArg::try_from(code)?)
+    })
+}
+
+/// Evaluate the code like [`clj_eval`], and convert the value by
+/// [`ToDe::to_de_edn`].
+pub fn clj_eval_to<T: DeserializeOwned>(code: &str) -> Result<T> {
+    clj_eval(code)?.to_de_edn()
+}
+
 /// Convert any rust struct which impl Serialize to clojure instance
 pub trait FromSerde {
+    /// Convert through JSON, the keywords become strings.
     fn from_ser<T: Serialize>(s: T) -> Result<Self>
     where
         Self: Sized;
+    /// Convert through EDN by [`crate::convert::edn::to_edn`], which keeps the
+    /// keywords.
+    fn from_ser_edn<T: Serialize>(s: T) -> Result<Self>
+    where
+        Self: Sized;
 }
 
 impl FromSerde for Instance {
@@ -99,17 +136,78 @@ impl FromSerde for Instance {
     {
         Ok(clj_from_json(&serde_json::to_string(&s)?)?)
     }
+
+    fn from_ser_edn<T: Serialize>(s: T) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Ok(clj_from_edn(&crate::convert::edn::to_edn(&s)?)?)
+    }
+}
+
+/// Marshal a rust value into a clojure data structure through EDN by
+/// [`crate::convert::edn::to_edn`], to pass it to an [`IFn`]: a `Vec` becomes
+/// a vector, a `HashMap` a map whose string keys are keywords, `None` is
+/// `nil`, and a [`Keyword`](crate::convert::edn::Keyword) is a keyword.
+///
+/// ```ignore
+/// let assoc = CLOJURE.var("assoc")?;
+/// let map = HashMap::from([("a", vec![1, 2])]);
+/// let res = assoc.call(&[&map, &Keyword("b"), &None::<i64>])?; // {:a [1 2], :b nil}
+/// ```
+pub trait ToClj {
+    fn to_clj(&self) -> Result<Instance>;
+
+    /// The clojure value as the argument of an invocation.
+    fn to_arg(&self) -> Result<InvocationArg> {
+        Ok(InvocationArg::from(self.to_clj()?))
+    }
+}
+
+impl<T: Serialize + ?Sized> ToClj for T {
+    fn to_clj(&self) -> Result<Instance> {
+        Instance::from_ser_edn(self)
+    }
+}
+
+impl IFn {
+    /// Invoke the function with the rust values marshalled by [`ToClj`].
+    pub fn call(&self, args: &[&dyn ToClj]) -> Result<Instance> {
+        let args = args
+            .iter()
+            .map(|arg| arg.to_arg())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self.invoke(&args)?)
+    }
 }
 
 /// Convert clojure instance to any rust struct which impl Serialize
 pub trait ToDe {
+    /// Convert through JSON, the keywords lose their namespaces.
     fn to_de<T: for<'de> serde::Deserialize<'de>>(self) -> Result<T>;
+    /// Convert through the EDN printed by one `pr-str`, read by
+    /// [`crate::convert::edn::parse_edn`], which keeps the namespaces of the
+    /// keywords and the sets.
+    fn to_de_edn<T: for<'de> serde::Deserialize<'de>>(self) -> Result<T>;
 }
 
 impl ToDe for Instance {
     fn to_de<T: for<'de> serde::Deserialize<'de>>(self) -> Result<T> {
         Ok(serde_json::from_str(&clj_jsonify(self)?)?)
     }
+
+    fn to_de_edn<T: for<'de> serde::Deserialize<'de>>(self) -> Result<T> {
+        de_edn(&clj_to_string(self)?)
+    }
+}
+
+/// Deserialize the only form of the printed EDN through its JSON value, so
+/// the map keys which are not strings, e.g. the ints of elle, are accepted.
+fn de_edn<T: for<'de> serde::Deserialize<'de>>(edn: &str) -> Result<T> {
+    match <[Value; 1]>::try_from(parse_edn(edn)?) {
+        Ok([value]) => Ok(serde_json::from_value(value)?),
+        Err(forms) => bail!("expect one EDN form, found {}", forms.len()),
+    }
 }
 
 #[cfg(test)]
@@ -117,7 +215,58 @@ mod tests {
     use serde::Deserialize;
 
     use super::*;
-    use crate::{cljeval, init_jvm};
+    use crate::{cljeval, convert::edn::Keyword, init_jvm};
+
+    #[test]
+    fn test_de_edn() -> Result<()> {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Res {
+            #[serde(rename = "valid?")]
+            valid: bool,
+            tags: Vec<String>,
+            anomalies: Value,
+        }
+        let res: Res = de_edn(
+            r#"{:valid? false, :tags #{:elle/g1c}, :anomalies {:G1c [{:cycle [#jepsen.history.Op{:index 1}]}], 2 :x}}"#,
+        )?;
+        assert_eq!(
+            res,
+            Res {
+                valid: false,
+                tags: vec!["elle/g1c".to_string()],
+                anomalies: serde_json::json!({"G1c": [{"cycle": [{"index": 1}]}], "2": "x"}),
+            }
+        );
+        assert!(de_edn::<Value>("1 2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clj_eval() -> Result<()> {
+        init_jvm();
+        let set: Vec<String> = clj_eval_to("(defn kw [x] (keyword \"a\" x)) #{(kw \"b\")}")?;
+        assert_eq!(set, ["a/b"]);
+        let sum = clj_eval("(+ 1 2)")?;
+        assert_eq!(clj_to_string(sum)?, "3");
+        let Err(err) = clj_eval("(+ 1") else {
+            panic!("unbalanced code should not be evaluated");
+        };
+        assert_eq!(err.kind(), crate::utils::FfiErrorKind::BadEdn);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_clj() -> Result<()> {
+        init_jvm();
+        let map = std::collections::HashMap::from([("a", vec![1, 2])]);
+        let res = CLOJURE
+            .var("assoc")?
+            .call(&[&map, &Keyword("b"), &None::<i64>])?;
+        assert_eq!(clj_to_string(res)?, "{:a [1 2], :b nil}");
+        let kw = CLOJURE.var("keyword?")?.call(&[&Keyword("c")])?;
+        assert!(kw.to_de_edn::<bool>()?);
+        Ok(())
+    }
 
     #[test]
     fn test_convertion_between_clojure_and_rust() {
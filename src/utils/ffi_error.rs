@@ -0,0 +1,320 @@
+//! The errors of clojure invocations, parsed from the Java exceptions.
+
+use std::fmt;
+
+use j4rs::errors::J4RsError;
+use serde_json::Value;
+
+use crate::convert::edn::parse_edn;
+
+/// What went wrong in a clojure invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FfiErrorKind {
+    /// The invocation timed out or was interrupted, e.g. a check of elle.
+    Timeout,
+    /// The EDN passed to clojure cannot be read.
+    BadEdn,
+    /// A class or namespace is not on the classpath.
+    ClassNotFound,
+    /// An `ex-info` thrown by clojure, see [`FfiError::data`].
+    ExInfo,
+    /// Another Java exception.
+    Java,
+    /// The current thread is not attached to the JVM, e.g. it was detached
+    /// by a dropped [`j4rs::Jvm`], and attaching it again failed.
+    ThreadNotAttached,
+    /// An error of j4rs or the JVM, not thrown by Java.
+    Other,
+}
+
+/// A clojure invocation error, with the Java exception parsed from the
+/// stack trace j4rs reports.
+///
+/// The exception is the root cause of the chain, as the outer ones are the
+/// reflective wrappers of j4rs and clojure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FfiError(Box<Inner>);
+
+/// Boxed to keep the results of invocations small.
+#[derive(Debug, Clone, PartialEq)]
+struct Inner {
+    kind: FfiErrorKind,
+    class: Option<String>,
+    message: String,
+    data: Option<Value>,
+    stack_trace: Vec<String>,
+    source: J4RsError,
+}
+
+/// An exception in the printed stack trace, e.g. the `Caused by:` sections.
+#[derive(Debug, Default)]
+struct Exception {
+    class: String,
+    message: String,
+    frames: Vec<String>,
+}
+
+/// Split the printed stack trace into the chain of exceptions, outermost
+/// first.
+fn exceptions(trace: &str) -> Vec<Exception> {
+    let mut chain: Vec<Exception> = vec![];
+    for line in trace.lines() {
+        if let Some(frame) = line.strip_prefix("\tat ") {
+            if let Some(last) = chain.last_mut() {
+                last.frames.push(frame.to_string());
+            }
+        } else if line.starts_with('\t') || line.trim().is_empty() {
+            // `... n more` and the suppressed exceptions
+        } else if let Some(header) = line
+            .strip_prefix("Caused by: ")
+            .or(chain.is_empty().then_some(line))
+        {
+            let (class, message) = header.split_once(": ").unwrap_or((header, ""));
+            chain.push(Exception {
+                class: class.trim().to_string(),
+                message: message.to_string(),
+                frames: vec![],
+            });
+        } else if let Some(last) = chain.last_mut() {
+            // a message of several lines
+            last.message.push('\n');
+            last.message.push_str(line);
+        }
+    }
+    chain
+}
+
+/// Split the message of an `ex-info` into the message and the data map
+/// printed after it.
+fn ex_info(message: &str) -> Option<(String, Value)> {
+    message.match_indices(" {").find_map(|(at, _)| {
+        match parse_edn(&message[at..]).ok()?.as_slice() {
+            [data @ Value::Object(_)] => Some((message[..at].to_string(), data.clone())),
+            _ => None,
+        }
+    })
+}
+
+/// Classify the chain, from the root cause out.
+fn kind(chain: &[Exception], data: Option<&Value>) -> FfiErrorKind {
+    let timeout = data
+        .and_then(|data| data.get("type"))
+        .and_then(Value::as_str)
+        .is_some_and(|ty| ty.contains("timeout"));
+    for e in chain.iter().rev() {
+        let class = e.class.as_str();
+        if timeout
+            || class == "java.util.concurrent.TimeoutException"
+            || class == "java.lang.InterruptedException"
+        {
+            return FfiErrorKind::Timeout;
+        }
+        if class == "java.lang.ClassNotFoundException"
+            || class == "java.lang.NoClassDefFoundError"
+            || (class == "java.io.FileNotFoundException" && e.message.contains("on classpath"))
+        {
+            return FfiErrorKind::ClassNotFound;
+        }
+        if class.ends_with("ReaderException")
+            || ["EOF while reading", "Invalid token", "Unmatched delimiter"]
+                .iter()
+                .any(|m| e.message.starts_with(m))
+        {
+            return FfiErrorKind::BadEdn;
+        }
+    }
+    match data {
+        Some(_) => FfiErrorKind::ExInfo,
+        None => FfiErrorKind::Java,
+    }
+}
+
+impl FfiError {
+    pub fn kind(&self) -> FfiErrorKind {
+        self.0.kind
+    }
+
+    /// The class of the root cause, `None` if the error is not thrown by Java.
+    pub fn class(&self) -> Option<&str> {
+        self.0.class.as_deref()
+    }
+
+    /// The message of the root cause, without the `ex-info` data.
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    /// The data map of the innermost `ex-info` in the chain, as JSON.
+    pub fn data(&self) -> Option<&Value> {
+        self.0.data.as_ref()
+    }
+
+    /// The frames of the root cause, e.g. `clojure.core$eval.invoke(core.clj:3)`.
+    pub fn stack_trace(&self) -> &[String] {
+        &self.0.stack_trace
+    }
+
+    /// The error reported by j4rs.
+    pub fn source(&self) -> &J4RsError {
+        &self.0.source
+    }
+}
+
+/// The error j4rs reports on a thread without the JNI env.
+const NO_JNI_ENV: &str = "Could not find the JNIEnv";
+
+impl From<J4RsError> for FfiError {
+    fn from(source: J4RsError) -> Self {
+        let trace = match &source {
+            J4RsError::JavaError(trace) if !trace.starts_with(NO_JNI_ENV) => trace,
+            _ => {
+                let kind = match source {
+                    J4RsError::Timeout => FfiErrorKind::Timeout,
+                    J4RsError::JavaError(_) => FfiErrorKind::ThreadNotAttached,
+                    _ => FfiErrorKind::Other,
+                };
+                return Self(Box::new(Inner {
+                    kind,
+                    class: None,
+                    message: source.to_string(),
+                    data: None,
+                    stack_trace: vec![],
+                    source,
+                }));
+            }
+        };
+        let chain = exceptions(trace);
+        let data = chain
+            .iter()
+            .rev()
+            .filter(|e| e.class == "clojure.lang.ExceptionInfo")
+            .find_map(|e| ex_info(&e.message));
+        let kind = kind(&chain, data.as_ref().map(|(_, data)| data));
+        let root = chain.into_iter().last().unwrap_or_else(|| Exception {
+            message: trace.trim().to_string(),
+            ..Default::default()
+        });
+        let message = match &data {
+            Some((message, _)) if root.class == "clojure.lang.ExceptionInfo" => message.clone(),
+            _ => root.message,
+        };
+        Self(Box::new(Inner {
+            kind,
+            class: (!root.class.is_empty()).then_some(root.class),
+            message,
+            data: data.map(|(_, data)| data),
+            stack_trace: root.frames,
+            source,
+        }))
+    }
+}
+
+impl FfiError {
+    /// The current thread cannot be attached to the JVM.
+    pub(crate) fn thread_not_attached(source: J4RsError) -> Self {
+        Self(Box::new(Inner {
+            kind: FfiErrorKind::ThreadNotAttached,
+            class: None,
+            message: format!("the thread is not attached to the JVM: {}", source),
+            data: None,
+            stack_trace: vec![],
+            source,
+        }))
+    }
+}
+
+impl From<FfiError> for J4RsError {
+    fn from(err: FfiError) -> Self {
+        err.0.source
+    }
+}
+
+impl fmt::Display for FfiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0.class {
+            Some(class) => write!(f, "{}: {}", class, self.0.message)?,
+            None => f.write_str(&self.0.message)?,
+        }
+        if let Some(data) = &self.0.data {
+            write!(f, " {}", data)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FfiError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn java(trace: &str) -> FfiError {
+        J4RsError::JavaError(trace.replace("\n    ", "\n\t")).into()
+    }
+
+    #[test]
+    fn test_ffi_error() {
+        let err = java(
+            "org.astonbitecode.j4rs.errors.InvocationException: While invoking method invoke of Class clojure.lang.Var
+    at org.astonbitecode.j4rs.api.instantiation.NativeInstantiationImpl.invokeMethod(NativeInstantiationImpl.java:137)
+Caused by: java.lang.reflect.InvocationTargetException
+    at java.base/jdk.internal.reflect.DirectMethodHandleAccessor.invoke(DirectMethodHandleAccessor.java:118)
+    ... 1 more
+Caused by: clojure.lang.ExceptionInfo: check failed {:type :elle/timeout, :ms 1000}
+    at elle.core$check.invokeStatic(core.clj:42)
+    at elle.core$check.invoke(core.clj:40)
+    ... 3 more",
+        );
+        assert_eq!(err.kind(), FfiErrorKind::Timeout);
+        assert_eq!(err.class(), Some("clojure.lang.ExceptionInfo"));
+        assert_eq!(err.message(), "check failed");
+        assert_eq!(
+            err.data(),
+            Some(&serde_json::json!({"type": "elle/timeout", "ms": 1000}))
+        );
+        assert_eq!(err.stack_trace().len(), 2);
+        assert_eq!(
+            err.stack_trace()[0],
+            "elle.core$check.invokeStatic(core.clj:42)"
+        );
+        assert!(matches!(J4RsError::from(err), J4RsError::JavaError(_)));
+
+        let err = java(
+            "clojure.lang.ExceptionInfo: bad op {:op [:w 1]}
+    at elle.core$op.invoke(core.clj:1)",
+        );
+        assert_eq!(err.kind(), FfiErrorKind::ExInfo);
+        assert_eq!(
+            err.to_string(),
+            r#"clojure.lang.ExceptionInfo: bad op {"op":["w",1]}"#
+        );
+
+        let err = java(
+            "java.lang.RuntimeException: EOF while reading
+    at clojure.lang.EdnReader.read(EdnReader.java:1)",
+        );
+        assert_eq!(err.kind(), FfiErrorKind::BadEdn);
+
+        let err = java(
+            "java.lang.RuntimeException: require failed
+Caused by: java.io.FileNotFoundException: Could not locate elle/foo__init.class, elle/foo.clj or elle/foo.cljc on classpath.
+    at clojure.lang.RT.load(RT.java:1)",
+        );
+        assert_eq!(err.kind(), FfiErrorKind::ClassNotFound);
+        assert_eq!(err.class(), Some("java.io.FileNotFoundException"));
+
+        let err = java("java.lang.NullPointerException\n    at Foo.bar(Foo.java:1)");
+        assert_eq!(err.kind(), FfiErrorKind::Java);
+        assert_eq!(err.message(), "");
+
+        let err = FfiError::from(J4RsError::JniError("no JVM".to_string()));
+        assert_eq!((err.kind(), err.class()), (FfiErrorKind::Other, None));
+        assert_eq!(err.to_string(), "no JVM");
+
+        let err = FfiError::from(J4RsError::JavaError(
+            "Could not find the JNIEnv in the thread local".to_string(),
+        ));
+        assert_eq!(err.kind(), FfiErrorKind::ThreadNotAttached);
+    }
+}
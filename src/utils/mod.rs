@@ -1,8 +1,16 @@
+#[cfg(feature = "clojure")]
 pub mod ffi;
+#[cfg(feature = "clojure")]
+pub mod ffi_error;
 pub mod iter;
+#[cfg(test)]
+pub(crate) mod testing;
 use std::ops::Range;
 
+#[cfg(feature = "clojure")]
 pub use ffi::*;
+#[cfg(feature = "clojure")]
+pub use ffi_error::*;
 pub use iter::*;
 
 pub trait OverflowingAddRange {
@@ -34,6 +42,23 @@ impl OverflowingAddRange for usize {
     }
 }
 
+/// The message of a panic payload.
+pub(crate) fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(s) => s,
+        None => panic.downcast_ref::<String>().map_or("unknown", |s| s),
+    }
+}
+
+/// The name of a value serialized as a JSON string, e.g. a unit variant.
+pub(crate) fn serialized_name(value: impl serde::Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(value) => value.to_string(),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
 #[cfg(test)]
 pub fn log_init() {
     use log::LevelFilter;
@@ -0,0 +1,62 @@
+//! The fixtures shared by the tests of the client, the workloads, the runner
+//! and the campaigns.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use anyhow::Result;
+
+use crate::{
+    checker::{Check, CheckOption, SerializableCheckResult},
+    generator::RawGenerator,
+    history::SerializableHistoryList,
+    op::Op,
+    workload::Workload,
+};
+
+/// The result of a valid history.
+pub const VALID: &str =
+    r#"{"valid?":true,"anomaly-types":[],"anomalies":{},"not":[],"also-not":[]}"#;
+
+/// Writes unique values to a few keys.
+pub struct WriteGenerator(pub u64);
+
+impl RawGenerator for WriteGenerator {
+    type Item = Op;
+    fn gen(&mut self) -> Self::Item {
+        self.0 += 1;
+        Op::Write(self.0 % 3, self.0)
+    }
+}
+
+/// Counts the history items it checks, the history is always valid.
+pub struct CountChecker(pub Arc<AtomicUsize>);
+
+impl Check for CountChecker {
+    fn check<F: serde::Serialize, ERR: serde::Serialize>(
+        &self,
+        history: &SerializableHistoryList<F, ERR>,
+        _option: CheckOption,
+    ) -> Result<SerializableCheckResult> {
+        self.0.store(history.0.len(), Ordering::SeqCst);
+        Ok(serde_json::from_str(VALID)?)
+    }
+}
+
+/// The [`WriteGenerator`] checked by a [`CountChecker`] of the counter.
+pub struct WriteWorkload(pub Arc<AtomicUsize>);
+
+impl Workload for WriteWorkload {
+    type Gen = WriteGenerator;
+    type Checker = CountChecker;
+
+    fn generator(&self) -> Result<Self::Gen> {
+        Ok(WriteGenerator(0))
+    }
+
+    fn checker(&self) -> Result<Self::Checker> {
+        Ok(CountChecker(self.0.clone()))
+    }
+}
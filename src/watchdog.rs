@@ -0,0 +1,177 @@
+//! The watchdog of a run, which reports, or aborts, the run once no history
+//! item is pushed for a while of the wall clock, e.g. when everything is
+//! deadlocked on the JVM or a mutex. Set it by
+//! [`JepsenClient::with_watchdog`](crate::client::JepsenClient::with_watchdog):
+//!
+//! ```ignore
+//! let client = JepsenClient::new(cluster, gen)
+//!     .with_watchdog(Watchdog::new(Duration::from_secs(60)).abort(true));
+//! ```
+//!
+//! The watchdog runs on a system thread, so it's not stuck with the run, and
+//! only tries the locks to capture the state: a lock it cannot take is
+//! reported as held, which is likely where the run is stuck. It watches the
+//! run until the nemeses are healed, not the check of the history.
+//!
+//! In a madsim runtime, system threads must be allowed, as
+//! [`runtime::block_on`](crate::runtime::block_on) does.
+
+use std::{
+    fmt,
+    sync::{Arc, TryLockError},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    generator::Global,
+    history::{HistoryProcess, HistoryType},
+};
+
+/// The file in the output directory the stalls are appended to.
+pub const STALL_FILE: &str = "watchdog.log";
+
+type StallCallback = Box<dyn Fn(&StallReport) + Send + Sync>;
+
+/// The config of the watchdog, see the [module](self) doc.
+pub struct Watchdog {
+    pub(crate) timeout: Duration,
+    pub(crate) abort: bool,
+    pub(crate) on_stall: Option<StallCallback>,
+}
+
+impl Watchdog {
+    /// Report a stall after no history item is pushed for the timeout.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            abort: false,
+            on_stall: None,
+        }
+    }
+
+    /// Abort the run on a stall like
+    /// [`RunHandle::abort`](crate::client::RunHandle::abort), besides
+    /// reporting it.
+    pub fn abort(mut self, abort: bool) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    /// Call the callback with every stall, besides logging it.
+    pub fn on_stall(mut self, f: impl Fn(&StallReport) + Send + Sync + 'static) -> Self {
+        self.on_stall = Some(Box::new(f));
+        self
+    }
+
+    /// The interval to poll the history at.
+    pub(crate) fn poll_interval(&self) -> Duration {
+        (self.timeout / 4).max(Duration::from_millis(10))
+    }
+}
+
+/// The state of a stalled run. The parts behind a held lock are `None`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StallReport {
+    /// The wall-clock time since the last history item is pushed.
+    pub stalled_for: Duration,
+    /// The number of the history items pushed.
+    pub pushed: u64,
+    /// The ids of the generators alive.
+    pub generators: Option<Vec<u64>>,
+    /// The number of the ops in flight to the cluster.
+    pub in_flight: usize,
+    /// The invokes without completions in the history in memory.
+    pub pending: Option<Vec<String>>,
+    /// The currently active nemeses, in debug form.
+    pub active_nemeses: Option<Vec<String>>,
+}
+
+/// The value behind the lock, or `None` if it's held.
+fn try_read<T, R>(lock: &std::sync::Mutex<T>, f: impl FnOnce(&T) -> R) -> Option<R> {
+    match lock.try_lock() {
+        Ok(guard) => Some(f(&guard)),
+        Err(TryLockError::Poisoned(guard)) => Some(f(&guard.into_inner())),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+impl StallReport {
+    /// Capture the state of the run.
+    pub(crate) fn capture<'a, T: Send + 'a, ERR: Send>(
+        global: &Arc<Global<'a, T, ERR>>,
+        stalled_for: Duration,
+        in_flight: usize,
+    ) -> Self {
+        let generators = try_read(&global.id_set, |ids| ids.iter().copied().collect());
+        let pending = try_read(&global.history.merged, |history| {
+            let mut pending = vec![];
+            for item in &history.list.0 {
+                let HistoryProcess::Gen(process) = item.process else {
+                    continue;
+                };
+                match item.type_ {
+                    HistoryType::Invoke => pending.push((process, item)),
+                    _ => pending.retain(|(p, _)| *p != process),
+                }
+            }
+            pending
+                .into_iter()
+                .map(|(process, item)| {
+                    format!(
+                        "process {} {:?} {:?} (index {})",
+                        process, item.f, item.value, item.index
+                    )
+                })
+                .collect()
+        });
+        let active_nemeses = try_read(&global.nemeses, |nemeses| {
+            nemeses
+                .snapshot()
+                .into_iter()
+                .map(|(record, _)| format!("{:?}", record))
+                .collect()
+        });
+        Self {
+            stalled_for,
+            pushed: global.pushed(),
+            generators,
+            in_flight,
+            pending,
+            active_nemeses,
+        }
+    }
+}
+
+impl fmt::Display for StallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn held<T>(part: &Option<Vec<T>>, f: impl Fn(&[T]) -> String) -> String {
+            part.as_deref().map_or("<lock held>".to_string(), f)
+        }
+        writeln!(
+            f,
+            "no history item pushed for {:?} after {} items, {} ops in flight",
+            self.stalled_for, self.pushed, self.in_flight
+        )?;
+        writeln!(
+            f,
+            "  generators: {}",
+            held(&self.generators, |ids| format!("{:?}", ids))
+        )?;
+        writeln!(
+            f,
+            "  active nemeses: {}",
+            held(&self.active_nemeses, |nemeses| nemeses.join(" "))
+        )?;
+        write!(
+            f,
+            "  pending ops: {}",
+            held(&self.pending, |ops| match ops {
+                [] => "none".to_string(),
+                ops => ops.iter().map(|op| format!("\n    {}", op)).collect(),
+            })
+        )
+    }
+}
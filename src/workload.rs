@@ -0,0 +1,255 @@
+//! Workloads, which bundle the generator of ops and the checker of the history
+//! of a kind of test, see [`JepsenClient::run_workload`].
+//!
+//! A workload requires the cluster client to support the ops its generator
+//! generates, e.g. [`RwRegisterWorkload`] requires only the reads, writes and
+//! txns of [`ElleRwClusterClient`](crate::client::ElleRwClusterClient).
+//!
+//! [`ListAppendWorkload`] requires the appends and list reads, and
+//! [`BankWorkload`] the batch reads and the txns of increments.
+//!
+//! [`JepsenClient::run_workload`]: crate::client::JepsenClient::run_workload
+
+use anyhow::Result;
+use default_struct_builder::DefaultBuilder;
+
+use crate::{
+    checker::{bank::BankChecker, Check, CheckOption},
+    generator::{bank::BankGenerator, controller::GeneratorGroupStrategy, RawGenerator},
+    op::Op,
+};
+#[cfg(feature = "clojure")]
+use crate::{
+    checker::{elle_rw::ElleRwChecker, ConsistencyModel},
+    generator::elle_rw::ElleRwGenerator,
+};
+
+/// A kind of test, i.e. the ops to generate and how to check their history.
+pub trait Workload {
+    type Gen: RawGenerator<Item = Op> + Send + 'static;
+    type Checker: Check;
+
+    /// Create the generator of the ops.
+    fn generator(&self) -> Result<Self::Gen>;
+    /// Create the checker of the history, which is called just before
+    /// checking.
+    fn checker(&self) -> Result<Self::Checker>;
+    /// The check option of the workload, used if the options of the run set
+    /// none.
+    fn check_option(&self) -> CheckOption {
+        CheckOption::default()
+    }
+    /// Whether the generator or the checker runs on the JVM, so the java and
+    /// the jars are checked before running, see [`crate::preflight`]. False
+    /// by default.
+    fn requires_jvm(&self) -> bool {
+        false
+    }
+}
+
+/// Options of running a workload.
+#[derive(Debug, Clone, DefaultBuilder)]
+pub struct WorkloadOptions {
+    /// The number of generators, i.e. concurrent processes.
+    pub(crate) generators: usize,
+    /// The number of ops generated by every generator.
+    pub(crate) ops: usize,
+    /// The scheduling of the generators.
+    pub(crate) strategy: GeneratorGroupStrategy,
+    /// The check option overriding the one of the workload.
+    #[builder(into)]
+    pub(crate) check_option: Option<CheckOption>,
+}
+
+impl Default for WorkloadOptions {
+    fn default() -> Self {
+        Self {
+            generators: 3,
+            ops: 100,
+            strategy: GeneratorGroupStrategy::default(),
+            check_option: None,
+        }
+    }
+}
+
+/// The elle rw-register workload: txns of reads and writes generated by
+/// `elle.rw-register`, checked for the given consistency model.
+#[cfg(feature = "clojure")]
+#[derive(Debug, Clone, Default)]
+pub struct RwRegisterWorkload {
+    model: ConsistencyModel,
+}
+
+#[cfg(feature = "clojure")]
+impl RwRegisterWorkload {
+    pub fn new(model: ConsistencyModel) -> Self {
+        Self { model }
+    }
+}
+
+#[cfg(feature = "clojure")]
+impl Workload for RwRegisterWorkload {
+    type Gen = ElleRwGenerator;
+    type Checker = ElleRwChecker;
+
+    fn generator(&self) -> Result<Self::Gen> {
+        Ok(ElleRwGenerator::new()?)
+    }
+
+    fn checker(&self) -> Result<Self::Checker> {
+        Ok(ElleRwChecker::default())
+    }
+
+    fn check_option(&self) -> CheckOption {
+        CheckOption::default().consistency_models(self.model.clone())
+    }
+
+    fn requires_jvm(&self) -> bool {
+        true
+    }
+}
+
+/// The elle list-append workload: txns of appends and list reads generated by
+/// `elle.list-append`, checked for the given consistency model.
+#[cfg(feature = "clojure")]
+#[derive(Debug, Clone, Default)]
+pub struct ListAppendWorkload {
+    model: ConsistencyModel,
+}
+
+#[cfg(feature = "clojure")]
+impl ListAppendWorkload {
+    pub fn new(model: ConsistencyModel) -> Self {
+        Self { model }
+    }
+}
+
+#[cfg(feature = "clojure")]
+impl Workload for ListAppendWorkload {
+    type Gen = ElleRwGenerator;
+    type Checker = ElleRwChecker;
+
+    fn generator(&self) -> Result<Self::Gen> {
+        Ok(ElleRwGenerator::list_append()?)
+    }
+
+    fn checker(&self) -> Result<Self::Checker> {
+        Ok(ElleRwChecker::list_append())
+    }
+
+    fn check_option(&self) -> CheckOption {
+        CheckOption::default().consistency_models(self.model.clone())
+    }
+
+    fn requires_jvm(&self) -> bool {
+        true
+    }
+}
+
+/// The bank workload: transfers between accounts and reads of all of them,
+/// checked for the total of the balances, see [`BankGenerator`].
+#[derive(Debug, Clone)]
+pub struct BankWorkload {
+    accounts: u64,
+    max_amount: i64,
+}
+
+impl BankWorkload {
+    /// Transfers between the accounts in `[0, accounts)`, with amounts in
+    /// `[1, 5]`.
+    pub fn new(accounts: u64) -> Self {
+        Self {
+            accounts,
+            max_amount: 5,
+        }
+    }
+
+    /// Draw the amounts of the transfers from `[1, max_amount]`.
+    pub fn with_max_amount(mut self, max_amount: i64) -> Self {
+        self.max_amount = max_amount;
+        self
+    }
+}
+
+impl Default for BankWorkload {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+impl Workload for BankWorkload {
+    type Gen = BankGenerator;
+    type Checker = BankChecker;
+
+    fn generator(&self) -> Result<Self::Gen> {
+        Ok(BankGenerator::new(self.accounts).with_max_amount(self.max_amount))
+    }
+
+    fn checker(&self) -> Result<Self::Checker> {
+        Ok(BankChecker::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{
+        client::JepsenClient,
+        history::HistoryValue,
+        mock::MockCluster,
+        utils::testing::{WriteGenerator, WriteWorkload},
+    };
+
+    #[madsim::test]
+    async fn test_run_workload() {
+        // the raw generator of the client is replaced by the workload
+        let client = JepsenClient::new(MockCluster::new(1), WriteGenerator(100));
+        let client: &'static _ = Box::leak(client.into());
+        let checked = Arc::new(AtomicUsize::new(0));
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-workload-{}", std::process::id()));
+        let opts = WorkloadOptions::default()
+            .generators(2)
+            .ops(10)
+            .check_option(CheckOption::default().directory(dir.clone()));
+        client
+            .run_workload(&WriteWorkload(checked.clone()), opts)
+            .await
+            .unwrap();
+        // an invoke and an ok for every op
+        assert_eq!(checked.load(Ordering::SeqCst), 2 * 2 * 10);
+        let history = client.global.full_history().unwrap();
+        assert!(history.0.iter().all(|item| match &item.value {
+            HistoryValue::Op(Op::Write(_, v)) => *v <= 20,
+            _ => false,
+        }));
+        assert!(dir.join(crate::client::FAULT_INTERVALS_FILE).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[madsim::test]
+    async fn test_bank_workload() {
+        let client = JepsenClient::new(MockCluster::new(1), WriteGenerator(100));
+        let client: &'static _ = Box::leak(client.into());
+        let dir = std::env::temp_dir().join(format!("jepsen-rs-bank-{}", std::process::id()));
+        let opts = WorkloadOptions::default()
+            .generators(3)
+            .ops(50)
+            .check_option(CheckOption::default().directory(dir.clone()));
+        let res = client
+            .run_workload(&BankWorkload::new(3), opts)
+            .await
+            .unwrap();
+        assert!(matches!(res.valid(), crate::checker::ValidType::True));
+        let history = client.global.full_history().unwrap();
+        assert!(history
+            .0
+            .iter()
+            .any(|item| matches!(&item.value, HistoryValue::Op(Op::BatchRead(_)))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,9 +2,12 @@ use std::{collections::HashMap, sync::Mutex};
 
 use anyhow::Result;
 use jepsen_rs::{
-    client::{Client, ElleRwClusterClient, JepsenClient},
+    client::{Client, ClusterLifecycle, ElleRwClusterClient, JepsenClient},
     generator::{controller::GeneratorGroupStrategy, elle_rw::ElleRwGenerator, GeneratorGroup},
-    op::Op,
+    nemesis::{NemesisClusterClient, ServerId},
+    op::{Op, OpOrNemesis},
+    runner::TestBuilder,
+    workload::RwRegisterWorkload,
 };
 use log::{info, LevelFilter};
 
@@ -32,6 +35,21 @@ impl ElleRwClusterClient for TestCluster {
     }
 }
 
+impl ClusterLifecycle for TestCluster {}
+
+#[async_trait::async_trait]
+impl NemesisClusterClient for TestCluster {
+    fn size(&self) -> usize {
+        1
+    }
+    fn get_node_id(&self, _id: ServerId) -> Option<madsim::task::NodeId> {
+        None
+    }
+    async fn get_leader_without_term(&self) -> ServerId {
+        0
+    }
+}
+
 #[test]
 pub fn intergration_test() -> Result<()> {
     _ = pretty_env_logger::formatted_builder()
@@ -53,7 +71,7 @@ pub fn intergration_test() -> Result<()> {
         // get generators, transform and merge them
         let g1 = client
             .new_generator(100)
-            .filter(|o| matches!(o, Op::Txn(txn) if txn.len() == 1))
+            .filter(|o| matches!(o, OpOrNemesis::Op(Op::Txn(txn)) if txn.len() == 1))
             .await;
         let g2 = client.new_generator(50);
         let g3 = client.new_generator(50);
@@ -66,3 +84,14 @@ pub fn intergration_test() -> Result<()> {
     });
     Ok(())
 }
+
+#[test]
+pub fn test_builder_test() -> Result<()> {
+    let report = TestBuilder::new(TestCluster::new, RwRegisterWorkload::default())
+        .concurrency(3)
+        .ops(50)
+        .build()
+        .run()?;
+    info!("history checked result: {:?}", report.result);
+    Ok(())
+}